@@ -21,3 +21,29 @@ fn bench_sigverify(bencher: &mut Bencher) {
         let _ans = sigverify::ed25519_verify(&batches);
     })
 }
+
+#[bench]
+fn bench_sigverify_cpu(bencher: &mut Bencher) {
+    let tx = test_tx();
+
+    // generate packet vector
+    let batches = to_packets(&vec![tx; 128]);
+
+    // verify every packet on its own
+    bencher.iter(|| {
+        let _ans = sigverify::ed25519_verify_cpu(&batches);
+    })
+}
+
+#[bench]
+fn bench_sigverify_cpu_batch(bencher: &mut Bencher) {
+    let tx = test_tx();
+
+    // generate packet vector
+    let batches = to_packets(&vec![tx; 128]);
+
+    // verify the packets in chunks of sigverify::VERIFY_CHUNK_SIZE
+    bencher.iter(|| {
+        let _ans = sigverify::ed25519_verify_cpu_batch(&batches);
+    })
+}
@@ -0,0 +1,534 @@
+//! The `account_store` module provides the storage behind the accounts of a `Bank`,
+//! either an in-memory map or an append-only file for account sets that don't fit in RAM.
+
+use bincode::{deserialize, serialize_into, serialized_size};
+use buffett_program_interface::account::Account;
+use buffett_program_interface::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+/// Storage for the accounts of a `Bank`. The bank locks the accounts of the transactions
+/// it processes, so a store only has to keep each of its own calls consistent.
+pub trait AccountStore: Send + Sync {
+    fn get_account(&self, pubkey: &Pubkey) -> io::Result<Option<Account>>;
+
+    /// Store the `accounts` of a transaction, all of them or none if the store fails.
+    /// An account with no tokens left is purged.
+    fn store_accounts(&self, accounts: &[(&Pubkey, &Account)]) -> io::Result<()>;
+
+    fn store_account(&self, pubkey: &Pubkey, account: &Account) -> io::Result<()> {
+        self.store_accounts(&[(pubkey, account)])
+    }
+
+    /// The number of accounts stored.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The keys of all the accounts, in order. The accounts themselves are read one
+    /// at a time, so the whole set never has to be in memory.
+    fn ordered_keys(&self) -> Vec<Pubkey>;
+
+    /// Remove all the accounts.
+    fn clear(&self) -> io::Result<()>;
+
+    /// Called when the bank writes a checkpoint. The accounts stored so far are made
+    /// durable, and a store may reclaim the space of the versions it no longer needs.
+    fn checkpoint(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps every account in memory.
+#[derive(Default)]
+pub struct MemoryAccountStore {
+    accounts: RwLock<HashMap<Pubkey, Account>>,
+}
+
+impl AccountStore for MemoryAccountStore {
+    fn get_account(&self, pubkey: &Pubkey) -> io::Result<Option<Account>> {
+        Ok(self.accounts.read().unwrap().get(pubkey).cloned())
+    }
+
+    fn store_accounts(&self, accounts: &[(&Pubkey, &Account)]) -> io::Result<()> {
+        let mut stored = self.accounts.write().unwrap();
+        for (pubkey, account) in accounts {
+            //purge if 0
+            if account.tokens == 0 {
+                stored.remove(pubkey);
+            } else {
+                stored.insert(**pubkey, (*account).clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.accounts.read().unwrap().len()
+    }
+
+    fn ordered_keys(&self) -> Vec<Pubkey> {
+        let mut keys: Vec<_> = self.accounts.read().unwrap().keys().cloned().collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.accounts.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+// location of the latest version of an account in the file
+#[derive(Clone, Copy)]
+struct StoredAccount {
+    offset: u64,
+    len: u64,
+}
+
+// the record appended for each stored account, None once it has been purged
+type Record = (Pubkey, Option<Account>);
+
+const SIZEOF_LEN: u64 = 8;
+
+fn err_bincode_to_io(e: Box<bincode::ErrorKind>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+// the latest version of each account and the file they are read from, both are
+// replaced when the file is compacted
+struct Index {
+    accounts: HashMap<Pubkey, StoredAccount>,
+    reader: File,
+}
+
+/// Appends every stored account to a file and keeps only the offset of the latest
+/// version of each account in memory. Reads don't take the writer's lock. The file is
+/// rewritten with only the latest versions at a checkpoint once most of it is old
+/// versions.
+pub struct AppendAccountStore {
+    path: PathBuf,
+    // locked before the index by the calls that take both
+    writer: Mutex<File>,
+    index: RwLock<Index>,
+}
+
+impl AppendAccountStore {
+    /// Open the store backed by the file at `path`, creating it if needed. The accounts
+    /// the file holds are indexed again, a record left partly written is dropped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let writer = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let accounts = recover(&writer)?;
+        let reader = writer.try_clone()?;
+        Ok(AppendAccountStore {
+            path: path.to_path_buf(),
+            writer: Mutex::new(writer),
+            index: RwLock::new(Index { accounts, reader }),
+        })
+    }
+
+    // Write the latest version of each account to a new file that replaces the old
+    // one once it is durable.
+    fn compact(&self, writer: &mut File) -> io::Result<()> {
+        let compact_path = self.path.with_extension("compact");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&compact_path)?;
+        let mut accounts = HashMap::new();
+        {
+            // the writer's lock keeps the index from changing until it is replaced
+            let index = self.index.read().unwrap();
+            let mut compacted = BufWriter::new(&file);
+            let mut offset = 0;
+            for (pubkey, stored) in &index.accounts {
+                let mut data = vec![0; (SIZEOF_LEN + stored.len) as usize];
+                index
+                    .reader
+                    .read_exact_at(&mut data, stored.offset - SIZEOF_LEN)?;
+                compacted.write_all(&data)?;
+                let stored = StoredAccount {
+                    offset: offset + SIZEOF_LEN,
+                    len: stored.len,
+                };
+                accounts.insert(*pubkey, stored);
+                offset = stored.offset + stored.len;
+            }
+            compacted.flush()?;
+        }
+        file.sync_all()?;
+        let reader = file.try_clone()?;
+        rename(&compact_path, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|dir| *dir != Path::new("")) {
+            File::open(dir)?.sync_all()?;
+        }
+
+        let mut index = self.index.write().unwrap();
+        info!(
+            "compacted {:?} from {} to {} bytes",
+            self.path,
+            writer.metadata()?.len(),
+            file.metadata()?.len()
+        );
+        *writer = file;
+        *index = Index { accounts, reader };
+        Ok(())
+    }
+}
+
+// index the records in `file`, a record left partly written is truncated
+fn recover(file: &File) -> io::Result<HashMap<Pubkey, StoredAccount>> {
+    let file_len = file.metadata()?.len();
+    let mut accounts = HashMap::new();
+    let mut offset = 0;
+    while offset + SIZEOF_LEN <= file_len {
+        let mut len = [0; SIZEOF_LEN as usize];
+        file.read_exact_at(&mut len, offset)?;
+        let len: u64 = deserialize(&len).map_err(err_bincode_to_io)?;
+        let stored = StoredAccount {
+            offset: offset + SIZEOF_LEN,
+            len,
+        };
+        if stored.offset + len > file_len {
+            break;
+        }
+        match read_record(file, stored)? {
+            (pubkey, Some(_)) => accounts.insert(pubkey, stored),
+            (pubkey, None) => accounts.remove(&pubkey),
+        };
+        offset = stored.offset + len;
+    }
+    if offset != file_len {
+        warn!(
+            "dropping {} bytes of a partial account record",
+            file_len - offset
+        );
+        file.set_len(offset)?;
+    }
+    Ok(accounts)
+}
+
+fn read_record(file: &File, stored: StoredAccount) -> io::Result<Record> {
+    let mut data = vec![0; stored.len as usize];
+    file.read_exact_at(&mut data, stored.offset)?;
+    deserialize(&data).map_err(err_bincode_to_io)
+}
+
+impl AccountStore for AppendAccountStore {
+    fn get_account(&self, pubkey: &Pubkey) -> io::Result<Option<Account>> {
+        let index = self.index.read().unwrap();
+        match index.accounts.get(pubkey) {
+            Some(stored) => Ok(read_record(&index.reader, *stored)?.1),
+            None => Ok(None),
+        }
+    }
+
+    fn store_accounts(&self, accounts: &[(&Pubkey, &Account)]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        //purge if 0, the old versions are left in the file until it is compacted
+        let mut records: Vec<(&Pubkey, Option<&Account>)> = vec![];
+        {
+            let index = self.index.read().unwrap();
+            for (pubkey, account) in accounts {
+                if account.tokens != 0 {
+                    records.push((*pubkey, Some(*account)));
+                } else if index.accounts.contains_key(*pubkey)
+                    || records.iter().any(|(key, _)| key == pubkey)
+                {
+                    // the purge is recorded so the account stays purged when the file is reopened
+                    records.push((*pubkey, None));
+                }
+            }
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let end = writer.seek(SeekFrom::End(0))?;
+        let mut data = vec![];
+        let mut stored = vec![];
+        for record in &records {
+            let len = serialized_size(record).map_err(err_bincode_to_io)?;
+            serialize_into(&mut data, &len).map_err(err_bincode_to_io)?;
+            stored.push(StoredAccount {
+                offset: end + data.len() as u64,
+                len,
+            });
+            serialize_into(&mut data, record).map_err(err_bincode_to_io)?;
+        }
+        if let Err(err) = writer.write_all(&data) {
+            // none of the records is indexed, so none of them is left in the file
+            writer.set_len(end)?;
+            return Err(err);
+        }
+
+        // the index is updated after the write so readers never see a partial account
+        let mut index = self.index.write().unwrap();
+        for ((pubkey, account), stored) in records.into_iter().zip(stored) {
+            if account.is_some() {
+                index.accounts.insert(*pubkey, stored);
+            } else {
+                index.accounts.remove(pubkey);
+            }
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.index.read().unwrap().accounts.len()
+    }
+
+    fn ordered_keys(&self) -> Vec<Pubkey> {
+        let mut keys: Vec<_> = self
+            .index
+            .read()
+            .unwrap()
+            .accounts
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        let writer = self.writer.lock().unwrap();
+        let mut index = self.index.write().unwrap();
+        writer.set_len(0)?;
+        index.accounts.clear();
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        let latest: u64 = {
+            let index = self.index.read().unwrap();
+            index
+                .accounts
+                .values()
+                .map(|stored| SIZEOF_LEN + stored.len)
+                .sum()
+        };
+        if writer.metadata()?.len() > 2 * latest {
+            self.compact(&mut writer)
+        } else {
+            writer.sync_data()
+        }
+    }
+}
+
+/// Which store a fullnode keeps its accounts in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStoreKind {
+    /// A `MemoryAccountStore`
+    Memory,
+    /// An `AppendAccountStore` in the ledger directory
+    Append,
+}
+
+impl Default for AccountStoreKind {
+    fn default() -> Self {
+        AccountStoreKind::Memory
+    }
+}
+
+impl AccountStoreKind {
+    /// Open a store of this kind for the ledger at `ledger_path`.
+    pub fn open(self, ledger_path: &str) -> io::Result<Box<AccountStore>> {
+        match self {
+            AccountStoreKind::Memory => Ok(Box::new(MemoryAccountStore::default())),
+            AccountStoreKind::Append => Ok(Box::new(AppendAccountStore::open(
+                &Path::new(ledger_path).join("accounts"),
+            )?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{Keypair, KeypairUtil};
+    use std::fs::remove_file;
+    use std::path::PathBuf;
+
+    fn tmp_store_path(name: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "/tmp/tmp-accounts-{}-{}",
+            name,
+            Keypair::new().pubkey()
+        ))
+    }
+
+    fn check_store(store: &AccountStore) {
+        let program_id = Keypair::new().pubkey();
+        let keys: Vec<_> = (0..3).map(|_| Keypair::new().pubkey()).collect();
+        assert!(store.is_empty());
+        assert!(store.get_account(&keys[0]).unwrap().is_none());
+
+        for (i, key) in keys.iter().enumerate() {
+            store
+                .store_account(key, &Account::new(i as i64 + 1, i, program_id))
+                .unwrap();
+        }
+        assert_eq!(store.len(), 3);
+        let account = store.get_account(&keys[2]).unwrap().unwrap();
+        assert_eq!(account.tokens, 3);
+        assert_eq!(account.userdata, vec![0; 2]);
+
+        // the latest version of an account is read back
+        let mut account = store.get_account(&keys[1]).unwrap().unwrap();
+        account.tokens = 10;
+        account.userdata = vec![1, 2, 3];
+        store.store_account(&keys[1], &account).unwrap();
+        let account = store.get_account(&keys[1]).unwrap().unwrap();
+        assert_eq!(account.userdata, vec![1, 2, 3]);
+
+        // and an account with no tokens is purged
+        store
+            .store_account(&keys[0], &Account::new(0, 0, program_id))
+            .unwrap();
+        assert!(store.get_account(&keys[0]).unwrap().is_none());
+        assert_eq!(store.len(), 2);
+
+        let ordered = store.ordered_keys();
+        let mut expected = vec![keys[1], keys[2]];
+        expected.sort();
+        assert_eq!(ordered, expected);
+
+        // the accounts of a transaction are stored together
+        let account = Account::new(4, 0, program_id);
+        let purged = Account::new(0, 0, program_id);
+        store
+            .store_accounts(&[(&keys[0], &account), (&keys[1], &purged)])
+            .unwrap();
+        assert_eq!(store.get_account(&keys[0]).unwrap().unwrap().tokens, 4);
+        assert!(store.get_account(&keys[1]).unwrap().is_none());
+        assert_eq!(store.len(), 2);
+
+        store.checkpoint().unwrap();
+        assert_eq!(store.get_account(&keys[2]).unwrap().unwrap().tokens, 3);
+
+        store.clear().unwrap();
+        assert!(store.is_empty());
+        assert!(store.get_account(&keys[2]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memory_account_store() {
+        check_store(&MemoryAccountStore::default());
+    }
+
+    #[test]
+    fn test_append_account_store() {
+        let path = tmp_store_path("test_append_account_store");
+        check_store(&AppendAccountStore::open(&path).unwrap());
+        assert_eq!(path.metadata().unwrap().len(), 0);
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_append_account_store_reopen() {
+        let path = tmp_store_path("test_append_account_store_reopen");
+        let program_id = Keypair::new().pubkey();
+        let keys: Vec<_> = (0..3).map(|_| Keypair::new().pubkey()).collect();
+        {
+            let store = AppendAccountStore::open(&path).unwrap();
+            for key in &keys {
+                store
+                    .store_account(key, &Account::new(1, 0, program_id))
+                    .unwrap();
+            }
+            store
+                .store_account(&keys[1], &Account::new(5, 3, program_id))
+                .unwrap();
+            store
+                .store_account(&keys[2], &Account::new(0, 0, program_id))
+                .unwrap();
+        }
+
+        // the latest versions are found again, and a purged account stays purged
+        let store = AppendAccountStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get_account(&keys[0]).unwrap().unwrap().tokens, 1);
+        let account = store.get_account(&keys[1]).unwrap().unwrap();
+        assert_eq!(account.userdata, vec![0; 3]);
+        assert!(store.get_account(&keys[2]).unwrap().is_none());
+        drop(store);
+
+        // a partly written record is dropped
+        let len = path.metadata().unwrap().len();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[9, 0, 0])
+            .unwrap();
+        let store = AppendAccountStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(path.metadata().unwrap().len(), len);
+        store
+            .store_account(&keys[2], &Account::new(7, 0, program_id))
+            .unwrap();
+        drop(store);
+        let store = AppendAccountStore::open(&path).unwrap();
+        assert_eq!(store.get_account(&keys[2]).unwrap().unwrap().tokens, 7);
+
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_append_account_store_compact() {
+        let path = tmp_store_path("test_append_account_store_compact");
+        let program_id = Keypair::new().pubkey();
+        let keys: Vec<_> = (0..3).map(|_| Keypair::new().pubkey()).collect();
+        let store = AppendAccountStore::open(&path).unwrap();
+        for key in &keys {
+            store
+                .store_account(key, &Account::new(1, 0, program_id))
+                .unwrap();
+        }
+        store.checkpoint().unwrap();
+        let len = path.metadata().unwrap().len();
+
+        // the file is rewritten once most of it is old versions
+        for tokens in 2..5 {
+            store
+                .store_account(&keys[0], &Account::new(tokens, 0, program_id))
+                .unwrap();
+        }
+        store
+            .store_account(&keys[1], &Account::new(0, 0, program_id))
+            .unwrap();
+        assert!(path.metadata().unwrap().len() > len);
+        store.checkpoint().unwrap();
+        assert!(path.metadata().unwrap().len() < len);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get_account(&keys[0]).unwrap().unwrap().tokens, 4);
+        assert!(store.get_account(&keys[1]).unwrap().is_none());
+
+        // and stored to as before
+        store
+            .store_account(&keys[1], &Account::new(6, 0, program_id))
+            .unwrap();
+        drop(store);
+        let store = AppendAccountStore::open(&path).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get_account(&keys[0]).unwrap().unwrap().tokens, 4);
+        assert_eq!(store.get_account(&keys[1]).unwrap().unwrap().tokens, 6);
+        assert_eq!(store.get_account(&keys[2]).unwrap().unwrap().tokens, 1);
+
+        remove_file(path).unwrap();
+    }
+}
@@ -5,6 +5,8 @@ extern crate serde_json;
 extern crate buffett;
 
 use clap::{App, Arg};
+use buffett::account_store::AccountStoreKind;
+use buffett::choose_gossip_peer_strategy::GossipStrategy;
 use buffett::crdt::FULLNODE_PORT_RANGE;
 use buffett::fullnode::Config;
 use buffett::logger;
@@ -43,6 +45,20 @@ fn main() {
                 .value_name("PORT")
                 .takes_value(true)
                 .help("Bind to port or address"),
+        ).arg(
+            Arg::with_name("gossip_strategy")
+                .long("gossip-strategy")
+                .value_name("STRATEGY")
+                .takes_value(true)
+                .possible_values(&["random", "weighted"])
+                .help("How to pick the peers to gossip with, defaults to weighted by stake"),
+        ).arg(
+            Arg::with_name("account_store")
+                .long("account-store")
+                .value_name("STORE")
+                .takes_value(true)
+                .possible_values(&["memory", "append"])
+                .help("Where to keep the accounts, defaults to memory, append uses the ledger directory"),
         ).get_matches();
 
     let bind_addr: SocketAddr = {
@@ -69,7 +85,19 @@ fn main() {
 
     // we need all the receiving sockets to be bound within the expected
     // port range that we open on aws
-    let config = Config::new(&bind_addr, pkcs8);
+    let mut config = Config::new(&bind_addr, pkcs8);
+    if let Some(gossip_strategy) = matches.value_of("gossip_strategy") {
+        config.gossip_strategy = match gossip_strategy {
+            "random" => GossipStrategy::Random,
+            _ => GossipStrategy::Weighted,
+        };
+    }
+    if let Some(account_store) = matches.value_of("account_store") {
+        config.account_store = match account_store {
+            "append" => AccountStoreKind::Append,
+            _ => AccountStoreKind::Memory,
+        };
+    }
     let stdout = io::stdout();
     serde_json::to_writer(stdout, &config).expect("serialize");
 }
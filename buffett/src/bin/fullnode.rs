@@ -8,6 +8,8 @@ extern crate serde_json;
 extern crate buffett;
 
 use clap::{App, Arg};
+use buffett::account_store::AccountStoreKind;
+use buffett::choose_gossip_peer_strategy::GossipStrategy;
 use buffett::client::mk_client;
 use buffett::crdt::Node;
 use buffett::token_service::DRONE_PORT;
@@ -15,6 +17,7 @@ use buffett::fullnode::{Config, Fullnode, FullnodeReturnType};
 use buffett::logger;
 use buffett::metrics::set_panic_hook;
 use buffett::signature::{Keypair, KeypairUtil};
+use buffett::sigverify::SigVerifyMode;
 use buffett::thin_client::poll_gossip_for_leader;
 use buffett::wallet::request_airdrop;
 use std::fs::File;
@@ -50,14 +53,23 @@ fn main() -> () {
                 .takes_value(true)
                 .required(true)
                 .help("use DIR as persistent ledger location"),
+        ).arg(
+            Arg::with_name("sigverify-batch")
+                .long("sigverify-batch")
+                .help("Verify transaction signatures in batches on the CPU"),
         ).get_matches();
 
-    let (keypair, ncp) = if let Some(i) = matches.value_of("identity") {
+    let (keypair, ncp, gossip_strategy, account_store) = if let Some(i) = matches.value_of("identity") {
         let path = i.to_string();
         if let Ok(file) = File::open(path.clone()) {
             let parse: serde_json::Result<Config> = serde_json::from_reader(file);
             if let Ok(data) = parse {
-                (data.keypair(), data.node_info.contact_info.ncp)
+                (
+                    data.keypair(),
+                    data.node_info.contact_info.ncp,
+                    data.gossip_strategy,
+                    data.account_store,
+                )
             } else {
                 eprintln!("failed to parse {}", path);
                 exit(1);
@@ -67,7 +79,12 @@ fn main() -> () {
             exit(1);
         }
     } else {
-        (Keypair::new(), socketaddr!(0, 8000))
+        (
+            Keypair::new(),
+            socketaddr!(0, 8000),
+            GossipStrategy::default(),
+            AccountStoreKind::default(),
+        )
     };
 
     let ledger_path = matches.value_of("ledger").unwrap();
@@ -83,7 +100,23 @@ fn main() -> () {
     let node_info = node.info.clone();
     let pubkey = keypair.pubkey();
 
-    let mut fullnode = Fullnode::new(node, ledger_path, keypair, network, false, None);
+    let mut fullnode = Fullnode::new_with_account_store(
+        node,
+        ledger_path,
+        keypair,
+        network,
+        false,
+        None,
+        account_store,
+    );
+    fullnode
+        .crdt()
+        .write()
+        .unwrap()
+        .set_gossip_strategy(gossip_strategy);
+    if matches.is_present("sigverify-batch") {
+        fullnode.set_sigverify_mode(SigVerifyMode::CpuBatch);
+    }
 
     // airdrop stuff, probably goes away at some point
     let leader = match network {
@@ -5,6 +5,7 @@ extern crate buffett;
 
 use clap::{App, Arg, SubCommand};
 use buffett::tx_vault::Bank;
+use buffett::checkpoint::{compress_ledger_to_checkpoints, prune_ledger_to_checkpoints};
 use buffett::ledger::{read_ledger, verify_ledger};
 use buffett::logger;
 use std::io::{stdout, Write};
@@ -46,6 +47,14 @@ fn main() {
         .subcommand(SubCommand::with_name("print").about("Print the ledger"))
         .subcommand(SubCommand::with_name("json").about("Print the ledger in JSON format"))
         .subcommand(SubCommand::with_name("verify").about("Verify the ledger's PoH"))
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about("Remove the ledger segments covered by the ledger's checkpoints"),
+        )
+        .subcommand(
+            SubCommand::with_name("compress")
+                .about("Compress the ledger segments covered by the ledger's checkpoints"),
+        )
         .get_matches();
 
     let ledger_path = matches.value_of("ledger").unwrap();
@@ -145,6 +154,20 @@ fn main() {
                 }
             }
         }
+        ("prune", _) => match prune_ledger_to_checkpoints(ledger_path) {
+            Ok(start) => println!("ledger starts at entry {}", start),
+            Err(err) => {
+                eprintln!("Failed to prune ledger at {}: {:?}", ledger_path, err);
+                exit(1);
+            }
+        },
+        ("compress", _) => match compress_ledger_to_checkpoints(ledger_path) {
+            Ok(compressed) => println!("compressed {} ledger segments", compressed),
+            Err(err) => {
+                eprintln!("Failed to compress ledger at {}: {:?}", ledger_path, err);
+                exit(1);
+            }
+        },
         ("", _) => {
             eprintln!("{}", matches.usage());
             exit(1);
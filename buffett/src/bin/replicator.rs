@@ -92,6 +92,7 @@ fn main() {
         &exit,
         ledger_path,
         node,
+        keypair,
         network_addr,
         done.clone(),
     );
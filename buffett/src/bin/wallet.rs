@@ -188,6 +188,21 @@ fn main() -> Result<(), Box<error::Error>> {
                         .multiple(true)
                         .use_delimiter(true)
                         .help("Any third party signatures required to unlock the tokens"),
+                ).arg(
+                    Arg::with_name("required-signatures")
+                        .long("required-signatures")
+                        .value_name("NUM")
+                        .takes_value(true)
+                        .requires("witness")
+                        .help("How many of the third party signatures unlock the tokens, all by default"),
+                ).arg(
+                    Arg::with_name("split")
+                        .long("split-to")
+                        .value_name("PUBKEY:NUM")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Pay NUM of the tokens to PUBKEY instead of the recipient"),
                 ).arg(
                     Arg::with_name("cancelable")
                         .long("cancelable")
@@ -209,7 +224,15 @@ fn main() -> Result<(), Box<error::Error>> {
                         .value_name("PROCESS_ID")
                         .takes_value(true)
                         .required(true)
-                        .help("The process id of the transfer to authorize")
+                        .help("The process id of the transfer to authorize"),
+                ).arg(
+                    Arg::with_name("payee")
+                        .long("payee")
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Any other payee of the transfer")
                 )
         ).subcommand(
             SubCommand::with_name("send-timestamp")
@@ -227,7 +250,15 @@ fn main() -> Result<(), Box<error::Error>> {
                         .value_name("PROCESS_ID")
                         .takes_value(true)
                         .required(true)
-                        .help("The process id of the transfer to unlock")
+                        .help("The process id of the transfer to unlock"),
+                ).arg(
+                    Arg::with_name("payee")
+                        .long("payee")
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Any other payee of the transfer"),
                 ).arg(
                     Arg::with_name("datetime")
                         .long("date")
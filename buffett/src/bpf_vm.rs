@@ -0,0 +1,399 @@
+//! The `bpf_vm` module interprets eBPF programs. A program can only address its
+//! input and its own stack, and is stopped after a bounded number of instructions,
+//! so a deployed program can't reach into the node or hang it.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Size of an instruction, `lddw` takes two.
+pub const INSN_SIZE: usize = 8;
+
+/// Size of the stack of a program, r10 points to its end.
+pub const STACK_SIZE: usize = 512;
+
+/// Number of instructions a program may execute before it is stopped.
+pub const MAX_INSTRUCTIONS: u64 = 100_000;
+
+// the addresses the stack and the input are mapped at, r1 points to the input
+const MM_STACK_START: u64 = 0x1_0000_0000;
+const MM_INPUT_START: u64 = 0x2_0000_0000;
+
+// instruction classes
+const BPF_LD: u8 = 0x00;
+const BPF_LDX: u8 = 0x01;
+const BPF_ST: u8 = 0x02;
+const BPF_STX: u8 = 0x03;
+const BPF_ALU: u8 = 0x04;
+const BPF_JMP: u8 = 0x05;
+const BPF_ALU64: u8 = 0x07;
+
+// load and store sizes and modes
+const BPF_W: u8 = 0x00;
+const BPF_H: u8 = 0x08;
+const BPF_B: u8 = 0x10;
+const BPF_DW: u8 = 0x18;
+const BPF_IMM: u8 = 0x00;
+const BPF_MEM: u8 = 0x60;
+
+// operand source
+const BPF_X: u8 = 0x08;
+
+// alu operations
+const BPF_ADD: u8 = 0x00;
+const BPF_SUB: u8 = 0x10;
+const BPF_MUL: u8 = 0x20;
+const BPF_DIV: u8 = 0x30;
+const BPF_OR: u8 = 0x40;
+const BPF_AND: u8 = 0x50;
+const BPF_LSH: u8 = 0x60;
+const BPF_RSH: u8 = 0x70;
+const BPF_NEG: u8 = 0x80;
+const BPF_MOD: u8 = 0x90;
+const BPF_XOR: u8 = 0xa0;
+const BPF_MOV: u8 = 0xb0;
+const BPF_ARSH: u8 = 0xc0;
+
+// jump operations
+const BPF_JA: u8 = 0x00;
+const BPF_JEQ: u8 = 0x10;
+const BPF_JGT: u8 = 0x20;
+const BPF_JGE: u8 = 0x30;
+const BPF_JSET: u8 = 0x40;
+const BPF_JNE: u8 = 0x50;
+const BPF_JSGT: u8 = 0x60;
+const BPF_JSGE: u8 = 0x70;
+const BPF_EXIT: u8 = 0x90;
+const BPF_JLT: u8 = 0xa0;
+const BPF_JLE: u8 = 0xb0;
+const BPF_JSLT: u8 = 0xc0;
+const BPF_JSLE: u8 = 0xd0;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BpfError {
+    /// The program is empty or not a whole number of instructions
+    InvalidProgram,
+    InvalidInstruction { pc: usize, opcode: u8 },
+    /// A load or store outside of the input and the stack
+    AccessViolation { pc: usize, address: u64, len: usize },
+    DivideByZero { pc: usize },
+    /// A jump, or the last instruction, leaves the program
+    JumpOutOfBounds { pc: usize },
+    InstructionLimitExceeded,
+}
+
+struct Insn {
+    opc: u8,
+    dst: usize,
+    src: usize,
+    off: i16,
+    imm: i32,
+}
+
+fn decode(prog: &[u8], pc: usize) -> Insn {
+    let bytes = &prog[pc * INSN_SIZE..(pc + 1) * INSN_SIZE];
+    Insn {
+        opc: bytes[0],
+        dst: (bytes[1] & 0x0f) as usize,
+        src: (bytes[1] >> 4) as usize,
+        off: LittleEndian::read_i16(&bytes[2..4]),
+        imm: LittleEndian::read_i32(&bytes[4..8]),
+    }
+}
+
+fn access_len(opc: u8) -> usize {
+    match opc & 0x18 {
+        BPF_W => 4,
+        BPF_H => 2,
+        BPF_B => 1,
+        _ => 8,
+    }
+}
+
+// the bytes of the stack or the input at address
+fn translate<'a>(
+    stack: &'a mut [u8],
+    input: &'a mut [u8],
+    address: u64,
+    len: usize,
+    pc: usize,
+) -> Result<&'a mut [u8], BpfError> {
+    let (memory, start) = if address >= MM_INPUT_START {
+        (input, MM_INPUT_START)
+    } else {
+        (stack, MM_STACK_START)
+    };
+    let offset = address.wrapping_sub(start) as usize;
+    offset
+        .checked_add(len)
+        .and_then(move |end| memory.get_mut(offset..end))
+        .ok_or(BpfError::AccessViolation { pc, address, len })
+}
+
+fn alu(op: u8, dst: u64, src: u64, is64: bool, pc: usize) -> Result<u64, BpfError> {
+    let (dst, src, bits) = if is64 {
+        (dst, src, 64)
+    } else {
+        (u64::from(dst as u32), u64::from(src as u32), 32)
+    };
+    let shift = (src % bits) as u32;
+    let result = match op {
+        BPF_ADD => dst.wrapping_add(src),
+        BPF_SUB => dst.wrapping_sub(src),
+        BPF_MUL => dst.wrapping_mul(src),
+        BPF_DIV => dst.checked_div(src).ok_or(BpfError::DivideByZero { pc })?,
+        BPF_OR => dst | src,
+        BPF_AND => dst & src,
+        BPF_LSH => dst << shift,
+        BPF_RSH => dst >> shift,
+        BPF_NEG => dst.wrapping_neg(),
+        BPF_MOD => dst.checked_rem(src).ok_or(BpfError::DivideByZero { pc })?,
+        BPF_XOR => dst ^ src,
+        BPF_MOV => src,
+        BPF_ARSH if is64 => ((dst as i64) >> shift) as u64,
+        BPF_ARSH => u64::from(((dst as u32 as i32) >> shift) as u32),
+        _ => return Err(BpfError::InvalidInstruction { pc, opcode: op }),
+    };
+    Ok(if is64 { result } else { result & 0xffff_ffff })
+}
+
+fn jump_taken(op: u8, dst: u64, src: u64) -> Option<bool> {
+    Some(match op {
+        BPF_JA => true,
+        BPF_JEQ => dst == src,
+        BPF_JGT => dst > src,
+        BPF_JGE => dst >= src,
+        BPF_JSET => dst & src != 0,
+        BPF_JNE => dst != src,
+        BPF_JSGT => (dst as i64) > (src as i64),
+        BPF_JSGE => (dst as i64) >= (src as i64),
+        BPF_JLT => dst < src,
+        BPF_JLE => dst <= src,
+        BPF_JSLT => (dst as i64) < (src as i64),
+        BPF_JSLE => (dst as i64) <= (src as i64),
+        _ => return None,
+    })
+}
+
+/// Run `prog` over `input`, which it may modify, and return the value it exits with.
+pub fn execute(prog: &[u8], input: &mut [u8]) -> Result<u64, BpfError> {
+    if prog.is_empty() || prog.len() % INSN_SIZE != 0 {
+        return Err(BpfError::InvalidProgram);
+    }
+    let num_insns = prog.len() / INSN_SIZE;
+    let mut stack = vec![0u8; STACK_SIZE];
+    let mut reg = [0u64; 11];
+    reg[1] = MM_INPUT_START;
+    reg[10] = MM_STACK_START + STACK_SIZE as u64;
+
+    let mut pc = 0;
+    let mut count = 0;
+    loop {
+        if pc >= num_insns {
+            return Err(BpfError::JumpOutOfBounds { pc });
+        }
+        count += 1;
+        if count > MAX_INSTRUCTIONS {
+            return Err(BpfError::InstructionLimitExceeded);
+        }
+        let insn = decode(prog, pc);
+        let invalid = BpfError::InvalidInstruction {
+            pc,
+            opcode: insn.opc,
+        };
+        if insn.dst >= reg.len() || insn.src >= reg.len() {
+            return Err(invalid);
+        }
+        let class = insn.opc & 0x07;
+        // r10 is read only
+        let writes_dst = match class {
+            BPF_LD | BPF_LDX | BPF_ALU | BPF_ALU64 => true,
+            _ => false,
+        };
+        if writes_dst && insn.dst == 10 {
+            return Err(invalid);
+        }
+
+        match class {
+            BPF_LD => {
+                // lddw, the upper half of the immediate is in the next slot
+                if insn.opc != BPF_LD | BPF_IMM | BPF_DW || pc + 1 >= num_insns {
+                    return Err(invalid);
+                }
+                let next = decode(prog, pc + 1);
+                reg[insn.dst] = u64::from(insn.imm as u32) | (u64::from(next.imm as u32) << 32);
+                pc += 1;
+            }
+            BPF_LDX => {
+                if insn.opc & 0xe0 != BPF_MEM {
+                    return Err(invalid);
+                }
+                let len = access_len(insn.opc);
+                let address = reg[insn.src].wrapping_add(insn.off as i64 as u64);
+                let bytes = translate(&mut stack, input, address, len, pc)?;
+                reg[insn.dst] = LittleEndian::read_uint(bytes, len);
+            }
+            BPF_ST | BPF_STX => {
+                if insn.opc & 0xe0 != BPF_MEM {
+                    return Err(invalid);
+                }
+                let len = access_len(insn.opc);
+                let value = if class == BPF_ST {
+                    insn.imm as i64 as u64
+                } else {
+                    reg[insn.src]
+                };
+                let address = reg[insn.dst].wrapping_add(insn.off as i64 as u64);
+                let bytes = translate(&mut stack, input, address, len, pc)?;
+                // write_uint panics on a value that doesn't fit, stores truncate
+                let value = if len == 8 {
+                    value
+                } else {
+                    value & ((1 << (len * 8)) - 1)
+                };
+                LittleEndian::write_uint(bytes, value, len);
+            }
+            BPF_ALU | BPF_ALU64 => {
+                let src = if insn.opc & BPF_X != 0 {
+                    reg[insn.src]
+                } else {
+                    insn.imm as i64 as u64
+                };
+                let is64 = class == BPF_ALU64;
+                reg[insn.dst] = alu(insn.opc & 0xf0, reg[insn.dst], src, is64, pc)?;
+            }
+            BPF_JMP => {
+                let op = insn.opc & 0xf0;
+                if op == BPF_EXIT {
+                    return Ok(reg[0]);
+                }
+                let src = if insn.opc & BPF_X != 0 {
+                    reg[insn.src]
+                } else {
+                    insn.imm as i64 as u64
+                };
+                if jump_taken(op, reg[insn.dst], src).ok_or(invalid)? {
+                    let target = pc as i64 + 1 + i64::from(insn.off);
+                    if target < 0 || target as usize >= num_insns {
+                        return Err(BpfError::JumpOutOfBounds { pc });
+                    }
+                    pc = target as usize;
+                    continue;
+                }
+            }
+            _ => return Err(invalid),
+        }
+        pc += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insn(opc: u8, dst: u8, src: u8, off: i16, imm: i32) -> Vec<u8> {
+        let mut bytes = vec![opc, src << 4 | dst, 0, 0, 0, 0, 0, 0];
+        LittleEndian::write_i16(&mut bytes[2..4], off);
+        LittleEndian::write_i32(&mut bytes[4..8], imm);
+        bytes
+    }
+
+    fn program(insns: &[Vec<u8>]) -> Vec<u8> {
+        insns.concat()
+    }
+
+    const EXIT: u8 = BPF_JMP | BPF_EXIT;
+
+    #[test]
+    fn test_alu_and_exit() {
+        let prog = program(&[
+            insn(BPF_ALU64 | BPF_MOV, 0, 0, 0, 6),
+            insn(BPF_ALU64 | BPF_MUL, 0, 0, 0, 7),
+            insn(BPF_ALU64 | BPF_MOV | BPF_X, 2, 0, 0, 0),
+            insn(BPF_ALU64 | BPF_SUB, 2, 0, 0, 50),
+            // 32 bit operations zero the upper half
+            insn(BPF_ALU | BPF_ADD | BPF_X, 2, 0, 0, 0),
+            insn(BPF_ALU64 | BPF_ADD | BPF_X, 0, 2, 0, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        // r2 is 42 - 50 + 42 in 32 bits
+        assert_eq!(execute(&prog, &mut []), Ok(42 + 34));
+    }
+
+    #[test]
+    fn test_lddw_and_jumps() {
+        let prog = program(&[
+            insn(BPF_LD | BPF_IMM | BPF_DW, 0, 0, 0, 1),
+            insn(0, 0, 0, 0, 2),
+            insn(BPF_ALU64 | BPF_MOV, 1, 0, 0, 0),
+            // loop five times
+            insn(BPF_ALU64 | BPF_ADD, 1, 0, 0, 1),
+            insn(BPF_JMP | BPF_JLT, 1, 0, -2, 5),
+            insn(BPF_ALU64 | BPF_ADD | BPF_X, 0, 1, 0, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_eq!(execute(&prog, &mut []), Ok((2 << 32) + 1 + 5));
+    }
+
+    #[test]
+    fn test_load_store() {
+        let prog = program(&[
+            insn(BPF_LDX | BPF_MEM | BPF_DW, 2, 1, 0, 0),
+            insn(BPF_ALU64 | BPF_ADD, 2, 0, 0, 1),
+            insn(BPF_STX | BPF_MEM | BPF_DW, 1, 2, 8, 0),
+            insn(BPF_ST | BPF_MEM | BPF_B, 1, 0, 0, 0x1ff),
+            // through the stack
+            insn(BPF_STX | BPF_MEM | BPF_W, 10, 2, -4, 0),
+            insn(BPF_LDX | BPF_MEM | BPF_W, 0, 10, -4, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        let mut input = vec![0u8; 16];
+        input[0] = 41;
+        assert_eq!(execute(&prog, &mut input), Ok(42));
+        assert_eq!(input[0], 0xff);
+        assert_eq!(LittleEndian::read_u64(&input[8..]), 42);
+    }
+
+    #[test]
+    fn test_faults() {
+        assert_eq!(execute(&[], &mut []), Err(BpfError::InvalidProgram));
+
+        // outside of the input
+        let prog = program(&[
+            insn(BPF_LDX | BPF_MEM | BPF_DW, 0, 1, 4, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_eq!(
+            execute(&prog, &mut [0; 8]),
+            Err(BpfError::AccessViolation {
+                pc: 0,
+                address: MM_INPUT_START + 4,
+                len: 8
+            })
+        );
+
+        // below the stack
+        let prog = program(&[
+            insn(BPF_ST | BPF_MEM | BPF_DW, 10, 0, -(STACK_SIZE as i16) - 8, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_matches!(execute(&prog, &mut []), Err(BpfError::AccessViolation { .. }));
+
+        let prog = program(&[
+            insn(BPF_ALU64 | BPF_DIV | BPF_X, 0, 2, 0, 0),
+            insn(EXIT, 0, 0, 0, 0),
+        ]);
+        assert_eq!(execute(&prog, &mut []), Err(BpfError::DivideByZero { pc: 0 }));
+
+        // r10 can't be written
+        let prog = program(&[insn(BPF_ALU64 | BPF_MOV, 10, 0, 0, 0), insn(EXIT, 0, 0, 0, 0)]);
+        assert_matches!(execute(&prog, &mut []), Err(BpfError::InvalidInstruction { .. }));
+
+        // running off the end, and jumping out
+        let prog = program(&[insn(BPF_ALU64 | BPF_MOV, 0, 0, 0, 0)]);
+        assert_eq!(execute(&prog, &mut []), Err(BpfError::JumpOutOfBounds { pc: 1 }));
+        let prog = program(&[insn(BPF_JMP | BPF_JA, 0, 0, -2, 0), insn(EXIT, 0, 0, 0, 0)]);
+        assert_eq!(execute(&prog, &mut []), Err(BpfError::JumpOutOfBounds { pc: 0 }));
+
+        let prog = program(&[insn(BPF_JMP | BPF_JA, 0, 0, -1, 0)]);
+        assert_eq!(execute(&prog, &mut []), Err(BpfError::InstructionLimitExceeded));
+    }
+}
@@ -3,7 +3,6 @@
 use crate::counter::Counter;
 use crate::crdt::{Crdt, CrdtError, NodeInfo};
 use crate::entry::Entry;
-#[cfg(feature = "erasure")]
 use crate::erasure;
 use crate::ledger::Block;
 use log::Level;
@@ -115,16 +114,13 @@ fn broadcast(
         }
 
         // Fill in the coding blob data from the window data blobs
-        #[cfg(feature = "erasure")]
-        {
-            erasure::generate_coding(
-                &id,
-                &mut window.write().unwrap(),
-                *receive_index,
-                blobs_len,
-                &mut transmit_index.coding,
-            )?;
-        }
+        erasure::generate_coding(
+            &id,
+            &mut window.write().unwrap(),
+            *receive_index,
+            blobs_len,
+            &mut transmit_index.coding,
+        )?;
 
         *receive_index += blobs_len as u64;
 
@@ -29,9 +29,24 @@ impl Condition {
             _ => false,
         }
     }
+
+    /// Return true if a Witness could satisfy both this Condition and `other`.
+    fn overlaps(&self, other: &Condition) -> bool {
+        match (self, other) {
+            (Condition::Signature(pubkey0), Condition::Signature(pubkey1)) => pubkey0 == pubkey1,
+            (Condition::Timestamp(_, pubkey0), Condition::Timestamp(_, pubkey1)) => {
+                pubkey0 == pubkey1
+            }
+            _ => false,
+        }
+    }
 }
 
-/// A data type representing a payment plan.
+/// A data type representing a payment plan. Plans are expressions that nest other plans,
+/// they are reduced by `Witness`es until only payments are left.
+///
+/// The variants are encoded by their position in contracts on the ledger, new ones go
+/// last.
 #[repr(C)]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum Budget {
@@ -47,6 +62,20 @@ pub enum Budget {
 
     /// Make a payment after both of two conditions are satisfied
     And(Condition, Condition, Payment),
+
+    /// Make a payment to each of several parties.
+    Split(Vec<Payment>),
+
+    /// Continue with a budget after some condition.
+    When(Condition, Box<Budget>),
+
+    /// Continue with either of two budgets, whichever is reduced to payments first.
+    Either(Box<Budget>, Box<Budget>),
+
+    /// Continue with a budget after the given number of the conditions are satisfied.
+    /// A witness only counts once, so no two of the conditions may be satisfied by the
+    /// same witness.
+    Threshold(u64, Vec<Condition>, Box<Budget>),
 }
 
 impl Budget {
@@ -55,6 +84,30 @@ impl Budget {
         Budget::Pay(Payment { tokens, to })
     }
 
+    /// Create a budget that pays each of `payments`.
+    pub fn new_split_payment(payments: Vec<Payment>) -> Self {
+        Budget::Split(payments)
+    }
+
+    /// Create a budget that continues with `budget` after `condition`.
+    pub fn new_after(condition: Condition, budget: Budget) -> Self {
+        match budget {
+            Budget::Pay(payment) => Budget::After(condition, payment),
+            budget => Budget::When(condition, Box::new(budget)),
+        }
+    }
+
+    /// Create a budget that continues with whichever of `budget0` and `budget1` is
+    /// reduced to payments first.
+    pub fn new_or(budget0: Budget, budget1: Budget) -> Self {
+        match (budget0, budget1) {
+            (Budget::After(cond0, payment0), Budget::After(cond1, payment1)) => {
+                Budget::Or((cond0, payment0), (cond1, payment1))
+            }
+            (budget0, budget1) => Budget::Either(Box::new(budget0), Box::new(budget1)),
+        }
+    }
+
     /// Create a budget that pays `tokens` to `to` after being witnessed by `from`.
     pub fn new_authorized_payment(from: Pubkey, tokens: i64, to: Pubkey) -> Self {
         Budget::After(Condition::Signature(from), Payment { tokens, to })
@@ -69,11 +122,24 @@ impl Budget {
         )
     }
 
+    /// Create a budget that continues with `budget` after being witnessed by `m` of the
+    /// `signers`.
+    pub fn new_multisig(m: u64, signers: &[Pubkey], budget: Budget) -> Self {
+        let conditions = signers.iter().map(|s| Condition::Signature(*s)).collect();
+        Budget::Threshold(m, conditions, Box::new(budget))
+    }
+
     /// Create a budget that pays `tokens` to `to` after the given DateTime.
     pub fn new_future_payment(dt: DateTime<Utc>, from: Pubkey, tokens: i64, to: Pubkey) -> Self {
         Budget::After(Condition::Timestamp(dt, from), Payment { tokens, to })
     }
 
+    /// Create a budget that continues with `budget` unless `from` cancels it first, which
+    /// pays the `tokens` back to `from`.
+    pub fn new_cancelable(budget: Budget, from: Pubkey, tokens: i64) -> Self {
+        Self::new_or(budget, Self::new_authorized_payment(from, tokens, from))
+    }
+
     /// Create a budget that pays `tokens` to `to` after the given DateTime
     /// unless cancelled by `from`.
     pub fn new_cancelable_future_payment(
@@ -82,16 +148,14 @@ impl Budget {
         tokens: i64,
         to: Pubkey,
     ) -> Self {
-        Budget::Or(
-            (Condition::Timestamp(dt, from), Payment { tokens, to }),
-            (Condition::Signature(from), Payment { tokens, to: from }),
-        )
+        Self::new_cancelable(Self::new_future_payment(dt, from, tokens, to), from, tokens)
     }
 
-    /// Return Payment if the budget requires no additional Witnesses.
-    pub fn final_payment(&self) -> Option<Payment> {
+    /// Return the payments if the budget requires no additional Witnesses.
+    pub fn final_payments(&self) -> Option<Vec<Payment>> {
         match self {
-            Budget::Pay(payment) => Some(payment.clone()),
+            Budget::Pay(payment) => Some(vec![payment.clone()]),
+            Budget::Split(payments) => Some(payments.clone()),
             _ => None,
         }
     }
@@ -103,6 +167,30 @@ impl Budget {
                 payment.tokens == spendable_tokens
             }
             Budget::Or(a, b) => a.1.tokens == spendable_tokens && b.1.tokens == spendable_tokens,
+            Budget::Split(payments) => {
+                !payments.is_empty()
+                    && payments.iter().all(|payment| payment.tokens >= 0)
+                    && payments
+                        .iter()
+                        .try_fold(0i64, |total, payment| total.checked_add(payment.tokens))
+                        == Some(spendable_tokens)
+            }
+            Budget::When(_, budget) => budget.verify(spendable_tokens),
+            Budget::Either(budget0, budget1) => {
+                budget0.verify(spendable_tokens) && budget1.verify(spendable_tokens)
+            }
+            Budget::Threshold(m, conditions, budget) => {
+                // a witness satisfying two of the conditions would count once, and the
+                // threshold might never be reached
+                let distinct = conditions
+                    .iter()
+                    .enumerate()
+                    .all(|(i, cond)| conditions[..i].iter().all(|other| !cond.overlaps(other)));
+                *m > 0
+                    && *m <= conditions.len() as u64
+                    && distinct
+                    && budget.verify(spendable_tokens)
+            }
         }
     }
 
@@ -128,6 +216,33 @@ impl Budget {
                     None
                 }
             }
+            Budget::When(cond, budget) if cond.is_satisfied(witness, from) => {
+                Some((**budget).clone())
+            }
+            Budget::Either(budget0, budget1) => {
+                budget0.apply_witness(witness, from);
+                budget1.apply_witness(witness, from);
+                if budget0.final_payments().is_some() {
+                    Some((**budget0).clone())
+                } else if budget1.final_payments().is_some() {
+                    Some((**budget1).clone())
+                } else {
+                    None
+                }
+            }
+            Budget::Threshold(m, conditions, budget) => {
+                let len = conditions.len();
+                conditions.retain(|cond| !cond.is_satisfied(witness, from));
+                let counted = conditions.len() < len && *m > 0;
+                if counted {
+                    *m -= 1;
+                }
+                if counted && *m == 0 {
+                    Some((**budget).clone())
+                } else {
+                    None
+                }
+            }
             _ => None,
         };
         if let Some(budget) = new_budget {
@@ -227,4 +342,120 @@ mod tests {
         budget.apply_witness(&Witness::Signature, &from0);
         assert_eq!(budget, Budget::new_authorized_payment(from1, 42, to));
     }
+
+    #[test]
+    fn test_verify_split_and_threshold() {
+        let from = Keypair::new().pubkey();
+        let to0 = Keypair::new().pubkey();
+        let to1 = Keypair::new().pubkey();
+        let split = |tokens0, tokens1| {
+            Budget::new_split_payment(vec![
+                Payment {
+                    tokens: tokens0,
+                    to: to0,
+                },
+                Payment {
+                    tokens: tokens1,
+                    to: to1,
+                },
+            ])
+        };
+        assert!(split(40, 2).verify(42));
+        assert!(!split(40, 1).verify(42));
+        assert!(!split(44, -2).verify(42));
+        assert!(!split(i64::max_value(), 1).verify(42));
+        assert!(!Budget::new_split_payment(vec![]).verify(0));
+
+        assert!(Budget::new_multisig(1, &[from, to0], split(40, 2)).verify(42));
+        assert!(Budget::new_multisig(2, &[from, to0], split(40, 2)).verify(42));
+        assert!(!Budget::new_multisig(3, &[from, to0], split(40, 2)).verify(42));
+        assert!(!Budget::new_multisig(0, &[from, to0], split(40, 2)).verify(42));
+        assert!(!Budget::new_multisig(1, &[from, to0], split(40, 1)).verify(42));
+
+        // one witness would satisfy both conditions
+        assert!(!Budget::new_multisig(2, &[from, from], split(40, 2)).verify(42));
+        assert!(!Budget::new_multisig(1, &[from, from], split(40, 2)).verify(42));
+        let dt0 = Utc.ymd(2014, 11, 14).and_hms(8, 9, 10);
+        let dt1 = Utc.ymd(2014, 11, 14).and_hms(10, 9, 8);
+        let timestamps = |pubkey0, pubkey1| {
+            Budget::Threshold(
+                2,
+                vec![
+                    Condition::Timestamp(dt0, pubkey0),
+                    Condition::Timestamp(dt1, pubkey1),
+                ],
+                Box::new(split(40, 2)),
+            )
+        };
+        assert!(!timestamps(from, from).verify(42));
+        assert!(timestamps(from, to0).verify(42));
+        let mixed = Budget::Threshold(
+            2,
+            vec![Condition::Signature(from), Condition::Timestamp(dt0, from)],
+            Box::new(split(40, 2)),
+        );
+        assert!(mixed.verify(42));
+    }
+
+    #[test]
+    fn test_m_of_n_multisig_payment() {
+        let from0 = Keypair::new().pubkey();
+        let from1 = Keypair::new().pubkey();
+        let from2 = Keypair::new().pubkey();
+        let to = Keypair::new().pubkey();
+
+        let mut budget =
+            Budget::new_multisig(2, &[from0, from1, from2], Budget::new_payment(42, to));
+        budget.apply_witness(&Witness::Signature, &to);
+        assert_eq!(budget.final_payments(), None);
+        budget.apply_witness(&Witness::Signature, &from1);
+        assert_eq!(budget.final_payments(), None);
+        // a signer only counts once
+        budget.apply_witness(&Witness::Signature, &from1);
+        assert_eq!(budget.final_payments(), None);
+        budget.apply_witness(&Witness::Signature, &from2);
+        assert_eq!(budget, Budget::new_payment(42, to));
+    }
+
+    #[test]
+    fn test_nested_budget() {
+        // pay `to0` and `to1` once both signers agree, or refund `from` after a deadline
+        let dt = Utc.ymd(2014, 11, 14).and_hms(8, 9, 10);
+        let from = Keypair::new().pubkey();
+        let signer0 = Keypair::new().pubkey();
+        let signer1 = Keypair::new().pubkey();
+        let to0 = Keypair::new().pubkey();
+        let to1 = Keypair::new().pubkey();
+        let payments = vec![
+            Payment {
+                tokens: 40,
+                to: to0,
+            },
+            Payment { tokens: 2, to: to1 },
+        ];
+        let budget = Budget::new_or(
+            Budget::new_after(
+                Condition::Timestamp(dt, from),
+                Budget::new_payment(42, from),
+            ),
+            Budget::new_multisig(
+                2,
+                &[signer0, signer1],
+                Budget::new_split_payment(payments.clone()),
+            ),
+        );
+        assert!(budget.verify(42));
+
+        // progress in one branch is kept until the other completes
+        let mut signed = budget.clone();
+        signed.apply_witness(&Witness::Signature, &signer0);
+        assert_eq!(signed.final_payments(), None);
+        signed.apply_witness(&Witness::Signature, &signer1);
+        assert_eq!(signed.final_payments(), Some(payments));
+
+        let mut expired = budget.clone();
+        expired.apply_witness(&Witness::Signature, &signer0);
+        expired.apply_witness(&Witness::Timestamp(dt), &from);
+        assert_eq!(expired, Budget::new_payment(42, from));
+    }
 }
@@ -1,4 +1,6 @@
 use crate::budget::Budget;
+use crate::signature::Signature;
+use buffett_program_interface::pubkey::Pubkey;
 use chrono::prelude::{DateTime, Utc};
 
 /// A smart contract.
@@ -16,7 +18,16 @@ pub struct Vote {
     pub version: u64,
     /// The version of the CRDT struct that has the same network configuration as this one
     pub contact_info_version: u64,
-    // TODO: add signature of the state here as well
+}
+
+/// What a voter signed its CRDT struct with when it voted, sent next to its `Vote` so
+/// the struct can be rebuilt and verified by the nodes that see the vote
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct VoteSignature {
+    /// The leader in the voter's CRDT struct at the vote's `version`
+    pub leader_id: Pubkey,
+    /// The signature of the voter over its CRDT struct at the vote's `version`
+    pub signature: Signature,
 }
 
 /// An instruction to progress the smart contract.
@@ -34,4 +45,8 @@ pub enum Instruction {
 
     /// Vote for a PoH that is equal to the lastid of this transaction
     NewVote(Vote),
+
+    /// A `NewVote` with the signature of the voter's CRDT struct. The votes already in
+    /// ledgers stay `NewVote`s, they can't be gossiped on.
+    NewSignedVote(Vote, VoteSignature),
 }
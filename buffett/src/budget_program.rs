@@ -3,7 +3,7 @@ use bincode::{self, deserialize, serialize_into, serialized_size};
 use crate::budget::Budget;
 use crate::budget_instruction::Instruction;
 use chrono::prelude::{DateTime, Utc};
-use payment_plan::Witness;
+use payment_plan::{Payment, Witness};
 use buffett_program_interface::account::Account;
 use buffett_program_interface::pubkey::Pubkey;
 use std::io;
@@ -43,31 +43,62 @@ impl BudgetState {
         program_id.as_ref() == BUDGET_PROGRAM_ID
     }
 
-    /// Process a Witness Signature. Any payment plans waiting on this signature
-    /// will progress one step.
-    fn apply_signature(
+    /// The index in `keys` of the account of each payee of `payments`, the payees are
+    /// looked up from `first_payee` on.
+    fn payee_indexes(
+        keys: &[Pubkey],
+        first_payee: usize,
+        payments: &[Payment],
+    ) -> Result<Vec<usize>, BudgetError> {
+        payments
+            .iter()
+            .map(|payment| {
+                keys.iter()
+                    .skip(first_payee)
+                    .position(|key| *key == payment.to)
+                    .map(|i| first_payee + i)
+                    .ok_or_else(|| {
+                        trace!("destination missing");
+                        BudgetError::DestinationMissing(payment.to)
+                    })
+            }).collect()
+    }
+
+    /// Apply a Witness to the pending budget, if that reduces it to payments they are
+    /// made from the contract to the payees in keys[2..].
+    fn apply_witness(
         &mut self,
         keys: &[Pubkey],
-        account: &mut [Account],
+        accounts: &mut [Account],
+        witness: &Witness,
     ) -> Result<(), BudgetError> {
-        let mut final_payment = None;
+        let mut final_payments = None;
         if let Some(ref mut budget) = self.pending_budget {
-            budget.apply_witness(&Witness::Signature, &keys[0]);
-            final_payment = budget.final_payment();
+            budget.apply_witness(witness, &keys[0]);
+            final_payments = budget.final_payments();
         }
 
-        if let Some(payment) = final_payment {
-            if keys.len() < 2 || payment.to != keys[2] {
-                trace!("destination missing");
-                return Err(BudgetError::DestinationMissing(payment.to));
-            }
+        if let Some(payments) = final_payments {
+            let payees = Self::payee_indexes(keys, 2, &payments)?;
             self.pending_budget = None;
-            account[1].tokens -= payment.tokens;
-            account[2].tokens += payment.tokens;
+            for (payment, payee) in payments.iter().zip(payees) {
+                accounts[1].tokens -= payment.tokens;
+                accounts[payee].tokens += payment.tokens;
+            }
         }
         Ok(())
     }
 
+    /// Process a Witness Signature. Any payment plans waiting on this signature
+    /// will progress one step.
+    fn apply_signature(
+        &mut self,
+        keys: &[Pubkey],
+        accounts: &mut [Account],
+    ) -> Result<(), BudgetError> {
+        self.apply_witness(keys, accounts, &Witness::Signature)
+    }
+
     /// Process a Witness Timestamp. Any payment plans waiting on this timestamp
     /// will progress one step.
     fn apply_timestamp(
@@ -76,24 +107,7 @@ impl BudgetState {
         accounts: &mut [Account],
         dt: DateTime<Utc>,
     ) -> Result<(), BudgetError> {
-        // Check to see if any timelocked transactions can be completed.
-        let mut final_payment = None;
-
-        if let Some(ref mut budget) = self.pending_budget {
-            budget.apply_witness(&Witness::Timestamp(dt), &keys[0]);
-            final_payment = budget.final_payment();
-        }
-
-        if let Some(payment) = final_payment {
-            if keys.len() < 2 || payment.to != keys[2] {
-                trace!("destination missing");
-                return Err(BudgetError::DestinationMissing(payment.to));
-            }
-            self.pending_budget = None;
-            accounts[1].tokens -= payment.tokens;
-            accounts[2].tokens += payment.tokens;
-        }
-        Ok(())
+        self.apply_witness(keys, accounts, &Witness::Timestamp(dt))
     }
 
     /// Deduct tokens from the source account if it has sufficient funds and the contract isn't
@@ -136,8 +150,11 @@ impl BudgetState {
         match instruction {
             Instruction::NewContract(contract) => {
                 let budget = contract.budget.clone();
-                if let Some(payment) = budget.final_payment() {
-                    accounts[1].tokens += payment.tokens;
+                if let Some(payments) = budget.final_payments() {
+                    let payees = Self::payee_indexes(&tx.keys, 1, &payments)?;
+                    for (payment, payee) in payments.iter().zip(payees) {
+                        accounts[payee].tokens += payment.tokens;
+                    }
                     Ok(())
                 } else {
                     let existing = Self::deserialize(&accounts[1].userdata).ok();
@@ -187,7 +204,7 @@ impl BudgetState {
                     Err(BudgetError::UninitializedContract(tx.keys[1]))
                 }
             }
-            Instruction::NewVote(_vote) => {
+            Instruction::NewVote(_) | Instruction::NewSignedVote(..) => {
                 // TODO: move vote instruction into a different contract
                 trace!("GOT VOTE! last_id={}", tx.last_id);
                 Ok(())
@@ -249,6 +266,15 @@ impl BudgetState {
         }
     }
 
+    /// The userdata a contract account needs to hold `budget` while it is pending
+    pub fn userdata_len(budget: &Budget) -> u64 {
+        let state = BudgetState {
+            initialized: true,
+            pending_budget: Some(budget.clone()),
+        };
+        8 + serialized_size(&state).unwrap()
+    }
+
     //TODO the contract needs to provide a "get_balance" introspection call of the userdata
     pub fn get_balance(account: &Account) -> i64 {
         if let Ok(state) = deserialize(&account.userdata) {
@@ -270,6 +296,7 @@ mod test {
     use crate::budget_transaction::BudgetTransaction;
     use chrono::prelude::{DateTime, NaiveDate, Utc};
     use crate::hash::Hash;
+    use payment_plan::Payment;
     use crate::signature::{GenKeys, Keypair, KeypairUtil};
     use buffett_program_interface::account::Account;
     use buffett_program_interface::pubkey::Pubkey;
@@ -461,6 +488,82 @@ mod test {
         assert_eq!(accounts[pay_account].tokens, 1);
     }
 
+    #[test]
+    fn test_multisig_split_payout() {
+        let mut accounts = vec![
+            Account::new(42, 0, BudgetState::id()),
+            Account::new(0, 512, BudgetState::id()),
+            Account::new(0, 0, BudgetState::id()),
+            Account::new(0, 0, BudgetState::id()),
+        ];
+        let from_account = 0;
+        let contract_account = 1;
+        let to0_account = 2;
+        let to1_account = 3;
+        let from = Keypair::new();
+        let contract = Keypair::new();
+        let signers: Vec<_> = (0..3).map(|_| Keypair::new()).collect();
+        let signer_ids: Vec<_> = signers.iter().map(|s| s.pubkey()).collect();
+        let to0 = Keypair::new().pubkey();
+        let to1 = Keypair::new().pubkey();
+        let payments = vec![
+            Payment {
+                tokens: 40,
+                to: to0,
+            },
+            Payment { tokens: 2, to: to1 },
+        ];
+        let tx = Transaction::budget_new_multisig(
+            &from,
+            contract.pubkey(),
+            &signer_ids,
+            2,
+            payments,
+            None,
+            42,
+            Hash::default(),
+        );
+        assert!(tx.verify_plan());
+        BudgetState::process_transaction(&tx, &mut accounts).unwrap();
+        assert_eq!(accounts[from_account].tokens, 0);
+        assert_eq!(accounts[contract_account].tokens, 42);
+
+        let tx = Transaction::budget_new_signature_to_payees(
+            &signers[0],
+            contract.pubkey(),
+            &[to0, to1],
+            Hash::default(),
+        );
+        BudgetState::process_transaction(&tx, &mut accounts).unwrap();
+        assert_eq!(accounts[contract_account].tokens, 42);
+
+        // every payee has to be given for the payout
+        let tx = Transaction::budget_new_signature_to_payees(
+            &signers[2],
+            contract.pubkey(),
+            &[to0],
+            Hash::default(),
+        );
+        assert_eq!(
+            BudgetState::process_transaction(&tx, &mut accounts),
+            Err(BudgetError::DestinationMissing(to1))
+        );
+        assert_eq!(accounts[contract_account].tokens, 42);
+
+        let tx = Transaction::budget_new_signature_to_payees(
+            &signers[2],
+            contract.pubkey(),
+            &[to0, to1],
+            Hash::default(),
+        );
+        BudgetState::process_transaction(&tx, &mut accounts).unwrap();
+        assert_eq!(accounts[contract_account].tokens, 0);
+        assert_eq!(accounts[to0_account].tokens, 40);
+        assert_eq!(accounts[to1_account].tokens, 2);
+        let state = BudgetState::deserialize(&accounts[contract_account].userdata).unwrap();
+        assert!(!state.is_pending());
+    }
+
     #[test]
     fn test_userdata_too_small() {
         let mut accounts = vec![
@@ -1,8 +1,8 @@
 //! The `budget_transaction` module provides functionality for creating Budget transactions.
 
 use bincode::{deserialize, serialize};
-use crate::budget::{Budget, Condition};
-use crate::budget_instruction::{Contract, Instruction, Vote};
+use crate::budget::Budget;
+use crate::budget_instruction::{Contract, Instruction, Vote, VoteSignature};
 use crate::budget_program::BudgetState;
 use chrono::prelude::*;
 use crate::hash::Hash;
@@ -37,7 +37,28 @@ pub trait BudgetTransaction {
         last_id: Hash,
     ) -> Self;
 
-    fn budget_new_vote(from_keypair: &Keypair, vote: Vote, last_id: Hash, fee: i64) -> Self;
+    fn budget_new_timestamp_to_payees(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        payees: &[Pubkey],
+        dt: DateTime<Utc>,
+        last_id: Hash,
+    ) -> Self;
+
+    fn budget_new_signature_to_payees(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        payees: &[Pubkey],
+        last_id: Hash,
+    ) -> Self;
+
+    fn budget_new_vote(
+        from_keypair: &Keypair,
+        vote: Vote,
+        signature: VoteSignature,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self;
 
     fn budget_new_on_date(
         from_keypair: &Keypair,
@@ -60,7 +81,26 @@ pub trait BudgetTransaction {
         last_id: Hash,
     ) -> Self;
 
-    fn vote(&self) -> Option<(Pubkey, Vote, Hash)>;
+    fn budget_new_contract(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        budget: Budget,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self;
+
+    fn budget_new_multisig(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        signers: &[Pubkey],
+        m: u64,
+        payments: Vec<Payment>,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self;
+
+    fn vote(&self) -> Option<(Pubkey, Vote, VoteSignature, Hash)>;
 
     fn instruction(&self) -> Option<Instruction>;
 
@@ -106,16 +146,7 @@ impl BudgetTransaction for Transaction {
         dt: DateTime<Utc>,
         last_id: Hash,
     ) -> Self {
-        let instruction = Instruction::ApplyTimestamp(dt);
-        let userdata = serialize(&instruction).unwrap();
-        Self::new(
-            from_keypair,
-            &[contract, to],
-            BudgetState::id(),
-            userdata,
-            last_id,
-            0,
-        )
+        Self::budget_new_timestamp_to_payees(from_keypair, contract, &[to], dt, last_id)
     }
 
     /// Create and sign a new Witness Signature. Used for unit-testing.
@@ -124,21 +155,49 @@ impl BudgetTransaction for Transaction {
         contract: Pubkey,
         to: Pubkey,
         last_id: Hash,
+    ) -> Self {
+        Self::budget_new_signature_to_payees(from_keypair, contract, &[to], last_id)
+    }
+
+    /// Create and sign a new Witness Timestamp, the accounts of all the payees the
+    /// contract may pay out to have to be given.
+    fn budget_new_timestamp_to_payees(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        payees: &[Pubkey],
+        dt: DateTime<Utc>,
+        last_id: Hash,
+    ) -> Self {
+        let instruction = Instruction::ApplyTimestamp(dt);
+        let userdata = serialize(&instruction).unwrap();
+        let mut keys = vec![contract];
+        keys.extend_from_slice(payees);
+        Self::new(from_keypair, &keys, BudgetState::id(), userdata, last_id, 0)
+    }
+
+    /// Create and sign a new Witness Signature, the accounts of all the payees the
+    /// contract may pay out to have to be given.
+    fn budget_new_signature_to_payees(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        payees: &[Pubkey],
+        last_id: Hash,
     ) -> Self {
         let instruction = Instruction::ApplySignature;
         let userdata = serialize(&instruction).unwrap();
-        Self::new(
-            from_keypair,
-            &[contract, to],
-            BudgetState::id(),
-            userdata,
-            last_id,
-            0,
-        )
+        let mut keys = vec![contract];
+        keys.extend_from_slice(payees);
+        Self::new(from_keypair, &keys, BudgetState::id(), userdata, last_id, 0)
     }
 
-    fn budget_new_vote(from_keypair: &Keypair, vote: Vote, last_id: Hash, fee: i64) -> Self {
-        let instruction = Instruction::NewVote(vote);
+    fn budget_new_vote(
+        from_keypair: &Keypair,
+        vote: Vote,
+        signature: VoteSignature,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self {
+        let instruction = Instruction::NewSignedVote(vote, signature);
         let userdata = serialize(&instruction).expect("serialize instruction");
         Self::new(from_keypair, &[], BudgetState::id(), userdata, last_id, fee)
     }
@@ -154,24 +213,11 @@ impl BudgetTransaction for Transaction {
         tokens: i64,
         last_id: Hash,
     ) -> Self {
-        let budget = if let Some(from) = cancelable {
-            Budget::Or(
-                (Condition::Timestamp(dt, dt_pubkey), Payment { tokens, to }),
-                (Condition::Signature(from), Payment { tokens, to: from }),
-            )
-        } else {
-            Budget::After(Condition::Timestamp(dt, dt_pubkey), Payment { tokens, to })
-        };
-        let instruction = Instruction::NewContract(Contract { budget, tokens });
-        let userdata = serialize(&instruction).expect("serialize instruction");
-        Self::new(
-            from_keypair,
-            &[contract],
-            BudgetState::id(),
-            userdata,
-            last_id,
-            0,
-        )
+        let mut budget = Budget::new_future_payment(dt, dt_pubkey, tokens, to);
+        if let Some(from) = cancelable {
+            budget = Budget::new_cancelable(budget, from, tokens);
+        }
+        Self::budget_new_contract(from_keypair, contract, budget, tokens, last_id)
     }
     /// Create and sign a multisig Transaction.
     fn budget_new_when_signed(
@@ -183,14 +229,21 @@ impl BudgetTransaction for Transaction {
         tokens: i64,
         last_id: Hash,
     ) -> Self {
-        let budget = if let Some(from) = cancelable {
-            Budget::Or(
-                (Condition::Signature(witness), Payment { tokens, to }),
-                (Condition::Signature(from), Payment { tokens, to: from }),
-            )
-        } else {
-            Budget::After(Condition::Signature(witness), Payment { tokens, to })
-        };
+        let mut budget = Budget::new_authorized_payment(witness, tokens, to);
+        if let Some(from) = cancelable {
+            budget = Budget::new_cancelable(budget, from, tokens);
+        }
+        Self::budget_new_contract(from_keypair, contract, budget, tokens, last_id)
+    }
+
+    /// Create and sign a Transaction that starts `budget` in the `contract` account.
+    fn budget_new_contract(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        budget: Budget,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self {
         let instruction = Instruction::NewContract(Contract { budget, tokens });
         let userdata = serialize(&instruction).expect("serialize instruction");
         Self::new(
@@ -203,9 +256,29 @@ impl BudgetTransaction for Transaction {
         )
     }
 
-    fn vote(&self) -> Option<(Pubkey, Vote, Hash)> {
-        if let Some(Instruction::NewVote(vote)) = self.instruction() {
-            Some((*self.from(), vote, self.last_id))
+    /// Create and sign an m-of-n multisig Transaction, once `m` of the `signers` have
+    /// witnessed it the `payments` are made.
+    fn budget_new_multisig(
+        from_keypair: &Keypair,
+        contract: Pubkey,
+        signers: &[Pubkey],
+        m: u64,
+        payments: Vec<Payment>,
+        cancelable: Option<Pubkey>,
+        tokens: i64,
+        last_id: Hash,
+    ) -> Self {
+        let mut budget = Budget::new_multisig(m, signers, Budget::new_split_payment(payments));
+        if let Some(from) = cancelable {
+            budget = Budget::new_cancelable(budget, from, tokens);
+        }
+        Self::budget_new_contract(from_keypair, contract, budget, tokens, last_id)
+    }
+
+    /// The signed vote of the transaction, the unsigned ones of older ledgers are skipped.
+    fn vote(&self) -> Option<(Pubkey, Vote, VoteSignature, Hash)> {
+        if let Some(Instruction::NewSignedVote(vote, signature)) = self.instruction() {
+            Some((*self.from(), vote, signature, self.last_id))
         } else {
             None
         }
@@ -1,12 +1,27 @@
+//! The `chacha` module encrypts ledger files with ChaCha20 in CBC mode for storage
+//! proofs, and samples the encrypted files into the `sha_state` of a mining proof.
+//! The cipher is implemented in Rust, the `chacha` feature switches to the `cpu-crypt`
+//! C library instead. Both produce the same ciphertext.
+
+use crate::hash::{Hash, Hasher};
 use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::io::Write;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom};
 use std::path::Path;
 
+pub const CHACHA_BLOCK_SIZE: usize = 64;
+pub const CHACHA_KEY_SIZE: usize = 32;
 const CHACHA_IVEC_SIZE: usize = 64;
+/// The rounds `cpu-crypt` runs for each block, rather than the 20 of ChaCha20.
+#[cfg(not(feature = "chacha"))]
+const CHACHA_ROUNDS: usize = 500;
+
+/// Bytes of an encrypted ledger file hashed at each sample offset.
+pub const SAMPLE_SIZE: usize = 32 * CHACHA_BLOCK_SIZE;
 
+#[cfg(feature = "chacha")]
 #[link(name = "cpu-crypt")]
 extern "C" {
     fn chacha20_cbc_encrypt(
@@ -18,19 +33,103 @@ extern "C" {
     );
 }
 
-pub fn chacha_cbc_encrypt(input: &[u8], output: &mut [u8], key: &[u8], ivec: &mut [u8]) {
+#[cfg(feature = "chacha")]
+pub fn chacha_cbc_encrypt(
+    input: &[u8],
+    output: &mut [u8],
+    key: &[u8; CHACHA_KEY_SIZE],
+    ivec: &mut [u8; CHACHA_IVEC_SIZE],
+) {
+    assert!(output.len() >= input.len());
+    // cpu-crypt writes the last block whole even when the input ends partway through it
+    let mut padded = vec![0; input.len() + CHACHA_BLOCK_SIZE];
     unsafe {
         chacha20_cbc_encrypt(
             input.as_ptr(),
-            output.as_mut_ptr(),
+            padded.as_mut_ptr(),
             input.len(),
             key.as_ptr(),
             ivec.as_mut_ptr(),
         );
     }
+    output[..input.len()].copy_from_slice(&padded[..input.len()]);
+}
+
+#[cfg(any(test, not(feature = "chacha")))]
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(any(test, not(feature = "chacha")))]
+fn le_words(bytes: &[u8], words: &mut [u32]) {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+}
+
+/// The ChaCha core: `rounds` rounds over `input`, added back to `input`.
+#[cfg(any(test, not(feature = "chacha")))]
+fn chacha_encrypt(input: &[u32; 16], rounds: usize) -> [u8; CHACHA_BLOCK_SIZE] {
+    let mut state = *input;
+    for _ in 0..rounds / 2 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; CHACHA_BLOCK_SIZE];
+    for (i, chunk) in block.chunks_mut(4).enumerate() {
+        chunk.copy_from_slice(&state[i].wrapping_add(input[i]).to_le_bytes());
+    }
+    block
+}
+
+/// Encrypt `input` into `output` in CBC mode, the way `cpu-crypt` does: each block is
+/// xored into the chaining value, which is then run through `CHACHA_ROUNDS` rounds of
+/// the ChaCha core as the whole 16 word state. Like `cpu-crypt`, `key` isn't used.
+/// `ivec` is chained from one call to the next. A final partial block is padded with
+/// the chaining value, only its first bytes are written to `output`.
+#[cfg(not(feature = "chacha"))]
+pub fn chacha_cbc_encrypt(
+    input: &[u8],
+    output: &mut [u8],
+    _key: &[u8; CHACHA_KEY_SIZE],
+    ivec: &mut [u8; CHACHA_IVEC_SIZE],
+) {
+    assert!(output.len() >= input.len());
+    let mut iv = *ivec;
+    for (in_block, out_block) in input
+        .chunks(CHACHA_BLOCK_SIZE)
+        .zip(output.chunks_mut(CHACHA_BLOCK_SIZE))
+    {
+        for (iv, byte) in iv.iter_mut().zip(in_block) {
+            *iv ^= *byte;
+        }
+        let mut state = [0u32; 16];
+        le_words(&iv, &mut state);
+        iv = chacha_encrypt(&state, CHACHA_ROUNDS);
+        out_block[..in_block.len()].copy_from_slice(&iv[..in_block.len()]);
+    }
+    *ivec = iv;
 }
 
-pub fn chacha_cbc_encrypt_files(in_path: &Path, out_path: &Path, key: String) -> io::Result<()> {
+pub fn chacha_cbc_encrypt_files(
+    in_path: &Path,
+    out_path: &Path,
+    key: &[u8; CHACHA_KEY_SIZE],
+) -> io::Result<()> {
     let mut in_file = BufReader::new(File::open(in_path).expect("Can't open ledger data file"));
     let mut out_file =
         BufWriter::new(File::create(out_path).expect("Can't open ledger encrypted data file"));
@@ -46,7 +145,7 @@ pub fn chacha_cbc_encrypt_files(in_path: &Path, out_path: &Path, key: String) ->
         chacha_cbc_encrypt(
             &buffer[..size],
             &mut encrypted_buffer[..size],
-            key.as_bytes(),
+            key,
             &mut ivec,
         );
         if let Err(res) = out_file.write(&encrypted_buffer[..size]) {
@@ -57,15 +156,124 @@ pub fn chacha_cbc_encrypt_files(in_path: &Path, out_path: &Path, key: String) ->
     Ok(())
 }
 
+/// Pick `num_samples` offsets into an encrypted file of `file_len` bytes. The offsets
+/// only depend on `seed`, so a validator can check the samples a replicator hashed.
+pub fn sample_offsets(seed: &Hash, file_len: u64, num_samples: usize) -> Vec<u64> {
+    let range = file_len.saturating_sub(SAMPLE_SIZE as u64) + 1;
+    (0..num_samples as u64)
+        .map(|i| {
+            let mut hasher = Hasher::default();
+            hasher.hashv(&[seed.as_ref(), &i.to_le_bytes()]);
+            let hash = hasher.result();
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&hash.as_ref()[..8]);
+            u64::from_le_bytes(word) % range
+        }).collect()
+}
+
+/// Hash `SAMPLE_SIZE` bytes of the encrypted file at each offset, in order, into
+/// the `sha_state` of a `StorageProgram::SubmitMiningProof`. Samples that run past
+/// the end of the file are hashed short.
+pub fn sample_file(in_path: &Path, sample_offsets: &[u64]) -> io::Result<[u8; 32]> {
+    let mut in_file = File::open(in_path)?;
+    let mut buffer = [0; SAMPLE_SIZE];
+    let mut hasher = Hasher::default();
+
+    for offset in sample_offsets {
+        in_file.seek(SeekFrom::Start(*offset))?;
+        let mut size = 0;
+        while size < SAMPLE_SIZE {
+            let n = in_file.read(&mut buffer[size..])?;
+            if n == 0 {
+                break;
+            }
+            size += n;
+        }
+        hasher.hash(&buffer[..size]);
+    }
+
+    let mut sha_state = [0u8; 32];
+    sha_state.copy_from_slice(hasher.result().as_ref());
+    Ok(sha_state)
+}
+
+/// `sample_file` over an encrypted segment held in memory.
+pub fn sample_slice(data: &[u8], sample_offsets: &[u64]) -> [u8; 32] {
+    let mut hasher = Hasher::default();
+    for offset in sample_offsets {
+        let start = (*offset as usize).min(data.len());
+        let end = (start + SAMPLE_SIZE).min(data.len());
+        hasher.hash(&data[start..end]);
+    }
+
+    let mut sha_state = [0u8; 32];
+    sha_state.copy_from_slice(hasher.result().as_ref());
+    sha_state
+}
+
 #[cfg(test)]
 mod tests {
-    use chacha::chacha_cbc_encrypt_files;
+    use crate::chacha::{
+        chacha_cbc_encrypt, chacha_cbc_encrypt_files, chacha_encrypt, le_words, sample_file,
+        sample_offsets, sample_slice, CHACHA_KEY_SIZE, SAMPLE_SIZE,
+    };
+    use crate::hash::{hash, Hash};
     use std::fs::remove_file;
     use std::fs::File;
     use std::io::Read;
     use std::io::Write;
     use std::path::Path;
 
+    fn test_key() -> [u8; CHACHA_KEY_SIZE] {
+        let mut key = [0u8; CHACHA_KEY_SIZE];
+        for (i, k) in key.iter_mut().enumerate() {
+            *k = i as u8;
+        }
+        key
+    }
+
+    #[test]
+    fn test_chacha20_block() {
+        // the ChaCha20 block function of RFC 7539 section 2.3.2
+        let nonce = [0, 0, 0, 9, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let mut input = [0u32; 16];
+        input[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+        input[12] = 1;
+        le_words(&test_key(), &mut input[4..12]);
+        le_words(&nonce, &mut input[13..]);
+        let block = chacha_encrypt(&input, 20);
+        assert_eq!(
+            block[..],
+            [
+                0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20,
+                0x71, 0xc4, 0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a,
+                0xc3, 0xd4, 0x6c, 0x4e, 0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x14, 0xc2,
+                0xd7, 0x05, 0xd9, 0x8b, 0x02, 0xa2, 0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9,
+                0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e,
+            ][..]
+        );
+    }
+
+    #[test]
+    fn test_encrypt_chaining() {
+        let key = test_key();
+        let input: Vec<u8> = (0..100).collect();
+        let mut whole = vec![0; input.len()];
+        let mut ivec = [0; 64];
+        chacha_cbc_encrypt(&input, &mut whole, &key, &mut ivec);
+        assert_eq!(
+            whole[60..70],
+            [255, 185, 89, 151, 165, 134, 191, 28, 210, 120]
+        );
+
+        // splitting on a block boundary chains through ivec
+        let mut split = vec![0; input.len()];
+        let mut ivec = [0; 64];
+        chacha_cbc_encrypt(&input[..64], &mut split[..64], &key, &mut ivec);
+        chacha_cbc_encrypt(&input[64..], &mut split[64..], &key, &mut ivec);
+        assert_eq!(split, whole);
+    }
+
     #[test]
     fn test_encrypt_file() {
         let in_path = Path::new("test_chacha_encrypt_file_input.txt");
@@ -74,10 +282,13 @@ mod tests {
             let mut in_file = File::create(in_path).unwrap();
             in_file.write("123456foobar".as_bytes()).unwrap();
         }
-        assert!(chacha_cbc_encrypt_files(in_path, out_path, "thetestkey".to_string()).is_ok());
+        let mut key = [0; CHACHA_KEY_SIZE];
+        key[..10].copy_from_slice("thetestkey".as_bytes());
+        assert!(chacha_cbc_encrypt_files(in_path, out_path, &key).is_ok());
         let mut out_file = File::open(out_path).unwrap();
         let mut buf = vec![];
         let size = out_file.read_to_end(&mut buf).unwrap();
+        // the ciphertext cpu-crypt produces, with or without the `chacha` feature
         assert_eq!(
             buf[..size],
             [106, 186, 59, 108, 165, 33, 118, 212, 70, 238, 205, 185]
@@ -85,4 +296,39 @@ mod tests {
         remove_file(in_path).unwrap();
         remove_file(out_path).unwrap();
     }
+
+    #[test]
+    fn test_sample_file() {
+        let in_path = Path::new("test_chacha_sample_file.txt.enc");
+        let data: Vec<u8> = (0..3 * SAMPLE_SIZE).map(|i| (i * 7) as u8).collect();
+        {
+            let mut in_file = File::create(in_path).unwrap();
+            in_file.write_all(&data).unwrap();
+        }
+
+        let seed = hash(b"storage seed");
+        let offsets = sample_offsets(&seed, data.len() as u64, 4);
+        assert_eq!(offsets, sample_offsets(&seed, data.len() as u64, 4));
+        assert_ne!(
+            offsets,
+            sample_offsets(&Hash::default(), data.len() as u64, 4)
+        );
+        for offset in &offsets {
+            assert!(*offset as usize + SAMPLE_SIZE <= data.len());
+        }
+
+        let sha_state = sample_file(in_path, &[0, SAMPLE_SIZE as u64]).unwrap();
+        assert_eq!(sha_state[..], hash(&data[..2 * SAMPLE_SIZE]).as_ref()[..]);
+
+        // a sample past the end is hashed short
+        let end = data.len() as u64 - 10;
+        let sha_state = sample_file(in_path, &[end]).unwrap();
+        assert_eq!(sha_state[..], hash(&data[data.len() - 10..]).as_ref()[..]);
+        assert_eq!(sample_slice(&data, &[end]), sha_state);
+        assert_eq!(
+            sample_slice(&data, &offsets),
+            sample_file(in_path, &offsets).unwrap()
+        );
+        remove_file(in_path).unwrap();
+    }
 }
@@ -0,0 +1,357 @@
+//! The `checkpoint` module stores snapshots of `Bank` state in the ledger directory,
+//! so a fullnode can boot from the latest one and the ledger entries after it
+//! instead of replaying the ledger from genesis.
+
+use crate::account_store::{AccountStore, MemoryAccountStore};
+use crate::entry::Entry;
+use crate::ledger::{compress_ledger, ledger_start, prune_ledger, read_ledger_from};
+use crate::program_registry::program_dir;
+use crate::result::Result;
+use crate::tx_vault::Bank;
+use std::fs::{create_dir_all, read_dir, remove_file, rename, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::window::WINDOW_SIZE;
+
+/// A checkpoint is taken each time the ledger grows past a multiple of this many entries.
+pub const CHECKPOINT_INTERVAL: u64 = 4096;
+
+/// The number of most recent checkpoints kept, older ones are removed.
+pub const MAX_CHECKPOINTS: usize = 2;
+
+fn checkpoint_dir(ledger_path: &str) -> PathBuf {
+    Path::new(ledger_path).join("checkpoints")
+}
+
+fn checkpoint_path(ledger_path: &str, entry_height: u64) -> PathBuf {
+    checkpoint_dir(ledger_path).join(entry_height.to_string())
+}
+
+fn staged_path(ledger_path: &str, entry_height: u64) -> PathBuf {
+    checkpoint_path(ledger_path, entry_height).with_extension("tmp")
+}
+
+/// True if a checkpoint is due after processing entries `from_height` to `to_height`.
+pub fn checkpoint_due(from_height: u64, to_height: u64) -> bool {
+    from_height / CHECKPOINT_INTERVAL != to_height / CHECKPOINT_INTERVAL
+}
+
+/// Entry heights of the checkpoints stored for a ledger, the latest first.
+pub fn checkpoint_heights(ledger_path: &str) -> Result<Vec<u64>> {
+    let dir = checkpoint_dir(ledger_path);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut heights = vec![];
+    for entry in read_dir(dir)? {
+        // skips the temporary files of checkpoints that were never completed
+        if let Some(height) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            heights.push(height);
+        }
+    }
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(heights)
+}
+
+/// Write a checkpoint of `bank` and remove all but the `MAX_CHECKPOINTS` latest ones.
+/// The ledger has to hold all the entries the bank has processed.
+pub fn write_checkpoint(ledger_path: &str, bank: &Bank) -> Result<u64> {
+    let entry_height = stage_checkpoint(ledger_path, bank)?;
+    commit_checkpoint(ledger_path, entry_height)?;
+    Ok(entry_height)
+}
+
+/// Write a checkpoint of `bank` that is not resumed from until `commit_checkpoint`,
+/// once the ledger holds all the entries it covers. Returns its entry height. Only
+/// one checkpoint is staged at a time, the ones left over are removed.
+pub fn stage_checkpoint(ledger_path: &str, bank: &Bank) -> Result<u64> {
+    let dir = checkpoint_dir(ledger_path);
+    create_dir_all(&dir)?;
+    for entry in read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |extension| extension == "tmp") {
+            remove_file(path)?;
+        }
+    }
+
+    let staging_path = dir.join("staging.tmp");
+    let entry_height = {
+        let mut file = BufWriter::new(File::create(&staging_path)?);
+        let checkpoint = bank.write_checkpoint(&mut file)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        checkpoint.entry_height
+    };
+    rename(&staging_path, staged_path(ledger_path, entry_height))?;
+    Ok(entry_height)
+}
+
+/// Make the checkpoint staged at `entry_height` available to resume from and remove
+/// all but the `MAX_CHECKPOINTS` latest ones.
+pub fn commit_checkpoint(ledger_path: &str, entry_height: u64) -> Result<()> {
+    // a checkpoint is only found under its entry height once it is complete
+    rename(
+        staged_path(ledger_path, entry_height),
+        checkpoint_path(ledger_path, entry_height),
+    )?;
+    info!("wrote checkpoint at entry {}", entry_height);
+
+    for height in checkpoint_heights(ledger_path)?.iter().skip(MAX_CHECKPOINTS) {
+        remove_file(checkpoint_path(ledger_path, *height))?;
+    }
+    Ok(())
+}
+
+fn memory_accounts() -> io::Result<Box<AccountStore>> {
+    Ok(Box::new(MemoryAccountStore::default()))
+}
+
+// the first entry the oldest checkpoint that resumes a bank needs, the ledger
+//  needs all of them if there is no such checkpoint
+fn checkpoints_keep_from(ledger_path: &str) -> Result<u64> {
+    // the oldest checkpoint is the last one tried when resuming
+    for entry_height in checkpoint_heights(ledger_path)?.iter().rev() {
+        match resume_bank(ledger_path, *entry_height, &memory_accounts, false) {
+            Ok(_) => return Ok(entry_height.saturating_sub(WINDOW_SIZE)),
+            Err(e) => warn!("checkpoint at entry {} is not valid: {:?}", entry_height, e),
+        }
+    }
+    Ok(ledger_start(ledger_path)?)
+}
+
+/// Remove the ledger segments that none of the stored checkpoints needs to resume
+/// from, only the checkpoints a bank can be resumed from count. Returns the index
+/// of the first entry the ledger still holds.
+pub fn prune_ledger_to_checkpoints(ledger_path: &str) -> Result<u64> {
+    let keep_from = checkpoints_keep_from(ledger_path)?;
+    Ok(prune_ledger(ledger_path, keep_from)?)
+}
+
+/// Compress the ledger segments that none of the stored checkpoints needs to resume
+/// from, see `prune_ledger_to_checkpoints`. Returns the number of segments compressed.
+pub fn compress_ledger_to_checkpoints(ledger_path: &str) -> Result<usize> {
+    let keep_from = checkpoints_keep_from(ledger_path)?;
+    Ok(compress_ledger(ledger_path, keep_from)?)
+}
+
+/// Restore a bank from the checkpoint at `entry_height` into the store `accounts`
+/// returns, and process the ledger entries after it. Returns the same as
+/// `Bank::process_ledger`.
+pub fn resume_bank(
+    ledger_path: &str,
+    entry_height: u64,
+    accounts: &Fn() -> io::Result<Box<AccountStore>>,
+    is_leader: bool,
+) -> Result<(Bank, u64, Vec<Entry>)> {
+    let file = File::open(checkpoint_path(ledger_path, entry_height))?;
+    let bank = Bank::read_checkpoint(&mut BufReader::new(file), accounts()?, is_leader)?;
+    bank.set_program_dir(program_dir(ledger_path));
+
+    let tail_start = entry_height.saturating_sub(WINDOW_SIZE);
+    let mut entries = read_ledger_from(ledger_path, tail_start, true)?;
+    let tail = entries
+        .by_ref()
+        .take((entry_height - tail_start) as usize)
+        .collect::<io::Result<Vec<Entry>>>()?;
+
+    // the entries after the checkpoint end at the first one that fails to parse
+    let mut error = None;
+    let entries = entries.scan(&mut error, |error, entry| match entry {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            **error = Some(err);
+            None
+        }
+    });
+    let (entry_height, ledger_tail) = bank.process_ledger_tail(tail, entries)?;
+    if let Some(err) = error {
+        return Err(err.into());
+    }
+    Ok((bank, entry_height, ledger_tail))
+}
+
+/// Restore a bank from the latest checkpoint that is valid for the ledger, see
+/// `resume_bank`. None if there is no such checkpoint.
+pub fn resume_latest_bank(
+    ledger_path: &str,
+    accounts: &Fn() -> io::Result<Box<AccountStore>>,
+    is_leader: bool,
+) -> Option<(Bank, u64, Vec<Entry>)> {
+    let heights = checkpoint_heights(ledger_path)
+        .map_err(|e| warn!("unable to list checkpoints: {:?}", e))
+        .ok()?;
+    for entry_height in heights {
+        match resume_bank(ledger_path, entry_height, accounts, is_leader) {
+            Ok(resumed) => return Some(resumed),
+            Err(e) => warn!("skipping checkpoint at entry {}: {:?}", entry_height, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account_store::AppendAccountStore;
+    use crate::coinery::Mint;
+    use crate::ledger::{ledger_segments, next_entries_mut, tmp_ledger_path, LedgerWriter};
+    use crate::signature::{Keypair, KeypairUtil};
+    use std::fs::remove_dir_all;
+    use crate::transaction::Transaction;
+    use crate::system_transaction::SystemTransaction;
+
+    fn make_ledger(name: &str, mint: &Mint, num_entries: usize) -> (String, Vec<Entry>) {
+        let ledger_path = tmp_ledger_path(name);
+        let mut entries = mint.create_entries();
+        let mut id = mint.last_id();
+        let mut num_hashes = 0;
+        for _ in 0..num_entries {
+            let tx =
+                Transaction::system_new(&mint.keypair(), Keypair::new().pubkey(), 1, mint.last_id());
+            entries.extend(next_entries_mut(&mut id, &mut num_hashes, vec![tx]));
+        }
+        let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 4).unwrap();
+        writer.write_entries(entries.clone()).unwrap();
+        (ledger_path, entries)
+    }
+
+    #[test]
+    fn test_checkpoint_due() {
+        assert!(!checkpoint_due(0, CHECKPOINT_INTERVAL - 1));
+        assert!(checkpoint_due(CHECKPOINT_INTERVAL - 1, CHECKPOINT_INTERVAL));
+        assert!(checkpoint_due(1, 2 * CHECKPOINT_INTERVAL + 1));
+        assert!(!checkpoint_due(CHECKPOINT_INTERVAL, CHECKPOINT_INTERVAL + 1));
+    }
+
+    #[test]
+    fn test_resume_bank() {
+        let mint = Mint::new(10_000);
+        let (ledger_path, entries) = make_ledger("test_resume_bank", &mint, 10);
+
+        let full = Bank::default();
+        let (ledger_height, tail) = full.process_ledger(entries.clone()).unwrap();
+
+        let bank = Bank::default();
+        bank.process_ledger(entries[..6].to_vec()).unwrap();
+        assert_eq!(write_checkpoint(&ledger_path, &bank).unwrap(), 6);
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![6]);
+
+        let (resumed, resumed_height, resumed_tail) =
+            resume_latest_bank(&ledger_path, &memory_accounts, false).unwrap();
+        assert_eq!(resumed_height, ledger_height);
+        assert_eq!(resumed_tail, tail);
+        assert_eq!(
+            resumed.hash_internal_state().unwrap(),
+            full.hash_internal_state().unwrap()
+        );
+        assert_eq!(resumed.last_id(), full.last_id());
+
+        // into a store on disk as well
+        let accounts_path = Path::new(&ledger_path).join("accounts");
+        let append_accounts = || -> io::Result<Box<AccountStore>> {
+            Ok(Box::new(AppendAccountStore::open(&accounts_path)?))
+        };
+        let (resumed, _, _) = resume_latest_bank(&ledger_path, &append_accounts, false).unwrap();
+        assert_eq!(
+            resumed.hash_internal_state().unwrap(),
+            full.hash_internal_state().unwrap()
+        );
+        drop(resumed);
+
+        // a staged checkpoint is only resumed from once it is committed
+        let bank = Bank::default();
+        bank.process_ledger(entries[..8].to_vec()).unwrap();
+        assert_eq!(stage_checkpoint(&ledger_path, &bank).unwrap(), 8);
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![6]);
+        commit_checkpoint(&ledger_path, 8).unwrap();
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![8, 6]);
+
+        // the tail the checkpoints are resumed with is never pruned
+        assert_eq!(prune_ledger_to_checkpoints(&ledger_path).unwrap(), 0);
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![0, 4, 8]);
+        assert!(resume_latest_bank(&ledger_path, &memory_accounts, false).is_some());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_prune_to_valid_checkpoints() {
+        let mint = Mint::new(10_000);
+        let ledger_path = tmp_ledger_path("test_prune_to_valid_checkpoints");
+        let mut entries = mint.create_entries();
+        let mut id = mint.last_id();
+        for _ in 0..WINDOW_SIZE + 610 {
+            entries.extend(next_entries_mut(&mut id, &mut 1, vec![]));
+        }
+        let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 256).unwrap();
+        writer.write_entries(entries.clone()).unwrap();
+
+        let checkpoint = |entry_height: u64| {
+            let bank = Bank::default();
+            bank.process_ledger(entries[..entry_height as usize].to_vec())
+                .unwrap();
+            write_checkpoint(&ledger_path, &bank).unwrap();
+        };
+        let corrupt = |entry_height: u64| {
+            File::create(checkpoint_path(&ledger_path, entry_height))
+                .unwrap()
+                .write_all(b"garbage")
+                .unwrap();
+        };
+        checkpoint(WINDOW_SIZE + 300);
+        checkpoint(WINDOW_SIZE + 600);
+
+        // nothing is pruned while no checkpoint resumes a bank
+        corrupt(WINDOW_SIZE + 300);
+        corrupt(WINDOW_SIZE + 600);
+        assert_eq!(prune_ledger_to_checkpoints(&ledger_path).unwrap(), 0);
+        assert_eq!(compress_ledger_to_checkpoints(&ledger_path).unwrap(), 0);
+        assert_eq!(ledger_segments(&ledger_path).unwrap().len(), 11);
+
+        // and the tail of the oldest one that does is kept
+        checkpoint(WINDOW_SIZE + 600);
+        assert_eq!(compress_ledger_to_checkpoints(&ledger_path).unwrap(), 2);
+        assert_eq!(prune_ledger_to_checkpoints(&ledger_path).unwrap(), 512);
+        let (_, resumed_height, _) =
+            resume_latest_bank(&ledger_path, &memory_accounts, false).unwrap();
+        assert_eq!(resumed_height, entries.len() as u64);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_latest_valid() {
+        let mint = Mint::new(10_000);
+        let (ledger_path, entries) = make_ledger("test_resume_latest_valid", &mint, 10);
+        assert!(resume_latest_bank(&ledger_path, &memory_accounts, false).is_none());
+
+        for height in 3..7 {
+            let bank = Bank::default();
+            bank.process_ledger(entries[..height].to_vec()).unwrap();
+            write_checkpoint(&ledger_path, &bank).unwrap();
+        }
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![6, 5]);
+
+        // a corrupted checkpoint is skipped
+        File::create(checkpoint_path(&ledger_path, 6))
+            .unwrap()
+            .write_all(b"garbage")
+            .unwrap();
+        let (resumed, resumed_height, _) =
+            resume_latest_bank(&ledger_path, &memory_accounts, false).unwrap();
+        assert_eq!(resumed_height, entries.len() as u64);
+        assert_eq!(resumed.get_balance(&mint.pubkey()), 10_000 - 10);
+
+        // and so is one ahead of the ledger
+        let mut more = entries.clone();
+        let mut id = more.last().unwrap().id;
+        more.extend(next_entries_mut(&mut id, &mut 1, vec![]));
+        let bank = Bank::default();
+        bank.process_ledger(more).unwrap();
+        write_checkpoint(&ledger_path, &bank).unwrap();
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![13, 6]);
+        assert!(resume_latest_bank(&ledger_path, &memory_accounts, false).is_none());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+}
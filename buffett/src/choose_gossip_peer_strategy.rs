@@ -1,6 +1,4 @@
 use crate::crdt::{CrdtError, NodeInfo};
-use rand::distributions::{Distribution, Weighted, WeightedChoice};
-use rand::thread_rng;
 use crate::result::Result;
 use buffett_program_interface::pubkey::Pubkey;
 use std;
@@ -8,6 +6,22 @@ use std::collections::HashMap;
 
 pub const DEFAULT_WEIGHT: u32 = 1;
 
+/// How a node picks the peer it asks for updates on every gossip round.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GossipStrategy {
+    /// Every peer is as likely to be picked, see `ChooseRandomPeerStrategy`
+    Random,
+    /// Peers are picked by their rumored updates and their stake, see
+    /// `ChooseWeightedPeerStrategy`
+    Weighted,
+}
+
+impl Default for GossipStrategy {
+    fn default() -> Self {
+        GossipStrategy::Weighted
+    }
+}
+
 pub trait ChooseGossipPeerStrategy {
     fn choose_peer<'a>(&self, options: Vec<&'a NodeInfo>) -> Result<&'a NodeInfo>;
 }
@@ -51,6 +65,12 @@ impl<'a> ChooseGossipPeerStrategy for ChooseRandomPeerStrategy<'a> {
 // observed update_index from the last direct communication validator "v", and
 // rumor_v(i) is the rumored update_index of validator "v" propagated by fellow validator "i".
 
+// The weight of a validator is then scaled by its own share of the stake of all the
+// choices, so a validator with n times the average stake is picked about n + 1 times as
+// often as it would be otherwise. The votes of the validators reach the leader directly,
+// and the leader is expected to hold the most stake, so the nodes with the most stake
+// are the ones with the freshest records to offer.
+//
 // This could be a problem if there are validators with large stakes lying about their
 // observed updates. There could also be a problem in network partitions, or even just
 // when certain validators are disproportionately active, where we hear more rumors about
@@ -69,6 +89,8 @@ pub struct ChooseWeightedPeerStrategy<'a> {
     // A function returning the size of the stake for a particular validator, corresponds
     // to stake(i) in the formula above.
     get_stake: &'a Fn(Pubkey) -> f64,
+    // The source of randomness of the weighted sample
+    random: &'a Fn() -> u64,
 }
 
 impl<'a> ChooseWeightedPeerStrategy<'a> {
@@ -76,11 +98,13 @@ impl<'a> ChooseWeightedPeerStrategy<'a> {
         remote: &'a HashMap<Pubkey, u64>,
         external_liveness: &'a HashMap<Pubkey, HashMap<Pubkey, u64>>,
         get_stake: &'a Fn(Pubkey) -> f64,
+        random: &'a Fn() -> u64,
     ) -> Self {
         ChooseWeightedPeerStrategy {
             remote,
             external_liveness,
             get_stake,
+            random,
         }
     }
 
@@ -157,8 +181,7 @@ impl<'a> ChooseWeightedPeerStrategy<'a> {
             sum + new_weight
         });
 
-        // Return u32 b/c the weighted sampling API from rand::distributions
-        // only takes u32 for weights
+        // Return u32 so the weights of all the choices add up to a u64
         if weighted_vote >= f64::from(std::u32::MAX) {
             return std::u32::MAX;
         }
@@ -169,6 +192,18 @@ impl<'a> ChooseWeightedPeerStrategy<'a> {
         // Otherwise, return the calculated weight.
         weighted_vote as u32 + DEFAULT_WEIGHT
     }
+
+    // 1 + the stake of the peer relative to the average stake of the `num_peers` choices
+    fn calculate_stake_weight(&self, peer_id: Pubkey, total_stake: f64, num_peers: usize) -> u32 {
+        if total_stake <= 0.0 {
+            return DEFAULT_WEIGHT;
+        }
+        let relative_stake = (self.get_stake)(peer_id) / total_stake * num_peers as f64;
+        if relative_stake >= f64::from(std::u32::MAX) {
+            return std::u32::MAX;
+        }
+        (relative_stake as u32).saturating_add(DEFAULT_WEIGHT)
+    }
 }
 
 impl<'a> ChooseGossipPeerStrategy for ChooseWeightedPeerStrategy<'a> {
@@ -177,20 +212,43 @@ impl<'a> ChooseGossipPeerStrategy for ChooseWeightedPeerStrategy<'a> {
             Err(CrdtError::NoPeers)?;
         }
 
+        let total_stake = options.iter().fold(0.0, |total_stake, peer| {
+            let stake = (self.get_stake)(peer.id);
+            if std::f64::MAX - total_stake < stake {
+                f64::max(stake, total_stake)
+            } else {
+                total_stake + stake
+            }
+        });
+        let num_peers = options.len();
+
         let mut weighted_peers = vec![];
         for peer in options {
-            let weight = self.calculate_weighted_remote_index(peer.id);
-            weighted_peers.push(Weighted { weight, item: peer });
+            let weight = self
+                .calculate_weighted_remote_index(peer.id)
+                .saturating_mul(self.calculate_stake_weight(peer.id, total_stake, num_peers));
+            weighted_peers.push((u64::from(weight), peer));
         }
 
-        let mut rng = thread_rng();
-        Ok(WeightedChoice::new(&mut weighted_peers).sample(&mut rng))
+        // every weight is at least DEFAULT_WEIGHT, the total isn't 0
+        let total_weight: u64 = weighted_peers.iter().map(|(weight, _)| weight).sum();
+        let mut pick = (self.random)() % total_weight;
+        for (weight, peer) in weighted_peers {
+            if pick < weight {
+                return Ok(peer);
+            }
+            pick -= weight;
+        }
+        unreachable!()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use choose_gossip_peer_strategy::{ChooseWeightedPeerStrategy, DEFAULT_WEIGHT};
+    use choose_gossip_peer_strategy::{
+        ChooseGossipPeerStrategy, ChooseWeightedPeerStrategy, DEFAULT_WEIGHT,
+    };
+    use crate::crdt::NodeInfo;
     use crate::logger;
     use crate::signature::{Keypair, KeypairUtil};
     use buffett_program_interface::pubkey::Pubkey;
@@ -201,6 +259,10 @@ mod tests {
         1.0
     }
 
+    fn random() -> u64 {
+        0
+    }
+
     #[test]
     fn test_default() {
         logger::setup();
@@ -212,7 +274,7 @@ mod tests {
         let external_liveness: HashMap<Pubkey, HashMap<Pubkey, u64>> = HashMap::new();
 
         let weighted_strategy =
-            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
 
         // If external_liveness table doesn't contain this entry,
         // return the default weight
@@ -239,7 +301,7 @@ mod tests {
         external_liveness.insert(key1, rumors);
 
         let weighted_strategy =
-            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
 
         let result = weighted_strategy.calculate_weighted_remote_index(key1);
         assert_eq!(result, test_value + DEFAULT_WEIGHT);
@@ -263,7 +325,7 @@ mod tests {
         external_liveness.insert(key1, rumors);
 
         let weighted_strategy =
-            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
 
         let result = weighted_strategy.calculate_weighted_remote_index(key1);
         assert_eq!(result, std::u32::MAX);
@@ -293,7 +355,7 @@ mod tests {
         external_liveness.insert(key1, rumors);
 
         let weighted_strategy =
-            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
 
         let result = weighted_strategy.calculate_weighted_remote_index(key1);
         assert_eq!(result, (num_peers / 2) as u32);
@@ -324,11 +386,61 @@ mod tests {
         external_liveness.insert(key1, rumors);
 
         let weighted_strategy =
-            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake);
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
 
         let result = weighted_strategy.calculate_weighted_remote_index(key1);
 
         // If nobody has seen a newer update then revert to default
         assert_eq!(result, DEFAULT_WEIGHT);
     }
+
+    #[test]
+    fn test_stake_weight() {
+        logger::setup();
+
+        let key1 = Keypair::new().pubkey();
+        let key2 = Keypair::new().pubkey();
+
+        let remote: HashMap<Pubkey, u64> = HashMap::new();
+        let external_liveness: HashMap<Pubkey, HashMap<Pubkey, u64>> = HashMap::new();
+
+        // equal stakes don't favor anybody
+        let weighted_strategy =
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
+        assert_eq!(weighted_strategy.calculate_stake_weight(key1, 2.0, 2), 2);
+
+        // key1 holds 9 times the stake of key2
+        let get_stake = |id| if id == key1 { 9.0 } else { 1.0 };
+        let weighted_strategy =
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
+        assert_eq!(weighted_strategy.calculate_stake_weight(key1, 10.0, 2), 2);
+        assert_eq!(weighted_strategy.calculate_stake_weight(key2, 10.0, 2), 1);
+        assert_eq!(weighted_strategy.calculate_stake_weight(key1, 0.0, 2), DEFAULT_WEIGHT);
+    }
+
+    #[test]
+    fn test_choose_peer() {
+        logger::setup();
+
+        let key1 = Keypair::new().pubkey();
+        let key2 = Keypair::new().pubkey();
+        let peer1 = NodeInfo::new_localhost(key1);
+        let peer2 = NodeInfo::new_localhost(key2);
+
+        let remote: HashMap<Pubkey, u64> = HashMap::new();
+        let external_liveness: HashMap<Pubkey, HashMap<Pubkey, u64>> = HashMap::new();
+
+        // key1 weighs 2, key2 weighs 1, the picks come from `random` modulo 3
+        let get_stake = |id| if id == key1 { 9.0 } else { 1.0 };
+        for (n, id) in &[(0, key1), (1, key1), (2, key2), (3, key1), (5, key2)] {
+            let random = || *n as u64;
+            let weighted_strategy =
+                ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
+            let peer = weighted_strategy.choose_peer(vec![&peer1, &peer2]).unwrap();
+            assert_eq!(peer.id, *id);
+        }
+        let weighted_strategy =
+            ChooseWeightedPeerStrategy::new(&remote, &external_liveness, &get_stake, &random);
+        assert!(weighted_strategy.choose_peer(vec![]).is_err());
+    }
 }
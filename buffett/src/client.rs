@@ -1,7 +1,7 @@
 use crate::crdt::{NodeInfo, FULLNODE_PORT_RANGE};
 use netutil::bind_in_range;
 use std::time::Duration;
-use crate::thin_client::ThinClient;
+use crate::thin_client::{ThinClient, Transport};
 
 pub fn mk_client(r: &NodeInfo) -> ThinClient {
     let (_, requests_socket) = bind_in_range(FULLNODE_PORT_RANGE).unwrap();
@@ -13,8 +13,10 @@ pub fn mk_client(r: &NodeInfo) -> ThinClient {
 
     ThinClient::new(
         r.contact_info.rpu,
-        requests_socket,
         r.contact_info.tpu,
-        transactions_socket,
+        Transport::Udp {
+            requests_socket,
+            transactions_socket,
+        },
     )
 }
@@ -11,30 +11,34 @@
 //! * layer 1 - As many nodes as we can fit
 //! * layer 2 - Everyone else, if layer 1 is `2^10`, layer 2 should be able to fit `2^20` number of nodes.
 //!
-//! Bank needs to provide an interface for us to query the stake weight
+//! The stake weight of the nodes is the balance of their accounts in the Bank while
+//! they keep voting, see `Crdt::refresh_stakes`.
 use bincode::{deserialize, serialize};
-use crate::budget_instruction::Vote;
-use choose_gossip_peer_strategy::{ChooseGossipPeerStrategy, ChooseWeightedPeerStrategy};
+use crate::budget_instruction::{Vote, VoteSignature};
+use choose_gossip_peer_strategy::{
+    ChooseGossipPeerStrategy, ChooseRandomPeerStrategy, ChooseWeightedPeerStrategy, GossipStrategy,
+};
 use crate::counter::Counter;
 use crate::hash::Hash;
 use crate::ledger::LedgerWindow;
 use log::Level;
-use netutil::{bind_in_range, bind_to, multi_bind_in_range};
+use netutil::{bind_in_range, bind_tcp_alongside, bind_to, multi_bind_in_range};
 use crate::packet::{to_blob, Blob, SharedBlob, BLOB_SIZE};
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, ChaChaRng, Rng, SeedableRng};
 use rayon::prelude::*;
 use crate::result::{Error, Result};
-use crate::signature::{Keypair, KeypairUtil};
+use crate::signature::{Keypair, KeypairUtil, Signature};
 use buffett_program_interface::pubkey::Pubkey;
 use std;
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{sleep, Builder, JoinHandle};
 use std::time::{Duration, Instant};
 use crate::streamer::{BlobReceiver, BlobSender};
 use crate::timing::{duration_as_ms, timestamp};
+use crate::tx_vault::Bank;
 use crate::window::{SharedWindow, WindowIndex};
 
 pub const FULLNODE_PORT_RANGE: (u16, u16) = (8000, 10_000);
@@ -43,6 +47,9 @@ pub const FULLNODE_PORT_RANGE: (u16, u16) = (8000, 10_000);
 const GOSSIP_SLEEP_MILLIS: u64 = 100;
 const GOSSIP_PURGE_MILLIS: u64 = 15000;
 
+/// milliseconds between the lookups of the stakes of the nodes in the Bank
+const STAKES_REFRESH_MILLIS: u64 = 1000;
+
 /// minimum membership table size before we start purging dead nodes
 const MIN_TABLE_SIZE: usize = 2;
 
@@ -110,6 +117,8 @@ pub struct NodeInfo {
     pub leader_id: Pubkey,
     /// information about the state of the ledger
     pub ledger_state: LedgerState,
+    /// signature of the node `id` over the rest of the record
+    pub signature: Signature,
 }
 
 impl NodeInfo {
@@ -136,9 +145,32 @@ impl NodeInfo {
             ledger_state: LedgerState {
                 last_id: Hash::default(),
             },
+            signature: Signature::default(),
         }
     }
 
+    fn get_sign_data(&self) -> Vec<u8> {
+        serialize(&(
+            &self.id,
+            self.version,
+            &self.contact_info,
+            &self.leader_id,
+            &self.ledger_state,
+        )).expect("serialize NodeInfo in fn get_sign_data")
+    }
+
+    /// Sign the record with the keypair of the node it describes.
+    pub fn sign(&mut self, keypair: &Keypair) {
+        let sign_data = self.get_sign_data();
+        self.signature = Signature::new(keypair.sign(&sign_data).as_ref());
+    }
+
+    /// Verify the record was signed by the node it describes.
+    pub fn verify(&self) -> bool {
+        self.signature
+            .verify(self.id.as_ref(), &self.get_sign_data())
+    }
+
     pub fn new_localhost(id: Pubkey) -> Self {
         Self::new(
             id,
@@ -229,9 +261,26 @@ pub struct Crdt {
     // TODO: Is there a better way to do this? We didn't make this a constant because
     // we want to be able to set it in integration tests so that the tests don't time out.
     pub leader_rotation_interval: u64,
+    /// signs the records of this node, without it they are dropped by the peers
+    keypair: Option<Arc<Keypair>>,
+    /// number of records received without a valid signature of their node
+    pub dropped_records: u64,
+    /// nodes whose record has the address we saw them at instead of their unspecified one,
+    /// the record no longer matches its signature so it isn't gossiped any further
+    observed: HashSet<Pubkey>,
+    /// stake of the nodes in the table, as of the last `refresh_stakes`
+    stakes: HashMap<Pubkey, u64>,
+    /// time of the last `refresh_stakes`
+    stakes_refreshed: u64,
+    /// how the peer of every gossip request is picked
+    gossip_strategy: GossipStrategy,
+    /// source of the random picks of the gossip peers
+    gossip_rng: Mutex<ChaChaRng>,
 }
 
-// TODO These messages should be signed, and go through the gpu pipeline for spam filtering
+// The NodeInfo records in these messages are signed by their nodes, the messages
+// themselves are not.
+// TODO These messages should go through the gpu pipeline for spam filtering
 #[derive(Serialize, Deserialize, Debug)]
 enum Protocol {
     /// forward your own latest data structure when requesting an update
@@ -262,11 +311,29 @@ impl Crdt {
             update_index: 1,
             scheduled_leaders: HashMap::new(),
             leader_rotation_interval: 100,
+            keypair: None,
+            dropped_records: 0,
+            observed: HashSet::new(),
+            stakes: HashMap::new(),
+            stakes_refreshed: 0,
+            gossip_strategy: GossipStrategy::default(),
+            gossip_rng: Mutex::new(ChaChaRng::from_seed(thread_rng().gen())),
         };
         me.local.insert(node_info.id, me.update_index);
         me.table.insert(node_info.id, node_info);
         Ok(me)
     }
+    /// A `Crdt` that signs every version of `node_info` with `keypair`. The records of a
+    /// `Crdt::new` are unsigned, it can learn the network but the network won't learn it.
+    pub fn new_with_keypair(mut node_info: NodeInfo, keypair: Arc<Keypair>) -> Result<Crdt> {
+        if node_info.id != keypair.pubkey() {
+            return Err(Error::CrdtError(CrdtError::BadNodeInfo));
+        }
+        node_info.sign(&keypair);
+        let mut me = Self::new(node_info)?;
+        me.keypair = Some(keypair);
+        Ok(me)
+    }
     pub fn my_data(&self) -> &NodeInfo {
         &self.table[&self.id]
     }
@@ -360,7 +427,15 @@ impl Crdt {
         self.external_liveness.get(key)
     }
 
-    pub fn insert_vote(&mut self, pubkey: &Pubkey, v: &Vote, last_id: Hash) {
+    /// Insert the vote of `pubkey` for `last_id`, the voter's record is rebuilt from the
+    /// vote and the copy in the table, and dropped unless it's the one the voter signed.
+    pub fn insert_vote(
+        &mut self,
+        pubkey: &Pubkey,
+        v: &Vote,
+        signature: &VoteSignature,
+        last_id: Hash,
+    ) {
         if self.table.get(pubkey).is_none() {
             warn!("{}: VOTE for unknown id: {}", self.id, pubkey);
             return;
@@ -382,22 +457,29 @@ impl Crdt {
             self.update_liveness(*pubkey);
             return;
         } else {
+            // the contact info is the one of the vote's version, the rest is in the vote
             let mut data = self.table[pubkey].clone();
             data.version = v.version;
+            data.leader_id = signature.leader_id;
             data.ledger_state.last_id = last_id;
+            data.signature = signature.signature;
+            if !data.verify() {
+                self.drop_record(&data);
+                return;
+            }
 
             debug!("{}: INSERTING VOTE! for {}", self.id, data.id);
             self.update_liveness(data.id);
             self.insert(&data);
         }
     }
-    pub fn insert_votes(&mut self, votes: &[(Pubkey, Vote, Hash)]) {
+    pub fn insert_votes(&mut self, votes: &[(Pubkey, Vote, VoteSignature, Hash)]) {
         inc_new_counter_info!("crdt-vote-count", votes.len());
         if !votes.is_empty() {
             info!("{}: INSERTING VOTES {}", self.id, votes.len());
         }
         for v in votes {
-            self.insert_vote(&v.0, &v.1, v.2);
+            self.insert_vote(&v.0, &v.1, &v.2, v.3);
         }
     }
 
@@ -412,7 +494,14 @@ impl Crdt {
                 inc_new_counter_info!("crdt-insert-new_entry", 1, 1);
             }
 
+            let mut v = v.clone();
+            if v.id == self.id {
+                if let Some(keypair) = &self.keypair {
+                    v.sign(keypair);
+                }
+            }
             self.update_index += 1;
+            self.observed.remove(&v.id);
             let _ = self.table.insert(v.id, v.clone());
             let _ = self.local.insert(v.id, self.update_index);
             self.update_liveness(v.id);
@@ -429,6 +518,27 @@ impl Crdt {
         }
     }
 
+    fn drop_record(&mut self, v: &NodeInfo) {
+        debug!(
+            "{}: dropping record without a valid signature for {}",
+            self.id, v.id
+        );
+        inc_new_counter_info!("crdt-insert-bad_signature", 1);
+        self.dropped_records += 1;
+    }
+
+    /// Insert a record received from the network, it is dropped unless it was
+    /// signed by the node it describes.
+    fn insert_verified(&mut self, v: &NodeInfo) -> usize {
+        // `insert` ignores the versions we already have, only newer ones need checking
+        let known = self.table.get(&v.id).map_or(false, |d| v.version <= d.version);
+        if !known && !v.verify() {
+            self.drop_record(v);
+            return 0;
+        }
+        self.insert(v)
+    }
+
     fn update_liveness(&mut self, id: Pubkey) {
         //update the liveness table
         let now = timestamp();
@@ -713,19 +823,49 @@ impl Crdt {
         self.remote.values().fold(max, |a, b| std::cmp::min(a, *b))
     }
 
-    // TODO: fill in with real implmentation once staking is implemented
-    fn get_stake(_id: Pubkey) -> f64 {
-        1.0
+    pub fn set_gossip_strategy(&mut self, gossip_strategy: GossipStrategy) {
+        self.gossip_strategy = gossip_strategy;
+    }
+
+    /// Pick the gossip peers with a generator seeded by `seed`, the same seed and the same
+    /// gossip make the same picks
+    pub fn set_gossip_seed(&mut self, seed: [u8; 32]) {
+        self.gossip_rng = Mutex::new(ChaChaRng::from_seed(seed));
+    }
+
+    /// Look up the stake of every node in the table in `bank`, at most once every
+    /// `STAKES_REFRESH_MILLIS`
+    pub fn refresh_stakes(me: &RwLock<Self>, bank: &Bank) {
+        let now = timestamp();
+        let ids: Vec<_> = {
+            let me = me.read().unwrap();
+            if now < me.stakes_refreshed + STAKES_REFRESH_MILLIS {
+                return;
+            }
+            me.table.keys().cloned().collect()
+        };
+        let stakes = bank.get_stakes(&ids);
+        let mut me = me.write().unwrap();
+        me.stakes = stakes;
+        me.stakes_refreshed = now;
+    }
+
+    // nodes without a known stake count as much as the smallest stake
+    fn get_stake(&self, id: Pubkey) -> f64 {
+        self.stakes.get(&id).map_or(1.0, |stake| (*stake).max(1) as f64)
     }
 
     fn get_updates_since(&self, v: u64) -> (Pubkey, u64, Vec<NodeInfo>) {
         //trace!("get updates since {}", v);
-        let data = self
+        let mut data: Vec<_> = self
             .table
             .values()
-            .filter(|x| x.id != Pubkey::default() && self.local[&x.id] > v)
-            .cloned()
+            .filter(|x| {
+                x.id != Pubkey::default() && self.local[&x.id] > v && !self.observed.contains(&x.id)
+            }).cloned()
             .collect();
+        // in the order they were learned
+        data.sort_by_key(|x| self.local[&x.id]);
         let id = self.id;
         let ups = self.update_index;
         (id, ups, data)
@@ -766,7 +906,7 @@ impl Crdt {
     /// * A - Address to send to
     /// * B - RequestUpdates protocol message
     fn gossip_request(&self) -> Result<(SocketAddr, Protocol)> {
-        let options: Vec<_> = self
+        let mut options: Vec<_> = self
             .table
             .values()
             .filter(|v| {
@@ -774,14 +914,19 @@ impl Crdt {
                     && !v.contact_info.ncp.ip().is_unspecified()
                     && !v.contact_info.ncp.ip().is_multicast()
             }).collect();
-
-        let choose_peer_strategy = ChooseWeightedPeerStrategy::new(
-            &self.remote,
-            &self.external_liveness,
-            &Self::get_stake,
-        );
-
-        let choose_peer_result = choose_peer_strategy.choose_peer(options);
+        // the picks depend only on `gossip_rng`, not on the order of the table
+        options.sort_by_key(|v| v.id);
+
+        let random: &Fn() -> u64 = &|| self.gossip_rng.lock().unwrap().gen();
+        let choose_peer_result = match self.gossip_strategy {
+            GossipStrategy::Random => ChooseRandomPeerStrategy::new(random).choose_peer(options),
+            GossipStrategy::Weighted => ChooseWeightedPeerStrategy::new(
+                &self.remote,
+                &self.external_liveness,
+                &|id| self.get_stake(id),
+                random,
+            ).choose_peer(options),
+        };
 
         if let Err(Error::CrdtError(CrdtError::NoPeers)) = &choose_peer_result {
             trace!("crdt too small for gossip {} {}", self.id, self.table.len());
@@ -801,22 +946,25 @@ impl Crdt {
         Ok((v.contact_info.ncp, req))
     }
 
-    pub fn new_vote(&mut self, last_id: Hash) -> Result<(Vote, SocketAddr)> {
+    pub fn new_vote(&mut self, last_id: Hash) -> Result<(Vote, VoteSignature, SocketAddr)> {
         let mut me = self.my_data().clone();
         let leader = self.leader_data().ok_or(CrdtError::NoLeader)?.clone();
         me.version += 1;
         me.ledger_state.last_id = last_id;
+        self.insert(&me);
         let vote = Vote {
             version: me.version,
             contact_info_version: me.contact_info.version,
         };
-        self.insert(&me);
-        Ok((vote, leader.contact_info.tpu))
+        let signature = VoteSignature {
+            leader_id: me.leader_id,
+            signature: self.my_data().signature,
+        };
+        Ok((vote, signature, leader.contact_info.tpu))
     }
 
     /// At random pick a node and try to get updated changes from them
     fn run_gossip(obj: &Arc<RwLock<Self>>, blob_sender: &BlobSender) -> Result<()> {
-        //TODO we need to keep track of stakes and weight the selection by stake size
         //TODO cache sockets
 
         // Lock the object only to do this operation and not for any longer
@@ -874,10 +1022,10 @@ impl Crdt {
     ) {
         trace!("got updates {}", data.len());
         // TODO we need to punish/spam resist here
-        // sigverify the whole update and slash anyone who sends a bad update
+        // slash anyone who sends a bad update
         let mut insert_total = 0;
         for v in data {
-            insert_total += self.insert(&v);
+            insert_total += self.insert_verified(&v);
         }
         inc_new_counter_info!("crdt-update-count", insert_total);
 
@@ -1040,7 +1188,6 @@ impl Crdt {
         ledger_window: &mut Option<&mut LedgerWindow>,
     ) -> Option<SharedBlob> {
         match request {
-            // TODO sigverify these
             Protocol::RequestUpdates(version, mut from) => {
                 let id = me.read().unwrap().id;
 
@@ -1062,10 +1209,15 @@ impl Crdt {
                     return None;
                 }
 
+                // verify before the address is filled in below, an unsigned requester is
+                //  still answered but isn't added to the table
+                let from_verified = from.verify();
+
                 // the remote side may not know his public IP:PORT, record what he looks like to us
                 //  this may or may not be correct for everybody but it's better than leaving him with
                 //  an unspecified address in our table
-                if from.contact_info.ncp.ip().is_unspecified() {
+                let observed = from.contact_info.ncp.ip().is_unspecified();
+                if observed {
                     inc_new_counter_info!("crdt-window-request-updates-unspec-ncp", 1);
                     from.contact_info.ncp = *from_addr;
                 }
@@ -1087,8 +1239,14 @@ impl Crdt {
                 // update entry only after collecting liveness
                 {
                     let mut me = me.write().unwrap();
-                    me.insert(&from);
-                    me.update_liveness(from.id);
+                    if from_verified {
+                        if me.insert(&from) == 1 && observed {
+                            me.observed.insert(from.id);
+                        }
+                        me.update_liveness(from.id);
+                    } else {
+                        me.drop_record(&from);
+                    }
                 }
 
                 trace!("get updates since response {} {}", version, data.len());
@@ -1146,7 +1304,6 @@ impl Crdt {
 
                 //TODO this doesn't depend on CRDT module, could be moved
                 //but we are using the listen thread to service these request
-                //TODO verify from is signed
 
                 if from.id == me.read().unwrap().id {
                     warn!(
@@ -1159,7 +1316,7 @@ impl Crdt {
                     return None;
                 }
 
-                me.write().unwrap().insert(&from);
+                me.write().unwrap().insert_verified(&from);
                 let me = me.read().unwrap().my_data().clone();
                 inc_new_counter_info!("crdt-window-request-recv", 1);
                 trace!("{}: received RequestWindowIndex {} {} ", me.id, from.id, ix,);
@@ -1265,6 +1422,9 @@ pub struct Sockets {
     pub broadcast: UdpSocket,
     pub repair: UdpSocket,
     pub retransmit: UdpSocket,
+    /// the tcp listeners on the ports of `requests` and `transaction`
+    pub requests_tcp: Option<TcpListener>,
+    pub transaction_tcp: Option<TcpListener>,
 }
 
 #[derive(Debug)]
@@ -1289,6 +1449,8 @@ impl Node {
         let broadcast = UdpSocket::bind("0.0.0.0:0").unwrap();
         let retransmit = UdpSocket::bind("0.0.0.0:0").unwrap();
         let storage = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let requests_tcp = bind_tcp_alongside(&requests);
+        let transaction_tcp = bind_tcp_alongside(&transaction);
         let info = NodeInfo::new(
             pubkey,
             gossip.local_addr().unwrap(),
@@ -1308,6 +1470,8 @@ impl Node {
                 broadcast,
                 repair,
                 retransmit,
+                requests_tcp,
+                transaction_tcp,
             },
         }
     }
@@ -1340,6 +1504,9 @@ impl Node {
         // response Udp packet correctly back to the requester.
         let respond = requests.try_clone().unwrap();
 
+        let requests_tcp = bind_tcp_alongside(&requests);
+        let transaction_tcp = bind_tcp_alongside(&transaction_sockets[0]);
+
         let info = NodeInfo::new(
             pubkey,
             SocketAddr::new(ncp.ip(), gossip_port),
@@ -1361,6 +1528,8 @@ impl Node {
                 broadcast,
                 repair,
                 retransmit,
+                requests_tcp,
+                transaction_tcp,
             },
         }
     }
@@ -1375,10 +1544,14 @@ fn report_time_spent(label: &str, time: &Duration, extra: &str) {
 
 #[cfg(test)]
 mod tests {
-    use crate::budget_instruction::Vote;
+    use bincode::deserialize;
+    use crate::budget_instruction::{Vote, VoteSignature};
+    use crate::budget_transaction::BudgetTransaction;
+    use crate::choose_gossip_peer_strategy::GossipStrategy;
+    use crate::coinery::Mint;
     use crate::crdt::{
         Crdt, CrdtError, Node, NodeInfo, Protocol, FULLNODE_PORT_RANGE, GOSSIP_PURGE_MILLIS,
-        GOSSIP_SLEEP_MILLIS, MIN_TABLE_SIZE,
+        GOSSIP_SLEEP_MILLIS, MIN_TABLE_SIZE, STAKES_REFRESH_MILLIS,
     };
     use crate::entry::Entry;
     use crate::hash::{hash, Hash};
@@ -1386,8 +1559,11 @@ mod tests {
     use crate::logger;
     use crate::packet::SharedBlob;
     use crate::result::Error;
-    use crate::signature::{Keypair, KeypairUtil};
+    use crate::signature::{GenKeys, Keypair, KeypairUtil, Signature};
+    use crate::transaction::Transaction;
+    use crate::tx_vault::Bank;
     use buffett_program_interface::pubkey::Pubkey;
+    use std::collections::HashMap;
     use std::fs::remove_dir_all;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::sync::atomic::{AtomicBool, Ordering};
@@ -1395,7 +1571,14 @@ mod tests {
     use std::sync::{Arc, RwLock};
     use std::thread::sleep;
     use std::time::Duration;
-    use crate::window::default_window;
+    use crate::window::{default_window, SharedWindow};
+
+    fn new_signed_with_socketaddr(bind_addr: &SocketAddr) -> NodeInfo {
+        let keypair = Keypair::new();
+        let mut node = NodeInfo::new_with_pubkey_socketaddr(keypair.pubkey(), bind_addr);
+        node.sign(&keypair);
+        node
+    }
 
     #[test]
     fn insert_test() {
@@ -1425,9 +1608,12 @@ mod tests {
     }
     #[test]
     fn test_new_vote() {
-        let d = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let keypair = Arc::new(Keypair::new());
+        let d =
+            NodeInfo::new_with_pubkey_socketaddr(keypair.pubkey(), &socketaddr!("127.0.0.1:1234"));
         assert_eq!(d.version, 0);
-        let mut crdt = Crdt::new(d.clone()).unwrap();
+        let mut crdt = Crdt::new_with_keypair(d.clone(), keypair).unwrap();
+        assert!(crdt.my_data().verify());
         assert_eq!(crdt.table[&d.id].version, 0);
         let leader = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.2:1235"));
         assert_ne!(d.id, leader.id);
@@ -1442,32 +1628,52 @@ mod tests {
         );
         crdt.set_leader(leader.id);
         assert_eq!(crdt.table[&d.id].version, 1);
+        assert!(crdt.my_data().verify());
+        let (vote, signature, addr) = crdt.new_vote(Hash::default()).unwrap();
         let v = Vote {
             version: 2, //version should increase when we vote
             contact_info_version: 0,
         };
-        let expected = (v, crdt.table[&leader.id].contact_info.tpu);
-        assert_eq!(crdt.new_vote(Hash::default()).unwrap(), expected);
+        assert_eq!((vote, addr), (v, crdt.table[&leader.id].contact_info.tpu));
+        // the vote is signed like the new version of the node's record
+        assert!(crdt.my_data().verify());
+        assert_eq!(signature.signature, crdt.my_data().signature);
+        assert_eq!(signature.leader_id, leader.id);
     }
 
     #[test]
     fn test_insert_vote() {
-        let d = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let keypair = Keypair::new();
+        let d =
+            NodeInfo::new_with_pubkey_socketaddr(keypair.pubkey(), &socketaddr!("127.0.0.1:1234"));
         assert_eq!(d.version, 0);
         let mut crdt = Crdt::new(d.clone()).unwrap();
         assert_eq!(crdt.table[&d.id].version, 0);
+        // what the voter signs when it votes with version 1
+        let mut voted = d.clone();
+        voted.version = 1;
+        voted.sign(&keypair);
+        let signature = VoteSignature {
+            leader_id: voted.leader_id,
+            signature: voted.signature,
+        };
         let vote_same_version = Vote {
             version: d.version,
             contact_info_version: 0,
         };
-        crdt.insert_vote(&d.id, &vote_same_version, Hash::default());
+        crdt.insert_vote(&d.id, &vote_same_version, &signature, Hash::default());
         assert_eq!(crdt.table[&d.id].version, 0);
 
         let vote_new_version_new_addrs = Vote {
             version: d.version + 1,
             contact_info_version: 1,
         };
-        crdt.insert_vote(&d.id, &vote_new_version_new_addrs, Hash::default());
+        crdt.insert_vote(
+            &d.id,
+            &vote_new_version_new_addrs,
+            &signature,
+            Hash::default(),
+        );
         //should be dropped since the address is newer then we know
         assert_eq!(crdt.table[&d.id].version, 0);
 
@@ -1475,9 +1681,44 @@ mod tests {
             version: d.version + 1,
             contact_info_version: 0,
         };
-        crdt.insert_vote(&d.id, &vote_new_version_old_addrs, Hash::default());
+        crdt.insert_vote(
+            &d.id,
+            &vote_new_version_old_addrs,
+            &VoteSignature::default(),
+            Hash::default(),
+        );
+        //should be dropped since the voter didn't sign it
+        assert_eq!(crdt.table[&d.id].version, 0);
+        assert_eq!(crdt.dropped_records, 1);
+
+        crdt.insert_vote(
+            &d.id,
+            &vote_new_version_old_addrs,
+            &signature,
+            Hash::default(),
+        );
         //should be accepted, since the update is for the same address field as the one we know
         assert_eq!(crdt.table[&d.id].version, 1);
+        assert!(crdt.table[&d.id].verify());
+
+        // the voter moved to a leader the table doesn't know about yet
+        let last_id = hash(b"last_id");
+        voted.version = 2;
+        voted.leader_id = Keypair::new().pubkey();
+        voted.ledger_state.last_id = last_id;
+        voted.sign(&keypair);
+        let signature = VoteSignature {
+            leader_id: voted.leader_id,
+            signature: voted.signature,
+        };
+        let vote = Vote {
+            version: 2,
+            contact_info_version: 0,
+        };
+        crdt.insert_vote(&d.id, &vote, &signature, last_id);
+        //should be accepted, the vote carries the leader it was signed with
+        assert_eq!(crdt.table[&d.id], voted);
+        assert_eq!(crdt.dropped_records, 1);
     }
     fn sorted(ls: &Vec<NodeInfo>) -> Vec<NodeInfo> {
         let mut copy: Vec<_> = ls.iter().cloned().collect();
@@ -1499,9 +1740,9 @@ mod tests {
     }
     #[test]
     fn update_test() {
-        let d1 = NodeInfo::new_localhost(Keypair::new().pubkey());
-        let d2 = NodeInfo::new_localhost(Keypair::new().pubkey());
-        let d3 = NodeInfo::new_localhost(Keypair::new().pubkey());
+        let d1 = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let d2 = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let d3 = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
         let mut crdt = Crdt::new(d1.clone()).expect("Crdt::new");
         let (key, ix, ups) = crdt.get_updates_since(0);
         assert_eq!(key, d1.id);
@@ -1885,10 +2126,11 @@ mod tests {
         logger::setup();
         let window = Arc::new(RwLock::new(default_window()));
 
-        let node = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
-        let node_with_same_addr = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let node = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let node_with_same_addr = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
         assert_ne!(node.id, node_with_same_addr.id);
-        let node_with_diff_addr = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:4321"));
+        let node_with_diff_addr = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:4321"));
+        let unsigned_node = NodeInfo::new_with_socketaddr(&socketaddr!("127.0.0.1:4321"));
 
         let crdt = Crdt::new(node.clone()).expect("Crdt::new");
         assert_eq!(crdt.alive.len(), 0);
@@ -1910,6 +2152,9 @@ mod tests {
         let request = Protocol::RequestUpdates(1, node_with_diff_addr.clone());
         Crdt::handle_protocol(&obj, &node.contact_info.ncp, request, &window, &mut None);
 
+        let request = Protocol::RequestUpdates(1, unsigned_node.clone());
+        Crdt::handle_protocol(&obj, &node.contact_info.ncp, request, &window, &mut None);
+
         let me = obj.write().unwrap();
 
         // |node| and |node_with_same_addr| are ok to me in me.alive, should not be in me.alive, but
@@ -1918,6 +2163,268 @@ mod tests {
         assert!(me.alive.contains_key(&node_with_same_addr.id));
         // |node_with_diff_addr| should now be.
         assert!(me.alive[&node_with_diff_addr.id] > 0);
+        // and a node that didn't sign its record is dropped
+        assert!(!me.alive.contains_key(&unsigned_node.id));
+        assert!(!me.table.contains_key(&unsigned_node.id));
+        assert_eq!(me.dropped_records, 1);
+    }
+
+    #[test]
+    fn test_forged_leader_rejected() {
+        logger::setup();
+        let leader_keypair = Arc::new(Keypair::new());
+        let leader_info = NodeInfo::new_localhost(leader_keypair.pubkey());
+        let mut leader = Crdt::new_with_keypair(leader_info, leader_keypair).unwrap();
+        leader.set_leader(leader.id);
+
+        // a spy, like the one `poll_gossip_for_leader` uses
+        let mut spy = Crdt::new(NodeInfo::new_localhost(Keypair::new().pubkey())).unwrap();
+        let (from, ix, ups) = leader.get_updates_since(0);
+        spy.apply_updates(from, ix, &ups, &[]);
+        spy.update_leader();
+        assert_eq!(spy.leader_data(), Some(leader.my_data()));
+
+        // an attacker advertises newer addresses for the leader
+        let attacker = Keypair::new();
+        let attacker_addr = socketaddr!("127.0.0.9:1234");
+        let mut forged = leader.my_data().clone();
+        forged.version += 1;
+        forged.contact_info.rpu = attacker_addr;
+        forged.contact_info.tpu = attacker_addr;
+        forged.sign(&attacker);
+        let mut unsigned = forged.clone();
+        unsigned.signature = Signature::default();
+        spy.apply_updates(attacker.pubkey(), 1, &[forged, unsigned], &[]);
+        assert_eq!(spy.dropped_records, 2);
+        assert_eq!(spy.leader_data(), Some(leader.my_data()));
+
+        // or claims to be the leader of a node
+        let mut forged = spy.table[&leader.id].clone();
+        forged.version += 1;
+        forged.leader_id = attacker.pubkey();
+        forged.sign(&attacker);
+        spy.apply_updates(attacker.pubkey(), 2, &[forged], &[]);
+        assert_eq!(spy.dropped_records, 3);
+        assert_eq!(spy.top_leader(), Some(leader.id));
+
+        // while the leader's own updates are still accepted
+        leader.set_leader(leader.id);
+        let (from, ix, ups) = leader.get_updates_since(0);
+        spy.apply_updates(from, ix, &ups, &[]);
+        assert_eq!(spy.table[&leader.id].version, 2);
+        assert_eq!(spy.dropped_records, 3);
+    }
+
+    #[test]
+    fn test_observed_address_not_gossiped() {
+        logger::setup();
+        let window = Arc::new(RwLock::new(default_window()));
+        let node = new_signed_with_socketaddr(&socketaddr!("127.0.0.1:1234"));
+        let obj = Arc::new(RwLock::new(Crdt::new(node).expect("Crdt::new")));
+
+        // a node that doesn't know its address asks for updates
+        let unspecified = new_signed_with_socketaddr(&socketaddr_any!());
+        let from_addr = socketaddr!("127.0.0.2:1234");
+        let request = Protocol::RequestUpdates(0, unspecified.clone());
+        Crdt::handle_protocol(&obj, &from_addr, request, &window, &mut None);
+        let crdt = obj.read().unwrap();
+        assert_eq!(crdt.table[&unspecified.id].contact_info.ncp, from_addr);
+
+        // a third node isn't sent the record that no longer matches its signature
+        let mut third = Crdt::new(new_signed_with_socketaddr(&socketaddr!("127.0.0.3:1234")))
+            .expect("Crdt::new");
+        let (from, ix, ups) = crdt.get_updates_since(0);
+        third.apply_updates(from, ix, &ups, &[]);
+        assert_eq!(third.dropped_records, 0);
+        assert!(third.table.contains_key(&crdt.id));
+        assert!(!third.table.contains_key(&unspecified.id));
+
+        // but gets the signed record from the node itself
+        third.apply_updates(unspecified.id, 1, &[unspecified.clone()], &[]);
+        assert_eq!(third.dropped_records, 0);
+        assert_eq!(third.table[&unspecified.id], unspecified);
+    }
+
+    // every node sends one gossip request, delivered in process to the peer it picked
+    fn gossip_round(
+        nodes: &[Arc<RwLock<Crdt>>],
+        addrs: &HashMap<SocketAddr, usize>,
+        window: &SharedWindow,
+    ) {
+        for node in nodes {
+            let (peer_addr, req) = node.read().unwrap().gossip_request().unwrap();
+            let addr = node.read().unwrap().my_data().contact_info.ncp;
+            let peer = &nodes[addrs[&peer_addr]];
+            if let Some(rsp) = Crdt::handle_protocol(peer, &addr, req, window, &mut None) {
+                let rsp = rsp.read().unwrap();
+                let rsp = deserialize(&rsp.data[..rsp.meta.size]).unwrap();
+                assert!(Crdt::handle_protocol(node, &peer_addr, rsp, window, &mut None).is_none());
+            }
+        }
+    }
+
+    // number of (node, record) pairs where the node doesn't have the latest version
+    fn stale_records(nodes: &[Arc<RwLock<Crdt>>]) -> usize {
+        let latest: Vec<_> = nodes
+            .iter()
+            .map(|node| node.read().unwrap().my_data().clone())
+            .collect();
+        nodes
+            .iter()
+            .map(|node| {
+                let node = node.read().unwrap();
+                latest
+                    .iter()
+                    .filter(|v| node.table.get(&v.id).map_or(true, |d| d.version < v.version))
+                    .count()
+            }).sum()
+    }
+
+    // Rounds of gossip a network of `num_nodes` takes to learn the votes of some of its
+    // validators. The votes reach the leader directly, as they do through the ledger, and
+    // the leader holds as much stake as all the validators together. The keys and the
+    // picks of the peers are seeded, every run takes the same number of rounds.
+    fn rounds_to_converge(num_nodes: usize, gossip_strategy: GossipStrategy) -> usize {
+        let num_voters = num_nodes / 10;
+        let keypairs: Vec<_> = GenKeys::new([1; 32])
+            .gen_n_keypairs(num_nodes as i64)
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        let leader_id = keypairs[0].pubkey();
+
+        // the stakes come from the bank, every node funded and voted once
+        let mint = Mint::new(20 * num_nodes as i64);
+        let bank = Bank::new(&mint);
+        for (i, keypair) in keypairs.iter().enumerate() {
+            let tokens = if i == 0 { 10 * num_nodes as i64 } else { 10 };
+            bank.transfer(tokens, &mint.keypair(), keypair.pubkey(), mint.last_id())
+                .unwrap();
+            let vote = Vote {
+                version: 1,
+                contact_info_version: 0,
+            };
+            let tx = Transaction::budget_new_vote(
+                keypair,
+                vote,
+                VoteSignature::default(),
+                mint.last_id(),
+                0,
+            );
+            bank.process_transaction(&tx).unwrap();
+        }
+
+        let mut crdts: Vec<_> = keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, keypair)| {
+                let addr = socketaddr!([127, 0, 0, 1], 10_000 + 10 * i as u16);
+                let node_info = NodeInfo::new_with_pubkey_socketaddr(keypair.pubkey(), &addr);
+                let mut crdt = Crdt::new_with_keypair(node_info, keypair.clone()).unwrap();
+                crdt.set_leader(leader_id);
+                crdt.set_gossip_strategy(gossip_strategy);
+                crdt.set_gossip_seed([i as u8; 32]);
+                crdt
+            }).collect();
+
+        // start from a network where everybody knows everybody
+        let records: Vec<_> = crdts.iter().map(|crdt| crdt.my_data().clone()).collect();
+        for crdt in &mut crdts {
+            for record in &records {
+                crdt.insert(record);
+            }
+        }
+        let addrs: HashMap<_, _> = records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| (record.contact_info.ncp, i))
+            .collect();
+        let nodes: Vec<_> = crdts
+            .into_iter()
+            .map(|crdt| Arc::new(RwLock::new(crdt)))
+            .collect();
+        let window = Arc::new(RwLock::new(default_window()));
+        for node in &nodes {
+            Crdt::refresh_stakes(node, &bank);
+        }
+        assert_eq!(
+            nodes[1].read().unwrap().get_stake(leader_id),
+            10.0 * num_nodes as f64
+        );
+
+        let vote_round = |round: usize| {
+            let last_id = Hash::new(&[round as u8; 32]);
+            for v in 0..num_voters {
+                let voter = 1 + (round * num_voters + v) % (num_nodes - 1);
+                let (vote, signature, _) =
+                    nodes[voter].write().unwrap().new_vote(last_id).unwrap();
+                let voter_id = keypairs[voter].pubkey();
+                nodes[0]
+                    .write()
+                    .unwrap()
+                    .insert_votes(&[(voter_id, vote, signature, last_id)]);
+            }
+            gossip_round(&nodes, &addrs, &window);
+        };
+        // the remote indexes and rumors the weights are based on come from earlier rounds
+        for round in 0..5 {
+            vote_round(round);
+        }
+
+        let mut rounds = 0;
+        while stale_records(&nodes) > 0 {
+            assert!(rounds < 50, "gossip didn't converge");
+            gossip_round(&nodes, &addrs, &window);
+            rounds += 1;
+        }
+        rounds
+    }
+
+    #[test]
+    fn test_stake_weighted_gossip_convergence() {
+        logger::setup();
+        let random = rounds_to_converge(100, GossipStrategy::Random);
+        let weighted = rounds_to_converge(100, GossipStrategy::Weighted);
+        info!(
+            "rounds to converge, random: {} weighted: {}",
+            random, weighted
+        );
+        assert!(weighted < random);
+    }
+
+    #[test]
+    fn test_refresh_stakes() {
+        let mint = Mint::new(1_000);
+        let bank = Bank::new(&mint);
+        let keypair = Arc::new(Keypair::new());
+        let node_info = NodeInfo::new_localhost(keypair.pubkey());
+        let crdt = RwLock::new(Crdt::new_with_keypair(node_info, keypair.clone()).unwrap());
+        bank.transfer(500, &mint.keypair(), keypair.pubkey(), mint.last_id())
+            .unwrap();
+
+        // no stake until the node votes
+        Crdt::refresh_stakes(&crdt, &bank);
+        assert_eq!(crdt.read().unwrap().stakes[&keypair.pubkey()], 0);
+
+        let vote = Vote {
+            version: 1,
+            contact_info_version: 0,
+        };
+        let tx = Transaction::budget_new_vote(
+            &keypair,
+            vote,
+            VoteSignature::default(),
+            mint.last_id(),
+            0,
+        );
+        bank.process_transaction(&tx).unwrap();
+
+        // the stakes aren't looked up again until STAKES_REFRESH_MILLIS passed
+        Crdt::refresh_stakes(&crdt, &bank);
+        assert_eq!(crdt.read().unwrap().stakes[&keypair.pubkey()], 0);
+        crdt.write().unwrap().stakes_refreshed -= STAKES_REFRESH_MILLIS;
+        Crdt::refresh_stakes(&crdt, &bank);
+        assert_eq!(crdt.read().unwrap().stakes[&keypair.pubkey()], 500);
     }
 
     #[test]
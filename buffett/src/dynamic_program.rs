@@ -1,15 +1,20 @@
 extern crate bincode;
 extern crate generic_array;
 
+use crate::bpf_vm::{self, BpfError};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use libc;
 use libloading;
 use buffett_program_interface::account::KeyedAccount;
+use std::io;
+use std::path::Path;
+#[cfg(test)]
 use std::path::PathBuf;
 
-#[cfg(debug_assertions)]
+#[cfg(all(test, debug_assertions))]
 const CARGO_PROFILE: &str = "debug";
 
-#[cfg(not(debug_assertions))]
+#[cfg(all(test, not(debug_assertions)))]
 const CARGO_PROFILE: &str = "release";
 
 /// Dynamic link library prefix
@@ -28,73 +33,136 @@ const PLATFORM_FILE_EXTENSION: &str = "so";
 #[cfg(windows)]
 const PLATFORM_FILE_EXTENSION: &str = "dll";
 
+/// The platform-specific file name of the library of the native program `name`
+pub fn library_file_name(name: &str) -> String {
+    format!("{}{}.{}", PLATFORM_FILE_PREFIX, name, PLATFORM_FILE_EXTENSION)
+}
+
+/// The directory cargo builds the native programs into
+#[cfg(test)]
+pub fn target_program_dir() -> PathBuf {
+    ["target", CARGO_PROFILE, "deps"].iter().collect()
+}
+
 /// Creates a platform-specific file path
+#[cfg(test)]
 fn create_library_path(name: &str) -> PathBuf {
-    let mut path = PathBuf::new();
-    path.push("target");
-    path.push(CARGO_PROFILE);
-    path.push("deps");
-    path.push(PLATFORM_FILE_PREFIX.to_string() + name);
-    path.set_extension(PLATFORM_FILE_EXTENSION);
-    path
+    target_program_dir().join(library_file_name(name))
 }
 
 // All programs export a symbol named process()
 const ENTRYPOINT: &str = "process";
 type Entrypoint = unsafe extern "C" fn(infos: &mut Vec<KeyedAccount>, data: &[u8]);
 
+#[derive(Debug, PartialEq)]
+pub enum ProgramError {
+    /// The native program doesn't export `ENTRYPOINT`
+    MissingEntrypoint,
+    /// The BPF program faulted
+    Bpf(BpfError),
+    /// The BPF program exited with a non-zero status
+    Failed(u64),
+}
+
 #[derive(Debug)]
 pub enum DynamicProgram {
-    /// Native program
+    /// Native program, loaded from a library installed in the program directory, see
+    /// `ProgramRegistry`
     /// * Transaction::keys[0..] - program dependent
-    /// * name - name of the program, translated to a file path of the program module
+    /// * name - name of the program
     /// * userdata - program specific user data
     Native {
         name: String,
         library: libloading::Library,
     },
-    /// Bpf program
+    /// Bpf program, run by `bpf_vm` over the accounts and userdata serialized by
+    /// `serialize_parameters`
     /// * Transaction::keys[0..] - program dependent
-    /// * TODO BPF specific stuff
+    /// * prog - the program's instructions
     /// * userdata - program specific user data
-    Bpf { userdata: Vec<u8> },
+    Bpf { prog: Vec<u8> },
 }
 
 impl DynamicProgram {
-    pub fn new(name: String) -> Self {
-        // TODO determine what kind of module to load
-
-        // create native program
-        let path = create_library_path(&name);
+    /// Load the native program in the library at `path`.
+    pub fn new_native(name: String, path: &Path) -> io::Result<Self> {
         // TODO linux tls bug can cause crash on dlclose, workaround by never unloading
         let os_lib =
-            libloading::os::unix::Library::open(Some(path), libc::RTLD_NODELETE | libc::RTLD_NOW)
-                .unwrap();
+            libloading::os::unix::Library::open(Some(path), libc::RTLD_NODELETE | libc::RTLD_NOW)?;
         let library = libloading::Library::from(os_lib);
-        DynamicProgram::Native { name, library }
+        Ok(DynamicProgram::Native { name, library })
+    }
+
+    pub fn new_bpf(prog: Vec<u8>) -> Self {
+        DynamicProgram::Bpf { prog }
     }
 
-    pub fn call(&self, infos: &mut Vec<KeyedAccount>, data: &[u8]) {
+    pub fn call(&self, infos: &mut Vec<KeyedAccount>, data: &[u8]) -> Result<(), ProgramError> {
         match self {
             DynamicProgram::Native { name, library } => unsafe {
                 let entrypoint: libloading::Symbol<Entrypoint> =
                     match library.get(ENTRYPOINT.as_bytes()) {
                         Ok(s) => s,
-                        Err(e) => panic!(
-                            "{:?} Unable to find {:?} in program {}",
-                            e, ENTRYPOINT, name
-                        ),
+                        Err(e) => {
+                            warn!("{:?} Unable to find {:?} in program {}", e, ENTRYPOINT, name);
+                            return Err(ProgramError::MissingEntrypoint);
+                        }
                     };
                 entrypoint(infos, data);
+                Ok(())
             },
-            DynamicProgram::Bpf { .. } => {
-                // TODO BPF
-                println!{"Bpf program not supported"}
+            DynamicProgram::Bpf { prog } => {
+                let mut input = serialize_parameters(infos, data);
+                match bpf_vm::execute(prog, &mut input) {
+                    Ok(0) => {
+                        deserialize_parameters(infos, &input);
+                        Ok(())
+                    }
+                    Ok(status) => Err(ProgramError::Failed(status)),
+                    Err(err) => Err(ProgramError::Bpf(err)),
+                }
             }
         }
     }
 }
 
+/// The input of a BPF program, all little endian:
+/// * u64 - the number of accounts, then for each one
+///   * the key
+///   * i64 - tokens
+///   * u64 - the length of its userdata, and the userdata
+///   * the program id
+/// * u64 - the length of the transaction's userdata, and the userdata
+pub fn serialize_parameters(infos: &[KeyedAccount], data: &[u8]) -> Vec<u8> {
+    let mut v: Vec<u8> = Vec::new();
+    v.write_u64::<LittleEndian>(infos.len() as u64).unwrap();
+    for info in infos {
+        v.extend_from_slice(info.key.as_ref());
+        v.write_i64::<LittleEndian>(info.account.tokens).unwrap();
+        v.write_u64::<LittleEndian>(info.account.userdata.len() as u64)
+            .unwrap();
+        v.extend_from_slice(&info.account.userdata);
+        v.extend_from_slice(info.account.program_id.as_ref());
+    }
+    v.write_u64::<LittleEndian>(data.len() as u64).unwrap();
+    v.extend_from_slice(data);
+    v
+}
+
+/// Read back the tokens and the userdata a BPF program may have changed, its
+/// keys, lengths and program ids are left as they were.
+pub fn deserialize_parameters(infos: &mut [KeyedAccount], input: &[u8]) {
+    let mut start = 8;
+    for info in infos.iter_mut() {
+        start += info.key.as_ref().len();
+        info.account.tokens = LittleEndian::read_i64(&input[start..]);
+        start += 8 + 8;
+        let end = start + info.account.userdata.len();
+        info.account.userdata.clone_from_slice(&input[start..end]);
+        start = end + info.account.program_id.as_ref().len();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,6 +172,62 @@ mod tests {
     use std::path::Path;
     use std::thread;
 
+    fn new_test_native(name: &str) -> DynamicProgram {
+        DynamicProgram::new_native(name.to_string(), &create_library_path(name)).unwrap()
+    }
+
+    #[test]
+    fn test_program_bpf_move_tokens() {
+        // ldxdw r2, [r1+40]; sub64 r2, 1; stxdw [r1+40], r2
+        // ldxdw r3, [r1+121]; add64 r3, 1; stxdw [r1+121], r3
+        // stb [r1+56], 7; mov64 r0, 0; exit
+        // the first account has a byte of userdata, the second none
+        let prog = vec![
+            0x79, 0x12, 40, 0, 0, 0, 0, 0, //
+            0x17, 0x02, 0, 0, 1, 0, 0, 0, //
+            0x7b, 0x21, 40, 0, 0, 0, 0, 0, //
+            0x79, 0x13, 121, 0, 0, 0, 0, 0, //
+            0x07, 0x03, 0, 0, 1, 0, 0, 0, //
+            0x7b, 0x31, 121, 0, 0, 0, 0, 0, //
+            0x72, 0x01, 56, 0, 7, 0, 0, 0, //
+            0xb7, 0x00, 0, 0, 0, 0, 0, 0, //
+            0x95, 0x00, 0, 0, 0, 0, 0, 0, //
+        ];
+        let keys = vec![Pubkey::default(); 2];
+        let mut accounts = vec![Account::new(100, 1, Pubkey::default()), Account::default()];
+        accounts[1].tokens = 1;
+        {
+            let mut infos: Vec<_> = (&keys)
+                .into_iter()
+                .zip(&mut accounts)
+                .map(|(key, account)| KeyedAccount { key, account })
+                .collect();
+
+            let dp = DynamicProgram::new_bpf(prog.clone());
+            dp.call(&mut infos, &[]).unwrap();
+        }
+        assert_eq!(accounts[0].tokens, 99);
+        assert_eq!(accounts[0].userdata, vec![7]);
+        assert_eq!(accounts[1].tokens, 2);
+
+        // a faulting program leaves the accounts alone
+        let prog = prog[..prog.len() - 8].to_vec();
+        {
+            let mut infos: Vec<_> = (&keys)
+                .into_iter()
+                .zip(&mut accounts)
+                .map(|(key, account)| KeyedAccount { key, account })
+                .collect();
+
+            let dp = DynamicProgram::new_bpf(prog);
+            assert_matches!(
+                dp.call(&mut infos, &[]),
+                Err(ProgramError::Bpf(BpfError::JumpOutOfBounds { .. }))
+            );
+        }
+        assert_eq!(accounts[0].tokens, 99);
+    }
+
     #[test]
     fn test_create_library_path() {
         let path = create_library_path("noop");
@@ -129,8 +253,8 @@ mod tests {
                 .map(|(key, account)| KeyedAccount { key, account })
                 .collect();
 
-            let dp = DynamicProgram::new("noop".to_string());
-            dp.call(&mut infos, &data);
+            let dp = new_test_native("noop");
+            dp.call(&mut infos, &data).unwrap();
         }
     }
 
@@ -150,8 +274,8 @@ mod tests {
                 .map(|(key, account)| KeyedAccount { key, account })
                 .collect();
 
-            let dp = DynamicProgram::new("print".to_string());
-            dp.call(&mut infos, &data);
+            let dp = new_test_native("print");
+            dp.call(&mut infos, &data).unwrap();
         }
     }
 
@@ -171,8 +295,8 @@ mod tests {
                 .map(|(key, account)| KeyedAccount { key, account })
                 .collect();
 
-            let dp = DynamicProgram::new("move_funds".to_string());
-            dp.call(&mut infos, &data);
+            let dp = new_test_native("move_funds");
+            dp.call(&mut infos, &data).unwrap();
         }
         assert_eq!(0, accounts[0].tokens);
         assert_eq!(101, accounts[1].tokens);
@@ -194,8 +318,8 @@ mod tests {
                 .map(|(key, account)| KeyedAccount { key, account })
                 .collect();
 
-            let dp = DynamicProgram::new("move_funds".to_string());
-            dp.call(&mut infos, &data);
+            let dp = new_test_native("move_funds");
+            dp.call(&mut infos, &data).unwrap();
         }
         assert_eq!(10, accounts[0].tokens);
         assert_eq!(1, accounts[1].tokens);
@@ -224,8 +348,8 @@ mod tests {
                                 .map(|(key, account)| KeyedAccount { key, account })
                                 .collect();
 
-                            let dp = DynamicProgram::new("move_funds".to_string());
-                            dp.call(&mut infos, &data);
+                            let dp = new_test_native("move_funds");
+                            dp.call(&mut infos, &data).unwrap();
                         }
                         assert_eq!(0, accounts[0].tokens);
                         assert_eq!(101, accounts[1].tokens);
@@ -0,0 +1,891 @@
+//! The `ed25519` module implements batched verification of ed25519 signatures
+//! on the CPU. A batch is checked with a single cofactored multi-scalar
+//! multiplication, and a failed batch doesn't say which of its signatures is bad.
+
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha512};
+
+const LOW_51_BIT_MASK: u64 = (1 << 51) - 1;
+
+/// An element of the field of integers modulo 2^255 - 19, held in five 51-bit limbs.
+#[derive(Clone, Copy, Debug)]
+struct FieldElement([u64; 5]);
+
+/// The curve constant d = -121665/121666.
+const EDWARDS_D: FieldElement = FieldElement([
+    929_955_233_495_203,
+    466_365_720_129_213,
+    1_662_059_464_998_953,
+    2_033_849_074_728_123,
+    1_442_794_654_840_575,
+]);
+
+/// 2 * d.
+const EDWARDS_D2: FieldElement = FieldElement([
+    1_859_910_466_990_425,
+    932_731_440_258_426,
+    1_072_319_116_312_658,
+    1_815_898_335_770_999,
+    633_789_495_995_903,
+]);
+
+/// A square root of -1.
+const SQRT_M1: FieldElement = FieldElement([
+    1_718_705_420_411_056,
+    234_908_883_556_509,
+    2_233_514_472_574_048,
+    2_117_202_627_021_982,
+    765_476_049_583_133,
+]);
+
+/// The compressed encoding of the ed25519 base point.
+const BASEPOINT_BYTES: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+/// The order of the base point, l = 2^252 + 27742317777372353535851937790883648493.
+const L: Scalar = Scalar([
+    0x5812_631a_5cf5_d3ed,
+    0x14de_f9de_a2f7_9cd6,
+    0x0000_0000_0000_0000,
+    0x1000_0000_0000_0000,
+]);
+
+impl FieldElement {
+    fn zero() -> Self {
+        FieldElement([0, 0, 0, 0, 0])
+    }
+
+    fn one() -> Self {
+        FieldElement([1, 0, 0, 0, 0])
+    }
+
+    /// Read the low 255 bits of a little-endian encoding, the top bit is ignored.
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load8 = |start: usize| {
+            let mut word = 0u64;
+            for (i, byte) in bytes[start..start + 8].iter().enumerate() {
+                word |= u64::from(*byte) << (8 * i);
+            }
+            word
+        };
+        FieldElement([
+            load8(0) & LOW_51_BIT_MASK,
+            (load8(6) >> 3) & LOW_51_BIT_MASK,
+            (load8(12) >> 6) & LOW_51_BIT_MASK,
+            (load8(19) >> 1) & LOW_51_BIT_MASK,
+            (load8(24) >> 12) & LOW_51_BIT_MASK,
+        ])
+    }
+
+    /// The canonical little-endian encoding, fully reduced below 2^255 - 19.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut limbs = Self::carry(self.0).0;
+
+        // q is 1 if the value is at least 2^255 - 19, adding 19 * q and dropping
+        // bit 255 subtracts the modulus
+        let mut q = (limbs[0] + 19) >> 51;
+        q = (limbs[1] + q) >> 51;
+        q = (limbs[2] + q) >> 51;
+        q = (limbs[3] + q) >> 51;
+        q = (limbs[4] + q) >> 51;
+        limbs[0] += 19 * q;
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= LOW_51_BIT_MASK;
+        }
+        limbs[4] &= LOW_51_BIT_MASK;
+
+        let mut bytes = [0u8; 32];
+        let mut acc = 0u128;
+        let mut acc_bits = 0;
+        let mut pos = 0;
+        for limb in &limbs {
+            acc |= u128::from(*limb) << acc_bits;
+            acc_bits += 51;
+            while acc_bits >= 8 {
+                bytes[pos] = acc as u8;
+                acc >>= 8;
+                acc_bits -= 8;
+                pos += 1;
+            }
+        }
+        bytes[pos] = acc as u8;
+        bytes
+    }
+
+    /// Carry the limbs back below 2^52, folding the overflow of the top limb into
+    /// the bottom one.
+    fn carry(mut limbs: [u64; 5]) -> Self {
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= LOW_51_BIT_MASK;
+        }
+        limbs[0] += 19 * (limbs[4] >> 51);
+        limbs[4] &= LOW_51_BIT_MASK;
+        FieldElement(limbs)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let (a, b) = (&self.0, &other.0);
+        Self::carry([
+            a[0] + b[0],
+            a[1] + b[1],
+            a[2] + b[2],
+            a[3] + b[3],
+            a[4] + b[4],
+        ])
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        // add 16 * (2^255 - 19) first so that no limb underflows
+        let (a, b) = (&self.0, &other.0);
+        Self::carry([
+            (a[0] + 36_028_797_018_963_664) - b[0],
+            (a[1] + 36_028_797_018_963_952) - b[1],
+            (a[2] + 36_028_797_018_963_952) - b[2],
+            (a[3] + 36_028_797_018_963_952) - b[3],
+            (a[4] + 36_028_797_018_963_952) - b[4],
+        ])
+    }
+
+    fn neg(&self) -> Self {
+        Self::zero().sub(self)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let m = |x: u64, y: u64| u128::from(x) * u128::from(y);
+        let (a, b) = (&self.0, &other.0);
+        let b1_19 = b[1] * 19;
+        let b2_19 = b[2] * 19;
+        let b3_19 = b[3] * 19;
+        let b4_19 = b[4] * 19;
+
+        let c0 = m(a[0], b[0]) + m(a[4], b1_19) + m(a[3], b2_19) + m(a[2], b3_19) + m(a[1], b4_19);
+        let mut c1 =
+            m(a[1], b[0]) + m(a[0], b[1]) + m(a[4], b2_19) + m(a[3], b3_19) + m(a[2], b4_19);
+        let mut c2 =
+            m(a[2], b[0]) + m(a[1], b[1]) + m(a[0], b[2]) + m(a[4], b3_19) + m(a[3], b4_19);
+        let mut c3 = m(a[3], b[0]) + m(a[2], b[1]) + m(a[1], b[2]) + m(a[0], b[3]) + m(a[4], b4_19);
+        let mut c4 = m(a[4], b[0]) + m(a[3], b[1]) + m(a[2], b[2]) + m(a[1], b[3]) + m(a[0], b[4]);
+
+        c1 += c0 >> 51;
+        c2 += c1 >> 51;
+        c3 += c2 >> 51;
+        c4 += c3 >> 51;
+        let mut limbs = [
+            (c0 as u64) & LOW_51_BIT_MASK,
+            (c1 as u64) & LOW_51_BIT_MASK,
+            (c2 as u64) & LOW_51_BIT_MASK,
+            (c3 as u64) & LOW_51_BIT_MASK,
+            (c4 as u64) & LOW_51_BIT_MASK,
+        ];
+        limbs[0] += ((c4 >> 51) as u64) * 19;
+        limbs[1] += limbs[0] >> 51;
+        limbs[0] &= LOW_51_BIT_MASK;
+        FieldElement(limbs)
+    }
+
+    fn square(&self) -> Self {
+        self.mul(self)
+    }
+
+    /// Square k times.
+    fn pow2k(&self, k: u32) -> Self {
+        let mut result = *self;
+        for _ in 0..k {
+            result = result.square();
+        }
+        result
+    }
+
+    /// Returns (self^(2^250 - 1), self^11), the shared start of the inversion and
+    /// square root exponentiations.
+    fn pow22501(&self) -> (Self, Self) {
+        let t0 = self.square();
+        let t1 = t0.pow2k(2);
+        let t2 = self.mul(&t1);
+        let t3 = t0.mul(&t2);
+        let t4 = t3.square();
+        let t5 = t2.mul(&t4);
+        let t7 = t5.pow2k(5).mul(&t5);
+        let t9 = t7.pow2k(10).mul(&t7);
+        let t11 = t9.pow2k(20).mul(&t9);
+        let t13 = t11.pow2k(10).mul(&t7);
+        let t15 = t13.pow2k(50).mul(&t13);
+        let t17 = t15.pow2k(100).mul(&t15);
+        let t19 = t17.pow2k(50).mul(&t13);
+        (t19, t3)
+    }
+
+    /// self^(p - 2), the inverse of a nonzero element.
+    #[cfg(test)]
+    fn invert(&self) -> Self {
+        let (t19, t3) = self.pow22501();
+        t19.pow2k(5).mul(&t3)
+    }
+
+    /// self^((p - 5) / 8).
+    fn pow_p58(&self) -> Self {
+        let (t19, _) = self.pow22501();
+        self.mul(&t19.pow2k(2))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.to_bytes() == [0u8; 32]
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+/// A point on the curve in extended twisted Edwards coordinates, x = X/Z, y = Y/Z
+/// and x * y = T/Z.
+#[derive(Clone, Copy, Debug)]
+struct EdwardsPoint {
+    x: FieldElement,
+    y: FieldElement,
+    z: FieldElement,
+    t: FieldElement,
+}
+
+/// A point prepared for repeated addition.
+#[derive(Clone, Copy, Debug)]
+struct CachedPoint {
+    y_plus_x: FieldElement,
+    y_minus_x: FieldElement,
+    z2: FieldElement,
+    t2d: FieldElement,
+}
+
+impl CachedPoint {
+    fn neg(&self) -> Self {
+        CachedPoint {
+            y_plus_x: self.y_minus_x,
+            y_minus_x: self.y_plus_x,
+            z2: self.z2,
+            t2d: self.t2d.neg(),
+        }
+    }
+}
+
+impl EdwardsPoint {
+    fn identity() -> Self {
+        EdwardsPoint {
+            x: FieldElement::zero(),
+            y: FieldElement::one(),
+            z: FieldElement::one(),
+            t: FieldElement::zero(),
+        }
+    }
+
+    /// Decode a point, rejecting non-canonical y coordinates and encodings that
+    /// aren't on the curve.
+    fn decompress(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 32 {
+            return None;
+        }
+        let mut encoding = [0u8; 32];
+        encoding.copy_from_slice(bytes);
+        let sign = encoding[31] >> 7 == 1;
+        let y = FieldElement::from_bytes(&encoding);
+        encoding[31] &= 0x7f;
+        if y.to_bytes() != encoding {
+            return None;
+        }
+
+        // x^2 = (y^2 - 1) / (d * y^2 + 1)
+        let yy = y.square();
+        let u = yy.sub(&FieldElement::one());
+        let v = EDWARDS_D.mul(&yy).add(&FieldElement::one());
+        let v3 = v.square().mul(&v);
+        let v7 = v3.square().mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow_p58());
+        let vxx = v.mul(&x.square());
+        if !vxx.equals(&u) {
+            if !vxx.equals(&u.neg()) {
+                return None;
+            }
+            x = x.mul(&SQRT_M1);
+        }
+        if sign && x.is_zero() {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(EdwardsPoint {
+            x,
+            y,
+            z: FieldElement::one(),
+            t: x.mul(&y),
+        })
+    }
+
+    #[cfg(test)]
+    fn compress(&self) -> [u8; 32] {
+        let recip = self.z.invert();
+        let x = self.x.mul(&recip);
+        let mut bytes = self.y.mul(&recip).to_bytes();
+        bytes[31] ^= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    fn to_cached(self) -> CachedPoint {
+        CachedPoint {
+            y_plus_x: self.y.add(&self.x),
+            y_minus_x: self.y.sub(&self.x),
+            z2: self.z.add(&self.z),
+            t2d: self.t.mul(&EDWARDS_D2),
+        }
+    }
+
+    fn add(&self, other: &CachedPoint) -> Self {
+        let a = self.y.sub(&self.x).mul(&other.y_minus_x);
+        let b = self.y.add(&self.x).mul(&other.y_plus_x);
+        let c = self.t.mul(&other.t2d);
+        let d = self.z.mul(&other.z2);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        EdwardsPoint {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    fn double(&self) -> Self {
+        let a = self.x.square();
+        let b = self.y.square();
+        let zz = self.z.square();
+        let c = zz.add(&zz);
+        let e = self.x.add(&self.y).square().sub(&a).sub(&b);
+        let g = b.sub(&a);
+        let f = g.sub(&c);
+        let h = a.add(&b).neg();
+        EdwardsPoint {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    fn neg(&self) -> Self {
+        EdwardsPoint {
+            x: self.x.neg(),
+            y: self.y,
+            z: self.z,
+            t: self.t.neg(),
+        }
+    }
+
+    fn mul_by_pow2(&self, k: u32) -> Self {
+        let mut result = *self;
+        for _ in 0..k {
+            result = result.double();
+        }
+        result
+    }
+
+    fn is_identity(&self) -> bool {
+        self.x.is_zero() && self.y.equals(&self.z)
+    }
+
+    /// Whether the point is one of the eight points of order dividing 8.
+    fn is_small_order(&self) -> bool {
+        self.mul_by_pow2(3).is_identity()
+    }
+}
+
+/// An integer modulo l in four little-endian 64-bit limbs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Scalar([u64; 4]);
+
+impl Scalar {
+    fn from_u128(value: u128) -> Self {
+        Scalar([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    /// Decode a scalar, rejecting encodings that aren't reduced modulo l.
+    fn from_canonical_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut limbs = [0u64; 4];
+        for (i, byte) in bytes.iter().enumerate() {
+            limbs[i / 8] |= u64::from(*byte) << (8 * (i % 8));
+        }
+        let scalar = Scalar(limbs);
+        if scalar.less_than(&L) {
+            Some(scalar)
+        } else {
+            None
+        }
+    }
+
+    /// Reduce a 512-bit little-endian value modulo l.
+    fn from_wide(limbs: &[u64; 8]) -> Self {
+        let mut r = Scalar([0; 4]);
+        for bit in (0..512).rev() {
+            // r < l < 2^253, so doubling it and shifting in a bit can't overflow
+            r.0[3] = (r.0[3] << 1) | (r.0[2] >> 63);
+            r.0[2] = (r.0[2] << 1) | (r.0[1] >> 63);
+            r.0[1] = (r.0[1] << 1) | (r.0[0] >> 63);
+            r.0[0] = (r.0[0] << 1) | ((limbs[bit / 64] >> (bit % 64)) & 1);
+            if !r.less_than(&L) {
+                r = r.sub_l();
+            }
+        }
+        r
+    }
+
+    fn from_hash(hash: &[u8]) -> Self {
+        let mut limbs = [0u64; 8];
+        for (i, byte) in hash.iter().enumerate() {
+            limbs[i / 8] |= u64::from(*byte) << (8 * (i % 8));
+        }
+        Self::from_wide(&limbs)
+    }
+
+    #[cfg(test)]
+    fn to_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (self.0[i / 8] >> (8 * (i % 8))) as u8;
+        }
+        bytes
+    }
+
+    fn less_than(&self, other: &Self) -> bool {
+        for i in (0..4).rev() {
+            if self.0[i] != other.0[i] {
+                return self.0[i] < other.0[i];
+            }
+        }
+        false
+    }
+
+    fn sub_l(&self) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for ((limb, a), b) in limbs.iter_mut().zip(&self.0).zip(&L.0) {
+            let (diff, b0) = a.overflowing_sub(*b);
+            let (diff, b1) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b0 || b1;
+        }
+        Scalar(limbs)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        // both are below l < 2^253, so the sum can't overflow the top limb
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for ((limb, a), b) in limbs.iter_mut().zip(&self.0).zip(&other.0) {
+            let (sum, c0) = a.overflowing_add(*b);
+            let (sum, c1) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c0 || c1;
+        }
+        let sum = Scalar(limbs);
+        if sum.less_than(&L) {
+            sum
+        } else {
+            sum.sub_l()
+        }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut wide = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let t = u128::from(self.0[i]) * u128::from(other.0[j])
+                    + u128::from(wide[i + j])
+                    + carry;
+                wide[i + j] = t as u64;
+                carry = t >> 64;
+            }
+            wide[i + 4] = carry as u64;
+        }
+        Self::from_wide(&wide)
+    }
+
+    /// Signed radix-16 digits in [-8, 8], least significant first.
+    fn to_radix_16(self) -> [i8; 64] {
+        let mut digits = [0i8; 64];
+        for (i, digit) in digits.iter_mut().enumerate() {
+            *digit = ((self.0[i / 16] >> (4 * (i % 16))) & 15) as i8;
+        }
+        for i in 0..63 {
+            let carry = (digits[i] + 8) >> 4;
+            digits[i] -= carry << 4;
+            digits[i + 1] += carry;
+        }
+        digits
+    }
+}
+
+/// Multiples 1 through 8 of a point.
+struct LookupTable([CachedPoint; 8]);
+
+impl LookupTable {
+    fn new(point: &EdwardsPoint) -> Self {
+        let cached = point.to_cached();
+        let mut table = [cached; 8];
+        let mut multiple = *point;
+        for entry in table.iter_mut().skip(1) {
+            multiple = multiple.add(&cached);
+            *entry = multiple.to_cached();
+        }
+        LookupTable(table)
+    }
+
+    fn select(&self, digit: i8) -> CachedPoint {
+        if digit > 0 {
+            self.0[digit as usize - 1]
+        } else {
+            self.0[(-digit) as usize - 1].neg()
+        }
+    }
+}
+
+/// Compute the sum of scalars[i] * points[i] with Straus' method, sharing the
+/// doublings across all of the terms.
+fn multiscalar_mul(scalars: &[Scalar], points: &[EdwardsPoint]) -> EdwardsPoint {
+    let tables: Vec<_> = points.iter().map(LookupTable::new).collect();
+    let digits: Vec<_> = scalars.iter().map(|s| s.to_radix_16()).collect();
+    let mut result = EdwardsPoint::identity();
+    for i in (0..64).rev() {
+        result = result.mul_by_pow2(4);
+        for (table, digits) in tables.iter().zip(&digits) {
+            if digits[i] != 0 {
+                result = result.add(&table.select(digits[i]));
+            }
+        }
+    }
+    result
+}
+
+/// Check that every signature is a valid signature of its message by its public key.
+/// Returns false if any of them isn't, or if any key or signature is malformed.
+///
+/// The batch holds when 8 * (-(sum z_i * s_i) * B + sum z_i * R_i + sum (z_i * h_i) * A_i)
+/// is the identity for random 128-bit z_i. The factor of 8 clears the small order
+/// components the random z_i could otherwise fail to cancel, so like ed25519-dalek's
+/// `verify_batch` a signature whose R or key has such a component can pass the batch and
+/// still fail an individual check. An R or key of small order is rejected outright, it
+/// lets anyone sign for it.
+pub fn verify_batch(messages: &[&[u8]], signatures: &[&[u8]], pubkeys: &[&[u8]]) -> bool {
+    let n = messages.len();
+    if signatures.len() != n || pubkeys.len() != n {
+        return false;
+    }
+    if n == 0 {
+        return true;
+    }
+
+    let mut rng = thread_rng();
+    let mut scalars = Vec::with_capacity(2 * n + 1);
+    let mut points = Vec::with_capacity(2 * n + 1);
+    let mut b_scalar = Scalar([0; 4]);
+    for ((message, signature), pubkey) in messages.iter().zip(signatures).zip(pubkeys) {
+        if signature.len() != 64 {
+            return false;
+        }
+        let (r_bytes, s_bytes) = signature.split_at(32);
+        let (r, a, s) = match (
+            EdwardsPoint::decompress(r_bytes),
+            EdwardsPoint::decompress(pubkey),
+            Scalar::from_canonical_bytes(s_bytes),
+        ) {
+            (Some(r), Some(a), Some(s)) => (r, a, s),
+            _ => return false,
+        };
+        if r.is_small_order() || a.is_small_order() {
+            return false;
+        }
+
+        let mut hasher = Sha512::default();
+        hasher.input(r_bytes);
+        hasher.input(pubkey);
+        hasher.input(message);
+        let h = Scalar::from_hash(&hasher.result());
+
+        let z =
+            Scalar::from_u128((u128::from(rng.gen::<u64>()) << 64) | u128::from(rng.gen::<u64>()));
+        b_scalar = b_scalar.add(&z.mul(&s));
+        scalars.push(z);
+        points.push(r);
+        scalars.push(z.mul(&h));
+        points.push(a);
+    }
+
+    let basepoint = EdwardsPoint::decompress(&BASEPOINT_BYTES).unwrap();
+    scalars.push(b_scalar);
+    points.push(basepoint.neg());
+
+    multiscalar_mul(&scalars, &points)
+        .mul_by_pow2(3)
+        .is_identity()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::{Keypair, KeypairUtil, Signature};
+    use std::slice;
+
+    fn signed_messages(n: usize) -> Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        (0..n)
+            .map(|i| {
+                let keypair = Keypair::new();
+                let message = vec![i as u8; i + 1];
+                let signature = keypair.sign(&message).as_ref().to_vec();
+                (message, signature, keypair.pubkey().as_ref().to_vec())
+            }).collect()
+    }
+
+    fn verify_all(batch: &[(Vec<u8>, Vec<u8>, Vec<u8>)]) -> bool {
+        let messages: Vec<_> = batch.iter().map(|x| &x.0[..]).collect();
+        let signatures: Vec<_> = batch.iter().map(|x| &x.1[..]).collect();
+        let pubkeys: Vec<_> = batch.iter().map(|x| &x.2[..]).collect();
+        verify_batch(&messages, &signatures, &pubkeys)
+    }
+
+    fn verify_each(signed: &(Vec<u8>, Vec<u8>, Vec<u8>)) -> bool {
+        Signature::new(&signed.1).verify(&signed.2, &signed.0)
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// A point of order 8.
+    fn torsion_point() -> EdwardsPoint {
+        (0u8..128)
+            .filter_map(|i| EdwardsPoint::decompress(&[i; 32]))
+            .map(|point| multiscalar_mul(&[L], &[point]))
+            .find(|point| !point.mul_by_pow2(2).is_identity())
+            .unwrap()
+    }
+
+    fn random_scalar() -> Scalar {
+        let mut rng = thread_rng();
+        let bytes: Vec<u8> = (0..64).map(|_| rng.gen()).collect();
+        Scalar::from_hash(&bytes)
+    }
+
+    /// Sign `message` with a random key and nonce, adding `a_torsion` to the public key
+    /// and `r_torsion` to R after the fact, the way a signer would to get a signature
+    /// past a cofactored check. A zero nonce leaves R of small order.
+    fn sign_with_torsion(
+        message: &[u8],
+        a_torsion: &EdwardsPoint,
+        r_torsion: &EdwardsPoint,
+        zero_nonce: bool,
+    ) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let a = random_scalar();
+        let r = if zero_nonce {
+            Scalar([0; 4])
+        } else {
+            random_scalar()
+        };
+        let basepoint = EdwardsPoint::decompress(&BASEPOINT_BYTES).unwrap();
+        let pubkey = multiscalar_mul(&[a], &[basepoint])
+            .add(&a_torsion.to_cached())
+            .compress();
+        let r_bytes = multiscalar_mul(&[r], &[basepoint])
+            .add(&r_torsion.to_cached())
+            .compress();
+
+        let mut hasher = Sha512::default();
+        hasher.input(&r_bytes);
+        hasher.input(&pubkey);
+        hasher.input(message);
+        let h = Scalar::from_hash(&hasher.result());
+        let s = r.add(&h.mul(&a));
+        let signature = [r_bytes, s.to_bytes()].concat();
+        (message.to_vec(), signature, pubkey.to_vec())
+    }
+
+    #[test]
+    fn test_field_constants() {
+        let minus_one = FieldElement::one().neg();
+        assert!(SQRT_M1.square().equals(&minus_one));
+        assert!(EDWARDS_D.add(&EDWARDS_D).equals(&EDWARDS_D2));
+        let d_121666 = EDWARDS_D.mul(&FieldElement([121_666, 0, 0, 0, 0]));
+        assert!(d_121666.equals(&FieldElement([121_665, 0, 0, 0, 0]).neg()));
+        let x = FieldElement([12_345, 6_789, 0, 0, 42]);
+        assert!(x.mul(&x.invert()).equals(&FieldElement::one()));
+    }
+
+    #[test]
+    fn test_point_arithmetic() {
+        let basepoint = EdwardsPoint::decompress(&BASEPOINT_BYTES).unwrap();
+        assert_eq!(basepoint.compress(), BASEPOINT_BYTES);
+        let doubled = basepoint.double();
+        let added = basepoint.add(&basepoint.to_cached());
+        assert_eq!(doubled.compress(), added.compress());
+        assert!(basepoint.add(&basepoint.to_cached().neg()).is_identity());
+
+        // (l - 1) * B is -B
+        let l_minus_one = Scalar([L.0[0] - 1, L.0[1], L.0[2], L.0[3]]);
+        let result = multiscalar_mul(&[l_minus_one], &[basepoint]);
+        assert!(!result.is_identity());
+        assert_eq!(result.neg().compress(), BASEPOINT_BYTES);
+        assert!(result.add(&basepoint.to_cached()).is_identity());
+    }
+
+    #[test]
+    fn test_scalar_reduction() {
+        let mut wide = [0u64; 8];
+        wide[..4].copy_from_slice(&L.0);
+        assert_eq!(Scalar::from_wide(&wide), Scalar([0; 4]));
+        wide[0] += 5;
+        assert_eq!(Scalar::from_wide(&wide), Scalar::from_u128(5));
+        let a = Scalar::from_u128(u128::max_value());
+        let mut expected = [0u64; 8];
+        expected[0] = 1;
+        expected[2] = !1;
+        expected[3] = !0;
+        assert_eq!(a.mul(&a), Scalar::from_wide(&expected));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        assert!(verify_all(&[]));
+        let batch = signed_messages(16);
+        assert!(verify_all(&batch));
+        assert!(verify_all(&batch[..1]));
+    }
+
+    #[test]
+    fn test_verify_batch_rfc8032() {
+        // test vectors 1 to 3 of RFC 8032, section 7.1: public key, message, signature
+        let vectors = [
+            (
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ];
+        let batch: Vec<_> = vectors
+            .iter()
+            .map(|&(pubkey, message, signature)| {
+                (from_hex(message), from_hex(signature), from_hex(pubkey))
+            }).collect();
+        for signed in &batch {
+            assert!(verify_each(signed));
+            assert!(verify_all(slice::from_ref(signed)));
+        }
+        assert!(verify_all(&batch));
+
+        let mut bad_signature = batch.clone();
+        bad_signature[1].1[40] ^= 1;
+        assert!(!verify_all(&bad_signature));
+    }
+
+    #[test]
+    fn test_verify_batch_small_order() {
+        let identity = EdwardsPoint::identity();
+        let torsion = torsion_point();
+        let valid = sign_with_torsion(b"valid", &identity, &identity, false);
+        assert!(verify_each(&valid));
+
+        // the cofactored batch ignores the torsion these add, an individual check doesn't,
+        // so their batches pass and only a failed batch falls back to that check
+        let mixed_r = sign_with_torsion(b"mixed r", &identity, &torsion, false);
+        let mixed_a = (0u8..128)
+            .map(|i| sign_with_torsion(&[i], &torsion, &identity, false))
+            .find(|signed| !verify_each(signed))
+            .unwrap();
+        for signed in &[mixed_r, mixed_a] {
+            assert!(!verify_each(signed));
+            assert!(verify_all(&[valid.clone(), signed.clone()]));
+        }
+
+        // an R of small order only holds up in a cofactored check
+        let small_r = sign_with_torsion(b"small r", &identity, &torsion, true);
+        assert!(!verify_each(&small_r));
+        // a small order key signs any message whose hash clears its torsion, with
+        // R the identity and s zero
+        let small_a = (0u8..128)
+            .map(|i| {
+                let signature = [identity.compress(), [0; 32]].concat();
+                (vec![i], signature, torsion.compress().to_vec())
+            }).find(verify_each)
+            .unwrap();
+
+        for signed in &[small_r, small_a] {
+            assert!(!verify_all(slice::from_ref(signed)));
+            assert!(!verify_all(&[valid.clone(), signed.clone()]));
+        }
+        assert!(verify_all(&[valid]));
+    }
+
+    #[test]
+    fn test_verify_batch_bad_message() {
+        let mut batch = signed_messages(8);
+        batch[3].0[0] ^= 1;
+        assert!(!verify_all(&batch));
+    }
+
+    #[test]
+    fn test_verify_batch_wrong_pubkey() {
+        let mut batch = signed_messages(8);
+        batch[5].2 = batch[4].2.clone();
+        assert!(!verify_all(&batch));
+    }
+
+    #[test]
+    fn test_verify_batch_non_canonical_s() {
+        // s + l satisfies the same equation as s, but isn't a valid encoding
+        let mut batch = signed_messages(2);
+        let s = Scalar::from_canonical_bytes(&batch[0].1[32..]).unwrap();
+        let mut carry = false;
+        for i in 0..4 {
+            let (sum, c0) = s.0[i].overflowing_add(L.0[i]);
+            let (sum, c1) = sum.overflowing_add(carry as u64);
+            for (j, byte) in batch[0].1[32 + 8 * i..40 + 8 * i].iter_mut().enumerate() {
+                *byte = (sum >> (8 * j)) as u8;
+            }
+            carry = c0 || c1;
+        }
+        assert!(!verify_all(&batch));
+    }
+
+    #[test]
+    fn test_verify_batch_malformed() {
+        let batch = signed_messages(2);
+        let messages: Vec<_> = batch.iter().map(|x| &x.0[..]).collect();
+        let signatures: Vec<_> = batch.iter().map(|x| &x.1[..]).collect();
+        let pubkeys: Vec<_> = batch.iter().map(|x| &x.2[..]).collect();
+        assert!(!verify_batch(&messages, &signatures[..1], &pubkeys));
+        assert!(!verify_batch(
+            &messages,
+            &[&signatures[0][..63], signatures[1]],
+            &pubkeys
+        ));
+    }
+}
@@ -29,152 +29,177 @@ pub enum ErasureError {
 
 pub type Result<T> = result::Result<T, ErasureError>;
 
-// k = number of data devices
-// m = number of coding devices
-// w = word size
-
-extern "C" {
-    fn jerasure_matrix_encode(
-        k: i32,
-        m: i32,
-        w: i32,
-        matrix: *const i32,
-        data_ptrs: *const *const u8,
-        coding_ptrs: *const *mut u8,
-        size: i32,
-    );
-    fn jerasure_matrix_decode(
-        k: i32,
-        m: i32,
-        w: i32,
-        matrix: *const i32,
-        row_k_ones: i32,
-        erasures: *const i32,
-        data_ptrs: *const *mut u8,
-        coding_ptrs: *const *mut u8,
-        size: i32,
-    ) -> i32;
-    fn galois_single_divide(a: i32, b: i32, w: i32) -> i32;
-}
+#[cfg(feature = "erasure")]
+mod jerasure {
+    use super::{ErasureError, Result};
+
+    // k = number of data devices
+    // m = number of coding devices
+    // w = word size
+
+    extern "C" {
+        fn jerasure_matrix_encode(
+            k: i32,
+            m: i32,
+            w: i32,
+            matrix: *const i32,
+            data_ptrs: *const *const u8,
+            coding_ptrs: *const *mut u8,
+            size: i32,
+        );
+        fn jerasure_matrix_decode(
+            k: i32,
+            m: i32,
+            w: i32,
+            matrix: *const i32,
+            row_k_ones: i32,
+            erasures: *const i32,
+            data_ptrs: *const *mut u8,
+            coding_ptrs: *const *mut u8,
+            size: i32,
+        ) -> i32;
+        fn galois_single_divide(a: i32, b: i32, w: i32) -> i32;
+    }
 
-fn get_matrix(m: i32, k: i32, w: i32) -> Vec<i32> {
-    let mut matrix = vec![0; (m * k) as usize];
-    for i in 0..m {
-        for j in 0..k {
-            unsafe {
-                matrix[(i * k + j) as usize] = galois_single_divide(1, i ^ (m + j), w);
+    fn get_matrix(m: i32, k: i32, w: i32) -> Vec<i32> {
+        let mut matrix = vec![0; (m * k) as usize];
+        for i in 0..m {
+            for j in 0..k {
+                unsafe {
+                    matrix[(i * k + j) as usize] = galois_single_divide(1, i ^ (m + j), w);
+                }
             }
         }
+        matrix
     }
-    matrix
-}
 
-pub const ERASURE_W: i32 = 32;
+    pub const ERASURE_W: i32 = 32;
 
-// Generate coding blocks into coding
-//   There are some alignment restrictions, blocks should be aligned by 16 bytes
-//   which means their size should be >= 16 bytes
-pub fn generate_coding_blocks(coding: &mut [&mut [u8]], data: &[&[u8]]) -> Result<()> {
-    if data.is_empty() {
-        return Ok(());
-    }
-    let k = data.len() as i32;
-    let m = coding.len() as i32;
-    let block_len = data[0].len() as i32;
-    let matrix: Vec<i32> = get_matrix(m, k, ERASURE_W);
-    let mut data_arg = Vec::with_capacity(data.len());
-    for block in data {
-        if block_len != block.len() as i32 {
-            error!(
-                "data block size incorrect {} expected {}",
-                block.len(),
-                block_len
-            );
-            return Err(ErasureError::InvalidBlockSize);
+    pub fn generate_coding_blocks(coding: &mut [&mut [u8]], data: &[&[u8]]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
         }
-        data_arg.push(block.as_ptr());
-    }
-    let mut coding_arg = Vec::with_capacity(coding.len());
-    for mut block in coding {
-        if block_len != block.len() as i32 {
-            error!(
-                "coding block size incorrect {} expected {}",
-                block.len(),
-                block_len
+        let k = data.len() as i32;
+        let m = coding.len() as i32;
+        let block_len = data[0].len() as i32;
+        let matrix: Vec<i32> = get_matrix(m, k, ERASURE_W);
+        let mut data_arg = Vec::with_capacity(data.len());
+        for block in data {
+            if block_len != block.len() as i32 {
+                error!(
+                    "data block size incorrect {} expected {}",
+                    block.len(),
+                    block_len
+                );
+                return Err(ErasureError::InvalidBlockSize);
+            }
+            data_arg.push(block.as_ptr());
+        }
+        let mut coding_arg = Vec::with_capacity(coding.len());
+        for mut block in coding {
+            if block_len != block.len() as i32 {
+                error!(
+                    "coding block size incorrect {} expected {}",
+                    block.len(),
+                    block_len
+                );
+                return Err(ErasureError::InvalidBlockSize);
+            }
+            coding_arg.push(block.as_mut_ptr());
+        }
+
+        unsafe {
+            jerasure_matrix_encode(
+                k,
+                m,
+                ERASURE_W,
+                matrix.as_ptr(),
+                data_arg.as_ptr(),
+                coding_arg.as_ptr(),
+                block_len,
             );
-            return Err(ErasureError::InvalidBlockSize);
         }
-        coding_arg.push(block.as_mut_ptr());
+        Ok(())
     }
 
-    unsafe {
-        jerasure_matrix_encode(
-            k,
-            m,
-            ERASURE_W,
-            matrix.as_ptr(),
-            data_arg.as_ptr(),
-            coding_arg.as_ptr(),
-            block_len,
-        );
+    pub fn decode_blocks(
+        data: &mut [&mut [u8]],
+        coding: &mut [&mut [u8]],
+        erasures: &[i32],
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let block_len = data[0].len();
+        let matrix: Vec<i32> = get_matrix(coding.len() as i32, data.len() as i32, ERASURE_W);
+
+        // generate coding pointers, blocks should be the same size
+        let mut coding_arg: Vec<*mut u8> = Vec::new();
+        for x in coding.iter_mut() {
+            if x.len() != block_len {
+                return Err(ErasureError::InvalidBlockSize);
+            }
+            coding_arg.push(x.as_mut_ptr());
+        }
+
+        // generate data pointers, blocks should be the same size
+        let mut data_arg: Vec<*mut u8> = Vec::new();
+        for x in data.iter_mut() {
+            if x.len() != block_len {
+                return Err(ErasureError::InvalidBlockSize);
+            }
+            data_arg.push(x.as_mut_ptr());
+        }
+        let ret = unsafe {
+            jerasure_matrix_decode(
+                data.len() as i32,
+                coding.len() as i32,
+                ERASURE_W,
+                matrix.as_ptr(),
+                0,
+                erasures.as_ptr(),
+                data_arg.as_ptr(),
+                coding_arg.as_ptr(),
+                data[0].len() as i32,
+            )
+        };
+        trace!("jerasure_matrix_decode ret: {}", ret);
+        for x in data[erasures[0] as usize][0..8].iter() {
+            trace!("{} ", x)
+        }
+        trace!("");
+        if ret < 0 {
+            return Err(ErasureError::DecodeError);
+        }
+        Ok(())
     }
-    Ok(())
+}
+
+// The Jerasure backend is used when the C libraries are linked in with the
+// `erasure` feature, otherwise the bit-compatible pure Rust one in `reed_solomon`.
+#[cfg(feature = "erasure")]
+use self::jerasure as backend;
+#[cfg(not(feature = "erasure"))]
+use crate::reed_solomon as backend;
+
+// Generate coding blocks into coding
+//   There are some alignment restrictions, blocks should be aligned by 16 bytes
+//   which means their size should be >= 16 bytes
+pub fn generate_coding_blocks(coding: &mut [&mut [u8]], data: &[&[u8]]) -> Result<()> {
+    backend::generate_coding_blocks(coding, data)
 }
 
 // Recover data + coding blocks into data blocks
 //   data: array of blocks to recover into
 //   coding: arry of coding blocks
-//   erasures: list of indices in data where blocks should be recovered
+//   erasures: list of indices in data where blocks should be recovered,
+//     terminated by -1
 pub fn decode_blocks(
     data: &mut [&mut [u8]],
     coding: &mut [&mut [u8]],
     erasures: &[i32],
 ) -> Result<()> {
-    if data.is_empty() {
-        return Ok(());
-    }
-    let block_len = data[0].len();
-    let matrix: Vec<i32> = get_matrix(coding.len() as i32, data.len() as i32, ERASURE_W);
-
-    // generate coding pointers, blocks should be the same size
-    let mut coding_arg: Vec<*mut u8> = Vec::new();
-    for x in coding.iter_mut() {
-        if x.len() != block_len {
-            return Err(ErasureError::InvalidBlockSize);
-        }
-        coding_arg.push(x.as_mut_ptr());
-    }
-
-    // generate data pointers, blocks should be the same size
-    let mut data_arg: Vec<*mut u8> = Vec::new();
-    for x in data.iter_mut() {
-        if x.len() != block_len {
-            return Err(ErasureError::InvalidBlockSize);
-        }
-        data_arg.push(x.as_mut_ptr());
-    }
-    let ret = unsafe {
-        jerasure_matrix_decode(
-            data.len() as i32,
-            coding.len() as i32,
-            ERASURE_W,
-            matrix.as_ptr(),
-            0,
-            erasures.as_ptr(),
-            data_arg.as_ptr(),
-            coding_arg.as_ptr(),
-            data[0].len() as i32,
-        )
-    };
-    trace!("jerasure_matrix_decode ret: {}", ret);
-    for x in data[erasures[0] as usize][0..8].iter() {
-        trace!("{} ", x)
-    }
-    trace!("");
-    if ret < 0 {
-        return Err(ErasureError::DecodeError);
-    }
-    Ok(())
+    backend::decode_blocks(data, coding, erasures)
 }
 
 // Generate coding blocks in window starting from start_idx,
@@ -652,6 +677,64 @@ mod test {
         assert_eq!(v_orig, vs[0]);
     }
 
+    // the blocks of test_coding coded with jerasure_matrix_encode, cauchy_original_coding_matrix
+    // and w = 32, both backends must produce them
+    #[test]
+    pub fn test_coding_known_answer() {
+        let data: Vec<Vec<u8>> = (0..4).map(|i| (i..(16 + i)).collect()).collect();
+        let mut coding_blocks: Vec<_> = (0..2).map(|_| vec![0u8; 16]).collect();
+        {
+            let mut coding_blocks_slices: Vec<_> =
+                coding_blocks.iter_mut().map(|x| x.as_mut_slice()).collect();
+            let data_slices: Vec<_> = data.iter().map(|x| x.as_slice()).collect();
+            erasure::generate_coding_blocks(&mut coding_blocks_slices, &data_slices).unwrap();
+        }
+        assert_eq!(
+            coding_blocks,
+            vec![
+                vec![232, 0, 160, 215, 21, 252, 222, 123, 190, 3, 246, 212, 232, 5, 99, 35],
+                vec![168, 106, 176, 195, 3, 105, 177, 198, 254, 105, 230, 192, 85, 110, 179, 204],
+            ]
+        );
+    }
+
+    #[cfg(feature = "erasure")]
+    #[test]
+    pub fn test_reed_solomon_matches_jerasure() {
+        use crate::reed_solomon;
+
+        let (k, m, len) = (16, 4, 1024);
+        let data: Vec<Vec<u8>> = (0..k)
+            .map(|_| (0..len).map(|_| thread_rng().gen()).collect())
+            .collect();
+        let data_slices: Vec<_> = data.iter().map(|x| x.as_slice()).collect();
+
+        let mut jerasure_coding = vec![vec![0u8; len]; m];
+        let mut rust_coding = vec![vec![0u8; len]; m];
+        {
+            let mut slices: Vec<_> = jerasure_coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+            erasure::jerasure::generate_coding_blocks(&mut slices, &data_slices).unwrap();
+        }
+        {
+            let mut slices: Vec<_> = rust_coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+            reed_solomon::generate_coding_blocks(&mut slices, &data_slices).unwrap();
+        }
+        assert_eq!(jerasure_coding, rust_coding);
+
+        // coding blobs made by Jerasure recover data with the Rust backend
+        let mut damaged = data.clone();
+        damaged[3] = vec![0; len];
+        damaged[7] = vec![0; len];
+        {
+            let mut data_slices: Vec<_> = damaged.iter_mut().map(|x| x.as_mut_slice()).collect();
+            let mut coding_slices: Vec<_> =
+                jerasure_coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+            reed_solomon::decode_blocks(&mut data_slices, &mut coding_slices, &[3, 7, -1])
+                .unwrap();
+        }
+        assert_eq!(damaged, data);
+    }
+
     fn print_window(window: &[WindowSlot]) {
         for (i, w) in window.iter().enumerate() {
             print!("window({:>w$}): ", i, w = 2);
@@ -902,6 +985,83 @@ mod test {
         }
     }
 
+    // contents of the data and coding blobs of the first block of a window
+    fn block_contents(window: &[WindowSlot]) -> Vec<Option<Vec<u8>>> {
+        let data = window[..erasure::NUM_DATA].iter().map(|slot| {
+            slot.data.as_ref().map(|b| {
+                let b_l = b.read().unwrap();
+                b_l.data[..b_l.meta.size].to_vec()
+            })
+        });
+        let coding_start = erasure::NUM_DATA - erasure::NUM_CODING;
+        let coding = window[coding_start..erasure::NUM_DATA].iter().map(|slot| {
+            slot.coding.as_ref().map(|b| {
+                let b_l = b.read().unwrap();
+                b_l.data()[..b_l.meta.size - BLOB_HEADER_SIZE].to_vec()
+            })
+        });
+        data.chain(coding).collect()
+    }
+
+    #[test]
+    pub fn test_window_recover_random() {
+        logger::setup();
+        let num_blobs = erasure::NUM_DATA + 2;
+        let id = Pubkey::default();
+        let coding_start = erasure::NUM_DATA - erasure::NUM_CODING;
+
+        for _ in 0..32 {
+            let mut window = generate_window(WINDOW_SIZE, num_blobs);
+            let mut index = num_blobs as u64;
+            assert!(erasure::generate_coding(&id, &mut window, 0, num_blobs, &mut index).is_ok());
+            let expected = block_contents(&window);
+
+            // drop up to NUM_CODING of the data and coding blobs, at least one of them data
+            let mut positions: Vec<usize> = (0..erasure::ERASURE_SET_SIZE).collect();
+            for i in (1..positions.len()).rev() {
+                let j = thread_rng().gen_range(0, i + 1);
+                positions.swap(i, j);
+            }
+            let num_erasures = thread_rng().gen_range(1, erasure::NUM_CODING + 1);
+            let mut erased = positions[..num_erasures].to_vec();
+            if erased.iter().all(|p| *p >= erasure::NUM_DATA) {
+                erased[0] = thread_rng().gen_range(0, erasure::NUM_DATA);
+            }
+            for p in &erased {
+                if *p < erasure::NUM_DATA {
+                    window[*p].data = None;
+                } else {
+                    window[coding_start + p - erasure::NUM_DATA].coding = None;
+                }
+            }
+            scramble_window_tails(&mut window, num_blobs);
+
+            assert!(erasure::recover(&id, &mut window, WINDOW_SIZE as u64, 0).is_ok());
+            assert_eq!(block_contents(&window), expected, "erased {:?}", erased);
+            for (i, slot) in window[..erasure::NUM_DATA].iter().enumerate() {
+                let blob = slot.data.as_ref().unwrap().read().unwrap();
+                assert_eq!(blob.get_index().unwrap(), (WINDOW_SIZE + i) as u64);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_window_recover_too_many_erasures() {
+        let num_blobs = erasure::NUM_DATA + 2;
+        let id = Pubkey::default();
+        let mut window = generate_window(WINDOW_SIZE, num_blobs);
+        let mut index = num_blobs as u64;
+        assert!(erasure::generate_coding(&id, &mut window, 0, num_blobs, &mut index).is_ok());
+
+        for slot in window[..erasure::NUM_CODING + 1].iter_mut() {
+            slot.data = None;
+        }
+        assert_eq!(
+            erasure::recover(&id, &mut window, WINDOW_SIZE as u64, 0),
+            Err(erasure::ErasureError::NotEnoughBlocksToDecode)
+        );
+    }
+
     //    //TODO This needs to be reworked
     //    #[test]
     //    #[ignore]
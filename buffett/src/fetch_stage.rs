@@ -1,12 +1,14 @@
-//! The `fetch_stage` batches input from a UDP socket and sends it to a channel.
+//! The `fetch_stage` batches input from UDP sockets, and optionally the connections
+//! of a TCP listener, and sends it to a channel.
 
 use crate::service::Service;
-use std::net::UdpSocket;
+use std::net::{TcpListener, UdpSocket};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use crate::streamer::{self, PacketReceiver};
+use crate::tcp_streamer;
 
 pub struct FetchStage {
     exit: Arc<AtomicBool>,
@@ -14,19 +16,32 @@ pub struct FetchStage {
 }
 
 impl FetchStage {
-    pub fn new(sockets: Vec<UdpSocket>, exit: Arc<AtomicBool>) -> (Self, PacketReceiver) {
+    pub fn new(
+        sockets: Vec<UdpSocket>,
+        listener: Option<TcpListener>,
+        exit: Arc<AtomicBool>,
+    ) -> (Self, PacketReceiver) {
         let tx_sockets = sockets.into_iter().map(Arc::new).collect();
-        Self::new_multi_socket(tx_sockets, exit)
+        Self::new_multi_socket(tx_sockets, listener, exit)
     }
     pub fn new_multi_socket(
         sockets: Vec<Arc<UdpSocket>>,
+        listener: Option<TcpListener>,
         exit: Arc<AtomicBool>,
     ) -> (Self, PacketReceiver) {
         let (sender, receiver) = channel();
-        let thread_hdls: Vec<_> = sockets
+        let mut thread_hdls: Vec<_> = sockets
             .into_iter()
             .map(|socket| streamer::receiver(socket, exit.clone(), sender.clone(), "fetch-stage"))
             .collect();
+        if let Some(listener) = listener {
+            thread_hdls.push(tcp_streamer::receiver(
+                listener,
+                exit.clone(),
+                sender,
+                "fetch-stage",
+            ));
+        }
 
         (FetchStage { exit, thread_hdls }, receiver)
     }
@@ -1,18 +1,24 @@
 //! The `fullnode` module hosts all the fullnode microservices.
 
-use crate::tx_vault::Bank;
+use crate::account_store::AccountStoreKind;
+use crate::tx_vault::{Bank, BankError};
 use broadcast_stage::BroadcastStage;
+use crate::checkpoint;
+use choose_gossip_peer_strategy::GossipStrategy;
 use crate::crdt::{Crdt, Node, NodeInfo};
 use crate::token_service::DRONE_PORT;
 use crate::entry::Entry;
-use crate::ledger::read_ledger;
+use crate::ledger::{ledger_start, read_ledger};
 use crate::ncp::Ncp;
+use crate::program_registry::program_dir;
+use crate::result;
 use rpc::{JsonRpcService, RPC_PORT};
 use rpu::Rpu;
 use crate::service::Service;
 use crate::signature::{Keypair, KeypairUtil};
+use crate::sigverify::{SharedSigVerifyMode, SigVerifyMode};
 use buffett_program_interface::pubkey::Pubkey;
-use std::net::UdpSocket;
+use std::net::{TcpListener, UdpSocket};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
@@ -72,17 +78,39 @@ pub enum FullnodeReturnType {
     LeaderRotation,
 }
 
+/// Signals the services of a fullnode's current role to exit. Unlike `Fullnode::exit`
+/// it can be used while another thread is blocked in `handle_role_transition`.
+#[derive(Clone)]
+pub struct RoleExitSignal(Arc<RwLock<Arc<AtomicBool>>>);
+
+impl RoleExitSignal {
+    fn new(exit: Arc<AtomicBool>) -> Self {
+        RoleExitSignal(Arc::new(RwLock::new(exit)))
+    }
+
+    fn set(&self, exit: Arc<AtomicBool>) {
+        *self.0.write().unwrap() = exit;
+    }
+
+    pub fn exit(&self) {
+        self.0.read().unwrap().store(true, Ordering::Relaxed);
+    }
+}
+
 pub struct Fullnode {
     pub node_role: Option<NodeRole>,
     keypair: Arc<Keypair>,
     exit: Arc<AtomicBool>,
+    role_exit: RoleExitSignal,
     rpu: Option<Rpu>,
     rpc_service: JsonRpcService,
     ncp: Ncp,
     bank: Arc<Bank>,
     crdt: Arc<RwLock<Crdt>>,
     ledger_path: String,
+    account_store: AccountStoreKind,
     sigverify_disabled: bool,
+    sigverify_mode: SharedSigVerifyMode,
     shared_window: window::SharedWindow,
     replicate_socket: Vec<UdpSocket>,
     repair_socket: UdpSocket,
@@ -91,6 +119,8 @@ pub struct Fullnode {
     broadcast_socket: UdpSocket,
     requests_socket: UdpSocket,
     respond_socket: UdpSocket,
+    requests_listener: Option<TcpListener>,
+    transaction_listener: Option<TcpListener>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -98,6 +128,12 @@ pub struct Fullnode {
 pub struct Config {
     pub node_info: NodeInfo,
     pkcs8: Vec<u8>,
+    /// how the node picks the peers it gossips with
+    #[serde(default)]
+    pub gossip_strategy: GossipStrategy,
+    /// where the node keeps its accounts
+    #[serde(default)]
+    pub account_store: AccountStoreKind,
 }
 
 /// Structure to be replicated by the network
@@ -107,7 +143,12 @@ impl Config {
             Keypair::from_pkcs8(Input::from(&pkcs8)).expect("from_pkcs8 in fullnode::Config new");
         let pubkey = keypair.pubkey();
         let node_info = NodeInfo::new_with_pubkey_socketaddr(pubkey, bind_addr);
-        Config { node_info, pkcs8 }
+        Config {
+            node_info,
+            pkcs8,
+            gossip_strategy: GossipStrategy::default(),
+            account_store: AccountStoreKind::default(),
+        }
     }
     pub fn keypair(&self) -> Keypair {
         Keypair::from_pkcs8(Input::from(&self.pkcs8))
@@ -115,6 +156,12 @@ impl Config {
     }
 }
 
+fn try_clone_listener(listener: &Option<TcpListener>) -> Option<TcpListener> {
+    listener
+        .as_ref()
+        .map(|l| l.try_clone().expect("Failed to clone tcp listener"))
+}
+
 impl Fullnode {
     pub fn new(
         node: Node,
@@ -123,9 +170,32 @@ impl Fullnode {
         leader_addr: Option<SocketAddr>,
         sigverify_disabled: bool,
         leader_rotation_interval: Option<u64>,
+    ) -> Self {
+        Self::new_with_account_store(
+            node,
+            ledger_path,
+            keypair,
+            leader_addr,
+            sigverify_disabled,
+            leader_rotation_interval,
+            AccountStoreKind::Memory,
+        )
+    }
+
+    /// Create a fullnode that keeps its accounts in a store of kind `account_store`.
+    pub fn new_with_account_store(
+        node: Node,
+        ledger_path: &str,
+        keypair: Keypair,
+        leader_addr: Option<SocketAddr>,
+        sigverify_disabled: bool,
+        leader_rotation_interval: Option<u64>,
+        account_store: AccountStoreKind,
     ) -> Self {
         info!("Tx_Valut created");
-        let (bank, entry_height, ledger_tail) = Self::new_bank_from_ledger(ledger_path);
+        let (bank, entry_height, ledger_tail) =
+            Self::new_bank_from_ledger(ledger_path, account_store, leader_addr.is_none())
+                .unwrap_or_else(|err| panic!("unable to load the ledger: {:?}", err));
 
         info!("Local_Gossip network set up.");
         let local_gossip_addr = node.sockets.gossip.local_addr().unwrap();
@@ -138,7 +208,7 @@ impl Fullnode {
         let local_requests_addr = node.sockets.requests.local_addr().unwrap();
         let requests_addr = node.info.contact_info.rpu;
         let leader_info = leader_addr.map(|i| NodeInfo::new_entry_point(&i));
-        let server = Self::new_with_bank(
+        let mut server = Self::new_with_bank(
             keypair,
             bank,
             entry_height,
@@ -150,6 +220,7 @@ impl Fullnode {
             leader_rotation_interval,
             None,
         );
+        server.account_store = account_store;
 
         match leader_addr {
             Some(leader_addr) => {
@@ -237,6 +308,7 @@ impl Fullnode {
         }
         let exit = Arc::new(AtomicBool::new(false));
         let bank = Arc::new(bank);
+        let sigverify_mode = Arc::new(RwLock::new(SigVerifyMode::default()));
 
         let rpu = Some(Rpu::new(
             &bank,
@@ -248,6 +320,7 @@ impl Fullnode {
                 .respond
                 .try_clone()
                 .expect("Failed to clone respond socket"),
+            try_clone_listener(&node.sockets.requests_tcp),
         ));
 
         // TODO: this code assumes this node is the leader
@@ -264,17 +337,20 @@ impl Fullnode {
             node.info.contact_info.tpu,
             drone_addr,
             rpc_addr,
+            Some(ledger_path),
             exit.clone(),
         );
 
         let window = window::new_window_from_entries(ledger_tail, entry_height, &node.info);
         let shared_window = Arc::new(RwLock::new(window));
 
-        let mut crdt = Crdt::new(node.info).expect("Crdt::new");
+        let keypair = Arc::new(keypair);
+        let mut crdt = Crdt::new_with_keypair(node.info, keypair.clone()).expect("Crdt::new");
         if let Some(interval) = leader_rotation_interval {
             crdt.set_leader_rotation_interval(interval);
         }
         let crdt = Arc::new(RwLock::new(crdt));
+        bank.set_leader_scheduler(&crdt);
 
         let ncp = Ncp::new(
             &crdt,
@@ -284,8 +360,8 @@ impl Fullnode {
             exit.clone(),
         );
 
-        let keypair = Arc::new(keypair);
         let node_role;
+        let role_exit;
         match leader_info {
             Some(leader_info) => {
                 // Start in validator mode.
@@ -312,6 +388,7 @@ impl Fullnode {
                         .expect("Failed to clone retransmit socket"),
                     Some(ledger_path),
                 );
+                role_exit = tvu.exit_signal();
                 let validator_state = ValidatorServices::new(tvu);
                 node_role = Some(NodeRole::Validator(validator_state));
             }
@@ -327,8 +404,10 @@ impl Fullnode {
                         .iter()
                         .map(|s| s.try_clone().expect("Failed to clone transaction sockets"))
                         .collect(),
+                    try_clone_listener(&node.sockets.transaction_tcp),
                     ledger_path,
                     sigverify_disabled,
+                    sigverify_mode.clone(),
                     entry_height,
                 );
 
@@ -341,8 +420,9 @@ impl Fullnode {
                     shared_window.clone(),
                     entry_height,
                     entry_receiver,
-                    tpu_exit,
+                    tpu_exit.clone(),
                 );
+                role_exit = tpu_exit;
                 let leader_state = LeaderServices::new(tpu, broadcast_stage);
                 node_role = Some(NodeRole::Leader(leader_state));
             }
@@ -354,12 +434,15 @@ impl Fullnode {
             shared_window,
             bank,
             sigverify_disabled,
+            sigverify_mode,
             rpu,
             ncp,
             rpc_service,
             node_role,
             ledger_path: ledger_path.to_owned(),
+            account_store: AccountStoreKind::Memory,
             exit,
+            role_exit: RoleExitSignal::new(role_exit),
             replicate_socket: node.sockets.replicate,
             repair_socket: node.sockets.repair,
             retransmit_socket: node.sockets.retransmit,
@@ -367,13 +450,18 @@ impl Fullnode {
             broadcast_socket: node.sockets.broadcast,
             requests_socket: node.sockets.requests,
             respond_socket: node.sockets.respond,
+            requests_listener: node.sockets.requests_tcp,
+            transaction_listener: node.sockets.transaction_tcp,
         }
     }
 
     fn leader_to_validator(&mut self) -> Result<()> {
         // TODO: We can avoid building the bank again once RecordStage is
         // integrated with BankingStage
-        let (bank, entry_height, _) = Self::new_bank_from_ledger(&self.ledger_path);
+        let (bank, entry_height, _) =
+            Self::new_bank_from_ledger(&self.ledger_path, self.account_store, false)
+                .unwrap_or_else(|err| panic!("unable to reload the ledger: {:?}", err));
+        bank.set_leader_scheduler(&self.crdt);
         self.bank = Arc::new(bank);
 
         {
@@ -400,6 +488,7 @@ impl Fullnode {
                 self.respond_socket
                     .try_clone()
                     .expect("Failed to clone respond socket"),
+                try_clone_listener(&self.requests_listener),
             ));
         }
 
@@ -421,6 +510,7 @@ impl Fullnode {
                 .expect("Failed to clone retransmit socket"),
             Some(&self.ledger_path),
         );
+        self.role_exit.set(tvu.exit_signal());
         let validator_state = ValidatorServices::new(tvu);
         self.node_role = Some(NodeRole::Validator(validator_state));
         Ok(())
@@ -437,8 +527,10 @@ impl Fullnode {
                 .iter()
                 .map(|s| s.try_clone().expect("Failed to clone transaction sockets"))
                 .collect(),
+            try_clone_listener(&self.transaction_listener),
             &self.ledger_path,
             self.sigverify_disabled,
+            self.sigverify_mode.clone(),
             entry_height,
         );
 
@@ -495,6 +587,27 @@ impl Fullnode {
         self.join()
     }
 
+    /// The signal for the services of the current role, which `handle_role_transition`
+    /// keeps pointing at the role it is blocked on.
+    pub fn role_exit_signal(&self) -> RoleExitSignal {
+        self.role_exit.clone()
+    }
+
+    /// The bank of the current role. A leader to validator transition replaces it.
+    pub fn bank(&self) -> Arc<Bank> {
+        self.bank.clone()
+    }
+
+    pub fn crdt(&self) -> Arc<RwLock<Crdt>> {
+        self.crdt.clone()
+    }
+
+    /// Switch how the TPU verifies signatures, taking effect from its next batch
+    /// of packets. The mode is kept across role transitions.
+    pub fn set_sigverify_mode(&self, mode: SigVerifyMode) {
+        *self.sigverify_mode.write().unwrap() = mode;
+    }
+
     // TODO: only used for testing, get rid of this once we have actual
     // leader scheduling
     pub fn set_scheduled_leader(&self, leader_id: Pubkey, entry_height: u64) {
@@ -504,17 +617,47 @@ impl Fullnode {
             .set_scheduled_leader(entry_height, leader_id);
     }
 
-    fn new_bank_from_ledger(ledger_path: &str) -> (Bank, u64, Vec<Entry>) {
-        let bank = Bank::new_default(false);
-        let entries = read_ledger(ledger_path, true).expect("opening ledger");
-        let entries = entries
-            .map(|e| e.unwrap_or_else(|err| panic!("failed to parse entry. error: {}", err)));
+    fn new_bank_from_ledger(
+        ledger_path: &str,
+        account_store: AccountStoreKind,
+        is_leader: bool,
+    ) -> result::Result<(Bank, u64, Vec<Entry>)> {
+        let accounts = || account_store.open(ledger_path);
+        if let Some(resumed) = checkpoint::resume_latest_bank(ledger_path, &accounts, is_leader) {
+            info!("resumed {} ledger from a checkpoint...", resumed.1);
+            return Ok(resumed);
+        }
+        // only a checkpoint gets a bank past the entries pruned from a ledger
+        let start = ledger_start(ledger_path)?;
+        if start > 0 {
+            error!(
+                "ledger starts at entry {} and none of its checkpoints resumes a bank",
+                start
+            );
+            return Err(BankError::LedgerVerificationFailed.into());
+        }
+        let bank = Bank::new_with_account_store(accounts()?, is_leader)?;
+        bank.set_program_dir(program_dir(ledger_path));
+        let entries = read_ledger(ledger_path, true)?;
+
+        // the ledger ends at the first entry that fails to parse
+        let mut error = None;
+        let entries = entries.scan(&mut error, |error, entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                **error = Some(err);
+                None
+            }
+        });
         info!("processing ledger...");
-        let (entry_height, ledger_tail) = bank.process_ledger(entries).expect("process_ledger");
+        let (entry_height, ledger_tail) = bank.process_ledger(entries)?;
+        if let Some(err) = error {
+            return Err(err.into());
+        }
         // entry_height is the network-wide agreed height of the ledger.
         //  initialize it from the input ledger
         info!("processed {} ledger...", entry_height);
-        (bank, entry_height, ledger_tail)
+        Ok((bank, entry_height, ledger_tail))
     }
 }
 
@@ -548,10 +691,12 @@ impl Service for Fullnode {
 
 #[cfg(test)]
 mod tests {
+    use crate::account_store::AccountStoreKind;
     use crate::tx_vault::Bank;
     use crate::crdt::Node;
     use crate::fullnode::{Fullnode, FullnodeReturnType};
-    use crate::ledger::genesis;
+    use crate::coinery::Mint;
+    use crate::ledger::{genesis, next_entries_mut, prune_ledger, tmp_ledger_path, LedgerWriter};
     use crate::packet::make_consecutive_blobs;
     use crate::service::Service;
     use crate::signature::{Keypair, KeypairUtil};
@@ -624,6 +769,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_new_bank_from_pruned_ledger() {
+        let ledger_path = tmp_ledger_path("test_new_bank_from_pruned_ledger");
+        let mint = Mint::new(10_000);
+        let mut entries = mint.create_entries();
+        let mut id = mint.last_id();
+        for _ in 0..4 {
+            entries.extend(next_entries_mut(&mut id, &mut 1, vec![]));
+        }
+        let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 2).unwrap();
+        writer.write_entries(entries).unwrap();
+
+        let (bank, entry_height, _) =
+            Fullnode::new_bank_from_ledger(&ledger_path, AccountStoreKind::Memory, false).unwrap();
+        assert_eq!(entry_height, 6);
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000);
+
+        // without a checkpoint, the ledger is no good once its start is pruned
+        assert_eq!(prune_ledger(&ledger_path, 4).unwrap(), 4);
+        assert!(
+            Fullnode::new_bank_from_ledger(&ledger_path, AccountStoreKind::Memory, false).is_err()
+        );
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
     #[test]
     fn test_validator_to_leader_transition() {
         // Make a leader identity
@@ -700,7 +871,9 @@ mod tests {
         }
 
         // Check the validator ledger to make sure it's the right height
-        let (_, entry_height, _) = Fullnode::new_bank_from_ledger(&validator_ledger_path);
+        let (_, entry_height, _) =
+            Fullnode::new_bank_from_ledger(&validator_ledger_path, AccountStoreKind::Memory, false)
+                .unwrap();
 
         assert_eq!(
             entry_height,
@@ -3,10 +3,11 @@
 //! access read to a persistent file-based ledger.
 
 use bincode::{self, deserialize, deserialize_from, serialize_into, serialized_size};
-use crate::budget_instruction::Vote;
+use crate::budget_instruction::{Vote, VoteSignature};
 use crate::budget_transaction::BudgetTransaction;
 use crate::entry::Entry;
 use crate::hash::Hash;
+use crate::ledger_index::{recover_ledger_index, trim_ledger_index, LedgerIndexWriter};
 use log::Level::Trace;
 #[cfg(test)]
 use crate::coinery::Mint;
@@ -16,27 +17,45 @@ use crate::result::{Error, Result};
 #[cfg(test)]
 use crate::signature::{Keypair, KeypairUtil};
 use buffett_program_interface::pubkey::Pubkey;
-use std::fs::{create_dir_all, remove_dir_all, File, OpenOptions};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, remove_file, rename, File, OpenOptions};
 use std::io::prelude::*;
-use std::io::{self, BufReader, BufWriter, Seek, SeekFrom};
+use std::io::{self, BufReader, BufWriter, Cursor, Seek, SeekFrom};
 use std::mem::size_of;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::transaction::Transaction;
 use crate::window::WINDOW_SIZE;
 
 //
-// A persistent ledger is 2 files:
+// A persistent ledger is a directory of segments, each holding a run of
+//  consecutive entries in 2 files:
 //  ledger_path/ --+
-//                 +-- index <== an array of u64 offsets into data,
-//                 |               each offset points to the first bytes
-//                 |               of a u64 that contains the length of
-//                 |               the entry.  To make the code smaller,
-//                 |               index[0] is set to 0, TODO: this field
-//                 |               could later be used for other stuff...
-//                 +-- data  <== concatenated instances of
-//                                    u64 length
-//                                    entry data
+//                 +-- segments/ --+
+//                 |               +-- <index of the segment's first entry>/ --+
+//                 |                   +-- index <== an array of u64 offsets into data,
+//                 |                   |               each offset points to the first bytes
+//                 |                   |               of a u64 that contains the length of
+//                 |                   |               the entry.  To make the code smaller,
+//                 |                   |               index[0] is set to 0, TODO: this field
+//                 |                   |               could later be used for other stuff...
+//                 |                   +-- data  <== concatenated instances of
+//                 |                                    u64 length
+//                 |                                    entry data
+//                 +-- transactions <== the transaction index, see ledger_index
+//
+// Only the last segment is ever appended to, a new one is started once it holds
+//   the writer's entries per segment.  Segments whose entries are covered by a
+//   bank checkpoint can be compressed, their data file is then replaced by a
+//   gzip of it in data.gz, or pruned, the ledger then starts at its first
+//   remaining segment.
+//
+// A ledger written before it was split into segments has its data and index
+//   files directly in ledger_path, it is moved into segment 0 the first time
+//   it is opened.
 //
 // When opening a ledger, we have the ability to "audit" it, which means we need
 //  to pick which file to use as "truth", and correct the other file as
@@ -49,6 +68,7 @@ use crate::window::WINDOW_SIZE;
 //     1. a partial write of data, which might be a partial write of length
 //          or a partial write entry data
 //     2. a partial or missing write to index for that entry
+//     3. a new segment that was started but holds no entry yet
 //
 // There is also the possibility of "unsynchronized" reading of the ledger
 //   during transfer across nodes via rsync (or whatever).  In this case, if the
@@ -58,19 +78,46 @@ use crate::window::WINDOW_SIZE;
 // The quickest and most reliable strategy for recovery is therefore to treat
 //   the data file as nearest to the "truth".
 //
-// The logic for "recovery/audit" is to open index and read backwards from the
-//   last u64-aligned entry to get to where index and data agree (i.e. where a
-//   successful deserialization of an entry can be performed), then truncate
-//   both files to this syncrhonization point.
+// The logic for "recovery/audit" is to drop an empty last segment, then open the
+//   index of the last segment and read backwards from the last u64-aligned entry
+//   to get to where index and data agree (i.e. where a successful deserialization
+//   of an entry can be performed), then truncate both files to this
+//   syncrhonization point.  The segments before the last one were complete when
+//   it was started, so they are never scanned.
 //
 
+/// The number of entries a ledger segment holds, unless the writer says otherwise.
+pub const LEDGER_SEGMENT_SIZE: u64 = 4096;
+
 // ledger window
 #[derive(Debug)]
 pub struct LedgerWindow {
+    ledger_path: PathBuf,
+    segments: Vec<u64>,
+    segment: Option<SegmentReader>,
+}
+
+// the open files of a segment
+struct SegmentReader {
+    first: u64,
     index: BufReader<File>,
-    data: BufReader<File>,
+    data: Box<SegmentData>,
+}
+
+impl fmt::Debug for SegmentReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SegmentReader {{ first: {} }}", self.first)
+    }
 }
 
+// the data file of a segment, read from the file or from memory once the
+//   segment is compressed
+trait SegmentData: BufRead + Seek + Send {}
+impl<T: BufRead + Seek + Send> SegmentData for T {}
+
+// the buffer of a reader that goes through the ledger in order
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
 // use a CONST because there's a cast, and we don't want "sizeof::<u64> as u64"...
 const SIZEOF_U64: u64 = size_of::<u64>() as u64;
 
@@ -98,49 +145,157 @@ fn u64_at<A: Read + Seek>(file: &mut A, at: u64) -> io::Result<u64> {
     deserialize_from(file.take(SIZEOF_U64)).map_err(err_bincode_to_io)
 }
 
+fn segments_path(ledger_path: &Path) -> PathBuf {
+    ledger_path.join("segments")
+}
+
+fn segment_path(ledger_path: &Path, first: u64) -> PathBuf {
+    segments_path(ledger_path).join(first.to_string())
+}
+
+// moves the data and index files of a ledger written before it was split into
+//   segments into its first segment, a ledger already split is left alone
+fn migrate_flat_ledger(ledger_path: &Path) -> io::Result<()> {
+    let segments = segments_path(ledger_path);
+    if segments.exists() {
+        return Ok(());
+    }
+    // the segment is only renamed into place once it has both files, so
+    //  a migration that was interrupted is picked up where it stopped
+    let staging = ledger_path.join("segments.tmp");
+    if !staging.exists() && !ledger_path.join("data").exists() {
+        return Ok(());
+    }
+    info!("moving ledger {:?} into segments", ledger_path);
+    let segment = staging.join("0");
+    create_dir_all(&segment)?;
+    for name in &["data", "index"] {
+        let flat = ledger_path.join(name);
+        if flat.exists() {
+            rename(flat, segment.join(name))?;
+        }
+    }
+    rename(staging, segments)
+}
+
+// opens the data file of a segment, buffering capacity bytes of it
+fn open_segment_data(segment_path: &Path, capacity: usize) -> io::Result<Box<SegmentData>> {
+    match File::open(segment_path.join("data")) {
+        Ok(data) => Ok(Box::new(BufReader::with_capacity(capacity, data))),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            let compressed = File::open(segment_path.join("data.gz"))?;
+            let mut data = vec![];
+            GzDecoder::new(BufReader::new(compressed)).read_to_end(&mut data)?;
+            Ok(Box::new(Cursor::new(data)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The index of the first entry of each segment of the ledger, in order.
+pub fn ledger_segments(ledger_path: &str) -> io::Result<Vec<u64>> {
+    migrate_flat_ledger(Path::new(ledger_path))?;
+    let mut segments = vec![];
+    for entry in read_dir(segments_path(Path::new(ledger_path)))? {
+        if let Some(first) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            segments.push(first);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// The index of the first entry the ledger holds, non-zero once it has been pruned.
+pub fn ledger_start(ledger_path: &str) -> io::Result<u64> {
+    ledger_segments(ledger_path)?
+        .first()
+        .cloned()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "ledger has no segments"))
+}
+
+// the first entry of the segment holding entry index
+fn segment_of(segments: &[u64], index: u64) -> io::Result<u64> {
+    match segments.binary_search(&index) {
+        Ok(i) => Ok(segments[i]),
+        Err(0) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("entry {} is not in the ledger", index),
+        )),
+        Err(i) => Ok(segments[i - 1]),
+    }
+}
+
+impl SegmentReader {
+    fn open(ledger_path: &Path, first: u64) -> io::Result<Self> {
+        let segment_path = segment_path(ledger_path, first);
+
+        let index = File::open(segment_path.join("index"))?;
+        let index = BufReader::with_capacity((WINDOW_SIZE * SIZEOF_U64) as usize, index);
+        let data = open_segment_data(&segment_path, WINDOW_SIZE as usize * BLOB_DATA_SIZE)?;
+
+        Ok(SegmentReader { first, index, data })
+    }
+}
+
 impl LedgerWindow {
     // opens a Ledger in directory, provides "infinite" window
     //
     pub fn open(ledger_path: &str) -> io::Result<Self> {
-        let ledger_path = Path::new(&ledger_path);
-
-        let index = File::open(ledger_path.join("index"))?;
-        let index = BufReader::with_capacity((WINDOW_SIZE * SIZEOF_U64) as usize, index);
-        let data = File::open(ledger_path.join("data"))?;
-        let data = BufReader::with_capacity(WINDOW_SIZE as usize * BLOB_DATA_SIZE, data);
+        let segments = ledger_segments(ledger_path)?;
+        let ledger_path = PathBuf::from(ledger_path);
+        let segment = match segments.last() {
+            Some(first) => Some(SegmentReader::open(&ledger_path, *first)?),
+            None => None,
+        };
 
-        Ok(LedgerWindow { index, data })
+        Ok(LedgerWindow {
+            ledger_path,
+            segments,
+            segment,
+        })
     }
 
     pub fn get_entry(&mut self, index: u64) -> io::Result<Entry> {
-        let offset = u64_at(&mut self.index, index * SIZEOF_U64)?;
-        entry_at(&mut self.data, offset)
+        // the ledger may have grown new segments since they were listed
+        if self.segments.last().map_or(true, |last| index >= *last) {
+            self.segments = ledger_segments(self.ledger_path.to_str().unwrap())?;
+        }
+        let first = segment_of(&self.segments, index)?;
+        if self.segment.as_ref().map(|segment| segment.first) != Some(first) {
+            self.segment = Some(SegmentReader::open(&self.ledger_path, first)?);
+        }
+        let segment = self.segment.as_mut().unwrap();
+
+        let offset = u64_at(&mut segment.index, (index - first) * SIZEOF_U64)?;
+        entry_at(&mut segment.data, offset)
     }
 }
 
-pub fn verify_ledger(ledger_path: &str) -> io::Result<()> {
-    let ledger_path = Path::new(&ledger_path);
-
-    let index = File::open(ledger_path.join("index"))?;
+// checks the files of the segment holding entries from first on, returns the
+//   number of entries in it
+fn verify_segment(segment_path: &Path, first: u64) -> io::Result<u64> {
+    let index = File::open(segment_path.join("index"))?;
 
     let index_len = index.metadata()?.len();
 
     if index_len % SIZEOF_U64 != 0 {
         Err(io::Error::new(
             io::ErrorKind::Other,
-            format!("index is not a multiple of {} bytes long", SIZEOF_U64),
+            format!(
+                "segment {} index is not a multiple of {} bytes long",
+                first, SIZEOF_U64
+            ),
         ))?;
     }
     let mut index = BufReader::with_capacity((WINDOW_SIZE * SIZEOF_U64) as usize, index);
 
-    let data = File::open(ledger_path.join("data"))?;
-    let mut data = BufReader::with_capacity(WINDOW_SIZE as usize * BLOB_DATA_SIZE, data);
+    let mut data = open_segment_data(segment_path, WINDOW_SIZE as usize * BLOB_DATA_SIZE)?;
 
     let mut last_data_offset = 0;
     let mut index_offset = 0;
     let mut data_read = 0;
     let mut last_len = 0;
-    let mut i = 0;
+    let mut i = first;
 
     while index_offset < index_len {
         let data_offset = u64_at(&mut index, index_offset)?;
@@ -160,7 +315,7 @@ pub fn verify_ledger(ledger_path: &str) -> io::Result<()> {
                 io::ErrorKind::Other,
                 format!(
                     "entry[{}] deserialize() failed at offset {}, err: {}",
-                    index_offset / SIZEOF_U64,
+                    i,
                     data_offset,
                     e.to_string(),
                 ),
@@ -175,27 +330,52 @@ pub fn verify_ledger(ledger_path: &str) -> io::Result<()> {
         index_offset += SIZEOF_U64;
         i += 1;
     }
-    let data = data.into_inner();
-    if data_read != data.metadata()?.len() {
+    if data_read != data.seek(SeekFrom::End(0))? {
         Err(io::Error::new(
             io::ErrorKind::Other,
-            "garbage on end of data file",
+            format!("garbage on end of segment {} data file", first),
         ))?;
     }
+    Ok(index_len / SIZEOF_U64)
+}
+
+pub fn verify_ledger(ledger_path: &str) -> io::Result<()> {
+    let segments = ledger_segments(ledger_path)?;
+    let ledger_path = Path::new(&ledger_path);
+
+    for (i, first) in segments.iter().enumerate() {
+        let num_entries = verify_segment(&segment_path(ledger_path, *first), *first)?;
+        if let Some(next) = segments.get(i + 1) {
+            if first + num_entries != *next {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!(
+                        "segment {} holds {} entries, the next segment starts at {}",
+                        first, num_entries, next
+                    ),
+                ))?;
+            }
+        }
+    }
     Ok(())
 }
 
-fn recover_ledger(ledger_path: &str) -> io::Result<()> {
-    let ledger_path = Path::new(ledger_path);
+// recovers the files of a segment, returns the number of entries left in it
+fn recover_segment(segment_path: &Path) -> io::Result<u64> {
+    // a segment can be started without its files
+    if !segment_path.join("index").exists() || !segment_path.join("data").exists() {
+        return Ok(0);
+    }
+
     let mut index = OpenOptions::new()
         .write(true)
         .read(true)
-        .open(ledger_path.join("index"))?;
+        .open(segment_path.join("index"))?;
 
     let mut data = OpenOptions::new()
         .write(true)
         .read(true)
-        .open(ledger_path.join("data"))?;
+        .open(segment_path.join("data"))?;
 
     // first, truncate to a multiple of SIZEOF_U64
     let len = index.metadata()?.len();
@@ -211,11 +391,9 @@ fn recover_ledger(ledger_path: &str) -> io::Result<()> {
         let len = index.metadata()?.len();
         trace!("recover: index len:{}", len);
 
-        // should never happen
         if len < SIZEOF_U64 {
-            trace!("recover: error index len {} too small", len);
-
-            Err(io::Error::new(io::ErrorKind::Other, "empty ledger index"))?;
+            trace!("recover: index len {} too small", len);
+            break;
         }
 
         let offset = u64_at(&mut index, len - SIZEOF_U64)?;
@@ -249,14 +427,83 @@ fn recover_ledger(ledger_path: &str) -> io::Result<()> {
             }
         }
     }
-    if log_enabled!(Trace) {
-        let num_entries = index.metadata()?.len() / SIZEOF_U64;
-        trace!("recover: done. {} entries", num_entries);
-    }
+    let num_entries = index.metadata()?.len() / SIZEOF_U64;
+    trace!("recover: done. {} entries", num_entries);
 
     // flush everything to disk...
     index.sync_all()?;
-    data.sync_all()
+    data.sync_all()?;
+    Ok(num_entries)
+}
+
+fn recover_ledger(ledger_path: &str) -> io::Result<()> {
+    let mut segments = ledger_segments(ledger_path)?;
+
+    while let Some(first) = segments.pop() {
+        let segment_path = segment_path(Path::new(ledger_path), first);
+        let num_entries = recover_segment(&segment_path)?;
+        if num_entries > 0 {
+            return recover_ledger_index(ledger_path, first + num_entries);
+        }
+        // should never happen to the first segment
+        if segments.is_empty() {
+            break;
+        }
+        trace!("recover: removing empty segment {}", first);
+        remove_dir_all(segment_path)?;
+    }
+    Err(io::Error::new(io::ErrorKind::Other, "empty ledger index"))
+}
+
+/// Remove the segments of the ledger that only hold entries before `keep_from`,
+/// the last segment is always kept, and the records of their transactions from
+/// the ledger's transaction index. Returns the index of the first entry the
+/// ledger still holds.
+pub fn prune_ledger(ledger_path: &str, keep_from: u64) -> io::Result<u64> {
+    let segments = ledger_segments(ledger_path)?;
+    let mut start = 0;
+    for (i, first) in segments.iter().enumerate() {
+        start = *first;
+        match segments.get(i + 1) {
+            Some(next) if *next <= keep_from => {
+                info!("pruning ledger segment {}", first);
+                remove_dir_all(segment_path(Path::new(ledger_path), *first))?;
+            }
+            _ => break,
+        }
+    }
+    trim_ledger_index(ledger_path, start)?;
+    Ok(start)
+}
+
+/// Compress the segments of the ledger that only hold entries before `keep_from`,
+/// the last segment is never compressed. Returns the number of segments compressed.
+pub fn compress_ledger(ledger_path: &str, keep_from: u64) -> io::Result<usize> {
+    let segments = ledger_segments(ledger_path)?;
+    let mut compressed = 0;
+    for (i, first) in segments.iter().enumerate() {
+        match segments.get(i + 1) {
+            Some(next) if *next <= keep_from => (),
+            _ => break,
+        }
+        let segment_path = segment_path(Path::new(ledger_path), *first);
+        let data_path = segment_path.join("data");
+        if !data_path.exists() {
+            continue;
+        }
+        info!("compressing ledger segment {}", first);
+        let tmp_path = segment_path.join("data.gz.tmp");
+        {
+            let mut data = BufReader::new(File::open(&data_path)?);
+            let mut encoder = GzEncoder::new(File::create(&tmp_path)?, Compression::default());
+            io::copy(&mut data, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+        }
+        rename(tmp_path, segment_path.join("data.gz"))?;
+        remove_file(data_path)?;
+        compressed += 1;
+    }
+    Ok(compressed)
 }
 
 // TODO?? ... we could open the files on demand to support [], but today
@@ -275,8 +522,43 @@ fn recover_ledger(ledger_path: &str) -> io::Result<()> {
 
 #[derive(Debug)]
 pub struct LedgerWriter {
+    ledger_path: PathBuf,
+    entries_per_segment: u64,
+    segment_first: u64,
     index: BufWriter<File>,
     data: BufWriter<File>,
+    transactions: LedgerIndexWriter,
+    entry_height: u64,
+}
+
+// opens the files of the segment in segment_path for appending
+fn open_segment(
+    segment_path: &Path,
+    create: bool,
+) -> io::Result<(BufWriter<File>, BufWriter<File>)> {
+    let index = OpenOptions::new()
+        .create(create)
+        .append(true)
+        .open(segment_path.join("index"))?;
+
+    if log_enabled!(Trace) {
+        let len = index.metadata()?.len();
+        trace!("LedgerWriter::new: index fp:{}", len);
+    }
+    let index = BufWriter::new(index);
+
+    let data = OpenOptions::new()
+        .create(create)
+        .append(true)
+        .open(segment_path.join("data"))?;
+
+    if log_enabled!(Trace) {
+        let len = data.metadata()?.len();
+        trace!("LedgerWriter::new: data fp:{}", len);
+    }
+    let data = BufWriter::new(data);
+
+    Ok((index, data))
 }
 
 impl LedgerWriter {
@@ -288,39 +570,67 @@ impl LedgerWriter {
 
     // opens or creates a LedgerWriter in ledger_path directory
     pub fn open(ledger_path: &str, create: bool) -> io::Result<Self> {
-        let ledger_path = Path::new(&ledger_path);
+        Self::open_with_segment_size(ledger_path, create, LEDGER_SEGMENT_SIZE)
+    }
 
+    // opens or creates a LedgerWriter in ledger_path directory that starts a new
+    //   segment every entries_per_segment entries
+    pub fn open_with_segment_size(
+        ledger_path: &str,
+        create: bool,
+        entries_per_segment: u64,
+    ) -> io::Result<Self> {
+        assert!(entries_per_segment > 0);
         if create {
             let _ignored = remove_dir_all(ledger_path);
-            create_dir_all(ledger_path)?;
-        }
-
-        let index = OpenOptions::new()
-            .create(create)
-            .append(true)
-            .open(ledger_path.join("index"))?;
-
-        if log_enabled!(Trace) {
-            let len = index.metadata()?.len();
-            trace!("LedgerWriter::new: index fp:{}", len);
+            create_dir_all(segment_path(Path::new(ledger_path), 0))?;
         }
-        let index = BufWriter::new(index);
-
-        let data = OpenOptions::new()
-            .create(create)
-            .append(true)
-            .open(ledger_path.join("data"))?;
 
-        if log_enabled!(Trace) {
-            let len = data.metadata()?.len();
-            trace!("LedgerWriter::new: data fp:{}", len);
-        }
-        let data = BufWriter::new(data);
+        let segment_first = *ledger_segments(ledger_path)?
+            .last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "ledger has no segments"))?;
+        let segment_path = segment_path(Path::new(ledger_path), segment_first);
+
+        // a segment the writer started, but didn't write to, may have no files yet
+        let create_segment = create || segment_first > 0;
+        let (index, data) = open_segment(&segment_path, create_segment)?;
+        let entry_height = segment_first + index.get_ref().metadata()?.len() / SIZEOF_U64;
+
+        let transactions = LedgerIndexWriter::open(ledger_path, create)?;
+
+        Ok(LedgerWriter {
+            ledger_path: PathBuf::from(ledger_path),
+            entries_per_segment,
+            segment_first,
+            index,
+            data,
+            transactions,
+            entry_height,
+        })
+    }
 
-        Ok(LedgerWriter { index, data })
+    // finishes the last segment and starts the next one at entry_height
+    fn start_segment(&mut self) -> io::Result<()> {
+        self.index.flush()?;
+        self.data.flush()?;
+        self.data.get_ref().sync_all()?;
+        self.index.get_ref().sync_all()?;
+
+        let segment_path = segment_path(&self.ledger_path, self.entry_height);
+        trace!("write_entry: starting segment {:?}", segment_path);
+        create_dir_all(&segment_path)?;
+        let (index, data) = open_segment(&segment_path, true)?;
+        self.index = index;
+        self.data = data;
+        self.segment_first = self.entry_height;
+        Ok(())
     }
 
     fn write_entry_noflush(&mut self, entry: &Entry) -> io::Result<()> {
+        if self.entry_height - self.segment_first >= self.entries_per_segment {
+            self.start_segment()?;
+        }
+
         let len = serialized_size(&entry).map_err(err_bincode_to_io)?;
 
         serialize_into(&mut self.data, &len).map_err(err_bincode_to_io)?;
@@ -344,6 +654,9 @@ impl LedgerWriter {
             let offset = self.index.seek(SeekFrom::Current(0))?;
             trace!("write_entry: end index fp:{}", offset);
         }
+
+        self.transactions.write_entry(self.entry_height, entry)?;
+        self.entry_height += 1;
         Ok(())
     }
 
@@ -351,6 +664,7 @@ impl LedgerWriter {
         self.write_entry_noflush(&entry)?;
         self.index.flush()?;
         self.data.flush()?;
+        self.transactions.flush()?;
         Ok(())
     }
 
@@ -363,27 +677,63 @@ impl LedgerWriter {
         }
         self.index.flush()?;
         self.data.flush()?;
+        self.transactions.flush()?;
         Ok(())
     }
 }
 
-#[derive(Debug)]
 pub struct LedgerReader {
-    data: BufReader<File>,
+    ledger_path: PathBuf,
+    // the segments after the one being read
+    segments: Vec<u64>,
+    first: u64,
+    data: Box<SegmentData>,
+}
+
+impl fmt::Debug for LedgerReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LedgerReader {{ ledger_path: {:?}, first: {} }}",
+            self.ledger_path, self.first
+        )
+    }
 }
 
 impl Iterator for LedgerReader {
     type Item = io::Result<Entry>;
 
     fn next(&mut self) -> Option<io::Result<Entry>> {
-        match next_entry(&mut self.data) {
-            Ok(entry) => Some(Ok(entry)),
-            Err(_) => None,
+        loop {
+            // the last segment may end in an entry that is still being written,
+            //  the ones before it were complete when the next one was started
+            if self.segments.is_empty() {
+                return next_entry(&mut self.data).ok().map(Ok);
+            }
+            let at_end = match self.data.fill_buf() {
+                Ok(buf) => buf.is_empty(),
+                Err(e) => return Some(Err(e)),
+            };
+            if !at_end {
+                let first = self.first;
+                return Some(next_entry(&mut self.data).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("ledger segment {}: {}", first, e),
+                    )
+                }));
+            }
+            self.first = self.segments.remove(0);
+            let segment_path = segment_path(&self.ledger_path, self.first);
+            match open_segment_data(&segment_path, DEFAULT_BUF_SIZE) {
+                Ok(data) => self.data = data,
+                Err(e) => return Some(Err(e)),
+            }
         }
     }
 }
 
-/// Return an iterator for all the entries in the given file.
+/// Return an iterator for all the entries the given ledger holds.
 pub fn read_ledger(
     ledger_path: &str,
     recover: bool,
@@ -392,11 +742,42 @@ pub fn read_ledger(
         recover_ledger(ledger_path)?;
     }
 
-    let ledger_path = Path::new(&ledger_path);
-    let data = File::open(ledger_path.join("data"))?;
-    let data = BufReader::new(data);
+    read_ledger_from(ledger_path, ledger_start(ledger_path)?, false)
+}
+
+/// Return an iterator for the entries of the given ledger from index `start` on,
+/// seeking past the ones before it.
+pub fn read_ledger_from(
+    ledger_path: &str,
+    start: u64,
+    recover: bool,
+) -> io::Result<impl Iterator<Item = io::Result<Entry>>> {
+    if recover {
+        recover_ledger(ledger_path)?;
+    }
 
-    Ok(LedgerReader { data })
+    let mut segments = ledger_segments(ledger_path)?;
+    let first = segment_of(&segments, start)?;
+    segments.retain(|segment| *segment > first);
+
+    let ledger_path = PathBuf::from(ledger_path);
+    let segment_path = segment_path(&ledger_path, first);
+    let mut index = File::open(segment_path.join("index"))?;
+    let mut data = open_segment_data(&segment_path, DEFAULT_BUF_SIZE)?;
+
+    let offset = if (start - first) * SIZEOF_U64 == index.metadata()?.len() {
+        data.seek(SeekFrom::End(0))?
+    } else {
+        u64_at(&mut index, (start - first) * SIZEOF_U64)?
+    };
+    data.seek(SeekFrom::Start(offset))?;
+
+    Ok(LedgerReader {
+        ledger_path,
+        segments,
+        first,
+        data,
+    })
 }
 
 // a Block is a slice of Entries
@@ -405,7 +786,7 @@ pub trait Block {
     fn verify(&self, start_hash: &Hash) -> bool;
     fn to_blobs(&self) -> Vec<SharedBlob>;
     fn to_blobs_with_id(&self, id: Pubkey, start_id: u64, addr: &SocketAddr) -> Vec<SharedBlob>;
-    fn votes(&self) -> Vec<(Pubkey, Vote, Hash)>;
+    fn votes(&self) -> Vec<(Pubkey, Vote, VoteSignature, Hash)>;
 }
 
 impl Block for [Entry] {
@@ -437,7 +818,7 @@ impl Block for [Entry] {
         self.to_blobs_with_id(Pubkey::default(), 0, &default_addr)
     }
 
-    fn votes(&self) -> Vec<(Pubkey, Vote, Hash)> {
+    fn votes(&self) -> Vec<(Pubkey, Vote, VoteSignature, Hash)> {
         self.iter()
             .flat_map(|entry| {
                 entry
@@ -563,11 +944,12 @@ pub fn genesis(name: &str, num: i64) -> (Mint, String) {
 mod tests {
     use super::*;
     use bincode::serialized_size;
-    use crate::budget_instruction::Vote;
+    use crate::budget_instruction::{Vote, VoteSignature};
     use crate::budget_transaction::BudgetTransaction;
     use chrono::prelude::*;
     use crate::entry::{next_entry, Entry};
     use crate::hash::hash;
+    use crate::ledger_index::LedgerIndex;
     use crate::packet::{to_blobs, BLOB_DATA_SIZE, PACKET_DATA_SIZE};
     use crate::signature::{Keypair, KeypairUtil};
     use std;
@@ -623,6 +1005,7 @@ mod tests {
                 version: 0,
                 contact_info_version: 1,
             },
+            VoteSignature::default(),
             one,
             1,
         );
@@ -675,16 +1058,17 @@ mod tests {
         let id = Hash::default();
         let next_id = hash(&id.as_ref());
         let keypair = Keypair::new();
-        let tx_small = Transaction::budget_new_vote(
+        let tx_small = Transaction::budget_new(&keypair, keypair.pubkey(), 1, next_id);
+        let tx_large = Transaction::budget_new_vote(
             &keypair,
             Vote {
                 version: 0,
                 contact_info_version: 2,
             },
+            VoteSignature::default(),
             next_id,
             2,
         );
-        let tx_large = Transaction::budget_new(&keypair, keypair.pubkey(), 1, next_id);
 
         let tx_small_size = serialized_size(&tx_small).unwrap() as usize;
         let tx_large_size = serialized_size(&tx_large).unwrap() as usize;
@@ -752,7 +1136,15 @@ mod tests {
         }
         assert!(window.get_entry(100).is_err());
 
-        std::fs::remove_file(Path::new(&ledger_path).join("data")).unwrap();
+        let read_entries: Vec<_> = read_ledger_from(&ledger_path, 4, true)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(read_entries, &entries[4..]);
+        assert_eq!(read_ledger_from(&ledger_path, 10, true).unwrap().count(), 0);
+        assert!(read_ledger_from(&ledger_path, 11, true).is_err());
+
+        std::fs::remove_file(segment_path(Path::new(&ledger_path), 0).join("data")).unwrap();
         // empty data file should fall over
         assert!(LedgerWindow::open(&ledger_path).is_err());
         assert!(read_ledger(&ledger_path, false).is_err());
@@ -770,7 +1162,7 @@ mod tests {
 
         let data = OpenOptions::new()
             .write(true)
-            .open(Path::new(&ledger_path).join("data"))
+            .open(segment_path(Path::new(&ledger_path), 0).join("data"))
             .unwrap();
         data.set_len(len - 4).unwrap();
     }
@@ -851,6 +1243,173 @@ mod tests {
         let _ignored = remove_dir_all(&ledger_path);
     }
 
+    #[test]
+    fn test_ledger_segments() {
+        let ledger_path = tmp_ledger_path("test_ledger_segments");
+        let entries = make_tiny_test_entries(12);
+        {
+            let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 3).unwrap();
+            writer.write_entries(entries[..10].to_vec()).unwrap();
+        }
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![0, 3, 6, 9]);
+        verify_ledger(&ledger_path).unwrap();
+        read_ledger_check(&ledger_path, entries.clone(), 10);
+        ledger_window_check(&ledger_path, entries.clone(), 10);
+
+        // reads start and carry on across segments
+        let read_entries: Vec<_> = read_ledger_from(&ledger_path, 5, false)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(read_entries, &entries[5..10]);
+        assert_eq!(read_ledger_from(&ledger_path, 10, false).unwrap().count(), 0);
+
+        // a reopened writer appends to the last segment, and a window sees it
+        let mut window = LedgerWindow::open(&ledger_path).unwrap();
+        {
+            let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, false, 3).unwrap();
+            writer.write_entries(entries[10..].to_vec()).unwrap();
+        }
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![0, 3, 6, 9]);
+        assert_eq!(window.get_entry(11).unwrap(), entries[11]);
+
+        // a segment that was started, but never written to, is dropped by recovery
+        create_dir_all(segment_path(Path::new(&ledger_path), 12)).unwrap();
+        assert!(verify_ledger(&ledger_path).is_err());
+        LedgerWriter::recover(&ledger_path).unwrap();
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![0, 3, 6, 9]);
+        verify_ledger(&ledger_path).unwrap();
+
+        // pruning only removes segments that end before the entry kept from
+        assert_eq!(prune_ledger(&ledger_path, 7).unwrap(), 6);
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![6, 9]);
+        assert_eq!(ledger_start(&ledger_path).unwrap(), 6);
+        verify_ledger(&ledger_path).unwrap();
+        let read_entries: Vec<_> = read_ledger(&ledger_path, true)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(read_entries, &entries[6..]);
+        let mut window = LedgerWindow::open(&ledger_path).unwrap();
+        assert!(window.get_entry(5).is_err());
+        assert_eq!(window.get_entry(6).unwrap(), entries[6]);
+        assert!(read_ledger_from(&ledger_path, 2, false).is_err());
+
+        // the transactions of the pruned entries are no longer in the index
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        let pruned = entries[5].transactions[0].signature;
+        assert_eq!(index.get_entry_index(&pruned).unwrap(), None);
+        let kept = entries[6].transactions[0].signature;
+        assert_eq!(index.get_entry_index(&kept).unwrap(), Some(6));
+
+        // and never the last one
+        assert_eq!(prune_ledger(&ledger_path, 100).unwrap(), 9);
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![9]);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_compress_ledger() {
+        let ledger_path = tmp_ledger_path("test_compress_ledger");
+        let entries = make_tiny_test_entries(10);
+        {
+            let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 3).unwrap();
+            writer.write_entries(entries.clone()).unwrap();
+        }
+
+        // compression, like pruning, stops at the segment holding the entry kept from
+        assert_eq!(compress_ledger(&ledger_path, 7).unwrap(), 2);
+        assert_eq!(compress_ledger(&ledger_path, 7).unwrap(), 0);
+        let segment = segment_path(Path::new(&ledger_path), 3);
+        assert!(!segment.join("data").exists());
+        assert!(segment.join("data.gz").exists());
+        assert!(segment_path(Path::new(&ledger_path), 6)
+            .join("data")
+            .exists());
+
+        verify_ledger(&ledger_path).unwrap();
+        read_ledger_check(&ledger_path, entries.clone(), 10);
+        ledger_window_check(&ledger_path, entries.clone(), 10);
+        let read_entries: Vec<_> = read_ledger_from(&ledger_path, 4, false)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(read_entries, &entries[4..]);
+
+        // and never touches the last one, which is still written to
+        assert_eq!(compress_ledger(&ledger_path, 100).unwrap(), 1);
+        {
+            let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, false, 3).unwrap();
+            writer.write_entries(make_tiny_test_entries(1)).unwrap();
+        }
+        assert_eq!(read_ledger(&ledger_path, true).unwrap().count(), 11);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_reader_corrupt_segment() {
+        let ledger_path = tmp_ledger_path("test_ledger_reader_corrupt_segment");
+        let entries = make_tiny_test_entries(6);
+        {
+            let mut writer = LedgerWriter::open_with_segment_size(&ledger_path, true, 3).unwrap();
+            writer.write_entries(entries.clone()).unwrap();
+        }
+
+        // an entry that doesn't parse in a complete segment is an error, not its end
+        {
+            let data_path = segment_path(Path::new(&ledger_path), 0).join("data");
+            let mut data = OpenOptions::new().write(true).open(data_path).unwrap();
+            data.set_len(SIZEOF_U64 + 1).unwrap();
+            data.seek(SeekFrom::End(0)).unwrap();
+            data.write_all(&[0xff; 64]).unwrap();
+        }
+        let mut reader = read_ledger(&ledger_path, false).unwrap();
+        assert!(reader.next().unwrap().is_err());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_flat_ledger() {
+        let ledger_path = tmp_ledger_path("test_migrate_flat_ledger");
+        let entries = make_tiny_test_entries(10);
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(entries.clone()).unwrap();
+        }
+
+        // lay the ledger out the way it was before segments
+        let segment = segment_path(Path::new(&ledger_path), 0);
+        for name in &["data", "index"] {
+            rename(segment.join(name), Path::new(&ledger_path).join(name)).unwrap();
+        }
+        remove_dir_all(segments_path(Path::new(&ledger_path))).unwrap();
+        remove_file(Path::new(&ledger_path).join("transactions")).unwrap();
+
+        read_ledger_check(&ledger_path, entries.clone(), 10);
+        assert_eq!(ledger_segments(&ledger_path).unwrap(), vec![0]);
+        assert!(!Path::new(&ledger_path).join("data").exists());
+        verify_ledger(&ledger_path).unwrap();
+
+        // a migration that stopped half way is finished
+        for name in &["data", "index"] {
+            rename(segment.join(name), Path::new(&ledger_path).join(name)).unwrap();
+        }
+        remove_dir_all(segments_path(Path::new(&ledger_path))).unwrap();
+        create_dir_all(Path::new(&ledger_path).join("segments.tmp").join("0")).unwrap();
+        rename(
+            Path::new(&ledger_path).join("data"),
+            Path::new(&ledger_path).join("segments.tmp/0/data"),
+        ).unwrap();
+        let mut writer = LedgerWriter::open(&ledger_path, false).unwrap();
+        writer.write_entries(make_tiny_test_entries(1)).unwrap();
+        assert_eq!(read_ledger(&ledger_path, true).unwrap().count(), 11);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
     #[test]
     fn test_verify_ledger() {
         use crate::logger;
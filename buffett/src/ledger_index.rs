@@ -0,0 +1,706 @@
+//! The `ledger_index` module keeps a secondary index of the transactions in a
+//! persistent ledger, so the entry holding a signature and the signatures that
+//! touched a pubkey can be found without scanning the ledger.
+
+use bincode::{self, deserialize_from, serialize};
+use crate::entry::Entry;
+use crate::ledger::{ledger_start, read_ledger};
+use crate::signature::Signature;
+use buffett_program_interface::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{rename, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+
+//
+// The index is one more file in the ledger directory:
+//  ledger_path/ --+
+//                 +-- transactions <== concatenated instances of
+//                                        u64 length
+//                                        TransactionRecord
+//
+// It is appended to after the entry is in data and index, so it can only be
+// behind the ledger, and is trimmed to the ledger when the ledger is recovered.
+// The records of the segments pruned from the ledger are dropped from its front.
+//
+
+const TRANSACTIONS_FILE: &str = "transactions";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransactionRecord {
+    pub signature: Signature,
+    /// The index of the entry holding the transaction in the ledger
+    pub entry_index: u64,
+    /// The keys of the transaction
+    pub keys: Vec<Pubkey>,
+}
+
+fn err_bincode_to_io(e: Box<bincode::ErrorKind>) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+fn err_trimmed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "ledger index trimmed")
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &TransactionRecord) -> io::Result<()> {
+    let data = serialize(record).map_err(err_bincode_to_io)?;
+    writer.write_all(&serialize(&(data.len() as u64)).map_err(err_bincode_to_io)?)?;
+    writer.write_all(&data)
+}
+
+// None at the end of the file, or of what has been completely written of it
+fn read_record<R: Read>(reader: &mut R) -> Option<(TransactionRecord, u64)> {
+    let len: u64 = deserialize_from(reader.take(8)).ok()?;
+    let record = deserialize_from(reader.take(len)).ok()?;
+    Some((record, 8 + len))
+}
+
+fn write_entry_records<W: Write>(
+    writer: &mut W,
+    entry_index: u64,
+    entry: &Entry,
+) -> io::Result<()> {
+    for tx in &entry.transactions {
+        let record = TransactionRecord {
+            signature: tx.signature,
+            entry_index,
+            keys: tx.keys.clone(),
+        };
+        write_record(writer, &record)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct LedgerIndexWriter {
+    transactions: BufWriter<File>,
+}
+
+impl LedgerIndexWriter {
+    // opens or creates the index of the ledger in ledger_path, an existing
+    //   ledger without one has it built first
+    pub fn open(ledger_path: &str, create: bool) -> io::Result<Self> {
+        let path = Path::new(ledger_path).join(TRANSACTIONS_FILE);
+        if !create && !path.exists() {
+            rebuild_ledger_index(ledger_path)?;
+        }
+        let transactions = OpenOptions::new()
+            .create(create)
+            .append(true)
+            .open(path)?;
+        Ok(LedgerIndexWriter {
+            transactions: BufWriter::new(transactions),
+        })
+    }
+
+    pub fn write_entry(&mut self, entry_index: u64, entry: &Entry) -> io::Result<()> {
+        write_entry_records(&mut self.transactions, entry_index, entry)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.transactions.flush()
+    }
+}
+
+/// Build the index of the ledger in `ledger_path` from its entries, replacing
+/// the current one.
+pub fn rebuild_ledger_index(ledger_path: &str) -> io::Result<()> {
+    let path = Path::new(ledger_path).join(TRANSACTIONS_FILE);
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut transactions = BufWriter::new(File::create(&tmp_path)?);
+        let start = ledger_start(ledger_path)?;
+        for (i, entry) in read_ledger(ledger_path, false)?.enumerate() {
+            write_entry_records(&mut transactions, start + i as u64, &entry?)?;
+        }
+        transactions.flush()?;
+    }
+    rename(tmp_path, path)
+}
+
+// trim the index to the records of the first num_entries entries of the ledger
+pub fn recover_ledger_index(ledger_path: &str, num_entries: u64) -> io::Result<()> {
+    let path = Path::new(ledger_path).join(TRANSACTIONS_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut len = 0;
+    while let Some((record, record_len)) = read_record(&mut reader) {
+        if record.entry_index >= num_entries {
+            break;
+        }
+        len += record_len;
+    }
+    let transactions = OpenOptions::new().write(true).open(&path)?;
+    if transactions.metadata()?.len() != len {
+        trace!("recover: trimming transaction index to {}", len);
+        transactions.set_len(len)?;
+    }
+    transactions.sync_all()
+}
+
+/// Drop the records of the entries before `start` from the index of the ledger in
+/// `ledger_path`, once the segments holding them have been pruned.
+pub fn trim_ledger_index(ledger_path: &str, start: u64) -> io::Result<()> {
+    let path = Path::new(ledger_path).join(TRANSACTIONS_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut len = 0;
+    while let Some((record, record_len)) = read_record(&mut reader) {
+        if record.entry_index >= start {
+            break;
+        }
+        len += record_len;
+    }
+    if len == 0 {
+        return Ok(());
+    }
+    trace!(
+        "trimming {} bytes of pruned entries from the transaction index",
+        len
+    );
+    let tmp_path = path.with_extension("tmp");
+    {
+        reader.seek(SeekFrom::Start(len))?;
+        let mut transactions = BufWriter::new(File::create(&tmp_path)?);
+        io::copy(&mut reader, &mut transactions)?;
+        transactions.flush()?;
+        transactions.get_ref().sync_all()?;
+    }
+    rename(tmp_path, path)
+}
+
+/// The most signatures `LedgerIndex::get_signatures` returns at once.
+pub const MAX_SIGNATURES_LIMIT: usize = 1000;
+
+/// The most records `LedgerIndex::open` keeps the offsets of in memory.
+pub const MAX_RECORDS_IN_MEMORY: usize = 1 << 20;
+
+/// Lookups by signature and by pubkey into the index of a ledger. Only the
+/// offsets of the latest records are kept in memory, up to a limit, the records
+/// are read from the index file when they are asked for, and the ones older than
+/// the records in memory are searched for in it. It catches up with the records
+/// written since it was opened on `refresh`.
+#[derive(Debug)]
+pub struct LedgerIndex {
+    path: PathBuf,
+    transactions: BufReader<File>,
+    offset: u64,
+    // the offset and signature of the last record read, to tell the index was
+    //  trimmed by a ledger recovery, or rebuilt, since
+    last_record: Option<(u64, Signature)>,
+    // the offset of the first record in memory, and the number of them
+    memory_start: u64,
+    memory_records: usize,
+    max_records: usize,
+    entries: HashMap<Signature, u64>,
+    signatures: HashMap<Pubkey, Vec<u64>>,
+}
+
+impl LedgerIndex {
+    pub fn open(ledger_path: &str) -> io::Result<Self> {
+        Self::open_with_memory_limit(ledger_path, MAX_RECORDS_IN_MEMORY)
+    }
+
+    /// Open the index of the ledger in `ledger_path` keeping the offsets of at most
+    /// `max_records` records in memory.
+    pub fn open_with_memory_limit(ledger_path: &str, max_records: usize) -> io::Result<Self> {
+        let path = Path::new(ledger_path).join(TRANSACTIONS_FILE);
+        let transactions = File::open(&path)?;
+        let mut index = LedgerIndex {
+            path,
+            transactions: BufReader::new(transactions),
+            offset: 0,
+            last_record: None,
+            memory_start: 0,
+            memory_records: 0,
+            max_records: max_records.max(1),
+            entries: HashMap::new(),
+            signatures: HashMap::new(),
+        };
+        index.refresh()?;
+        Ok(index)
+    }
+
+    fn read_record_at(&mut self, offset: u64) -> io::Result<Option<TransactionRecord>> {
+        self.transactions.seek(SeekFrom::Start(offset))?;
+        Ok(read_record(&mut self.transactions).map(|(record, _)| record))
+    }
+
+    // whether the records read so far are still the ones in the index file
+    fn is_current(&mut self) -> io::Result<bool> {
+        if self.transactions.get_ref().metadata()?.len() < self.offset {
+            return Ok(false);
+        }
+        match self.last_record {
+            Some((offset, signature)) => Ok(self
+                .read_record_at(offset)?
+                .map_or(false, |record| record.signature == signature)),
+            None => Ok(true),
+        }
+    }
+
+    /// Read the records appended to the index since the last refresh, starting
+    /// over if the ones already read are gone.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        // the index may have been replaced since it was opened
+        self.transactions = BufReader::new(File::open(&self.path)?);
+        if !self.is_current()? {
+            trace!("ledger index changed, reloading it");
+            self.offset = 0;
+            self.last_record = None;
+            self.memory_start = 0;
+            self.memory_records = 0;
+            self.entries.clear();
+            self.signatures.clear();
+        }
+
+        self.transactions.seek(SeekFrom::Start(self.offset))?;
+        while let Some((record, record_len)) = read_record(&mut self.transactions) {
+            let offset = self.offset;
+            self.offset += record_len;
+            self.last_record = Some((offset, record.signature));
+            self.memory_records += 1;
+            self.entries.insert(record.signature, offset);
+            for key in record.keys {
+                let offsets = self.signatures.entry(key).or_insert_with(Vec::new);
+                // a transaction can have the same key more than once
+                if offsets.last() != Some(&offset) {
+                    offsets.push(offset);
+                }
+            }
+        }
+        if self.memory_records > self.max_records {
+            self.drop_oldest()?;
+        }
+        Ok(())
+    }
+
+    // drop the oldest records from memory, a quarter of the limit at a time
+    fn drop_oldest(&mut self) -> io::Result<()> {
+        let keep = self.max_records - self.max_records / 4;
+        let mut keys = HashSet::new();
+        self.transactions.seek(SeekFrom::Start(self.memory_start))?;
+        while self.memory_records > keep {
+            let (record, record_len) =
+                read_record(&mut self.transactions).ok_or_else(err_trimmed)?;
+            if self.entries.get(&record.signature) == Some(&self.memory_start) {
+                self.entries.remove(&record.signature);
+            }
+            keys.extend(record.keys);
+            self.memory_start += record_len;
+            self.memory_records -= 1;
+        }
+        for key in keys {
+            let memory_start = self.memory_start;
+            let dropped = self.signatures.get_mut(&key).map_or(false, |offsets| {
+                offsets.retain(|offset| *offset >= memory_start);
+                offsets.is_empty()
+            });
+            if dropped {
+                self.signatures.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    // call f with each of the records older than the ones in memory and its offset,
+    //  in order, until it returns false
+    fn scan_dropped<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(u64, TransactionRecord) -> bool,
+    {
+        self.transactions.seek(SeekFrom::Start(0))?;
+        let mut offset = 0;
+        while offset < self.memory_start {
+            let (record, record_len) =
+                read_record(&mut self.transactions).ok_or_else(err_trimmed)?;
+            if !f(offset, record) {
+                break;
+            }
+            offset += record_len;
+        }
+        Ok(())
+    }
+
+    // the offset of the record of the transaction with signature
+    fn find_offset(&mut self, signature: &Signature) -> io::Result<Option<u64>> {
+        if let Some(offset) = self.entries.get(signature) {
+            return Ok(Some(*offset));
+        }
+        let mut found = None;
+        self.scan_dropped(|offset, record| {
+            if record.signature == *signature {
+                found = Some(offset);
+            }
+            found.is_none()
+        })?;
+        Ok(found)
+    }
+
+    /// The index of the entry holding the transaction with `signature`.
+    pub fn get_entry_index(&mut self, signature: &Signature) -> io::Result<Option<u64>> {
+        match self.find_offset(signature)? {
+            Some(offset) => Ok(self
+                .read_record_at(offset)?
+                .map(|record| record.entry_index)),
+            None => Ok(None),
+        }
+    }
+
+    /// Up to `limit` signatures of the transactions with `pubkey` in their keys,
+    /// newest first, starting with the one before the transaction with `before`.
+    /// Nothing is before a signature the index doesn't hold.
+    pub fn get_signatures(
+        &mut self,
+        pubkey: &Pubkey,
+        before: Option<&Signature>,
+        limit: usize,
+    ) -> io::Result<Vec<Signature>> {
+        let end = match before {
+            Some(before) => match self.find_offset(before)? {
+                Some(offset) => offset,
+                None => return Ok(vec![]),
+            },
+            None => self.offset,
+        };
+        let limit = limit.min(MAX_SIGNATURES_LIMIT);
+        let mut page: Vec<_> = match self.signatures.get(pubkey) {
+            Some(offsets) => {
+                let end = match offsets.binary_search(&end) {
+                    Ok(i) | Err(i) => i,
+                };
+                offsets[end.saturating_sub(limit)..end]
+                    .iter()
+                    .rev()
+                    .cloned()
+                    .collect()
+            }
+            None => vec![],
+        };
+        // the rest of the page is in the records older than the ones in memory
+        if page.len() < limit && end > 0 && self.memory_start > 0 {
+            let missing = limit - page.len();
+            let mut older = VecDeque::with_capacity(missing);
+            self.scan_dropped(|offset, record| {
+                if offset >= end {
+                    return false;
+                }
+                if record.keys.contains(pubkey) {
+                    if older.len() == missing {
+                        older.pop_front();
+                    }
+                    older.push_back(offset);
+                }
+                true
+            })?;
+            page.extend(older.into_iter().rev());
+        }
+
+        let mut signatures = Vec::with_capacity(page.len());
+        for offset in page {
+            let record = self.read_record_at(offset)?.ok_or_else(err_trimmed)?;
+            signatures.push(record.signature);
+        }
+        Ok(signatures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coinery::Mint;
+    use crate::ledger::{next_entries, tmp_ledger_path, LedgerWriter};
+    use crate::signature::{Keypair, KeypairUtil};
+    use std::fs::remove_dir_all;
+    use crate::system_transaction::SystemTransaction;
+    use crate::transaction::Transaction;
+
+    // the amounts differ, so transfers to the same key have different signatures
+    fn make_transfers(mint: &Mint, to: &[Pubkey]) -> Vec<Entry> {
+        let keypair = mint.keypair();
+        let transactions = to
+            .iter()
+            .enumerate()
+            .map(|(i, to)| Transaction::system_new(&keypair, *to, i as i64 + 1, mint.last_id()))
+            .collect();
+        next_entries(&mint.last_id(), 0, transactions)
+    }
+
+    #[test]
+    fn test_ledger_index() {
+        let ledger_path = tmp_ledger_path("test_ledger_index");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+        let bob = Keypair::new().pubkey();
+
+        let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+        writer.write_entries(mint.create_entries()).unwrap();
+        let entries = make_transfers(&mint, &[alice, bob, alice]);
+        writer.write_entries(entries.clone()).unwrap();
+
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        let signatures: Vec<_> = entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter().map(|tx| tx.signature))
+            .collect();
+        assert_eq!(index.get_entry_index(&signatures[1]).unwrap(), Some(2));
+        assert_eq!(
+            index.get_signatures(&alice, None, 10).unwrap(),
+            vec![signatures[2], signatures[0]]
+        );
+        assert_eq!(
+            index.get_signatures(&bob, None, 10).unwrap(),
+            vec![signatures[1]]
+        );
+        assert_eq!(
+            index
+                .get_signatures(&mint.pubkey(), None, 10)
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(index.get_entry_index(&Signature::default()).unwrap(), None);
+
+        // records written after it was opened are found once it is refreshed
+        let more = make_transfers(&mint, &[bob]);
+        writer.write_entries(more.clone()).unwrap();
+        assert_eq!(index.get_signatures(&bob, None, 10).unwrap().len(), 1);
+        index.refresh().unwrap();
+        assert_eq!(
+            index.get_signatures(&bob, None, 10).unwrap(),
+            vec![more[0].transactions[0].signature, signatures[1]]
+        );
+        assert_eq!(
+            index
+                .get_entry_index(&more[0].transactions[0].signature)
+                .unwrap(),
+            Some(3)
+        );
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_index_pages() {
+        let ledger_path = tmp_ledger_path("test_ledger_index_pages");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+
+        let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+        writer.write_entries(mint.create_entries()).unwrap();
+        let entries = make_transfers(&mint, &[alice; 5]);
+        writer.write_entries(entries.clone()).unwrap();
+        let signatures: Vec<_> = entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter().map(|tx| tx.signature))
+            .rev()
+            .collect();
+
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        let first = index.get_signatures(&alice, None, 2).unwrap();
+        assert_eq!(first, &signatures[..2]);
+        let second = index.get_signatures(&alice, Some(&first[1]), 2).unwrap();
+        assert_eq!(second, &signatures[2..4]);
+        let last = index.get_signatures(&alice, Some(&second[1]), 2).unwrap();
+        assert_eq!(last, &signatures[4..]);
+        assert!(index
+            .get_signatures(&alice, Some(&last[0]), 2)
+            .unwrap()
+            .is_empty());
+
+        // a transaction that didn't touch alice still marks a place in the ledger
+        let mint_signature = mint.create_entries()[1].transactions[0].signature;
+        assert!(index
+            .get_signatures(&alice, Some(&mint_signature), 2)
+            .unwrap()
+            .is_empty());
+        assert!(index
+            .get_signatures(&alice, Some(&Signature::default()), 2)
+            .unwrap()
+            .is_empty());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_index_trimmed() {
+        let ledger_path = tmp_ledger_path("test_ledger_index_trimmed");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+        let bob = Keypair::new().pubkey();
+        let alice_entries = make_transfers(&mint, &[alice]);
+        let bob_entries = make_transfers(&mint, &[bob]);
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(mint.create_entries()).unwrap();
+            writer.write_entries(alice_entries.clone()).unwrap();
+        }
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        assert_eq!(index.get_signatures(&alice, None, 10).unwrap().len(), 1);
+
+        // the ledger loses its last entry
+        recover_ledger_index(&ledger_path, 2).unwrap();
+        index.refresh().unwrap();
+        assert!(index.get_signatures(&alice, None, 10).unwrap().is_empty());
+
+        // and gets another one in its place before the index is refreshed
+        let path = Path::new(&ledger_path).join(TRANSACTIONS_FILE);
+        let len = path.metadata().unwrap().len();
+        let mut transactions = OpenOptions::new().append(true).open(&path).unwrap();
+        write_entry_records(&mut transactions, 2, &alice_entries[0]).unwrap();
+        index.refresh().unwrap();
+        assert_eq!(index.get_signatures(&alice, None, 10).unwrap().len(), 1);
+
+        transactions.set_len(len).unwrap();
+        write_entry_records(&mut transactions, 2, &bob_entries[0]).unwrap();
+        index.refresh().unwrap();
+        assert!(index.get_signatures(&alice, None, 10).unwrap().is_empty());
+        assert_eq!(
+            index.get_signatures(&bob, None, 10).unwrap(),
+            vec![bob_entries[0].transactions[0].signature]
+        );
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_index_memory_limit() {
+        let ledger_path = tmp_ledger_path("test_ledger_index_memory_limit");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+        let bob = Keypair::new().pubkey();
+
+        let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+        writer.write_entries(mint.create_entries()).unwrap();
+        let entries = make_transfers(&mint, &[alice, bob, alice, alice, bob, alice]);
+        writer.write_entries(entries.clone()).unwrap();
+        let signatures: Vec<_> = entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter().map(|tx| tx.signature))
+            .collect();
+
+        // the lookups of the records dropped from memory read the file
+        let mut index = LedgerIndex::open_with_memory_limit(&ledger_path, 3).unwrap();
+        assert!(index.entries.len() <= 3);
+        assert_eq!(index.get_entry_index(&signatures[0]).unwrap(), Some(2));
+        assert_eq!(index.get_entry_index(&signatures[5]).unwrap(), Some(2));
+        assert_eq!(index.get_entry_index(&Signature::default()).unwrap(), None);
+        assert_eq!(
+            index.get_signatures(&alice, None, 10).unwrap(),
+            vec![signatures[5], signatures[3], signatures[2], signatures[0]]
+        );
+        assert_eq!(
+            index
+                .get_signatures(&alice, Some(&signatures[3]), 1)
+                .unwrap(),
+            vec![signatures[2]]
+        );
+        assert_eq!(
+            index
+                .get_signatures(&bob, Some(&signatures[4]), 10)
+                .unwrap(),
+            vec![signatures[1]]
+        );
+
+        // and so do the ones dropped on refresh
+        let more = make_transfers(&Mint::new(100), &[bob, bob, bob]);
+        writer.write_entries(more.clone()).unwrap();
+        index.refresh().unwrap();
+        assert!(index.entries.len() <= 3);
+        assert_eq!(index.get_signatures(&bob, None, 10).unwrap().len(), 5);
+        assert_eq!(
+            index.get_signatures(&alice, None, 2).unwrap(),
+            vec![signatures[5], signatures[3]]
+        );
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_trim_ledger_index() {
+        let ledger_path = tmp_ledger_path("test_trim_ledger_index");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+        let alice_entries = make_transfers(&mint, &[alice]);
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(mint.create_entries()).unwrap();
+            writer.write_entries(alice_entries.clone()).unwrap();
+        }
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        let mint_signature = mint.create_entries()[1].transactions[0].signature;
+        assert_eq!(index.get_entry_index(&mint_signature).unwrap(), Some(1));
+
+        // only the records of the entries before the start are dropped
+        trim_ledger_index(&ledger_path, 2).unwrap();
+        index.refresh().unwrap();
+        assert_eq!(index.get_entry_index(&mint_signature).unwrap(), None);
+        let alice_signature = alice_entries[0].transactions[0].signature;
+        assert_eq!(index.get_entry_index(&alice_signature).unwrap(), Some(2));
+        assert_eq!(
+            index.get_signatures(&mint.pubkey(), None, 10).unwrap(),
+            vec![alice_signature]
+        );
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_rebuild_ledger_index() {
+        let ledger_path = tmp_ledger_path("test_rebuild_ledger_index");
+        let mint = Mint::new(100);
+        let alice = Keypair::new().pubkey();
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(mint.create_entries()).unwrap();
+            writer
+                .write_entries(make_transfers(&mint, &[alice, alice]))
+                .unwrap();
+        }
+        let path = Path::new(&ledger_path).join(TRANSACTIONS_FILE);
+        let len = path.metadata().unwrap().len();
+
+        // a ledger without an index gets one when it is opened for writing
+        std::fs::remove_file(&path).unwrap();
+        LedgerWriter::open(&ledger_path, false).unwrap();
+        assert_eq!(path.metadata().unwrap().len(), len);
+        assert_eq!(
+            LedgerIndex::open(&ledger_path)
+                .unwrap()
+                .get_signatures(&alice, None, 10)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // the records of entries the ledger lost are trimmed
+        recover_ledger_index(&ledger_path, 2).unwrap();
+        let mut index = LedgerIndex::open(&ledger_path).unwrap();
+        assert!(index.get_signatures(&alice, None, 10).unwrap().is_empty());
+        assert_eq!(
+            index
+                .get_signatures(&mint.pubkey(), None, 10)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // and so is a partial record
+        {
+            let mut transactions = OpenOptions::new().append(true).open(&path).unwrap();
+            transactions.write_all(&[1, 2, 3]).unwrap();
+        }
+        let len = path.metadata().unwrap().len();
+        recover_ledger_index(&ledger_path, 2).unwrap();
+        assert_eq!(path.metadata().unwrap().len(), len - 3);
+
+        remove_dir_all(ledger_path).unwrap();
+        assert!(LedgerIndex::open(&ledger_path).is_err());
+    }
+}
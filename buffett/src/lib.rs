@@ -1,15 +1,19 @@
 //! Module list
 
 #![cfg_attr(feature = "unstable", feature(test))]
+pub mod account_store;
 #[macro_use]
 pub mod counter;
 pub mod tx_vault;
 pub mod tx_process;
 pub mod socket_streamer;
+pub mod bpf_vm;
 pub mod broadcast_stage;
 pub mod budget;
 pub mod budget_instruction;
 pub mod budget_transaction;
+pub mod chacha;
+pub mod checkpoint;
 pub mod choose_gossip_peer_strategy;
 pub mod client;
 #[macro_use]
@@ -17,14 +21,17 @@ pub mod crdt;
 pub mod budget_program;
 pub mod token_service;
 pub mod dynamic_program;
+pub mod ed25519;
 pub mod entry;
 pub mod entry_writer;
-#[cfg(feature = "erasure")]
 pub mod erasure;
 pub mod fetch_stage;
 pub mod fullnode;
 pub mod hash;
 pub mod ledger;
+pub mod ledger_index;
+pub mod loader_program;
+pub mod loader_transaction;
 pub mod logger;
 pub mod metrics;
 pub mod coinery;
@@ -34,7 +41,9 @@ pub mod packet;
 pub mod payment_plan;
 pub mod poh;
 pub mod poh_recorder;
+pub mod program_registry;
 pub mod recvmmsg;
+pub mod reed_solomon;
 pub mod replicate_stage;
 pub mod replicator;
 pub mod request;
@@ -49,10 +58,12 @@ pub mod signature;
 pub mod sigverify;
 pub mod sigverify_stage;
 pub mod storage_program;
+pub mod storage_transaction;
 pub mod store_ledger_stage;
 pub mod streamer;
 pub mod system_program;
 pub mod system_transaction;
+pub mod tcp_streamer;
 pub mod thin_client;
 pub mod tictactoe_dashboard_program;
 pub mod tictactoe_program;
@@ -72,6 +83,7 @@ extern crate bytes;
 extern crate chrono;
 extern crate clap;
 extern crate dirs;
+extern crate flate2;
 extern crate generic_array;
 extern crate ipnetwork;
 extern crate itertools;
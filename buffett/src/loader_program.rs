@@ -0,0 +1,180 @@
+//! loader program
+//!  Deploy programs on-chain. The code of a program is written into the userdata
+//!  of an account assigned to the loader, once it is finalized transactions can name
+//!  the account as their program id.
+
+use bincode::deserialize;
+use buffett_program_interface::account::Account;
+use buffett_program_interface::pubkey::Pubkey;
+use crate::transaction::Transaction;
+
+pub const LOADER_PROGRAM_ID: [u8; 32] = [
+    5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Bytes of the userdata of a program account before its code, the state of the
+/// program: 0 while it is written, then the `ProgramKind` it was finalized as.
+pub const PROGRAM_HEADER_SIZE: usize = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramKind {
+    /// A dynamic library. It is never loaded from the account, only from the same
+    /// library installed in the program directory of a node, see `ProgramRegistry`
+    Native,
+    /// eBPF instructions, interpreted by `bpf_vm`
+    Bpf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LoaderProgram {
+    /// Write `bytes` into the code of the program at `offset`
+    /// * Transaction::keys[0] - the program account
+    Write { offset: u64, bytes: Vec<u8> },
+
+    /// Complete the program, its code can't be written to afterwards
+    /// * Transaction::keys[0] - the program account
+    Finalize { kind: ProgramKind },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoaderError {
+    InvalidUserData,
+    InvalidArguments,
+    UserdataTooSmall,
+    AlreadyFinalized,
+}
+
+impl LoaderProgram {
+    pub fn check_id(program_id: &Pubkey) -> bool {
+        program_id.as_ref() == LOADER_PROGRAM_ID
+    }
+
+    pub fn id() -> Pubkey {
+        Pubkey::new(&LOADER_PROGRAM_ID)
+    }
+
+    pub fn get_balance(account: &Account) -> i64 {
+        account.tokens
+    }
+
+    /// The kind and the code of a finalized program account, None for any other account.
+    pub fn program(account: &Account) -> Option<(ProgramKind, &[u8])> {
+        if !Self::check_id(&account.program_id) || account.userdata.len() < PROGRAM_HEADER_SIZE {
+            return None;
+        }
+        let kind = match account.userdata[0] {
+            1 => ProgramKind::Native,
+            2 => ProgramKind::Bpf,
+            _ => return None,
+        };
+        Some((kind, &account.userdata[PROGRAM_HEADER_SIZE..]))
+    }
+
+    pub fn process_transaction(
+        tx: &Transaction,
+        accounts: &mut [Account],
+    ) -> Result<(), LoaderError> {
+        let instruction = deserialize(&tx.userdata).map_err(|_| LoaderError::InvalidUserData)?;
+        let account = &mut accounts[0];
+        if !Self::check_id(&account.program_id) || account.userdata.len() < PROGRAM_HEADER_SIZE {
+            error!("account is not a program account of the loader");
+            return Err(LoaderError::InvalidArguments);
+        }
+        if account.userdata[0] != 0 {
+            return Err(LoaderError::AlreadyFinalized);
+        }
+        match instruction {
+            LoaderProgram::Write { offset, bytes } => {
+                let range = (offset as usize)
+                    .checked_add(PROGRAM_HEADER_SIZE)
+                    .and_then(|start| Some((start, start.checked_add(bytes.len())?)));
+                match range {
+                    Some((start, end)) if end <= account.userdata.len() => {
+                        account.userdata[start..end].copy_from_slice(&bytes);
+                    }
+                    _ => {
+                        warn!(
+                            "writing {} bytes at {} past the end of a {} byte program",
+                            bytes.len(),
+                            offset,
+                            account.userdata.len() - PROGRAM_HEADER_SIZE
+                        );
+                        return Err(LoaderError::UserdataTooSmall);
+                    }
+                }
+            }
+            LoaderProgram::Finalize { kind } => {
+                account.userdata[0] = match kind {
+                    ProgramKind::Native => 1,
+                    ProgramKind::Bpf => 2,
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::Hash;
+    use crate::loader_transaction::LoaderTransaction;
+    use crate::signature::{Keypair, KeypairUtil};
+
+    #[test]
+    fn test_write_and_finalize() {
+        let keypair = Keypair::new();
+        let mut accounts = vec![Account::new(1, PROGRAM_HEADER_SIZE + 4, LoaderProgram::id())];
+
+        let tx = Transaction::loader_write(&keypair, 1, vec![1, 2, 3], Hash::default(), 0);
+        assert!(LoaderProgram::process_transaction(&tx, &mut accounts).is_ok());
+        assert_eq!(LoaderProgram::program(&accounts[0]), None);
+
+        // writes past the end of the program are rejected
+        let tx = Transaction::loader_write(&keypair, 2, vec![1, 2, 3], Hash::default(), 0);
+        assert_eq!(
+            LoaderProgram::process_transaction(&tx, &mut accounts),
+            Err(LoaderError::UserdataTooSmall)
+        );
+        let tx = Transaction::loader_write(&keypair, u64::max_value(), vec![1], Hash::default(), 0);
+        assert_eq!(
+            LoaderProgram::process_transaction(&tx, &mut accounts),
+            Err(LoaderError::UserdataTooSmall)
+        );
+
+        // the code is deployed as either kind
+        let mut native = accounts.clone();
+        let tx = Transaction::loader_finalize(&keypair, ProgramKind::Native, Hash::default(), 0);
+        assert!(LoaderProgram::process_transaction(&tx, &mut native).is_ok());
+        assert_eq!(
+            LoaderProgram::program(&native[0]),
+            Some((ProgramKind::Native, &[0, 1, 2, 3][..]))
+        );
+
+        let tx = Transaction::loader_finalize(&keypair, ProgramKind::Bpf, Hash::default(), 0);
+        assert!(LoaderProgram::process_transaction(&tx, &mut accounts).is_ok());
+        assert_eq!(
+            LoaderProgram::program(&accounts[0]),
+            Some((ProgramKind::Bpf, &[0, 1, 2, 3][..]))
+        );
+
+        // and a finalized program can't change
+        let tx = Transaction::loader_write(&keypair, 0, vec![1], Hash::default(), 0);
+        assert_eq!(
+            LoaderProgram::process_transaction(&tx, &mut accounts),
+            Err(LoaderError::AlreadyFinalized)
+        );
+    }
+
+    #[test]
+    fn test_not_a_program_account() {
+        let keypair = Keypair::new();
+        let mut accounts = vec![Account::new(1, 4, keypair.pubkey())];
+        let tx = Transaction::loader_write(&keypair, 0, vec![1], Hash::default(), 0);
+        assert_eq!(
+            LoaderProgram::process_transaction(&tx, &mut accounts),
+            Err(LoaderError::InvalidArguments)
+        );
+        assert_eq!(LoaderProgram::program(&accounts[0]), None);
+    }
+}
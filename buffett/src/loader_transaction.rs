@@ -0,0 +1,65 @@
+//! The `loader_transaction` module provides functionality for creating loader transactions.
+
+use bincode::serialize;
+use crate::hash::Hash;
+use crate::loader_program::{LoaderProgram, ProgramKind};
+use crate::signature::Keypair;
+use crate::transaction::Transaction;
+
+pub trait LoaderTransaction {
+    fn loader_write(
+        program_keypair: &Keypair,
+        offset: u64,
+        bytes: Vec<u8>,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self;
+
+    fn loader_finalize(
+        program_keypair: &Keypair,
+        kind: ProgramKind,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self;
+}
+
+impl LoaderTransaction for Transaction {
+    /// Create and sign a write of `bytes` into the code of the `program_keypair` account
+    fn loader_write(
+        program_keypair: &Keypair,
+        offset: u64,
+        bytes: Vec<u8>,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self {
+        let write = LoaderProgram::Write { offset, bytes };
+        let userdata = serialize(&write).unwrap();
+        Transaction::new(
+            program_keypair,
+            &[],
+            LoaderProgram::id(),
+            userdata,
+            last_id,
+            fee,
+        )
+    }
+
+    /// Create and sign the completion of the program in the `program_keypair` account
+    fn loader_finalize(
+        program_keypair: &Keypair,
+        kind: ProgramKind,
+        last_id: Hash,
+        fee: i64,
+    ) -> Self {
+        let finalize = LoaderProgram::Finalize { kind };
+        let userdata = serialize(&finalize).unwrap();
+        Transaction::new(
+            program_keypair,
+            &[],
+            LoaderProgram::id(),
+            userdata,
+            last_id,
+            fee,
+        )
+    }
+}
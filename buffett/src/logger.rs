@@ -1,16 +1,69 @@
 //! The `logger` module provides a setup function for `env_logger`. Its only function,
 //! `setup()` may be called multiple times.
+//!
+//! Records that pass the `RUST_LOG` filter are written to stderr, unless a sink has
+//! been registered with `set_sink()`, in which case they are handed to the sink instead.
 
-use std::sync::{Once, ONCE_INIT};
+use log::{Log, Metadata, Record};
+use std::sync::{Arc, Once, RwLock, ONCE_INIT};
 extern crate env_logger;
 
 static INIT: Once = ONCE_INIT;
 
+/// A destination for log records other than stderr, see `set_sink()`.
+pub type LogSink = Fn(&Record) + Send + Sync;
+
+fn get_singleton_sink() -> &'static RwLock<Option<Arc<LogSink>>> {
+    static SINK_INIT: Once = ONCE_INIT;
+    static mut SINK: Option<RwLock<Option<Arc<LogSink>>>> = None;
+    unsafe {
+        SINK_INIT.call_once(|| SINK = Some(RwLock::new(None)));
+        match SINK {
+            Some(ref sink) => sink,
+            None => panic!("Failed to initialize log sink"),
+        }
+    }
+}
+
+/// Route log records to `sink`, or back to stderr when `None`.
+pub fn set_sink(sink: Option<Arc<LogSink>>) {
+    *get_singleton_sink().write().unwrap() = sink;
+}
+
+struct SinkLogger {
+    env_logger: env_logger::Logger,
+}
+
+impl Log for SinkLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.env_logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.env_logger.matches(record) {
+            return;
+        }
+        // the sink may log or call set_sink, it runs after the guard is dropped
+        let sink = get_singleton_sink().read().unwrap().clone();
+        match sink {
+            Some(sink) => sink(record),
+            None => self.env_logger.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.env_logger.flush();
+    }
+}
+
 /// Setup function that is only run once, even if called multiple times.
 pub fn setup() {
     INIT.call_once(|| {
-        env_logger::Builder::from_default_env()
+        let env_logger = env_logger::Builder::from_default_env()
             .default_format_timestamp_nanos(true)
-            .init();
+            .build();
+        log::set_max_level(env_logger.filter());
+        log::set_boxed_logger(Box::new(SinkLogger { env_logger }))
+            .expect("a logger was already installed");
     });
 }
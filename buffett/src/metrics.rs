@@ -3,7 +3,7 @@
 use influx_db_client as influxdb;
 use std::env;
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
-use std::sync::{Arc, Barrier, Mutex, Once, ONCE_INIT};
+use std::sync::{Arc, Barrier, Mutex, Once, RwLock, ONCE_INIT};
 use std::thread;
 use std::time::{Duration, Instant};
 use sys_info::hostname;
@@ -25,14 +25,38 @@ trait MetricsWriter {
     fn write(&self, points: Vec<influxdb::Point>);
 }
 
+/// A destination for points other than InfluxDB, see `set_sink`. It is called on
+/// the internal MetricsAgent worker thread with each batch of points.
+pub type MetricsSink = Fn(&[influxdb::Point]) + Send + Sync;
+
+fn get_singleton_sink() -> &'static RwLock<Option<Arc<MetricsSink>>> {
+    static INIT: Once = ONCE_INIT;
+    static mut SINK: Option<RwLock<Option<Arc<MetricsSink>>>> = None;
+    unsafe {
+        INIT.call_once(|| SINK = Some(RwLock::new(None)));
+        match SINK {
+            Some(ref sink) => sink,
+            None => panic!("Failed to initialize metrics sink"),
+        }
+    }
+}
+
+/// Route points to `sink` instead of InfluxDB, or back to InfluxDB when `None`.
+pub fn set_sink(sink: Option<Arc<MetricsSink>>) {
+    *get_singleton_sink().write().unwrap() = sink;
+}
+
 struct InfluxDbMetricsWriter {
     client: Option<influxdb::Client>,
+    // the sink that takes precedence over `client`, the one `set_sink` installs
+    sink: &'static RwLock<Option<Arc<MetricsSink>>>,
 }
 
 impl InfluxDbMetricsWriter {
     fn new() -> Self {
         InfluxDbMetricsWriter {
             client: Self::build_client(),
+            sink: get_singleton_sink(),
         }
     }
 
@@ -57,6 +81,12 @@ impl InfluxDbMetricsWriter {
 
 impl MetricsWriter for InfluxDbMetricsWriter {
     fn write(&self, points: Vec<influxdb::Point>) {
+        // the sink may call back in to set_sink, it runs after the guard is dropped
+        let sink = self.sink.read().unwrap().clone();
+        if let Some(sink) = sink {
+            sink(&points);
+            return;
+        }
         if let Some(ref client) = self.client {
             debug!("submitting {} points", points.len());
             if let Err(err) = client.write_points(
@@ -349,4 +379,25 @@ mod test {
         agent.submit(point);
     }
 
+    #[test]
+    fn test_sink() {
+        let points_written = Arc::new(AtomicUsize::new(0));
+        let sink_points_written = points_written.clone();
+        let sink: Arc<MetricsSink> = Arc::new(move |points: &[influxdb::Point]| {
+            sink_points_written.fetch_add(points.len(), Ordering::SeqCst);
+        });
+
+        // a sink of its own, other tests submit through the global one concurrently
+        let writer = Arc::new(InfluxDbMetricsWriter {
+            client: None,
+            sink: Box::leak(Box::new(RwLock::new(Some(sink)))),
+        });
+        let agent = MetricsAgent::new(writer, Duration::from_secs(10));
+        agent.submit(influxdb::Point::new("point 1"));
+        agent.submit(influxdb::Point::new("point 2"));
+        agent.flush();
+
+        assert_eq!(points_written.load(Ordering::SeqCst), 2);
+    }
+
 }
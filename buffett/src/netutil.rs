@@ -7,7 +7,7 @@ use rand::{thread_rng, Rng};
 use reqwest;
 use socket2::{Domain, SockAddr, Socket, Type};
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
 use std::os::unix::io::AsRawFd;
 
 /// A data type representing a public Udp socket
@@ -148,6 +148,19 @@ pub fn multi_bind_in_range(range: (u16, u16), num: usize) -> io::Result<(u16, Ve
     Ok((port, sockets))
 }
 
+/// Listen for tcp connections on the address of `sock`, so a service can be reached
+/// over tcp at the same address it is over udp. None if the port is taken for tcp.
+pub fn bind_tcp_alongside(sock: &UdpSocket) -> Option<TcpListener> {
+    let addr = sock.local_addr().ok()?;
+    match TcpListener::bind(addr) {
+        Ok(listener) => Some(listener),
+        Err(err) => {
+            warn!("no tcp listener on {}: {:?}", addr, err);
+            None
+        }
+    }
+}
+
 pub fn bind_to(port: u16, reuseaddr: bool) -> io::Result<UdpSocket> {
     let sock = udp_socket(reuseaddr)?;
 
@@ -150,6 +150,8 @@ impl Default for Blob {
 pub enum BlobError {
     /// the Blob's meta and data are not self-consistent
     BadState,
+    /// the serialized data doesn't fit in a Blob
+    TooLarge,
 }
 
 impl Packets {
@@ -232,7 +234,9 @@ pub fn to_blob<T: Serialize>(resp: T, rsp_addr: SocketAddr) -> Result<SharedBlob
         let mut b = blob.write().unwrap();
         let v = serialize(&resp)?;
         let len = v.len();
-        assert!(len <= BLOB_SIZE);
+        if len > BLOB_SIZE {
+            return Err(Error::BlobError(BlobError::TooLarge));
+        }
         b.data[..len].copy_from_slice(&v);
         b.meta.size = len;
         b.meta.set_addr(&rsp_addr);
@@ -423,13 +427,14 @@ pub fn make_consecutive_blobs(
 #[cfg(test)]
 mod tests {
     use crate::packet::{
-        to_packets, Blob, Meta, Packet, Packets, SharedBlob, SharedPackets, NUM_PACKETS,
-        PACKET_DATA_SIZE,
+        to_blob, to_packets, Blob, BlobError, Meta, Packet, Packets, SharedBlob, SharedPackets,
+        BLOB_SIZE, NUM_PACKETS, PACKET_DATA_SIZE,
     };
     use crate::request::Request;
+    use crate::result::Error;
     use std::io;
     use std::io::Write;
-    use std::net::UdpSocket;
+    use std::net::{SocketAddr, UdpSocket};
 
     #[test]
     pub fn packet_send_recv() {
@@ -468,6 +473,16 @@ mod tests {
         assert_eq!(rv[1].read().unwrap().packets.len(), 1);
     }
 
+    #[test]
+    fn test_to_blob_too_large() {
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        assert!(to_blob(vec![0u8; BLOB_SIZE - 8], addr).is_ok());
+        match to_blob(vec![0u8; BLOB_SIZE], addr) {
+            Err(Error::BlobError(BlobError::TooLarge)) => (),
+            _ => panic!("a response larger than a blob"),
+        }
+    }
+
     #[test]
     pub fn blob_send_recv() {
         trace!("start");
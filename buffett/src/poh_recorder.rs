@@ -36,6 +36,7 @@ impl PohRecorder {
     pub fn tick(&self) -> Result<()> {
         // Register and send the entry out while holding the lock.
         // This guarantees PoH order and Entry production and banks LastId queue is the same
+        let _recording = self.bank.recording();
         let mut poh = self.poh.lock().unwrap();
         let tick = poh.tick();
         self.bank.register_entry_id(&tick.id);
@@ -48,6 +49,7 @@ impl PohRecorder {
         Ok(())
     }
 
+    /// The caller holds `Bank::recording` since processing `txs`.
     pub fn record(&self, mixin: Hash, txs: Vec<Transaction>) -> Result<()> {
         // Register and send the entry out while holding the lock.
         // This guarantees PoH order and Entry production and banks LastId queue is the same.
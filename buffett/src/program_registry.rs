@@ -0,0 +1,190 @@
+//! The `program_registry` module keeps the programs a bank runs by the hash of their
+//! code. BPF programs are deployed on-chain and interpreted. Native code is never
+//! written out from the ledger: a node runs a deployed native program only if the
+//! same library was installed in its program directory, named after the hash of its
+//! code, and `SystemProgram::Load` only loads libraries from that directory.
+
+use crate::dynamic_program::{library_file_name, DynamicProgram};
+use crate::hash::{hash, Hash};
+use crate::loader_program::ProgramKind;
+use buffett_program_interface::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// The directory in a ledger that native programs are installed in.
+pub const PROGRAM_DIR: &str = "programs";
+
+/// The program directory of the node with its ledger at `ledger_path`.
+pub fn program_dir(ledger_path: &str) -> PathBuf {
+    Path::new(ledger_path).join(PROGRAM_DIR)
+}
+
+#[derive(Debug, Default)]
+pub struct ProgramRegistry {
+    programs: RwLock<HashMap<Hash, Arc<DynamicProgram>>>,
+    /// the programs `SystemProgram::Load` gave an id
+    loaded: RwLock<HashMap<Pubkey, Arc<DynamicProgram>>>,
+    /// where native libraries are installed, none are loaded without one
+    program_dir: RwLock<Option<PathBuf>>,
+}
+
+impl ProgramRegistry {
+    /// Load native programs from the libraries in `dir`.
+    pub fn set_program_dir(&self, dir: PathBuf) {
+        *self.program_dir.write().unwrap() = Some(dir);
+    }
+
+    /// The program with `code`, loaded the first time it is called. A native program
+    /// is the library installed as `<hash of code>` in the program directory.
+    pub fn load(&self, kind: ProgramKind, code: &[u8]) -> io::Result<Arc<DynamicProgram>> {
+        let code_hash = hash(code);
+        if let Some(program) = self.programs.read().unwrap().get(&code_hash) {
+            return Ok(program.clone());
+        }
+        let program = match kind {
+            ProgramKind::Native => {
+                let path = self.library_path(&code_hash.to_string())?;
+                Self::load_native(&path, &code_hash)?
+            }
+            ProgramKind::Bpf => DynamicProgram::new_bpf(code.to_vec()),
+        };
+        Ok(self.insert(kind, code_hash, program))
+    }
+
+    /// Give the library `name` in the program directory the id `program_id`.
+    pub fn load_library(&self, program_id: Pubkey, name: &str) -> io::Result<()> {
+        let path = self.library_path(name)?;
+        let code_hash = hash(&fs::read(&path)?);
+        let cached = self.programs.read().unwrap().get(&code_hash).cloned();
+        let program = match cached {
+            Some(program) => program,
+            None => {
+                let program = Self::load_native(&path, &code_hash)?;
+                self.insert(ProgramKind::Native, code_hash, program)
+            }
+        };
+        self.loaded.write().unwrap().insert(program_id, program);
+        Ok(())
+    }
+
+    /// The program `load_library` gave the id `program_id`.
+    pub fn loaded(&self, program_id: &Pubkey) -> Option<Arc<DynamicProgram>> {
+        self.loaded.read().unwrap().get(program_id).cloned()
+    }
+
+    fn insert(
+        &self,
+        kind: ProgramKind,
+        code_hash: Hash,
+        program: DynamicProgram,
+    ) -> Arc<DynamicProgram> {
+        info!("loaded {:?} program {}", kind, code_hash);
+        let program = Arc::new(program);
+        self.programs
+            .write()
+            .unwrap()
+            .insert(code_hash, program.clone());
+        program
+    }
+
+    fn library_path(&self, name: &str) -> io::Result<PathBuf> {
+        // a name is a file in the directory, not a path out of it
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(|c: char| c == '/' || c == '\\')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid program name {:?}", name),
+            ));
+        }
+        match *self.program_dir.read().unwrap() {
+            Some(ref dir) => Ok(dir.join(library_file_name(name))),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no program directory to load native programs from",
+            )),
+        }
+    }
+
+    // the library is checked against the hash it is loaded as, it may have been
+    // replaced since it was installed
+    fn load_native(path: &Path, code_hash: &Hash) -> io::Result<DynamicProgram> {
+        if hash(&fs::read(path)?) != *code_hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} doesn't hash to {}", path, code_hash),
+            ));
+        }
+        DynamicProgram::new_native(code_hash.to_string(), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamic_program::target_program_dir;
+    use crate::ledger::tmp_ledger_path;
+
+    #[test]
+    fn test_load_bpf() {
+        let registry = ProgramRegistry::default();
+        let code = vec![0x95, 0, 0, 0, 0, 0, 0, 0];
+        let program = registry.load(ProgramKind::Bpf, &code).unwrap();
+        assert!(Arc::ptr_eq(
+            &program,
+            &registry.load(ProgramKind::Bpf, &code).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_load_native() {
+        let library_path = target_program_dir().join(library_file_name("noop"));
+        let code = fs::read(&library_path).unwrap();
+        let registry = ProgramRegistry::default();
+        assert!(registry.load(ProgramKind::Native, &code).is_err());
+
+        // only a library installed under the hash of the code is loaded
+        let dir = program_dir(&tmp_ledger_path("test_load_native"));
+        fs::create_dir_all(&dir).unwrap();
+        registry.set_program_dir(dir.clone());
+        assert!(registry.load(ProgramKind::Native, &code).is_err());
+        let installed = dir.join(library_file_name(&hash(&code).to_string()));
+        fs::copy(&library_path, &installed).unwrap();
+        let program = registry.load(ProgramKind::Native, &code).unwrap();
+        assert!(Arc::ptr_eq(
+            &program,
+            &registry.load(ProgramKind::Native, &code).unwrap()
+        ));
+
+        // whatever is installed under the hash of other code isn't
+        let other = [0x7f, b'E', b'L', b'F'];
+        let installed = dir.join(library_file_name(&hash(&other).to_string()));
+        fs::copy(&library_path, &installed).unwrap();
+        assert!(registry.load(ProgramKind::Native, &other).is_err());
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_load_library() {
+        let registry = ProgramRegistry::default();
+        let program_id = Pubkey::new(&[1; 32]);
+        assert!(registry.load_library(program_id, "noop").is_err());
+
+        registry.set_program_dir(target_program_dir());
+        assert!(registry
+            .load_library(program_id, "no_such_program")
+            .is_err());
+        assert!(registry
+            .load_library(program_id, "../deps/libnoop")
+            .is_err());
+        assert!(registry.loaded(&program_id).is_none());
+
+        registry.load_library(program_id, "noop").unwrap();
+        assert!(registry.loaded(&program_id).is_some());
+    }
+}
@@ -0,0 +1,339 @@
+//! The `reed_solomon` module is a pure Rust replacement for the parts of Jerasure
+//! and gf_complete used by `erasure`. Blocks are coded with the same Cauchy matrix
+//! over GF(2^32) as `jerasure_matrix_encode` with w = 32, so coding blobs produced
+//! by either implementation can be used to recover blobs with the other.
+
+use crate::erasure::{ErasureError, Result, JERASURE_ALIGN};
+
+/// The low 32 bits of gf_complete's default primitive polynomial for w = 32,
+/// x^32 + x^22 + x^2 + x + 1.
+const PRIM_POLY: u32 = 0x0040_0007;
+
+fn gf_mul(mut a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        b >>= 1;
+        a = if a & 0x8000_0000 != 0 {
+            (a << 1) ^ PRIM_POLY
+        } else {
+            a << 1
+        };
+    }
+    product
+}
+
+// a^(2^32 - 2) is the inverse of a in GF(2^32)
+fn gf_inverse(a: u32) -> u32 {
+    let mut inverse = 1;
+    let mut square = a;
+    let mut exp: u32 = 0xffff_fffe;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            inverse = gf_mul(inverse, square);
+        }
+        square = gf_mul(square, square);
+        exp >>= 1;
+    }
+    inverse
+}
+
+/// Same as `galois_single_divide(a, b, 32)`, `b` must not be 0.
+pub fn gf_divide(a: u32, b: u32) -> u32 {
+    gf_mul(a, gf_inverse(b))
+}
+
+/// The m x k Cauchy matrix `erasure` codes with, row major like Jerasure's.
+pub fn cauchy_matrix(m: usize, k: usize) -> Vec<u32> {
+    let mut matrix = Vec::with_capacity(m * k);
+    for i in 0..m {
+        for j in 0..k {
+            matrix.push(gf_divide(1, (i ^ (m + j)) as u32));
+        }
+    }
+    matrix
+}
+
+// Multiplying by a constant is linear, so a word is multiplied one byte at a time
+// with a table per byte position.
+struct MulTable([[u32; 256]; 4]);
+
+impl MulTable {
+    fn new(factor: u32) -> Self {
+        let mut table = [[0u32; 256]; 4];
+        for (shift, row) in table.iter_mut().enumerate() {
+            for (byte, product) in row.iter_mut().enumerate() {
+                *product = gf_mul(factor, (byte as u32) << (8 * shift));
+            }
+        }
+        MulTable(table)
+    }
+
+    fn mul(&self, word: u32) -> u32 {
+        self.0[0][(word & 0xff) as usize]
+            ^ self.0[1][((word >> 8) & 0xff) as usize]
+            ^ self.0[2][((word >> 16) & 0xff) as usize]
+            ^ self.0[3][(word >> 24) as usize]
+    }
+}
+
+// dst ^= factor * src, one little endian 32 bit word at a time like gf_complete
+// on the x86 machines the coding layout was defined on
+fn region_multiply_xor(src: &[u8], factor: u32, dst: &mut [u8]) {
+    match factor {
+        0 => (),
+        1 => {
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d ^= *s;
+            }
+        }
+        _ => {
+            let table = MulTable::new(factor);
+            for (d, s) in dst.chunks_mut(4).zip(src.chunks(4)) {
+                let word = u32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+                let product = table.mul(word).to_le_bytes();
+                for (d, p) in d.iter_mut().zip(&product) {
+                    *d ^= *p;
+                }
+            }
+        }
+    }
+}
+
+// invert a k x k matrix with Gauss-Jordan elimination, None if it is singular
+fn invert_matrix(mut matrix: Vec<u32>, k: usize) -> Option<Vec<u32>> {
+    let mut inverse = vec![0; k * k];
+    for i in 0..k {
+        inverse[i * k + i] = 1;
+    }
+
+    for col in 0..k {
+        let pivot = (col..k).find(|row| matrix[row * k + col] != 0)?;
+        if pivot != col {
+            for j in 0..k {
+                matrix.swap(pivot * k + j, col * k + j);
+                inverse.swap(pivot * k + j, col * k + j);
+            }
+        }
+
+        let scale = gf_inverse(matrix[col * k + col]);
+        for j in 0..k {
+            matrix[col * k + j] = gf_mul(matrix[col * k + j], scale);
+            inverse[col * k + j] = gf_mul(inverse[col * k + j], scale);
+        }
+
+        for row in 0..k {
+            let factor = matrix[row * k + col];
+            if row == col || factor == 0 {
+                continue;
+            }
+            for j in 0..k {
+                matrix[row * k + j] ^= gf_mul(factor, matrix[col * k + j]);
+                inverse[row * k + j] ^= gf_mul(factor, inverse[col * k + j]);
+            }
+        }
+    }
+    Some(inverse)
+}
+
+fn check_block_len(block_len: usize, len: usize) -> Result<()> {
+    if len != block_len || block_len % JERASURE_ALIGN != 0 {
+        error!("block size incorrect {} expected {}", len, block_len);
+        return Err(ErasureError::InvalidBlockSize);
+    }
+    Ok(())
+}
+
+/// Same contract as `erasure::generate_coding_blocks`.
+pub fn generate_coding_blocks(coding: &mut [&mut [u8]], data: &[&[u8]]) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let k = data.len();
+    let block_len = data[0].len();
+    for block in data {
+        check_block_len(block_len, block.len())?;
+    }
+    for block in coding.iter() {
+        check_block_len(block_len, block.len())?;
+    }
+
+    let matrix = cauchy_matrix(coding.len(), k);
+    for (i, block) in coding.iter_mut().enumerate() {
+        for b in block.iter_mut() {
+            *b = 0;
+        }
+        for (j, src) in data.iter().enumerate() {
+            region_multiply_xor(src, matrix[i * k + j], block);
+        }
+    }
+    Ok(())
+}
+
+/// Same contract as `erasure::decode_blocks`, `erasures` is terminated by -1.
+pub fn decode_blocks(
+    data: &mut [&mut [u8]],
+    coding: &mut [&mut [u8]],
+    erasures: &[i32],
+) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let k = data.len();
+    let m = coding.len();
+    let block_len = data[0].len();
+    for block in data.iter().chain(coding.iter()) {
+        check_block_len(block_len, block.len())?;
+    }
+
+    let mut erased = vec![false; k + m];
+    for erasure in erasures.iter().take_while(|erasure| **erasure >= 0) {
+        let erasure = *erasure as usize;
+        if erasure >= k + m {
+            return Err(ErasureError::DecodeError);
+        }
+        erased[erasure] = true;
+    }
+    if erased.iter().filter(|erased| **erased).count() > m {
+        return Err(ErasureError::NotEnoughBlocksToDecode);
+    }
+
+    let matrix = cauchy_matrix(m, k);
+
+    if erased[..k].iter().any(|erased| *erased) {
+        // k surviving blocks, data first, and the rows of the coding matrix that produced them
+        let survivors: Vec<usize> = (0..k + m).filter(|i| !erased[*i]).take(k).collect();
+        let mut rows = vec![0; k * k];
+        for (r, survivor) in survivors.iter().enumerate() {
+            if *survivor < k {
+                rows[r * k + survivor] = 1;
+            } else {
+                let row = survivor - k;
+                rows[r * k..(r + 1) * k].copy_from_slice(&matrix[row * k..(row + 1) * k]);
+            }
+        }
+        let decoding = invert_matrix(rows, k).ok_or(ErasureError::DecodeError)?;
+
+        let mut recovered = Vec::new();
+        for i in (0..k).filter(|i| erased[*i]) {
+            let mut block = vec![0u8; block_len];
+            for (r, survivor) in survivors.iter().enumerate() {
+                let src: &[u8] = if *survivor < k {
+                    &data[*survivor]
+                } else {
+                    &coding[survivor - k]
+                };
+                region_multiply_xor(src, decoding[i * k + r], &mut block);
+            }
+            recovered.push((i, block));
+        }
+        for (i, block) in recovered {
+            data[i].copy_from_slice(&block);
+        }
+    }
+
+    // with the data whole again the missing coding blocks are encoded from scratch
+    for i in (0..m).filter(|i| erased[k + i]) {
+        let block = &mut coding[i];
+        for b in block.iter_mut() {
+            *b = 0;
+        }
+        for (j, src) in data.iter().enumerate() {
+            region_multiply_xor(src, matrix[i * k + j], block);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_reduction() {
+        // x^31 * x = x^32 = x^22 + x^2 + x + 1
+        assert_eq!(gf_mul(0x8000_0000, 2), PRIM_POLY);
+        assert_eq!(gf_mul(0x1234_5678, 1), 0x1234_5678);
+        assert_eq!(gf_mul(0x1234_5678, 0), 0);
+        for a in &[1u32, 2, 3, 0x8000_0000, 0xdead_beef, 0xffff_ffff] {
+            assert_eq!(gf_mul(*a, gf_inverse(*a)), 1);
+            assert_eq!(gf_divide(*a, *a), 1);
+        }
+    }
+
+    #[test]
+    fn test_mul_table() {
+        let table = MulTable::new(0x0bad_cafe);
+        for word in &[0u32, 1, 0xff, 0x1234_5678, 0xffff_ffff] {
+            assert_eq!(table.mul(*word), gf_mul(0x0bad_cafe, *word));
+        }
+    }
+
+    #[test]
+    fn test_invert_matrix() {
+        let (m, k) = (4, 4);
+        let matrix = cauchy_matrix(m, k);
+        let inverse = invert_matrix(matrix.clone(), k).unwrap();
+        for i in 0..k {
+            for j in 0..k {
+                let product = (0..k).fold(0, |acc, x| acc ^ gf_mul(matrix[i * k + x], inverse[x * k + j]));
+                assert_eq!(product, if i == j { 1 } else { 0 });
+            }
+        }
+        assert!(invert_matrix(vec![1, 1, 1, 1], 2).is_none());
+    }
+
+    #[test]
+    fn test_decode_every_pair() {
+        let (k, m, len) = (6, 2, 32);
+        let data: Vec<Vec<u8>> = (0..k)
+            .map(|i| (0..len).map(|j| (i * 31 + j * 7) as u8).collect())
+            .collect();
+        let mut coding = vec![vec![0u8; len]; m];
+        {
+            let data_slices: Vec<&[u8]> = data.iter().map(|x| x.as_slice()).collect();
+            let mut coding_slices: Vec<&mut [u8]> =
+                coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+            generate_coding_blocks(&mut coding_slices, &data_slices).unwrap();
+        }
+
+        for a in 0..k + m {
+            for b in a + 1..k + m {
+                let mut damaged_data = data.clone();
+                let mut damaged_coding = coding.clone();
+                for erasure in &[a, b] {
+                    if *erasure < k {
+                        damaged_data[*erasure] = vec![0; len];
+                    } else {
+                        damaged_coding[erasure - k] = vec![0; len];
+                    }
+                }
+                {
+                    let mut data_slices: Vec<&mut [u8]> =
+                        damaged_data.iter_mut().map(|x| x.as_mut_slice()).collect();
+                    let mut coding_slices: Vec<&mut [u8]> =
+                        damaged_coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+                    decode_blocks(&mut data_slices, &mut coding_slices, &[a as i32, b as i32, -1])
+                        .unwrap();
+                }
+                assert_eq!(damaged_data, data);
+                assert_eq!(damaged_coding, coding);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_too_many_erasures() {
+        let mut data = vec![vec![0u8; 16]; 4];
+        let mut coding = vec![vec![0u8; 16]; 2];
+        let mut data_slices: Vec<&mut [u8]> = data.iter_mut().map(|x| x.as_mut_slice()).collect();
+        let mut coding_slices: Vec<&mut [u8]> =
+            coding.iter_mut().map(|x| x.as_mut_slice()).collect();
+        assert_eq!(
+            decode_blocks(&mut data_slices, &mut coding_slices, &[0, 1, 2, -1]),
+            Err(ErasureError::NotEnoughBlocksToDecode)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! The `replicate_stage` replicates transactions broadcast by the leader.
 
 use crate::tx_vault::Bank;
+use crate::checkpoint::{checkpoint_due, write_checkpoint};
 use crate::counter::Counter;
 use crate::crdt::Crdt;
 use crate::entry::EntryReceiver;
@@ -49,6 +50,7 @@ impl ReplicateStage {
         crdt: &Arc<RwLock<Crdt>>,
         window_receiver: &EntryReceiver,
         ledger_writer: Option<&mut LedgerWriter>,
+        ledger_path: Option<&str>,
         keypair: &Arc<Keypair>,
         vote_blob_sender: Option<&BlobSender>,
     ) -> Result<()> {
@@ -59,6 +61,7 @@ impl ReplicateStage {
             entries.append(&mut more);
         }
 
+        let entry_height = bank.entry_height();
         let res = bank.process_entries(&entries);
 
         if let Some(sender) = vote_blob_sender {
@@ -69,6 +72,7 @@ impl ReplicateStage {
             let mut wcrdt = crdt.write().unwrap();
             wcrdt.insert_votes(&entries.votes());
         }
+        Crdt::refresh_stakes(crdt, bank);
 
         inc_new_counter_info!(
             "replicate-transactions",
@@ -81,6 +85,13 @@ impl ReplicateStage {
         }
 
         res?;
+
+        // the checkpoint is taken once the entries it covers are in the ledger
+        if let Some(ledger_path) = ledger_path {
+            if checkpoint_due(entry_height, bank.entry_height()) {
+                write_checkpoint(ledger_path, bank)?;
+            }
+        }
         Ok(())
     }
 
@@ -97,6 +108,7 @@ impl ReplicateStage {
         let t_responder = responder("replicate_stage", Arc::new(send), vote_blob_receiver);
 
         let mut ledger_writer = ledger_path.map(|p| LedgerWriter::open(p, false).unwrap());
+        let ledger_path = ledger_path.map(|p| p.to_string());
         let keypair = Arc::new(keypair);
 
         let t_replicate = Builder::new()
@@ -119,6 +131,7 @@ impl ReplicateStage {
                         &crdt,
                         &window_receiver,
                         ledger_writer.as_mut(),
+                        ledger_path.as_ref().map(|p| p.as_str()),
                         &keypair,
                         vote_sender,
                     ) {
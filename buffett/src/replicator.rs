@@ -1,9 +1,23 @@
+use bincode::serialize;
+use buffett_program_interface::pubkey::Pubkey;
+use crate::chacha::{chacha_cbc_encrypt, sample_file, sample_offsets};
 use crate::socket_streamer::BlobFetchStage;
 use crate::crdt::{Crdt, Node, NodeInfo};
+use crate::hash::Hash;
+use crate::ledger::LedgerWindow;
 use crate::ncp::Ncp;
 use crate::service::Service;
+use crate::signature::{Keypair, KeypairUtil};
+use crate::storage_program::{
+    storage_key, storage_seed, ENTRIES_PER_SEGMENT, NUM_STORAGE_SAMPLES,
+};
+use crate::storage_transaction::StorageTransaction;
+use crate::transaction::Transaction;
+use std::fs::File;
+use std::io::{self, Write};
 use std::net::SocketAddr;
 use std::net::UdpSocket;
+use std::path::Path;
 use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, RwLock};
@@ -19,9 +33,42 @@ pub struct Replicator {
     fetch_stage: BlobFetchStage,
     store_ledger_stage: StoreLedgerStage,
     t_window: JoinHandle<Option<WindowServiceReturnType>>,
+    keypair: Arc<Keypair>,
     pub retransmit_receiver: BlobReceiver,
 }
 
+/// Encrypt `segment` of the ledger at `ledger_path` into `segment-<segment>.enc` next to
+/// its segments and sample the encrypted file into the `sha_state` of the mining proof of
+/// `storage_account`. A validator recomputes the same state with
+/// `storage_program::verify_mining_proof`.
+pub fn sample_segment(
+    ledger_path: &str,
+    segment: u64,
+    storage_account: &Pubkey,
+) -> io::Result<[u8; 32]> {
+    let mut ledger = LedgerWindow::open(ledger_path)?;
+    let start = segment * ENTRIES_PER_SEGMENT;
+    let mut entry_id = Hash::default();
+    let mut data = vec![];
+    for index in start..start + ENTRIES_PER_SEGMENT {
+        let entry = ledger.get_entry(index)?;
+        if index == start {
+            entry_id = entry.id;
+        }
+        data.extend_from_slice(&serialize(&entry).unwrap());
+    }
+
+    let mut encrypted = vec![0; data.len()];
+    let mut ivec = [0; 64];
+    chacha_cbc_encrypt(&data, &mut encrypted, &storage_key(&entry_id), &mut ivec);
+    let encrypted_path = Path::new(ledger_path).join(format!("segment-{}.enc", segment));
+    File::create(&encrypted_path)?.write_all(&encrypted)?;
+
+    let seed = storage_seed(&entry_id, storage_account);
+    let offsets = sample_offsets(&seed, encrypted.len() as u64, NUM_STORAGE_SAMPLES);
+    sample_file(&encrypted_path, &offsets)
+}
+
 impl Replicator {
     pub fn new(
         entry_height: u64,
@@ -29,13 +76,16 @@ impl Replicator {
         exit: &Arc<AtomicBool>,
         ledger_path: Option<&str>,
         node: Node,
+        keypair: Keypair,
         network_addr: Option<SocketAddr>,
         done: Arc<AtomicBool>,
     ) -> Replicator {
         let window = window::new_window_from_entries(&[], entry_height, &node.info);
         let shared_window = Arc::new(RwLock::new(window));
 
-        let crdt = Arc::new(RwLock::new(Crdt::new(node.info).expect("Crdt::new")));
+        let keypair = Arc::new(keypair);
+        let crdt = Crdt::new_with_keypair(node.info, keypair.clone()).expect("Crdt::new");
+        let crdt = Arc::new(RwLock::new(crdt));
 
         let leader_info = network_addr.map(|i| NodeInfo::new_entry_point(&i));
 
@@ -82,10 +132,28 @@ impl Replicator {
             fetch_stage,
             store_ledger_stage,
             t_window,
+            keypair,
             retransmit_receiver,
         }
     }
 
+    /// A transaction submitting the mining proof for `segment` of the ledger stored at
+    /// `ledger_path`, signed by this replicator's storage account.
+    pub fn mining_proof_transaction(
+        &self,
+        ledger_path: &str,
+        segment: u64,
+        last_id: Hash,
+    ) -> io::Result<Transaction> {
+        let sha_state = sample_segment(ledger_path, segment, &self.keypair.pubkey())?;
+        Ok(Transaction::storage_new_mining_proof(
+            &self.keypair,
+            sha_state,
+            segment,
+            last_id,
+        ))
+    }
+
     pub fn join(self) {
         self.ncp.join().unwrap();
         self.fetch_stage.join().unwrap();
@@ -106,10 +174,13 @@ impl Replicator {
 mod tests {
     use client::mk_client;
     use crate::crdt::Node;
+    use crate::entry::Entry;
     use crate::fullnode::Fullnode;
-    use crate::ledger::{genesis, read_ledger};
+    use crate::hash::Hash;
+    use crate::ledger::{genesis, read_ledger, tmp_ledger_path, LedgerWriter};
     use crate::logger;
-    use replicator::Replicator;
+    use crate::storage_program::{mining_proof, verify_mining_proof, ENTRIES_PER_SEGMENT};
+    use replicator::{sample_segment, Replicator};
     use crate::signature::{Keypair, KeypairUtil};
     use std::fs::remove_dir_all;
     use std::sync::atomic::{AtomicBool, Ordering};
@@ -164,6 +235,7 @@ mod tests {
             &exit,
             Some(replicator_ledger_path),
             replicator_node,
+            replicator_keypair,
             Some(network_addr),
             done.clone(),
         );
@@ -198,4 +270,29 @@ mod tests {
         let _ignored = remove_dir_all(&leader_ledger_path);
         let _ignored = remove_dir_all(&replicator_ledger_path);
     }
+
+    #[test]
+    fn test_sample_segment() {
+        let ledger_path = tmp_ledger_path("test_sample_segment");
+        let mut entries = vec![Entry::new(&Hash::default(), 1, vec![])];
+        while entries.len() < 2 * ENTRIES_PER_SEGMENT as usize {
+            let last_id = entries.last().unwrap().id;
+            entries.push(Entry::new(&last_id, 1, vec![]));
+        }
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(entries.clone()).unwrap();
+        }
+
+        let replicator = Keypair::new().pubkey();
+        let sha_state = sample_segment(&ledger_path, 1, &replicator).unwrap();
+        assert_eq!(
+            sha_state,
+            mining_proof(&entries[ENTRIES_PER_SEGMENT as usize..], &replicator)
+        );
+        assert!(verify_mining_proof(&ledger_path, 1, &replicator, &sha_state).unwrap());
+        assert!(sample_segment(&ledger_path, 2, &replicator).is_err());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
 }
@@ -1,18 +1,41 @@
 //! The `request` module defines the messages for the thin client.
 
 use crate::hash::Hash;
+use crate::tx_vault::BankError;
 use crate::signature::Signature;
 use buffett_program_interface::account::Account;
 use buffett_program_interface::pubkey::Pubkey;
 
+/// The most keys a `GetAccounts` request can hold and still fit in a packet
+pub const MAX_ACCOUNTS_PER_REQUEST: usize = 15;
+
+/// The most signatures a `GetSignatures` request can hold and still fit in a packet
+pub const MAX_SIGNATURES_PER_REQUEST: usize = 7;
+
+/// Requests sent over a stream transport are numbered from 1 and their responses
+/// carry the same id, the updates of account subscriptions are pushed with this one.
+pub const SUBSCRIPTION_UPDATE_ID: u64 = 0;
+
+/// A subscription made over a datagram transport ends this long after it was made,
+/// subscribe again to keep it. The ones made over a stream end with the connection.
+pub const SUBSCRIPTION_TTL_SECS: u64 = 60;
+
 #[cfg_attr(feature = "cargo-clippy", allow(large_enum_variant))]
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     GetAccount { key: Pubkey },
     GetLastId,
     GetTransactionCount,
     GetSignature { signature: Signature },
     GetFinality,
+    GetSignatureStatus { signature: Signature },
+    GetAccounts { keys: Vec<Pubkey> },
+    GetSignatures { signatures: Vec<Signature> },
+    /// Answered with the account, and then with a `Response::Account` every time
+    /// the account changes until the requester unsubscribes or the subscription
+    /// expires, see `SUBSCRIPTION_TTL_SECS`
+    SubscribeAccount { key: Pubkey },
+    UnsubscribeAccount { key: Pubkey },
 }
 
 impl Request {
@@ -40,4 +63,42 @@ pub enum Response {
     Finality {
         time: usize,
     },
+    SignatureResult {
+        signature: Signature,
+        result: Result<(), BankError>,
+    },
+    Accounts {
+        accounts: Vec<(Pubkey, Option<Account>)>,
+    },
+    SignatureResults {
+        results: Vec<(Signature, Result<(), BankError>)>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::serialized_size;
+    use crate::packet::PACKET_DATA_SIZE;
+
+    #[test]
+    fn test_batch_requests_fit_in_a_packet() {
+        let req = Request::GetAccounts {
+            keys: vec![Pubkey::default(); MAX_ACCOUNTS_PER_REQUEST],
+        };
+        assert!(serialized_size(&req).unwrap() as usize <= PACKET_DATA_SIZE);
+        let req = Request::GetAccounts {
+            keys: vec![Pubkey::default(); MAX_ACCOUNTS_PER_REQUEST + 1],
+        };
+        assert!(serialized_size(&req).unwrap() as usize > PACKET_DATA_SIZE);
+
+        let req = Request::GetSignatures {
+            signatures: vec![Signature::default(); MAX_SIGNATURES_PER_REQUEST],
+        };
+        assert!(serialized_size(&req).unwrap() as usize <= PACKET_DATA_SIZE);
+        let req = Request::GetSignatures {
+            signatures: vec![Signature::default(); MAX_SIGNATURES_PER_REQUEST + 1],
+        };
+        assert!(serialized_size(&req).unwrap() as usize > PACKET_DATA_SIZE);
+    }
 }
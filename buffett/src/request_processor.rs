@@ -1,25 +1,117 @@
 //! The `request_processor` processes thin client Request messages.
 
+use bincode::serialize;
+use crate::hash::{hash, Hash};
 use crate::tx_vault::Bank;
-use crate::request::{Request, Response};
+use crate::request::{Request, Response, SUBSCRIPTION_TTL_SECS};
+use buffett_program_interface::account::Account;
+use buffett_program_interface::pubkey::Pubkey;
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The most account subscriptions kept, over all the subscribers
+pub const MAX_SUBSCRIPTIONS: usize = 10_000;
+
+/// The most account subscriptions kept for the subscribers at one ip address
+pub const MAX_SUBSCRIPTIONS_PER_IP: usize = 64;
+
+// what a subscriber of an account was last told about it
+struct Subscriber {
+    // the hash of the state of the account it was last sent
+    state: Hash,
+    // when the subscription ends, None for the ones that end with their connection
+    expires: Option<Instant>,
+}
+
+type Subscriptions = HashMap<Pubkey, HashMap<SocketAddr, Subscriber>>;
+
+fn account_state(account: &Option<Account>) -> Hash {
+    hash(&serialize(account).unwrap())
+}
+
+fn expire_subscriptions(subscriptions: &mut Subscriptions, now: Instant) {
+    for subscribers in subscriptions.values_mut() {
+        subscribers.retain(|_, subscriber| subscriber.expires.map_or(true, |t| t > now));
+    }
+    subscriptions.retain(|_, subscribers| !subscribers.is_empty());
+}
+
+/// The change to an account to send to one of its subscribers. It is sent again
+/// until `RequestProcessor::commit_subscription_update` records it was delivered.
+pub struct SubscriptionUpdate {
+    pub rsp: Response,
+    pub rsp_addr: SocketAddr,
+    key: Pubkey,
+    state: Hash,
+}
 
 pub struct RequestProcessor {
     bank: Arc<Bank>,
+    subscriptions: Mutex<Subscriptions>,
+    // the last entry id of the bank when the subscriptions were last checked
+    subscriptions_last_id: Mutex<Hash>,
 }
 
 impl RequestProcessor {
     /// Create a new Tpu that wraps the given Bank.
     pub fn new(bank: Arc<Bank>) -> Self {
-        RequestProcessor { bank }
+        let last_id = bank.last_id();
+        RequestProcessor {
+            bank,
+            subscriptions: Mutex::new(HashMap::new()),
+            subscriptions_last_id: Mutex::new(last_id),
+        }
+    }
+
+    // Make `rsp_addr` a subscriber of `key`, or renew its subscription. A new
+    // subscriber is refused once the subscriptions are at one of their limits.
+    fn subscribe(
+        &self,
+        key: Pubkey,
+        rsp_addr: SocketAddr,
+        account: &Option<Account>,
+        ttl: Option<Duration>,
+    ) -> bool {
+        let now = Instant::now();
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        expire_subscriptions(&mut subscriptions, now);
+        let renewed = subscriptions
+            .get(&key)
+            .map_or(false, |subscribers| subscribers.contains_key(&rsp_addr));
+        if !renewed {
+            let mut total = 0;
+            let mut same_ip = 0;
+            for subscribers in subscriptions.values() {
+                total += subscribers.len();
+                same_ip += subscribers
+                    .keys()
+                    .filter(|addr| addr.ip() == rsp_addr.ip())
+                    .count();
+            }
+            if total >= MAX_SUBSCRIPTIONS || same_ip >= MAX_SUBSCRIPTIONS_PER_IP {
+                return false;
+            }
+        }
+        let subscriber = Subscriber {
+            state: account_state(account),
+            expires: ttl.map(|ttl| now + ttl),
+        };
+        subscriptions
+            .entry(key)
+            .or_insert_with(HashMap::new)
+            .insert(rsp_addr, subscriber);
+        true
     }
 
-    /// Process Request items sent by clients.
+    /// Process Request items sent by clients. The account subscriptions made with
+    /// them last `ttl`, or until `unsubscribe_all` when it's None.
     fn process_request(
         &self,
         msg: Request,
         rsp_addr: SocketAddr,
+        ttl: Option<Duration>,
     ) -> Option<(Response, SocketAddr)> {
         match msg {
             Request::GetAccount { key } => {
@@ -52,15 +144,284 @@ impl RequestProcessor {
                 info!("Response::Finality {:?}", rsp);
                 Some(rsp)
             }
+            Request::GetSignatureStatus { signature } => {
+                let result = self.bank.get_signature_status(&signature);
+                let rsp = (Response::SignatureResult { signature, result }, rsp_addr);
+                info!("Response::SignatureResult {:?}", rsp);
+                Some(rsp)
+            }
+            Request::GetAccounts { keys } => {
+                let accounts = keys
+                    .into_iter()
+                    .map(|key| (key, self.bank.get_account(&key)))
+                    .collect();
+                let rsp = (Response::Accounts { accounts }, rsp_addr);
+                info!("Response::Accounts {:?}", rsp);
+                Some(rsp)
+            }
+            Request::GetSignatures { signatures } => {
+                let results = signatures
+                    .into_iter()
+                    .map(|signature| (signature, self.bank.get_signature_status(&signature)))
+                    .collect();
+                let rsp = (Response::SignatureResults { results }, rsp_addr);
+                info!("Response::SignatureResults {:?}", rsp);
+                Some(rsp)
+            }
+            Request::SubscribeAccount { key } => {
+                let account = self.bank.get_account(&key);
+                if !self.subscribe(key, rsp_addr, &account, ttl) {
+                    warn!("too many subscriptions, {} refused for {}", key, rsp_addr);
+                    return None;
+                }
+                // answer with the state the updates will follow from
+                let rsp = (Response::Account { key, account }, rsp_addr);
+                info!("Response::Account subscribed {:?}", rsp);
+                Some(rsp)
+            }
+            Request::UnsubscribeAccount { key } => {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                let unsubscribed = match subscriptions.get_mut(&key) {
+                    Some(subscribers) => {
+                        subscribers.remove(&rsp_addr);
+                        subscribers.is_empty()
+                    }
+                    None => false,
+                };
+                if unsubscribed {
+                    subscriptions.remove(&key);
+                }
+                None
+            }
         }
     }
 
+    /// Process the requests that came in datagrams, their senders can't be told from
+    /// spoofed ones so their account subscriptions expire after `SUBSCRIPTION_TTL_SECS`.
     pub fn process_requests(
         &self,
         reqs: Vec<(Request, SocketAddr)>,
     ) -> Vec<(Response, SocketAddr)> {
+        let ttl = Some(Duration::from_secs(SUBSCRIPTION_TTL_SECS));
         reqs.into_iter()
-            .filter_map(|(req, rsp_addr)| self.process_request(req, rsp_addr))
+            .filter_map(|(req, rsp_addr)| self.process_request(req, rsp_addr, ttl))
             .collect()
     }
+
+    /// Process the requests of a connected client, its account subscriptions last
+    /// until it disconnects and `unsubscribe_all` is called.
+    pub fn process_stream_requests(
+        &self,
+        reqs: Vec<(Request, SocketAddr)>,
+    ) -> Vec<(Response, SocketAddr)> {
+        reqs.into_iter()
+            .filter_map(|(req, rsp_addr)| self.process_request(req, rsp_addr, None))
+            .collect()
+    }
+
+    /// End the account subscriptions of the client at `rsp_addr`.
+    pub fn unsubscribe_all(&self, rsp_addr: &SocketAddr) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        for subscribers in subscriptions.values_mut() {
+            subscribers.remove(rsp_addr);
+        }
+        subscriptions.retain(|_, subscribers| !subscribers.is_empty());
+    }
+
+    /// The updates to send to the subscribers that weren't sent the current state of
+    /// their accounts. Accounts only change when the bank registers an entry, so there
+    /// is nothing to do until its last id moves, an update that wasn't committed is
+    /// sent again then.
+    pub fn process_subscriptions(&self) -> Vec<SubscriptionUpdate> {
+        let last_id = self.bank.last_id();
+        {
+            let mut subscriptions_last_id = self.subscriptions_last_id.lock().unwrap();
+            if *subscriptions_last_id == last_id {
+                return vec![];
+            }
+            *subscriptions_last_id = last_id;
+        }
+
+        let mut updates = vec![];
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        expire_subscriptions(&mut subscriptions, Instant::now());
+        for (key, subscribers) in subscriptions.iter() {
+            let account = self.bank.get_account(key);
+            let state = account_state(&account);
+            for (rsp_addr, subscriber) in subscribers {
+                if subscriber.state == state {
+                    continue;
+                }
+                let account = account.clone();
+                updates.push(SubscriptionUpdate {
+                    rsp: Response::Account { key: *key, account },
+                    rsp_addr: *rsp_addr,
+                    key: *key,
+                    state,
+                });
+            }
+        }
+        if !updates.is_empty() {
+            info!("Response::Account updates: {}", updates.len());
+        }
+        updates
+    }
+
+    /// Record that `update` was sent to its subscriber.
+    pub fn commit_subscription_update(&self, update: &SubscriptionUpdate) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if let Some(subscriber) = subscriptions
+            .get_mut(&update.key)
+            .and_then(|subscribers| subscribers.get_mut(&update.rsp_addr))
+        {
+            subscriber.state = update.state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coinery::Mint;
+    use crate::signature::{Keypair, KeypairUtil};
+    use crate::system_transaction::SystemTransaction;
+    use crate::transaction::Transaction;
+    use std::thread::sleep;
+
+    fn account_tokens(rsp: &Response) -> Option<i64> {
+        match rsp {
+            Response::Account { account, .. } => account.as_ref().map(|account| account.tokens),
+            _ => panic!("not an account response {:?}", rsp),
+        }
+    }
+
+    fn commit_all(request_processor: &RequestProcessor, updates: &[SubscriptionUpdate]) {
+        for update in updates {
+            request_processor.commit_subscription_update(update);
+        }
+    }
+
+    #[test]
+    fn test_subscribe_account() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        let request_processor = RequestProcessor::new(bank.clone());
+        let bob = Keypair::new().pubkey();
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let other_addr: SocketAddr = "127.0.0.1:1235".parse().unwrap();
+
+        let rsps = request_processor.process_requests(vec![
+            (Request::SubscribeAccount { key: bob }, addr),
+            (Request::SubscribeAccount { key: bob }, other_addr),
+        ]);
+        assert_eq!(rsps.len(), 2);
+        assert_eq!(account_tokens(&rsps[0].0), None);
+        assert!(request_processor.process_subscriptions().is_empty());
+
+        // a change is only seen once the bank registers an entry
+        let tx = Transaction::system_new(&mint.keypair(), bob, 500, mint.last_id());
+        bank.process_transaction(&tx).unwrap();
+        assert!(request_processor.process_subscriptions().is_empty());
+        bank.register_entry_id(&Hash::default());
+        let updates = request_processor.process_subscriptions();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(account_tokens(&updates[0].rsp), Some(500));
+
+        // an update that wasn't sent is sent again after the next entry
+        let (sent, unsent): (Vec<_>, Vec<_>) = updates
+            .into_iter()
+            .partition(|update| update.rsp_addr == addr);
+        commit_all(&request_processor, &sent);
+        bank.register_entry_id(&Hash::new(&[1; 32]));
+        let updates = request_processor.process_subscriptions();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].rsp_addr, unsent[0].rsp_addr);
+        assert_eq!(account_tokens(&updates[0].rsp), Some(500));
+        commit_all(&request_processor, &updates);
+
+        // entries that don't touch the account send nothing
+        bank.register_entry_id(&Hash::new(&[2; 32]));
+        assert!(request_processor.process_subscriptions().is_empty());
+
+        let rsps = request_processor
+            .process_requests(vec![(Request::UnsubscribeAccount { key: bob }, other_addr)]);
+        assert!(rsps.is_empty());
+        let tx = Transaction::system_new(&mint.keypair(), bob, 1, mint.last_id());
+        bank.process_transaction(&tx).unwrap();
+        bank.register_entry_id(&Hash::new(&[3; 32]));
+        let updates = request_processor.process_subscriptions();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].rsp_addr, addr);
+        assert_eq!(account_tokens(&updates[0].rsp), Some(501));
+        commit_all(&request_processor, &updates);
+
+        request_processor.unsubscribe_all(&addr);
+        let tx = Transaction::system_new(&mint.keypair(), bob, 1, mint.last_id());
+        bank.process_transaction(&tx).unwrap();
+        bank.register_entry_id(&Hash::new(&[4; 32]));
+        assert!(request_processor.process_subscriptions().is_empty());
+    }
+
+    #[test]
+    fn test_subscription_expires() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        let request_processor = RequestProcessor::new(bank.clone());
+        let bob = Keypair::new().pubkey();
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let stream_addr: SocketAddr = "127.0.0.1:1235".parse().unwrap();
+
+        let req = Request::SubscribeAccount { key: bob };
+        assert!(request_processor
+            .process_request(req.clone(), addr, Some(Duration::new(0, 0)))
+            .is_some());
+        sleep(Duration::from_millis(1));
+        assert_eq!(
+            request_processor
+                .process_stream_requests(vec![(req, stream_addr)])
+                .len(),
+            1
+        );
+
+        // only the subscription made over a stream is still there
+        let tx = Transaction::system_new(&mint.keypair(), bob, 500, mint.last_id());
+        bank.process_transaction(&tx).unwrap();
+        bank.register_entry_id(&Hash::default());
+        let updates = request_processor.process_subscriptions();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].rsp_addr, stream_addr);
+    }
+
+    #[test]
+    fn test_subscription_limits() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        let request_processor = RequestProcessor::new(bank);
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let keys: Vec<_> = (0..=MAX_SUBSCRIPTIONS_PER_IP)
+            .map(|_| Keypair::new().pubkey())
+            .collect();
+
+        let reqs: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                // a different port doesn't get around the limit of the address
+                let mut addr = addr;
+                addr.set_port(1234 + i as u16);
+                (Request::SubscribeAccount { key: *key }, addr)
+            }).collect();
+        let rsps = request_processor.process_requests(reqs.clone());
+        assert_eq!(rsps.len(), MAX_SUBSCRIPTIONS_PER_IP);
+
+        // renewing a subscription isn't refused
+        let rsps = request_processor.process_requests(reqs[..1].to_vec());
+        assert_eq!(rsps.len(), 1);
+
+        // other addresses still get theirs
+        let other_addr: SocketAddr = "127.0.0.2:1234".parse().unwrap();
+        let req = Request::SubscribeAccount { key: keys[0] };
+        let rsps = request_processor.process_requests(vec![(req, other_addr)]);
+        assert_eq!(rsps.len(), 1);
+    }
 }
@@ -3,21 +3,26 @@
 use bincode::deserialize;
 use crate::counter::Counter;
 use log::Level;
-use crate::packet::{to_blobs, Packets, SharedPackets};
+use crate::packet::{to_blob, Packets, SharedBlob, SharedPackets};
 use rayon::prelude::*;
-use crate::request::Request;
+use crate::request::{Request, Response, SUBSCRIPTION_UPDATE_ID};
 use request_processor::RequestProcessor;
 use crate::result::{Error, Result};
 use crate::service::Service;
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicUsize;
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
 use std::sync::Arc;
 use std::thread::{self, Builder, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use crate::streamer::{self, BlobReceiver, BlobSender};
+use crate::tcp_streamer::Connections;
 use crate::timing;
 
+// how often the subscribed accounts are checked for changes when no requests come in
+const SUBSCRIPTION_POLL_MS: u64 = 100;
+
 pub struct RequestStage {
     thread_hdl: JoinHandle<()>,
     pub request_processor: Arc<RequestProcessor>,
@@ -34,12 +39,28 @@ impl RequestStage {
             }).collect()
     }
 
+    // The blob of a response, None for the ones that don't fit in one. They are
+    // dropped on their own so the rest of the batch is still answered.
+    fn response_blob(rsp: &Response, rsp_addr: SocketAddr) -> Option<SharedBlob> {
+        match to_blob(rsp, rsp_addr) {
+            Ok(blob) => Some(blob),
+            Err(e) => {
+                warn!("response to {} dropped: {:?}", rsp_addr, e);
+                inc_new_counter_info!("request_stage-dropped_responses", 1);
+                None
+            }
+        }
+    }
+
     pub fn process_request_packets(
         request_processor: &RequestProcessor,
         packet_receiver: &Receiver<SharedPackets>,
         blob_sender: &BlobSender,
     ) -> Result<()> {
-        let (batch, batch_len, _recv_time) = streamer::recv_batch(packet_receiver)?;
+        let (batch, batch_len, _recv_time) = streamer::recv_batch_timeout(
+            packet_receiver,
+            Duration::from_millis(SUBSCRIPTION_POLL_MS),
+        )?;
 
         debug!(
             "@{:?} request_stage: processing: {}",
@@ -58,7 +79,10 @@ impl RequestStage {
 
             let rsps = request_processor.process_requests(reqs);
 
-            let blobs = to_blobs(rsps)?;
+            let blobs: Vec<_> = rsps
+                .iter()
+                .filter_map(|(rsp, rsp_addr)| Self::response_blob(rsp, *rsp_addr))
+                .collect();
             if !blobs.is_empty() {
                 info!("process: sending blobs: {}", blobs.len());
                 //don't wake up the other side if there is nothing
@@ -78,9 +102,51 @@ impl RequestStage {
         );
         Ok(())
     }
+
+    /// Push the changes to subscribed accounts to their subscribers, over the tcp
+    /// connection of the ones in `connections` and in blobs to the others. Only the
+    /// updates that were sent are committed, the others are tried again later. The
+    /// subscriptions of a connection that fails a write end with it.
+    pub fn process_subscriptions(
+        request_processor: &RequestProcessor,
+        connections: &Connections,
+        blob_sender: &BlobSender,
+    ) -> Result<()> {
+        let mut blobs = vec![];
+        let mut queued = vec![];
+        let mut dropped = HashSet::new();
+        for update in request_processor.process_subscriptions() {
+            if dropped.contains(&update.rsp_addr) {
+                continue;
+            }
+            if connections.contains(&update.rsp_addr) {
+                match connections.send(&update.rsp_addr, SUBSCRIPTION_UPDATE_ID, &update.rsp) {
+                    Ok(()) => request_processor.commit_subscription_update(&update),
+                    Err(e) => {
+                        warn!("subscription update to {} failed: {:?}", update.rsp_addr, e);
+                        request_processor.unsubscribe_all(&update.rsp_addr);
+                        dropped.insert(update.rsp_addr);
+                    }
+                }
+            } else if let Some(blob) = Self::response_blob(&update.rsp, update.rsp_addr) {
+                blobs.push(blob);
+                queued.push(update);
+            }
+        }
+        if !blobs.is_empty() {
+            info!("process: sending subscription blobs: {}", blobs.len());
+            blob_sender.send(blobs)?;
+            for update in &queued {
+                request_processor.commit_subscription_update(update);
+            }
+        }
+        Ok(())
+    }
+
     pub fn new(
         request_processor: RequestProcessor,
         packet_receiver: Receiver<SharedPackets>,
+        connections: Arc<Connections>,
     ) -> (Self, BlobReceiver) {
         let request_processor = Arc::new(request_processor);
         let request_processor_ = request_processor.clone();
@@ -99,6 +165,11 @@ impl RequestStage {
                         _ => error!("{:?}", e),
                     }
                 }
+                if let Err(e) =
+                    Self::process_subscriptions(&request_processor_, &connections, &blob_sender)
+                {
+                    error!("{:?}", e);
+                }
             }).unwrap();
         (
             RequestStage {
@@ -3,7 +3,6 @@
 use crate::tx_vault;
 use bincode;
 use crdt;
-#[cfg(feature = "erasure")]
 use crate::erasure;
 use packet;
 use serde_json;
@@ -22,7 +21,6 @@ pub enum Error {
     BankError(tx_vault::BankError),
     CrdtError(crdt::CrdtError),
     BlobError(packet::BlobError),
-    #[cfg(feature = "erasure")]
     ErasureError(erasure::ErasureError),
     SendError,
 }
@@ -57,7 +55,6 @@ impl std::convert::From<crdt::CrdtError> for Error {
         Error::CrdtError(e)
     }
 }
-#[cfg(feature = "erasure")]
 impl std::convert::From<erasure::ErasureError> for Error {
     fn from(e: erasure::ErasureError) -> Error {
         Error::ErasureError(e)
@@ -5,6 +5,9 @@ use bincode::deserialize;
 use bs58;
 use jsonrpc_core::*;
 use jsonrpc_http_server::*;
+use jsonrpc_macros::Trailing;
+use crate::ledger::LedgerWindow;
+use crate::ledger_index::{LedgerIndex, MAX_SIGNATURES_LIMIT};
 use crate::service::Service;
 use crate::signature::Signature;
 use buffett_program_interface::account::Account;
@@ -13,7 +16,7 @@ use std::mem;
 use std::net::{SocketAddr, UdpSocket};
 use std::result;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, sleep, Builder, JoinHandle};
 use std::time::Duration;
 use std::time::Instant;
@@ -32,9 +35,15 @@ impl JsonRpcService {
         transactions_addr: SocketAddr,
         drone_addr: SocketAddr,
         rpc_addr: SocketAddr,
+        ledger_path: Option<&str>,
         exit: Arc<AtomicBool>,
     ) -> Self {
-        let request_processor = JsonRpcRequestProcessor::new(bank.clone());
+        let request_processor = match ledger_path {
+            Some(ledger_path) => {
+                JsonRpcRequestProcessor::new_with_ledger(bank.clone(), ledger_path)
+            }
+            None => JsonRpcRequestProcessor::new(bank.clone()),
+        };
         let thread_hdl = Builder::new()
             .name("bitconch-jsonrpc".to_string())
             .spawn(move || {
@@ -94,6 +103,20 @@ pub enum RpcSignatureStatus {
     GenericFailure,
 }
 
+#[derive(Deserialize, Default)]
+pub struct RpcSignaturesConfig {
+    /// Only return signatures of transactions before the one with this signature
+    pub before: Option<String>,
+    /// The most signatures to return, up to `MAX_SIGNATURES_LIMIT`
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct RpcTransaction {
+    pub entry_index: u64,
+    pub transaction: Transaction,
+}
+
 build_rpc_trait! {
     pub trait RpcSol {
         type Metadata;
@@ -113,9 +136,15 @@ build_rpc_trait! {
         #[rpc(meta, name = "getLastId")]
         fn get_last_id(&self, Self::Metadata) -> Result<String>;
 
+        #[rpc(meta, name = "getSignaturesForAddress")]
+        fn get_signatures_for_address(&self, Self::Metadata, String, Trailing<RpcSignaturesConfig>) -> Result<Vec<String>>;
+
         #[rpc(meta, name = "getSignatureStatus")]
         fn get_signature_status(&self, Self::Metadata, String) -> Result<RpcSignatureStatus>;
 
+        #[rpc(meta, name = "getTransaction")]
+        fn get_transaction(&self, Self::Metadata, String) -> Result<RpcTransaction>;
+
         #[rpc(meta, name = "getTransactionCount")]
         fn get_transaction_count(&self, Self::Metadata) -> Result<u64>;
 
@@ -162,6 +191,40 @@ impl RpcSol for RpcSolImpl {
     fn get_last_id(&self, meta: Self::Metadata) -> Result<String> {
         meta.request_processor.get_last_id()
     }
+    fn get_signatures_for_address(
+        &self,
+        meta: Self::Metadata,
+        id: String,
+        config: Trailing<RpcSignaturesConfig>,
+    ) -> Result<Vec<String>> {
+        let pubkey_vec = bs58::decode(id)
+            .into_vec()
+            .map_err(|_| Error::invalid_request())?;
+        if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+            return Err(Error::invalid_request());
+        }
+        let pubkey = Pubkey::new(&pubkey_vec);
+        let config: Option<RpcSignaturesConfig> = config.into();
+        let config = config.unwrap_or_default();
+        let before = match config.before {
+            Some(before) => {
+                let signature_vec = bs58::decode(before)
+                    .into_vec()
+                    .map_err(|_| Error::invalid_request())?;
+                if signature_vec.len() != mem::size_of::<Signature>() {
+                    return Err(Error::invalid_request());
+                }
+                Some(Signature::new(&signature_vec))
+            }
+            None => None,
+        };
+        let limit = config.limit.unwrap_or(MAX_SIGNATURES_LIMIT);
+        if limit > MAX_SIGNATURES_LIMIT {
+            return Err(Error::invalid_request());
+        }
+        meta.request_processor
+            .get_signatures_for_address(pubkey, before, limit)
+    }
     fn get_signature_status(&self, meta: Self::Metadata, id: String) -> Result<RpcSignatureStatus> {
         let signature_vec = bs58::decode(id)
             .into_vec()
@@ -182,6 +245,16 @@ impl RpcSol for RpcSolImpl {
             },
         )
     }
+    fn get_transaction(&self, meta: Self::Metadata, id: String) -> Result<RpcTransaction> {
+        let signature_vec = bs58::decode(id)
+            .into_vec()
+            .map_err(|_| Error::invalid_request())?;
+        if signature_vec.len() != mem::size_of::<Signature>() {
+            return Err(Error::invalid_request());
+        }
+        let signature = Signature::new(&signature_vec);
+        meta.request_processor.get_transaction(signature)
+    }
     fn get_transaction_count(&self, meta: Self::Metadata) -> Result<u64> {
         meta.request_processor.get_transaction_count()
     }
@@ -226,11 +299,52 @@ impl RpcSol for RpcSolImpl {
 #[derive(Clone)]
 pub struct JsonRpcRequestProcessor {
     bank: Arc<Bank>,
+    ledger_path: Option<String>,
+    ledger_index: Arc<Mutex<Option<LedgerIndex>>>,
 }
 impl JsonRpcRequestProcessor {
     /// Create a new request processor that wraps the given Bank.
     pub fn new(bank: Arc<Bank>) -> Self {
-        JsonRpcRequestProcessor { bank }
+        JsonRpcRequestProcessor {
+            bank,
+            ledger_path: None,
+            ledger_index: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Create a new request processor that also looks transactions up in the
+    /// ledger at `ledger_path`.
+    pub fn new_with_ledger(bank: Arc<Bank>, ledger_path: &str) -> Self {
+        JsonRpcRequestProcessor {
+            bank,
+            ledger_path: Some(ledger_path.to_string()),
+            ledger_index: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // runs f with the ledger index, opened on first use and caught up with the ledger
+    fn with_ledger_index<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut LedgerIndex) -> Result<T>,
+    {
+        let ledger_path = self.ledger_path.as_ref().ok_or_else(|| {
+            debug!("no ledger to look transactions up in");
+            Error::internal_error()
+        })?;
+        let mut ledger_index = self.ledger_index.lock().unwrap();
+        if ledger_index.is_none() {
+            let index = LedgerIndex::open(ledger_path).map_err(|err| {
+                debug!("unable to open ledger index: {:?}", err);
+                Error::internal_error()
+            })?;
+            *ledger_index = Some(index);
+        }
+        let index = ledger_index.as_mut().unwrap();
+        index.refresh().map_err(|err| {
+            debug!("unable to read ledger index: {:?}", err);
+            Error::internal_error()
+        })?;
+        f(index)
     }
 
     /// Process JSON-RPC request items sent via JSON-RPC.
@@ -256,6 +370,50 @@ impl JsonRpcRequestProcessor {
     fn get_transaction_count(&self) -> Result<u64> {
         Ok(self.bank.transaction_count() as u64)
     }
+    fn get_transaction(&self, signature: Signature) -> Result<RpcTransaction> {
+        let entry_index = self.with_ledger_index(|index| {
+            index
+                .get_entry_index(&signature)
+                .map_err(|err| {
+                    debug!("unable to read ledger index: {:?}", err);
+                    Error::internal_error()
+                })?
+                .ok_or_else(Error::invalid_request)
+        })?;
+        let entry = LedgerWindow::open(self.ledger_path.as_ref().unwrap())
+            .and_then(|mut window| window.get_entry(entry_index))
+            .map_err(|err| {
+                debug!("unable to read entry {}: {:?}", entry_index, err);
+                Error::internal_error()
+            })?;
+        entry
+            .transactions
+            .into_iter()
+            .find(|tx| tx.signature == signature)
+            .map(|transaction| RpcTransaction {
+                entry_index,
+                transaction,
+            }).ok_or_else(Error::internal_error)
+    }
+    fn get_signatures_for_address(
+        &self,
+        pubkey: Pubkey,
+        before: Option<Signature>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.with_ledger_index(|index| {
+            let signatures = index
+                .get_signatures(&pubkey, before.as_ref(), limit)
+                .map_err(|err| {
+                    debug!("unable to read ledger index: {:?}", err);
+                    Error::internal_error()
+                })?;
+            Ok(signatures
+                .iter()
+                .map(|signature| bs58::encode(signature).into_string())
+                .collect())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -264,7 +422,9 @@ mod tests {
     use crate::tx_vault::Bank;
     use jsonrpc_core::Response;
     use crate::coinery::Mint;
+    use crate::ledger::{next_entries, tmp_ledger_path, LedgerWriter};
     use crate::signature::{Keypair, KeypairUtil};
+    use std::fs::remove_dir_all;
     use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::sync::Arc;
     use crate::system_transaction::SystemTransaction;
@@ -339,6 +499,102 @@ mod tests {
         assert_eq!(expected, result);
     }
     #[test]
+    fn test_rpc_ledger_requests() {
+        let ledger_path = tmp_ledger_path("test_rpc_ledger_requests");
+        let alice = Mint::new(10_000);
+        let bob_pubkey = Keypair::new().pubkey();
+        let bank = Bank::new(&alice);
+
+        let tx = Transaction::system_new(&alice.keypair(), bob_pubkey, 20, alice.last_id());
+        let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+        writer.write_entries(alice.create_entries()).unwrap();
+        writer
+            .write_entries(next_entries(&alice.last_id(), 0, vec![tx.clone()]))
+            .unwrap();
+
+        let mut io = MetaIoHandler::default();
+        let rpc = RpcSolImpl;
+        io.extend_with(rpc.to_delegate());
+        let meta = Meta {
+            request_processor: JsonRpcRequestProcessor::new_with_ledger(
+                Arc::new(bank),
+                &ledger_path,
+            ),
+            transactions_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            drone_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        };
+
+        let req = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"getSignaturesForAddress","params":["{}"]}}"#,
+            bob_pubkey
+        );
+        let res = io.handle_request_sync(&req, meta.clone());
+        let expected = format!(
+            r#"{{"jsonrpc":"2.0","result":["{}"],"id":1}}"#,
+            bs58::encode(tx.signature).into_string()
+        );
+        let expected: Response =
+            serde_json::from_str(&expected).expect("expected response deserialization");
+        let result: Response = serde_json::from_str(&res.expect("actual response"))
+            .expect("actual response deserialization");
+        assert_eq!(expected, result);
+
+        // the mint's signatures a page at a time, newest first
+        let req = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"getSignaturesForAddress","params":["{}",{{"limit":1}}]}}"#,
+            alice.pubkey()
+        );
+        let res = io.handle_request_sync(&req, meta.clone());
+        let result: serde_json::Value = serde_json::from_str(&res.expect("actual response"))
+            .expect("actual response deserialization");
+        assert_eq!(
+            result["result"],
+            json!([bs58::encode(tx.signature).into_string()])
+        );
+        let req = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"getSignaturesForAddress","params":["{}",{{"before":"{}","limit":10}}]}}"#,
+            alice.pubkey(),
+            bs58::encode(tx.signature).into_string()
+        );
+        let res = io.handle_request_sync(&req, meta.clone());
+        let result: serde_json::Value = serde_json::from_str(&res.expect("actual response"))
+            .expect("actual response deserialization");
+        let mint_signature = alice.create_entries()[1].transactions[0].signature;
+        assert_eq!(
+            result["result"],
+            json!([bs58::encode(mint_signature).into_string()])
+        );
+
+        let req = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"getTransaction","params":["{}"]}}"#,
+            bs58::encode(tx.signature).into_string()
+        );
+        let res = io.handle_request_sync(&req, meta.clone());
+        let result: serde_json::Value = serde_json::from_str(&res.expect("actual response"))
+            .expect("actual response deserialization");
+        assert_eq!(result["result"]["entry_index"], 2);
+        assert_eq!(
+            result["result"]["transaction"],
+            serde_json::to_value(&tx).unwrap()
+        );
+
+        // a transaction that isn't in the ledger
+        let req = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"getTransaction","params":["{}"]}}"#,
+            bs58::encode(Signature::default()).into_string()
+        );
+        let res = io.handle_request_sync(&req, meta);
+        let expected =
+            r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":1}"#;
+        let expected: Response =
+            serde_json::from_str(expected).expect("expected response deserialization");
+        let result: Response = serde_json::from_str(&res.expect("actual response"))
+            .expect("actual response deserialization");
+        assert_eq!(expected, result);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+    #[test]
     fn test_rpc_request_bad_parameter_type() {
         let alice = Mint::new(10_000);
         let bank = Bank::new(&alice);
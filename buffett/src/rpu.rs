@@ -22,17 +22,21 @@
 //!              |                                      |
 //!              `--------------------------------------`
 //! ```
+//!
+//! Given a tcp listener, the RPU also answers requests sent over tcp connections
+//! with the `tcp_streamer` framing.
 
 use crate::tx_vault::Bank;
 use request_processor::RequestProcessor;
 use request_stage::RequestStage;
 use crate::service::Service;
-use std::net::UdpSocket;
+use std::net::{TcpListener, UdpSocket};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use crate::streamer;
+use crate::tcp_streamer::{self, Connections};
 
 pub struct Rpu {
     request_stage: RequestStage,
@@ -41,7 +45,12 @@ pub struct Rpu {
 }
 
 impl Rpu {
-    pub fn new(bank: &Arc<Bank>, requests_socket: UdpSocket, respond_socket: UdpSocket) -> Self {
+    pub fn new(
+        bank: &Arc<Bank>,
+        requests_socket: UdpSocket,
+        respond_socket: UdpSocket,
+        requests_listener: Option<TcpListener>,
+    ) -> Self {
         let exit = Arc::new(AtomicBool::new(false));
         let (packet_sender, packet_receiver) = channel();
         let t_receiver = streamer::receiver(
@@ -52,11 +61,21 @@ impl Rpu {
         );
 
         let request_processor = RequestProcessor::new(bank.clone());
-        let (request_stage, blob_receiver) = RequestStage::new(request_processor, packet_receiver);
+        let connections = Arc::new(Connections::default());
+        let (request_stage, blob_receiver) =
+            RequestStage::new(request_processor, packet_receiver, connections.clone());
 
         let t_responder = streamer::responder("rpu", Arc::new(respond_socket), blob_receiver);
 
-        let thread_hdls = vec![t_receiver, t_responder];
+        let mut thread_hdls = vec![t_receiver, t_responder];
+        if let Some(listener) = requests_listener {
+            thread_hdls.push(tcp_streamer::request_server(
+                listener,
+                exit.clone(),
+                request_stage.request_processor.clone(),
+                connections,
+            ));
+        }
 
         Rpu {
             thread_hdls,
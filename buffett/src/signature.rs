@@ -5,6 +5,7 @@ use generic_array::typenum::U64;
 use generic_array::GenericArray;
 use rand::{ChaChaRng, Rng, SeedableRng};
 use rayon::prelude::*;
+use ring::rand::SecureRandom;
 use ring::signature::Ed25519KeyPair;
 use ring::{rand, signature};
 use serde_json;
@@ -16,6 +17,16 @@ use untrusted::Input;
 
 pub type Keypair = Ed25519KeyPair;
 
+/// The length of a secret key: the 32 byte seed followed by the 32 byte public key.
+pub const SECRET_KEY_LENGTH: usize = 64;
+
+// Ed25519 PKCS#8 v2 documents, as `Ed25519KeyPair::generate_pkcs8` produces them, are
+// the seed and the public key wrapped in these fixed bytes.
+const PKCS8_SEED_PREFIX: [u8; 16] = [
+    0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+const PKCS8_PUBLIC_KEY_PREFIX: [u8; 5] = [0xa1, 0x23, 0x03, 0x21, 0x00];
+
 #[derive(Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Signature(GenericArray<u8, U64>);
 
@@ -96,6 +107,44 @@ impl GenKeys {
     }
 }
 
+/// Return a new secret key, see `SECRET_KEY_LENGTH`.
+pub fn gen_secret_key() -> Result<Vec<u8>, Box<error::Error>> {
+    let mut seed = [0u8; 32];
+    rand::SystemRandom::new()
+        .fill(&mut seed)
+        .map_err(|_| "failed to generate a seed")?;
+    let keypair = Ed25519KeyPair::from_seed_unchecked(Input::from(&seed))?;
+    let mut secret = seed.to_vec();
+    secret.extend_from_slice(keypair.public_key_bytes());
+    Ok(secret)
+}
+
+/// Load a keypair from a secret key, checking that its public key half matches the seed.
+pub fn keypair_from_secret_key(secret: &[u8]) -> Result<Keypair, Box<error::Error>> {
+    if secret.len() != SECRET_KEY_LENGTH {
+        Err(format!(
+            "secret key must be {} bytes, got {}",
+            SECRET_KEY_LENGTH,
+            secret.len()
+        ))?;
+    }
+    let keypair = Ed25519KeyPair::from_seed_and_public_key(
+        Input::from(&secret[..32]),
+        Input::from(&secret[32..]),
+    )?;
+    Ok(keypair)
+}
+
+/// Convert a secret key to the PKCS#8 document stored in keypair files.
+pub fn pkcs8_from_secret_key(secret: &[u8]) -> Result<Vec<u8>, Box<error::Error>> {
+    keypair_from_secret_key(secret)?;
+    let mut pkcs8 = PKCS8_SEED_PREFIX.to_vec();
+    pkcs8.extend_from_slice(&secret[..32]);
+    pkcs8.extend_from_slice(&PKCS8_PUBLIC_KEY_PREFIX);
+    pkcs8.extend_from_slice(&secret[32..]);
+    Ok(pkcs8)
+}
+
 pub fn read_pkcs8(path: &str) -> Result<Vec<u8>, Box<error::Error>> {
     let file = File::open(path.to_string())?;
     let pkcs8: Vec<u8> = serde_json::from_reader(file)?;
@@ -137,4 +186,24 @@ mod tests {
         let seed = [0u8; 32];
         assert_eq!(gen_n_pubkeys(seed, 50), gen_n_pubkeys(seed, 50));
     }
+
+    #[test]
+    fn test_secret_key() {
+        let secret = gen_secret_key().unwrap();
+        assert_eq!(secret.len(), SECRET_KEY_LENGTH);
+        let keypair = keypair_from_secret_key(&secret).unwrap();
+        assert_eq!(keypair.public_key_bytes(), &secret[32..]);
+
+        let pkcs8 = pkcs8_from_secret_key(&secret).unwrap();
+        let keypair = Ed25519KeyPair::from_pkcs8(Input::from(&pkcs8)).unwrap();
+        assert_eq!(keypair.public_key_bytes(), &secret[32..]);
+    }
+
+    #[test]
+    fn test_secret_key_mismatched_public_key() {
+        let mut secret = gen_secret_key().unwrap();
+        secret[63] ^= 1;
+        assert!(keypair_from_secret_key(&secret).is_err());
+        assert!(keypair_from_secret_key(&secret[..32]).is_err());
+    }
 }
@@ -1,18 +1,41 @@
 //! The `sigverify` module provides digital signature verification functions.
 //! By default, signatures are verified in parallel using all available CPU
 //! cores.  When `--features=cuda` is enabled, signature verification is
-//! offloaded to the GPU.
+//! offloaded to the GPU. The CPU can also verify signatures in batches, which
+//! is selected at runtime with `SigVerifyMode`.
 //!
 
 use crate::counter::Counter;
+use crate::ed25519;
 use log::Level;
 use crate::packet::{Packet, SharedPackets};
 use std::mem::size_of;
 use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, RwLock};
 use crate::transaction::{PUB_KEY_OFFSET, SIGNED_DATA_OFFSET, SIG_OFFSET};
 
 pub const TX_OFFSET: usize = 0;
 
+/// The number of packets the CPU batch mode verifies together. A bad signature
+/// fails its whole chunk, which is then verified packet by packet.
+pub const VERIFY_CHUNK_SIZE: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SigVerifyMode {
+    /// Verify every signature on its own, on the GPU when it's available.
+    PerPacket,
+    /// Verify chunks of signatures together on the CPU.
+    CpuBatch,
+}
+
+impl Default for SigVerifyMode {
+    fn default() -> Self {
+        SigVerifyMode::PerPacket
+    }
+}
+
+pub type SharedSigVerifyMode = Arc<RwLock<SigVerifyMode>>;
+
 #[cfg(feature = "cuda")]
 #[repr(C)]
 struct Elems {
@@ -42,11 +65,11 @@ pub fn init() {
     // stub
 }
 
-fn verify_packet(packet: &Packet) -> u8 {
-    use ring::signature;
+/// Returns the signed message, signature and public key of a packet, or None if
+/// it's too short to hold a signed message.
+fn packet_parts(packet: &Packet) -> Option<(&[u8], &[u8], &[u8])> {
     use crate::signature::Signature;
     use buffett_program_interface::pubkey::Pubkey;
-    use untrusted;
 
     let msg_start = TX_OFFSET + SIGNED_DATA_OFFSET;
     let sig_start = TX_OFFSET + SIG_OFFSET;
@@ -55,16 +78,49 @@ fn verify_packet(packet: &Packet) -> u8 {
     let pubkey_end = pubkey_start + size_of::<Pubkey>();
 
     if packet.meta.size <= msg_start {
-        return 0;
+        return None;
     }
 
     let msg_end = packet.meta.size;
-    signature::verify(
-        &signature::ED25519,
-        untrusted::Input::from(&packet.data[pubkey_start..pubkey_end]),
-        untrusted::Input::from(&packet.data[msg_start..msg_end]),
-        untrusted::Input::from(&packet.data[sig_start..sig_end]),
-    ).is_ok() as u8
+    Some((
+        &packet.data[msg_start..msg_end],
+        &packet.data[sig_start..sig_end],
+        &packet.data[pubkey_start..pubkey_end],
+    ))
+}
+
+fn verify_packet(packet: &Packet) -> u8 {
+    use ring::signature;
+    use untrusted;
+
+    match packet_parts(packet) {
+        Some((msg, sig, pubkey)) => signature::verify(
+            &signature::ED25519,
+            untrusted::Input::from(pubkey),
+            untrusted::Input::from(msg),
+            untrusted::Input::from(sig),
+        ).is_ok() as u8,
+        None => 0,
+    }
+}
+
+fn verify_chunk(packets: &[Packet]) -> Vec<u8> {
+    let parts: Vec<_> = packets.iter().map(packet_parts).collect();
+    let mut msgs = Vec::with_capacity(packets.len());
+    let mut sigs = Vec::with_capacity(packets.len());
+    let mut pubkeys = Vec::with_capacity(packets.len());
+    for (msg, sig, pubkey) in parts.iter().filter_map(|x| *x) {
+        msgs.push(msg);
+        sigs.push(sig);
+        pubkeys.push(pubkey);
+    }
+    if ed25519::verify_batch(&msgs, &sigs, &pubkeys) {
+        return parts.iter().map(|x| x.is_some() as u8).collect();
+    }
+
+    // the batch doesn't say which signature is bad, so check them one at a time
+    inc_new_counter_info!("ed25519_verify_cpu_batch-fallback", packets.len());
+    packets.iter().map(verify_packet).collect()
 }
 
 fn verify_packet_disabled(_packet: &Packet) -> u8 {
@@ -102,6 +158,24 @@ pub fn ed25519_verify_cpu(batches: &[SharedPackets]) -> Vec<Vec<u8>> {
     rv
 }
 
+pub fn ed25519_verify_cpu_batch(batches: &[SharedPackets]) -> Vec<Vec<u8>> {
+    use rayon::prelude::*;
+    let count = batch_size(batches);
+    info!("CPU batch ECDSA for {}", count);
+    let rv = batches
+        .into_par_iter()
+        .map(|p| {
+            p.read()
+                .unwrap()
+                .packets
+                .par_chunks(VERIFY_CHUNK_SIZE)
+                .flat_map(verify_chunk)
+                .collect()
+        }).collect();
+    inc_new_counter_info!("ed25519_verify_cpu_batch", count);
+    rv
+}
+
 pub fn ed25519_verify_disabled(batches: &[SharedPackets]) -> Vec<Vec<u8>> {
     use rayon::prelude::*;
     let count = batch_size(batches);
@@ -256,10 +330,12 @@ mod tests {
 
         // verify packets
         let ans = sigverify::ed25519_verify(&batches);
+        let batch_ans = sigverify::ed25519_verify_cpu_batch(&batches);
 
         // check result
         let ref_ans = if modify_data { 0u8 } else { 1u8 };
         assert_eq!(ans, vec![vec![ref_ans; n], vec![ref_ans; n]]);
+        assert_eq!(batch_ans, ans);
     }
 
     #[test]
@@ -281,4 +357,26 @@ mod tests {
     fn test_verify_fail() {
         test_verify_n(5, true);
     }
+
+    #[test]
+    fn test_verify_cpu_batch_mixed() {
+        let good = make_packet_from_transaction(test_tx());
+        let mut bad = good.clone();
+        bad.data[20] = bad.data[20].wrapping_add(10);
+        let mut short = good.clone();
+        short.meta.size = 10;
+
+        // one bad packet in the first chunk and a short one in the second
+        let n = sigverify::VERIFY_CHUNK_SIZE + 7;
+        let packets = SharedPackets::default();
+        packets.write().unwrap().packets = vec![good; n];
+        packets.write().unwrap().packets[3] = bad;
+        packets.write().unwrap().packets[n - 1] = short;
+
+        let ans = sigverify::ed25519_verify_cpu_batch(&[packets]);
+        let mut ref_ans = vec![1u8; n];
+        ref_ans[3] = 0;
+        ref_ans[n - 1] = 0;
+        assert_eq!(ans, vec![ref_ans]);
+    }
 }
@@ -3,7 +3,8 @@
 //! top-level list with a list of booleans, telling the next stage whether the
 //! signature in that packet is valid. It assumes each packet contains one
 //! transaction. All processing is done on the CPU by default and on a GPU
-//! if the `cuda` feature is enabled with `--features=cuda`.
+//! if the `cuda` feature is enabled with `--features=cuda`. The shared
+//! `SigVerifyMode` switches the stage to CPU batch verification at runtime.
 
 use crate::counter::Counter;
 use influx_db_client as influxdb;
@@ -13,7 +14,7 @@ use crate::packet::SharedPackets;
 use rand::{thread_rng, Rng};
 use crate::result::{Error, Result};
 use crate::service::Service;
-use crate::sigverify;
+use crate::sigverify::{self, SharedSigVerifyMode, SigVerifyMode};
 use std::sync::atomic::AtomicUsize;
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
@@ -32,19 +33,32 @@ impl SigVerifyStage {
     pub fn new(
         packet_receiver: Receiver<SharedPackets>,
         sigverify_disabled: bool,
+        sigverify_mode: SharedSigVerifyMode,
     ) -> (Self, Receiver<VerifiedPackets>) {
         sigverify::init();
         let (verified_sender, verified_receiver) = channel();
-        let thread_hdls =
-            Self::verifier_services(packet_receiver, verified_sender, sigverify_disabled);
+        let thread_hdls = Self::verifier_services(
+            packet_receiver,
+            verified_sender,
+            sigverify_disabled,
+            &sigverify_mode,
+        );
         (SigVerifyStage { thread_hdls }, verified_receiver)
     }
 
-    fn verify_batch(batch: Vec<SharedPackets>, sigverify_disabled: bool) -> VerifiedPackets {
+    fn verify_batch(
+        batch: Vec<SharedPackets>,
+        sigverify_disabled: bool,
+        sigverify_mode: &SharedSigVerifyMode,
+    ) -> VerifiedPackets {
+        let mode = *sigverify_mode.read().unwrap();
         let r = if sigverify_disabled {
             sigverify::ed25519_verify_disabled(&batch)
         } else {
-            sigverify::ed25519_verify(&batch)
+            match mode {
+                SigVerifyMode::PerPacket => sigverify::ed25519_verify(&batch),
+                SigVerifyMode::CpuBatch => sigverify::ed25519_verify_cpu_batch(&batch),
+            }
         };
         batch.into_iter().zip(r).collect()
     }
@@ -53,6 +67,7 @@ impl SigVerifyStage {
         recvr: &Arc<Mutex<PacketReceiver>>,
         sendr: &Arc<Mutex<Sender<VerifiedPackets>>>,
         sigverify_disabled: bool,
+        sigverify_mode: &SharedSigVerifyMode,
     ) -> Result<()> {
         let (batch, len, recv_time) =
             streamer::recv_batch(&recvr.lock().expect("'recvr' lock in fn verifier"))?;
@@ -68,7 +83,7 @@ impl SigVerifyStage {
             rand_id
         );
 
-        let verified_batch = Self::verify_batch(batch, sigverify_disabled);
+        let verified_batch = Self::verify_batch(batch, sigverify_disabled, sigverify_mode);
         inc_new_counter_info!(
             "sigverify_stage-verified_entries_send",
             verified_batch.len()
@@ -116,9 +131,15 @@ impl SigVerifyStage {
         packet_receiver: Arc<Mutex<PacketReceiver>>,
         verified_sender: Arc<Mutex<Sender<VerifiedPackets>>>,
         sigverify_disabled: bool,
+        sigverify_mode: SharedSigVerifyMode,
     ) -> JoinHandle<()> {
         spawn(move || loop {
-            if let Err(e) = Self::verifier(&packet_receiver, &verified_sender, sigverify_disabled) {
+            if let Err(e) = Self::verifier(
+                &packet_receiver,
+                &verified_sender,
+                sigverify_disabled,
+                &sigverify_mode,
+            ) {
                 match e {
                     Error::RecvTimeoutError(RecvTimeoutError::Disconnected) => break,
                     Error::RecvTimeoutError(RecvTimeoutError::Timeout) => (),
@@ -135,12 +156,19 @@ impl SigVerifyStage {
         packet_receiver: PacketReceiver,
         verified_sender: Sender<VerifiedPackets>,
         sigverify_disabled: bool,
+        sigverify_mode: &SharedSigVerifyMode,
     ) -> Vec<JoinHandle<()>> {
         let sender = Arc::new(Mutex::new(verified_sender));
         let receiver = Arc::new(Mutex::new(packet_receiver));
         (0..4)
-            .map(|_| Self::verifier_service(receiver.clone(), sender.clone(), sigverify_disabled))
-            .collect()
+            .map(|_| {
+                Self::verifier_service(
+                    receiver.clone(),
+                    sender.clone(),
+                    sigverify_disabled,
+                    sigverify_mode.clone(),
+                )
+            }).collect()
     }
 }
 
@@ -2,22 +2,206 @@
 //!  Receive mining proofs from miners, validate the answers
 //!  and give reward for good proofs.
 
-use bincode::deserialize;
+use bincode::{self, deserialize, serialize, serialize_into, serialized_size};
 use buffett_program_interface::account::Account;
 use buffett_program_interface::pubkey::Pubkey;
+use crate::chacha::{chacha_cbc_encrypt, sample_offsets, sample_slice, CHACHA_KEY_SIZE};
+use crate::entry::Entry;
+use crate::hash::{hash, hashv, Hash};
+use crate::ledger::LedgerWindow;
+use std::io;
 use crate::transaction::Transaction;
 
+/// Number of entries in a ledger segment, a mining proof covers one segment.
+pub const ENTRIES_PER_SEGMENT: u64 = 16;
+
+/// Number of samples of the encrypted segment hashed into a mining proof.
+pub const NUM_STORAGE_SAMPLES: usize = 4;
+
+/// Tokens paid out of a reward pool for each validated proof.
+pub const STORAGE_REWARD: i64 = 10;
+
+/// Most proofs a storage account holds at once, rewarded proofs are dropped.
+pub const MAX_PROOFS: usize = 8;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum StorageProgram {
-    SubmitMiningProof { sha_state: [u8; 32] },
+    /// Record a replicator's proof that it stores a ledger segment
+    /// * Transaction::keys[0] - the replicator's storage account
+    SubmitMiningProof { sha_state: [u8; 32], segment: u64 },
+
+    /// Record a validator's check of a submitted proof. `sha_state` is the state the
+    /// validator recomputed from its own ledger with `ledger_mining_proof`, the proof is
+    /// valid if it matches the submitted one.
+    /// * Transaction::keys[0] - the validator, one of the reward pool's validators
+    /// * Transaction::keys[1] - the replicator's storage account
+    /// * Transaction::keys[2] - the reward pool
+    ProofValidation { segment: u64, sha_state: [u8; 32] },
+
+    /// Pay `STORAGE_REWARD` for a proof validated by one of the reward pool's validators
+    /// * Transaction::keys[0] - the replicator's storage account
+    /// * Transaction::keys[1] - the reward pool
+    /// * Transaction::keys[2] - the account that receives the reward
+    ClaimStorageReward { segment: u64 },
+
+    /// Turn a new account owned by the storage program into a reward pool that only
+    /// accepts proof validations from `validators`
+    /// * Transaction::keys[0] - the reward pool
+    CreateRewardPool { validators: Vec<Pubkey> },
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum StorageError {
     InvalidUserData,
+    InvalidArguments,
+    UserdataTooSmall,
+    ProofAlreadySubmitted,
+    ProofNotFound,
+    ProofNotValid,
+    InsufficientRewardFunds,
+    UnauthorizedValidator,
+    TooManyProofs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStatus {
+    /// Waiting for a validator
+    Pending,
+    Valid,
+    Invalid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MiningProof {
+    pub segment: u64,
+    pub sha_state: [u8; 32],
+    pub status: ProofStatus,
+    /// The validator that checked the proof
+    pub validator: Option<Pubkey>,
+}
+
+/// The state of a replicator's storage account, the proofs it submitted by segment.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct StorageState {
+    pub proofs: Vec<MiningProof>,
+    /// Proofs for segments below this are no longer accepted, it is raised past every
+    /// segment that was rewarded
+    pub first_open_segment: u64,
+}
+
+impl StorageState {
+    fn proof_mut(&mut self, segment: u64) -> Result<&mut MiningProof, StorageError> {
+        self.proofs
+            .iter_mut()
+            .find(|proof| proof.segment == segment)
+            .ok_or(StorageError::ProofNotFound)
+    }
+}
+
+/// The state of a reward pool, the validators whose proof validations it pays out for.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct RewardPool {
+    pub validators: Vec<Pubkey>,
+}
+
+/// The userdata of an account owned by the storage program. The kind is part of the
+/// encoding so a replicator's proofs can never be read as a pool's validators.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum StorageAccount {
+    Replicator(StorageState),
+    RewardPool(RewardPool),
+}
+
+impl StorageAccount {
+
+    fn serialize(&self, output: &mut [u8]) -> Result<(), StorageError> {
+        let len = serialized_size(self).unwrap() as u64;
+        if output.len() < 8 + len as usize {
+            warn!(
+                "{} bytes required to serialize, only have {} bytes",
+                8 + len,
+                output.len()
+            );
+            return Err(StorageError::UserdataTooSmall);
+        }
+        serialize_into(&mut output[..8], &len).unwrap();
+        serialize_into(&mut output[8..8 + len as usize], self).unwrap();
+        Ok(())
+    }
+
+    /// A new storage account has zeroed userdata, which holds no state yet.
+    pub fn deserialize(input: &[u8]) -> bincode::Result<Option<Self>> {
+        if input.len() < 8 {
+            return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        }
+        let len: u64 = deserialize(&input[..8])?;
+        if len == 0 {
+            return Ok(None);
+        }
+        if input.len() < 8 + len as usize {
+            return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        }
+        deserialize(&input[8..8 + len as usize]).map(Some)
+    }
 }
 
 pub const STORAGE_PROGRAM_ID: [u8; 32] = [1u8; 32];
 
+/// The chacha key a segment is encrypted with, derived from the id of its first entry.
+pub fn storage_key(entry_id: &Hash) -> [u8; CHACHA_KEY_SIZE] {
+    let mut key = [0u8; CHACHA_KEY_SIZE];
+    key.copy_from_slice(hash(entry_id.as_ref()).as_ref());
+    key
+}
+
+/// The seed of the samples of a segment, different for every storage account so that
+/// a replicator can't submit the proof of another one.
+pub fn storage_seed(entry_id: &Hash, storage_account: &Pubkey) -> Hash {
+    hashv(&[entry_id.as_ref(), storage_account.as_ref()])
+}
+
+/// The `sha_state` a replicator storing the entries of a segment submits: the segment
+/// is encrypted with `storage_key` and sampled with `storage_seed`.
+pub fn mining_proof(entries: &[Entry], storage_account: &Pubkey) -> [u8; 32] {
+    let entry_id = entries.first().map(|entry| entry.id).unwrap_or_default();
+    let mut data = vec![];
+    for entry in entries {
+        data.extend_from_slice(&serialize(entry).unwrap());
+    }
+    let mut encrypted = vec![0; data.len()];
+    let mut ivec = [0; 64];
+    chacha_cbc_encrypt(&data, &mut encrypted, &storage_key(&entry_id), &mut ivec);
+
+    let seed = storage_seed(&entry_id, storage_account);
+    let offsets = sample_offsets(&seed, encrypted.len() as u64, NUM_STORAGE_SAMPLES);
+    sample_slice(&encrypted, &offsets)
+}
+
+/// Recompute the `sha_state` of `storage_account`'s proof for `segment` from a validator's
+/// ledger, the state a validator submits in `ProofValidation`.
+pub fn ledger_mining_proof(
+    ledger_path: &str,
+    segment: u64,
+    storage_account: &Pubkey,
+) -> io::Result<[u8; 32]> {
+    let mut ledger = LedgerWindow::open(ledger_path)?;
+    let start = segment * ENTRIES_PER_SEGMENT;
+    let entries = (start..start + ENTRIES_PER_SEGMENT)
+        .map(|index| ledger.get_entry(index))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(mining_proof(&entries, storage_account))
+}
+
+/// Check a proof submitted by `storage_account` against the segment in a validator's ledger.
+pub fn verify_mining_proof(
+    ledger_path: &str,
+    segment: u64,
+    storage_account: &Pubkey,
+    sha_state: &[u8; 32],
+) -> io::Result<bool> {
+    Ok(ledger_mining_proof(ledger_path, segment, storage_account)? == *sha_state)
+}
+
 impl StorageProgram {
     pub fn check_id(program_id: &Pubkey) -> bool {
         program_id.as_ref() == STORAGE_PROGRAM_ID
@@ -31,22 +215,476 @@ impl StorageProgram {
         account.tokens
     }
 
+    fn storage_account(account: &Account) -> Result<Option<StorageAccount>, StorageError> {
+        if !Self::check_id(&account.program_id) {
+            error!("account is not assigned to the STORAGE_PROGRAM_ID");
+            return Err(StorageError::InvalidArguments);
+        }
+        StorageAccount::deserialize(&account.userdata).map_err(|err| {
+            error!("Unable to deserialize storage state: {:?}", err);
+            StorageError::InvalidUserData
+        })
+    }
+
+    fn storage_state(account: &Account) -> Result<StorageState, StorageError> {
+        match Self::storage_account(account)? {
+            None => Ok(StorageState::default()),
+            Some(StorageAccount::Replicator(state)) => Ok(state),
+            Some(StorageAccount::RewardPool(_)) => Err(StorageError::InvalidArguments),
+        }
+    }
+
+    fn reward_pool(account: &Account) -> Result<RewardPool, StorageError> {
+        match Self::storage_account(account)? {
+            Some(StorageAccount::RewardPool(pool)) => Ok(pool),
+            _ => Err(StorageError::InvalidArguments),
+        }
+    }
+
     pub fn process_transaction(
         tx: &Transaction,
-        _accounts: &mut [Account],
+        accounts: &mut [Account],
     ) -> Result<(), StorageError> {
-        if let Ok(syscall) = deserialize(&tx.userdata) {
-            match syscall {
-                StorageProgram::SubmitMiningProof { sha_state } => {
-                    info!("Mining proof submitted with state {}", sha_state[0]);
-                    return Ok(());
+        let syscall = deserialize(&tx.userdata).map_err(|_| StorageError::InvalidUserData)?;
+        match syscall {
+            StorageProgram::SubmitMiningProof { sha_state, segment } => {
+                info!("Mining proof submitted for segment {} with state {}", segment, sha_state[0]);
+                let mut state = Self::storage_state(&accounts[0])?;
+                if segment < state.first_open_segment {
+                    return Err(StorageError::ProofAlreadySubmitted);
                 }
+                // an invalid proof may be replaced, any other is final
+                if let Some(proof) = state.proofs.iter().find(|proof| proof.segment == segment) {
+                    if proof.status != ProofStatus::Invalid {
+                        return Err(StorageError::ProofAlreadySubmitted);
+                    }
+                }
+                state.proofs.retain(|proof| proof.segment != segment);
+                if state.proofs.len() >= MAX_PROOFS {
+                    return Err(StorageError::TooManyProofs);
+                }
+                state.proofs.push(MiningProof {
+                    segment,
+                    sha_state,
+                    status: ProofStatus::Pending,
+                    validator: None,
+                });
+                StorageAccount::Replicator(state).serialize(&mut accounts[0].userdata)
+            }
+            StorageProgram::ProofValidation { segment, sha_state } => {
+                if accounts.len() < 3 || tx.keys[0] == tx.keys[1] {
+                    return Err(StorageError::InvalidArguments);
+                }
+                // only validators the pool trusts to recompute proofs from their ledger
+                if !Self::reward_pool(&accounts[2])?
+                    .validators
+                    .contains(&tx.keys[0])
+                {
+                    return Err(StorageError::UnauthorizedValidator);
+                }
+                let mut state = Self::storage_state(&accounts[1])?;
+                {
+                    let proof = state.proof_mut(segment)?;
+                    if proof.status != ProofStatus::Pending {
+                        return Err(StorageError::InvalidArguments);
+                    }
+                    proof.status = if proof.sha_state == sha_state {
+                        ProofStatus::Valid
+                    } else {
+                        ProofStatus::Invalid
+                    };
+                    proof.validator = Some(tx.keys[0]);
+                }
+                StorageAccount::Replicator(state).serialize(&mut accounts[1].userdata)
+            }
+            StorageProgram::ClaimStorageReward { segment } => {
+                if accounts.len() < 3 {
+                    return Err(StorageError::InvalidArguments);
+                }
+                let pool = Self::reward_pool(&accounts[1])?;
+                let mut state = Self::storage_state(&accounts[0])?;
+                {
+                    let proof = state.proof_mut(segment)?;
+                    if proof.status != ProofStatus::Valid {
+                        return Err(StorageError::ProofNotValid);
+                    }
+                    // a pool only pays for proofs its own validators checked
+                    match proof.validator {
+                        Some(validator) if pool.validators.contains(&validator) => (),
+                        _ => return Err(StorageError::UnauthorizedValidator),
+                    }
+                }
+                if accounts[1].tokens < STORAGE_REWARD {
+                    return Err(StorageError::InsufficientRewardFunds);
+                }
+                // the segment can't be proven again, so the proof is no longer needed
+                state.proofs.retain(|proof| proof.segment != segment);
+                state.first_open_segment = state.first_open_segment.max(segment + 1);
+                StorageAccount::Replicator(state).serialize(&mut accounts[0].userdata)?;
+                accounts[1].tokens -= STORAGE_REWARD;
+                accounts[2].tokens += STORAGE_REWARD;
+                Ok(())
+            }
+            StorageProgram::CreateRewardPool { validators } => {
+                if Self::storage_account(&accounts[0])?.is_some() {
+                    return Err(StorageError::InvalidArguments);
+                }
+                StorageAccount::RewardPool(RewardPool { validators })
+                    .serialize(&mut accounts[0].userdata)
             }
-        } else {
-            return Err(StorageError::InvalidUserData);
         }
     }
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+    use crate::ledger::{tmp_ledger_path, LedgerWriter};
+    use crate::signature::{Keypair, KeypairUtil};
+    use crate::storage_transaction::StorageTransaction;
+    use std::fs::remove_dir_all;
+
+    fn storage_account(tokens: i64) -> Account {
+        Account::new(tokens, 1024, StorageProgram::id())
+    }
+
+    fn reward_pool(tokens: i64, validators: Vec<Pubkey>) -> Account {
+        let pool = Keypair::new();
+        let tx = Transaction::storage_new_reward_pool(&pool, validators, Hash::default());
+        let mut accounts = vec![storage_account(tokens)];
+        StorageProgram::process_transaction(&tx, &mut accounts).unwrap();
+        accounts.pop().unwrap()
+    }
+
+    fn proofs(account: &Account) -> Vec<MiningProof> {
+        match StorageAccount::deserialize(&account.userdata).unwrap() {
+            Some(StorageAccount::Replicator(state)) => state.proofs,
+            state => panic!("not a replicator's account: {:?}", state),
+        }
+    }
+
+    #[test]
+    fn test_storage_state_serde() {
+        assert_eq!(StorageAccount::deserialize(&[0; 64]).unwrap(), None);
+
+        let mut state = StorageState::default();
+        state.proofs.push(MiningProof {
+            segment: 3,
+            sha_state: [7; 32],
+            status: ProofStatus::Pending,
+            validator: None,
+        });
+        let state = StorageAccount::Replicator(state);
+        let mut userdata = vec![0; 128];
+        state.serialize(&mut userdata).unwrap();
+        assert_eq!(StorageAccount::deserialize(&userdata).unwrap(), Some(state));
+        assert_eq!(
+            StorageAccount::Replicator(StorageState::default()).serialize(&mut [0; 8]),
+            Err(StorageError::UserdataTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_submit_validate_claim() {
+        let replicator = Keypair::new();
+        let validator = Keypair::new();
+        let pool = Keypair::new().pubkey();
+        let to = Keypair::new().pubkey();
+        let last_id = Hash::default();
+        let mut accounts = vec![
+            storage_account(1),
+            reward_pool(100, vec![validator.pubkey()]),
+            Account::default(),
+        ];
+
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], 2, last_id);
+        StorageProgram::process_transaction(&tx, &mut accounts[..1]).unwrap();
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts[..1]),
+            Err(StorageError::ProofAlreadySubmitted)
+        );
+
+        // nothing to claim before a validator has checked the proof
+        let claim = Transaction::storage_new_reward_claim(&replicator, pool, to, 2, last_id);
+        assert_eq!(
+            StorageProgram::process_transaction(&claim, &mut accounts),
+            Err(StorageError::ProofNotValid)
+        );
+
+        let tx = Transaction::storage_new_proof_validation(
+            &validator,
+            replicator.pubkey(),
+            pool,
+            2,
+            [1; 32],
+            last_id,
+        );
+        let mut validation_accounts = vec![
+            Account::default(),
+            accounts[0].clone(),
+            accounts[1].clone(),
+        ];
+        StorageProgram::process_transaction(&tx, &mut validation_accounts).unwrap();
+        accounts[0] = validation_accounts[1].clone();
+        let proofs = proofs(&accounts[0]);
+        assert_eq!(proofs[0].status, ProofStatus::Valid);
+        assert_eq!(proofs[0].validator, Some(validator.pubkey()));
+
+        StorageProgram::process_transaction(&claim, &mut accounts).unwrap();
+        assert_eq!(accounts[1].tokens, 100 - STORAGE_REWARD);
+        assert_eq!(accounts[2].tokens, STORAGE_REWARD);
+        assert!(proofs(&accounts[0]).is_empty());
+        assert_eq!(
+            StorageProgram::process_transaction(&claim, &mut accounts),
+            Err(StorageError::ProofNotFound)
+        );
+
+        // and the rewarded segment can't be proven again
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], 2, last_id);
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts[..1]),
+            Err(StorageError::ProofAlreadySubmitted)
+        );
+    }
+
+    #[test]
+    fn test_storage_account_stays_bounded() {
+        let replicator = Keypair::new();
+        let validator = Keypair::new();
+        let pool = Keypair::new().pubkey();
+        let to = Keypair::new().pubkey();
+        let last_id = Hash::default();
+        let mut accounts = vec![
+            storage_account(1),
+            reward_pool(1_000, vec![validator.pubkey()]),
+            Account::default(),
+        ];
+
+        // many more segments than fit in the account at once
+        for segment in 0..4 * MAX_PROOFS as u64 {
+            let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], segment, last_id);
+            StorageProgram::process_transaction(&tx, &mut accounts[..1]).unwrap();
+            let tx = Transaction::storage_new_proof_validation(
+                &validator,
+                replicator.pubkey(),
+                pool,
+                segment,
+                [1; 32],
+                last_id,
+            );
+            let mut validation_accounts = vec![
+                Account::default(),
+                accounts[0].clone(),
+                accounts[1].clone(),
+            ];
+            StorageProgram::process_transaction(&tx, &mut validation_accounts).unwrap();
+            accounts[0] = validation_accounts[1].clone();
+            let claim =
+                Transaction::storage_new_reward_claim(&replicator, pool, to, segment, last_id);
+            StorageProgram::process_transaction(&claim, &mut accounts).unwrap();
+        }
+        assert!(proofs(&accounts[0]).is_empty());
+
+        // proofs that are never validated are capped
+        let first = 4 * MAX_PROOFS as u64;
+        for segment in first..first + MAX_PROOFS as u64 {
+            let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], segment, last_id);
+            StorageProgram::process_transaction(&tx, &mut accounts[..1]).unwrap();
+        }
+        let segment = first + MAX_PROOFS as u64;
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], segment, last_id);
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts[..1]),
+            Err(StorageError::TooManyProofs)
+        );
+
+        // but an invalid proof can be replaced
+        let tx = Transaction::storage_new_proof_validation(
+            &validator,
+            replicator.pubkey(),
+            pool,
+            first,
+            [2; 32],
+            last_id,
+        );
+        let mut validation_accounts = vec![
+            Account::default(),
+            accounts[0].clone(),
+            accounts[1].clone(),
+        ];
+        StorageProgram::process_transaction(&tx, &mut validation_accounts).unwrap();
+        accounts[0] = validation_accounts[1].clone();
+        let tx = Transaction::storage_new_mining_proof(&replicator, [2; 32], first, last_id);
+        StorageProgram::process_transaction(&tx, &mut accounts[..1]).unwrap();
+        let proof = proofs(&accounts[0])
+            .into_iter()
+            .find(|proof| proof.segment == first)
+            .unwrap();
+        assert_eq!(proof.status, ProofStatus::Pending);
+        assert_eq!(proof.sha_state, [2; 32]);
+    }
+
+    #[test]
+    fn test_validation_recomputes_proof() {
+        let replicator = Keypair::new();
+        let validator = Keypair::new();
+        let pool = Keypair::new().pubkey();
+        let mut accounts = vec![
+            Account::default(),
+            storage_account(1),
+            reward_pool(100, vec![validator.pubkey()]),
+        ];
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], 0, Hash::default());
+        StorageProgram::process_transaction(&tx, &mut accounts[1..2]).unwrap();
+
+        // the validator's ledger gives a different state than the one submitted
+        let tx = Transaction::storage_new_proof_validation(
+            &validator,
+            replicator.pubkey(),
+            pool,
+            0,
+            [2; 32],
+            Hash::default(),
+        );
+        StorageProgram::process_transaction(&tx, &mut accounts).unwrap();
+        assert_eq!(proofs(&accounts[1])[0].status, ProofStatus::Invalid);
+    }
+
+    #[test]
+    fn test_unauthorized_validator() {
+        let replicator = Keypair::new();
+        let validator = Keypair::new();
+        let sybil = Keypair::new();
+        let pool = Keypair::new().pubkey();
+        let to = Keypair::new().pubkey();
+        let mut accounts = vec![
+            Account::default(),
+            storage_account(1),
+            reward_pool(100, vec![validator.pubkey()]),
+        ];
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], 0, Hash::default());
+        StorageProgram::process_transaction(&tx, &mut accounts[1..2]).unwrap();
+
+        let tx = Transaction::storage_new_proof_validation(
+            &sybil,
+            replicator.pubkey(),
+            pool,
+            0,
+            [1; 32],
+            Hash::default(),
+        );
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts),
+            Err(StorageError::UnauthorizedValidator)
+        );
+
+        // a replicator's account can't stand in for a pool listing the sybil
+        let mut fake_pool = vec![storage_account(100)];
+        let tx = Transaction::storage_new_mining_proof(&sybil, [1; 32], 0, Hash::default());
+        StorageProgram::process_transaction(&tx, &mut fake_pool).unwrap();
+        let tx = Transaction::storage_new_proof_validation(
+            &sybil,
+            replicator.pubkey(),
+            pool,
+            0,
+            [1; 32],
+            Hash::default(),
+        );
+        let mut sybil_accounts = vec![
+            Account::default(),
+            accounts[1].clone(),
+            fake_pool.pop().unwrap(),
+        ];
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut sybil_accounts),
+            Err(StorageError::InvalidArguments)
+        );
+
+        // a proof validated for one pool can't be claimed from another
+        let tx = Transaction::storage_new_proof_validation(
+            &validator,
+            replicator.pubkey(),
+            pool,
+            0,
+            [1; 32],
+            Hash::default(),
+        );
+        StorageProgram::process_transaction(&tx, &mut accounts).unwrap();
+        let claim = Transaction::storage_new_reward_claim(&replicator, pool, to, 0, Hash::default());
+        let mut claim_accounts = vec![
+            accounts[1].clone(),
+            reward_pool(100, vec![sybil.pubkey()]),
+            Account::default(),
+        ];
+        assert_eq!(
+            StorageProgram::process_transaction(&claim, &mut claim_accounts),
+            Err(StorageError::UnauthorizedValidator)
+        );
+        assert_eq!(claim_accounts[1].tokens, 100);
+
+        // and a pool is only set up once
+        let tx = Transaction::storage_new_reward_pool(&sybil, vec![sybil.pubkey()], Hash::default());
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts[2..]),
+            Err(StorageError::InvalidArguments)
+        );
+    }
+
+    #[test]
+    fn test_validate_own_proof() {
+        let replicator = Keypair::new();
+        let mut accounts = vec![storage_account(1)];
+        let tx = Transaction::storage_new_mining_proof(&replicator, [1; 32], 0, Hash::default());
+        StorageProgram::process_transaction(&tx, &mut accounts).unwrap();
+
+        let tx = Transaction::storage_new_proof_validation(
+            &replicator,
+            replicator.pubkey(),
+            Keypair::new().pubkey(),
+            0,
+            [1; 32],
+            Hash::default(),
+        );
+        let mut accounts = vec![
+            accounts[0].clone(),
+            accounts[0].clone(),
+            reward_pool(100, vec![replicator.pubkey()]),
+        ];
+        assert_eq!(
+            StorageProgram::process_transaction(&tx, &mut accounts),
+            Err(StorageError::InvalidArguments)
+        );
+    }
+
+    #[test]
+    fn test_verify_mining_proof() {
+        let ledger_path = tmp_ledger_path("test_verify_mining_proof");
+        let mut entries = vec![Entry::new(&Hash::default(), 1, vec![])];
+        while entries.len() < 2 * ENTRIES_PER_SEGMENT as usize {
+            let last_id = entries.last().unwrap().id;
+            entries.push(Entry::new(&last_id, 1, vec![]));
+        }
+        {
+            let mut writer = LedgerWriter::open(&ledger_path, true).unwrap();
+            writer.write_entries(entries.clone()).unwrap();
+        }
+
+        let replicator = Keypair::new().pubkey();
+        let segment = &entries[ENTRIES_PER_SEGMENT as usize..];
+        let sha_state = mining_proof(segment, &replicator);
+        assert_eq!(
+            ledger_mining_proof(&ledger_path, 1, &replicator).unwrap(),
+            sha_state
+        );
+        assert!(verify_mining_proof(&ledger_path, 1, &replicator, &sha_state).unwrap());
+        assert!(!verify_mining_proof(&ledger_path, 0, &replicator, &sha_state).unwrap());
+        assert!(verify_mining_proof(&ledger_path, 2, &replicator, &sha_state).is_err());
+
+        // another replicator's proof of the same segment is of no use
+        let other = Keypair::new().pubkey();
+        assert_ne!(mining_proof(segment, &other), sha_state);
+        assert!(!verify_mining_proof(&ledger_path, 1, &other, &sha_state).unwrap());
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! The `storage_transaction` module provides functionality for creating storage transactions.
+
+use bincode::serialize;
+use crate::hash::Hash;
+use crate::signature::Keypair;
+use buffett_program_interface::pubkey::Pubkey;
+use crate::storage_program::StorageProgram;
+use crate::transaction::Transaction;
+
+pub trait StorageTransaction {
+    fn storage_new_mining_proof(
+        from_keypair: &Keypair,
+        sha_state: [u8; 32],
+        segment: u64,
+        last_id: Hash,
+    ) -> Self;
+
+    fn storage_new_proof_validation(
+        from_keypair: &Keypair,
+        storage_account: Pubkey,
+        reward_pool: Pubkey,
+        segment: u64,
+        sha_state: [u8; 32],
+        last_id: Hash,
+    ) -> Self;
+
+    fn storage_new_reward_claim(
+        from_keypair: &Keypair,
+        reward_pool: Pubkey,
+        to: Pubkey,
+        segment: u64,
+        last_id: Hash,
+    ) -> Self;
+
+    fn storage_new_reward_pool(
+        from_keypair: &Keypair,
+        validators: Vec<Pubkey>,
+        last_id: Hash,
+    ) -> Self;
+}
+
+impl StorageTransaction for Transaction {
+    /// Create and sign a new mining proof for the `from_keypair` storage account
+    fn storage_new_mining_proof(
+        from_keypair: &Keypair,
+        sha_state: [u8; 32],
+        segment: u64,
+        last_id: Hash,
+    ) -> Self {
+        let program = StorageProgram::SubmitMiningProof { sha_state, segment };
+        let userdata = serialize(&program).unwrap();
+        Transaction::new(
+            from_keypair,
+            &[],
+            StorageProgram::id(),
+            userdata,
+            last_id,
+            0,
+        )
+    }
+
+    /// Create and sign a validator's check of the proof `storage_account` submitted for
+    /// `segment`, `sha_state` is the state the validator recomputed from its ledger
+    fn storage_new_proof_validation(
+        from_keypair: &Keypair,
+        storage_account: Pubkey,
+        reward_pool: Pubkey,
+        segment: u64,
+        sha_state: [u8; 32],
+        last_id: Hash,
+    ) -> Self {
+        let program = StorageProgram::ProofValidation { segment, sha_state };
+        let userdata = serialize(&program).unwrap();
+        Transaction::new(
+            from_keypair,
+            &[storage_account, reward_pool],
+            StorageProgram::id(),
+            userdata,
+            last_id,
+            0,
+        )
+    }
+
+    /// Create and sign a claim paying the reward for a validated proof from `reward_pool` to `to`
+    fn storage_new_reward_claim(
+        from_keypair: &Keypair,
+        reward_pool: Pubkey,
+        to: Pubkey,
+        segment: u64,
+        last_id: Hash,
+    ) -> Self {
+        let program = StorageProgram::ClaimStorageReward { segment };
+        let userdata = serialize(&program).unwrap();
+        Transaction::new(
+            from_keypair,
+            &[reward_pool, to],
+            StorageProgram::id(),
+            userdata,
+            last_id,
+            0,
+        )
+    }
+
+    /// Create and sign the setup of the `from_keypair` account as a reward pool
+    fn storage_new_reward_pool(
+        from_keypair: &Keypair,
+        validators: Vec<Pubkey>,
+        last_id: Hash,
+    ) -> Self {
+        let program = StorageProgram::CreateRewardPool { validators };
+        let userdata = serialize(&program).unwrap();
+        Transaction::new(
+            from_keypair,
+            &[],
+            StorageProgram::id(),
+            userdata,
+            last_id,
+            0,
+        )
+    }
+}
@@ -71,7 +71,13 @@ fn recv_send(sock: &UdpSocket, r: &BlobReceiver) -> Result<()> {
 }
 
 pub fn recv_batch(recvr: &PacketReceiver) -> Result<(Vec<SharedPackets>, usize, u64)> {
-    let timer = Duration::new(1, 0);
+    recv_batch_timeout(recvr, Duration::new(1, 0))
+}
+
+pub fn recv_batch_timeout(
+    recvr: &PacketReceiver,
+    timer: Duration,
+) -> Result<(Vec<SharedPackets>, usize, u64)> {
     let msgs = recvr.recv_timeout(timer)?;
     let recv_start = Instant::now();
     trace!("got msgs");
@@ -1,11 +1,10 @@
 //! system program
 
 use bincode::deserialize;
-use crate::dynamic_program::DynamicProgram;
+use crate::hash::Hash;
+use crate::program_registry::ProgramRegistry;
 use buffett_program_interface::account::Account;
 use buffett_program_interface::pubkey::Pubkey;
-use std::collections::HashMap;
-use std::sync::RwLock;
 use crate::transaction::Transaction;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,12 +29,24 @@ pub enum SystemProgram {
     Move { tokens: i64 },
     /// Load a program
     /// program_id - id to associate this program
-    /// nanme - file path of the program to load
+    /// name - name of the library of the program in the program directory
     Load { program_id: Pubkey, name: String },
+    /// Collect the fees of the batch this transaction is the first of, the bank
+    /// credits them, see `Bank::process_transactions`
+    /// * Transaction::keys[0] - the leader that produced the batch
+    /// * batch - the hash of the other transactions the leader processed the batch with,
+    ///   which keeps the signatures of its collections distinct
+    CollectFees { batch: Hash },
 }
 
 pub const SYSTEM_PROGRAM_ID: [u8; 32] = [0u8; 32];
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum SystemError {
+    /// The library of a `Load` can't be loaded from the program directory
+    ProgramNotLoaded,
+}
+
 impl SystemProgram {
     pub fn check_id(program_id: &Pubkey) -> bool {
         program_id.as_ref() == SYSTEM_PROGRAM_ID
@@ -47,11 +58,18 @@ impl SystemProgram {
     pub fn get_balance(account: &Account) -> i64 {
         account.tokens
     }
+    /// True if `tx` is a `SystemProgram::CollectFees` transaction.
+    pub fn is_collect_fees(tx: &Transaction) -> bool {
+        Self::check_id(&tx.program_id) && match deserialize(&tx.userdata) {
+            Ok(SystemProgram::CollectFees { .. }) => true,
+            _ => false,
+        }
+    }
     pub fn process_transaction(
         tx: &Transaction,
         accounts: &mut [Account],
-        loaded_programs: &RwLock<HashMap<Pubkey, DynamicProgram>>,
-    ) {
+        programs: &ProgramRegistry,
+    ) -> Result<(), SystemError> {
         if let Ok(syscall) = deserialize(&tx.userdata) {
             trace!("process_transaction: {:?}", syscall);
             match syscall {
@@ -61,13 +79,13 @@ impl SystemProgram {
                     program_id,
                 } => {
                     if !Self::check_id(&accounts[0].program_id) {
-                        return;
+                        return Ok(());
                     }
                     if space > 0
                         && (!accounts[1].userdata.is_empty()
                             || !Self::check_id(&accounts[1].program_id))
                     {
-                        return;
+                        return Ok(());
                     }
                     accounts[0].tokens -= tokens;
                     accounts[1].tokens += tokens;
@@ -76,7 +94,7 @@ impl SystemProgram {
                 }
                 SystemProgram::Assign { program_id } => {
                     if !Self::check_id(&accounts[0].program_id) {
-                        return;
+                        return Ok(());
                     }
                     accounts[0].program_id = program_id;
                 }
@@ -86,13 +104,19 @@ impl SystemProgram {
                     accounts[1].tokens += tokens;
                 }
                 SystemProgram::Load { program_id, name } => {
-                    let mut hashmap = loaded_programs.write().unwrap();
-                    hashmap.insert(program_id, DynamicProgram::new(name));
+                    if let Err(err) = programs.load_library(program_id, &name) {
+                        warn!("unable to load program {}: {:?}", name, err);
+                        return Err(SystemError::ProgramNotLoaded);
+                    }
+                }
+                SystemProgram::CollectFees { .. } => {
+                    // the fees are credited by the bank
                 }
             }
         } else {
             info!("Invalid transaction userdata: {:?}", tx.userdata);
         }
+        Ok(())
     }
 }
 #[cfg(test)]
@@ -102,10 +126,10 @@ mod test {
     use crate::signature::{Keypair, KeypairUtil};
     use buffett_program_interface::account::{Account, KeyedAccount};
     use buffett_program_interface::pubkey::Pubkey;
-    use std::collections::HashMap;
-    use std::sync::RwLock;
+    use crate::dynamic_program::target_program_dir;
+    use crate::program_registry::ProgramRegistry;
     use std::thread;
-    use system_program::SystemProgram;
+    use system_program::{SystemError, SystemProgram};
     use crate::system_transaction::SystemTransaction;
     use crate::transaction::Transaction;
 
@@ -115,8 +139,8 @@ mod test {
         let to = Keypair::new();
         let mut accounts = vec![Account::default(), Account::default()];
         let tx = Transaction::system_new(&from, to.pubkey(), 0, Hash::default());
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[0].tokens, 0);
         assert_eq!(accounts[1].tokens, 0);
     }
@@ -127,8 +151,8 @@ mod test {
         let mut accounts = vec![Account::default(), Account::default()];
         accounts[0].tokens = 1;
         let tx = Transaction::system_new(&from, to.pubkey(), 1, Hash::default());
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[0].tokens, 0);
         assert_eq!(accounts[1].tokens, 1);
     }
@@ -140,8 +164,8 @@ mod test {
         accounts[0].tokens = 1;
         accounts[0].program_id = from.pubkey();
         let tx = Transaction::system_new(&from, to.pubkey(), 1, Hash::default());
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[0].tokens, 1);
         assert_eq!(accounts[1].tokens, 0);
     }
@@ -152,8 +176,8 @@ mod test {
         let mut accounts = vec![Account::default(), Account::default()];
         let tx =
             Transaction::system_create(&from, to.pubkey(), Hash::default(), 0, 1, to.pubkey(), 0);
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert!(accounts[0].userdata.is_empty());
         assert_eq!(accounts[1].userdata.len(), 1);
         assert_eq!(accounts[1].program_id, to.pubkey());
@@ -173,8 +197,8 @@ mod test {
             Pubkey::default(),
             0,
         );
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert!(accounts[1].userdata.is_empty());
     }
     #[test]
@@ -192,8 +216,8 @@ mod test {
             Pubkey::default(),
             0,
         );
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert!(accounts[1].userdata.is_empty());
     }
     #[test]
@@ -211,55 +235,79 @@ mod test {
             Pubkey::default(),
             0,
         );
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[1].userdata.len(), 3);
     }
+    // calls the program `programs` loaded as `program_id` to move 100 tokens
+    fn call_move_funds(programs: &ProgramRegistry, program_id: &Pubkey) {
+        let keys = vec![Pubkey::default(), Pubkey::default()];
+        let mut accounts = vec![Account::default(), Account::default()];
+        accounts[0].tokens = 100;
+        accounts[1].tokens = 1;
+        let tokens: i64 = 100;
+        let data: Vec<u8> = serialize(&tokens).unwrap();
+        {
+            let mut infos: Vec<_> = (&keys)
+                .into_iter()
+                .zip(&mut accounts)
+                .map(|(key, account)| KeyedAccount { key, account })
+                .collect();
+            let dp = programs.loaded(program_id).expect("failed to find program");
+            dp.call(&mut infos, &data).unwrap();
+        }
+        assert_eq!(0, accounts[0].tokens);
+        assert_eq!(101, accounts[1].tokens);
+    }
     #[test]
     fn test_load_call() {
         // first load the program
-        let loaded_programs = RwLock::new(HashMap::new());
-        {
-            let from = Keypair::new();
-            let mut accounts = vec![Account::default(), Account::default()];
-            let program_id = Pubkey::default(); // same program id for both
-            let tx = Transaction::system_load(
-                &from,
-                Hash::default(),
-                0,
-                program_id,
-                "move_funds".to_string(),
-            );
+        let programs = ProgramRegistry::default();
+        programs.set_program_dir(target_program_dir());
+        let from = Keypair::new();
+        let mut accounts = vec![Account::default(), Account::default()];
+        let program_id = Pubkey::default();
+        let tx = Transaction::system_load(
+            &from,
+            Hash::default(),
+            0,
+            program_id,
+            "move_funds".to_string(),
+        );
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
 
-            SystemProgram::process_transaction(&tx, &mut accounts, &loaded_programs);
-        }
         // then call the program
-        {
-            let program_id = Pubkey::default(); // same program id for both
-            let keys = vec![Pubkey::default(), Pubkey::default()];
-            let mut accounts = vec![Account::default(), Account::default()];
-            accounts[0].tokens = 100;
-            accounts[1].tokens = 1;
-            let tokens: i64 = 100;
-            let data: Vec<u8> = serialize(&tokens).unwrap();
-            {
-                let hash = loaded_programs.write().unwrap();
-                match hash.get(&program_id) {
-                    Some(dp) => {
-                        let mut infos: Vec<_> = (&keys)
-                            .into_iter()
-                            .zip(&mut accounts)
-                            .map(|(key, account)| KeyedAccount { key, account })
-                            .collect();
+        call_move_funds(&programs, &program_id);
+    }
+    #[test]
+    fn test_load_missing() {
+        let programs = ProgramRegistry::default();
+        let from = Keypair::new();
+        let mut accounts = vec![Account::default(), Account::default()];
+        let program_id = Pubkey::default();
 
-                        dp.call(&mut infos, &data);
-                    }
-                    None => panic!("failed to find program in hash"),
-                }
-            }
-            assert_eq!(0, accounts[0].tokens);
-            assert_eq!(101, accounts[1].tokens);
-        }
+        // without a program directory nothing is loaded
+        let tx = Transaction::system_load(
+            &from,
+            Hash::default(),
+            0,
+            program_id,
+            "move_funds".to_string(),
+        );
+        assert_eq!(
+            SystemProgram::process_transaction(&tx, &mut accounts, &programs),
+            Err(SystemError::ProgramNotLoaded)
+        );
+
+        // nor a library that isn't in it
+        programs.set_program_dir(target_program_dir());
+        let tx =
+            Transaction::system_load(&from, Hash::default(), 0, program_id, "nope".to_string());
+        assert_eq!(
+            SystemProgram::process_transaction(&tx, &mut accounts, &programs),
+            Err(SystemError::ProgramNotLoaded)
+        );
+        assert!(programs.loaded(&program_id).is_none());
     }
     #[test]
     fn test_load_call_many_threads() {
@@ -268,51 +316,24 @@ mod test {
         let mut threads = Vec::new();
         for _t in 0..num_threads {
             threads.push(thread::spawn(move || {
-                let _tid = thread::current().id();
                 for _i in 0..num_iters {
                     // first load the program
-                    let loaded_programs = RwLock::new(HashMap::new());
-                    {
-                        let from = Keypair::new();
-                        let mut accounts = vec![Account::default(), Account::default()];
-                        let program_id = Pubkey::default(); // same program id for both
-                        let tx = Transaction::system_load(
-                            &from,
-                            Hash::default(),
-                            0,
-                            program_id,
-                            "move_funds".to_string(),
-                        );
+                    let programs = ProgramRegistry::default();
+                    programs.set_program_dir(target_program_dir());
+                    let from = Keypair::new();
+                    let mut accounts = vec![Account::default(), Account::default()];
+                    let program_id = Pubkey::default();
+                    let tx = Transaction::system_load(
+                        &from,
+                        Hash::default(),
+                        0,
+                        program_id,
+                        "move_funds".to_string(),
+                    );
+                    SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
 
-                        SystemProgram::process_transaction(&tx, &mut accounts, &loaded_programs);
-                    }
                     // then call the program
-                    {
-                        let program_id = Pubkey::default(); // same program id for both
-                        let keys = vec![Pubkey::default(), Pubkey::default()];
-                        let mut accounts = vec![Account::default(), Account::default()];
-                        accounts[0].tokens = 100;
-                        accounts[1].tokens = 1;
-                        let tokens: i64 = 100;
-                        let data: Vec<u8> = serialize(&tokens).unwrap();
-                        {
-                            let hash = loaded_programs.write().unwrap();
-                            match hash.get(&program_id) {
-                                Some(dp) => {
-                                    let mut infos: Vec<_> = (&keys)
-                                        .into_iter()
-                                        .zip(&mut accounts)
-                                        .map(|(key, account)| KeyedAccount { key, account })
-                                        .collect();
-
-                                    dp.call(&mut infos, &data);
-                                }
-                                None => panic!("failed to find program in hash"),
-                            }
-                        }
-                        assert_eq!(0, accounts[0].tokens);
-                        assert_eq!(101, accounts[1].tokens);
-                    }
+                    call_move_funds(&programs, &program_id);
                 }
             }));
         }
@@ -327,8 +348,8 @@ mod test {
         let program = Keypair::new();
         let mut accounts = vec![Account::default()];
         let tx = Transaction::system_assign(&from, Hash::default(), program.pubkey(), 0);
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[0].program_id, program.pubkey());
     }
     #[test]
@@ -338,8 +359,8 @@ mod test {
         let mut accounts = vec![Account::default(), Account::default()];
         accounts[0].tokens = 1;
         let tx = Transaction::system_new(&from, to.pubkey(), 1, Hash::default());
-        let hash = RwLock::new(HashMap::new());
-        SystemProgram::process_transaction(&tx, &mut accounts, &hash);
+        let programs = ProgramRegistry::default();
+        SystemProgram::process_transaction(&tx, &mut accounts, &programs).unwrap();
         assert_eq!(accounts[0].tokens, 0);
         assert_eq!(accounts[1].tokens, 1);
     }
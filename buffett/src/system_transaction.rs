@@ -37,6 +37,8 @@ pub trait SystemTransaction {
         program_id: Pubkey,
         name: String,
     ) -> Self;
+
+    fn system_collect_fees(leader_keypair: &Keypair, batch: Hash, last_id: Hash) -> Self;
 }
 
 impl SystemTransaction for Transaction {
@@ -116,6 +118,18 @@ impl SystemTransaction for Transaction {
             fee,
         )
     }
+    /// Create and sign new SystemProgram::CollectFees transaction
+    fn system_collect_fees(leader_keypair: &Keypair, batch: Hash, last_id: Hash) -> Self {
+        let collect = SystemProgram::CollectFees { batch };
+        Transaction::new(
+            leader_keypair,
+            &[],
+            SystemProgram::id(),
+            serialize(&collect).unwrap(),
+            last_id,
+            0,
+        )
+    }
 }
 
 pub fn test_tx() -> Transaction {
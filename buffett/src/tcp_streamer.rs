@@ -0,0 +1,463 @@
+//! The `tcp_streamer` module carries length-prefixed frames over TCP connections, the
+//! optional stream transport of the Rpu and the Tpu. A frame is a little-endian `u32`
+//! length followed by that many bytes of bincode. Transactions are sent as-is, a
+//! `Request` is sent with an id as `(u64, Request)` and its responses carry that id
+//! back as `(u64, Response)`.
+
+use bincode::{deserialize, serialize};
+use byteorder::{ByteOrder, LittleEndian};
+use crate::counter::Counter;
+use log::Level;
+use crate::packet::{Packet, Packets, BLOB_SIZE, PACKET_DATA_SIZE};
+use crate::request::{Request, Response};
+use crate::request_processor::RequestProcessor;
+use crate::streamer::PacketSender;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{sleep, Builder, JoinHandle};
+use std::time::Duration;
+
+/// The largest frame either side accepts
+pub const MAX_FRAME_SIZE: usize = BLOB_SIZE;
+
+// how often the listeners check for new connections and the exit signal
+const ACCEPT_POLL_MS: u64 = 100;
+
+/// The most connections a listener serves at once, the ones past it are refused
+pub const MAX_CONNECTIONS: usize = 1024;
+
+/// The most connections a listener serves at once from the same ip
+pub const MAX_CONNECTIONS_PER_IP: usize = 16;
+
+// how long a write waits for a client that doesn't read, its connection is dropped after
+const WRITE_TIMEOUT_MS: u64 = 1000;
+
+/// Whether `e` is a read that timed out, which leaves the stream at a frame boundary
+pub fn is_timeout(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => true,
+        _ => false,
+    }
+}
+
+pub fn write_frame_data<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame too large",
+        ));
+    }
+    // one write per frame, so frames aren't split across segments needlessly
+    let mut frame = vec![0u8; 4];
+    LittleEndian::write_u32(&mut frame, data.len() as u32);
+    frame.extend_from_slice(data);
+    writer.write_all(&frame)
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, msg: &T) -> io::Result<()> {
+    let data = serialize(msg).expect("serialize frame in pub fn write_frame");
+    write_frame_data(writer, &data)
+}
+
+// A timeout in the middle of a frame leaves the rest of it in the stream, so it is
+// reported as an error the stream can't be read past instead of as a timeout.
+fn read_rest<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    reader.read_exact(buf).map_err(|e| {
+        if is_timeout(&e) {
+            io::Error::new(io::ErrorKind::Other, "timed out in the middle of a frame")
+        } else {
+            e
+        }
+    })
+}
+
+pub fn read_frame_data<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len_buf = [0u8; 4];
+    let read = reader.read(&mut len_buf)?;
+    if read == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed",
+        ));
+    }
+    read_rest(reader, &mut len_buf[read..])?;
+    let len = LittleEndian::read_u32(&len_buf) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    let mut data = vec![0u8; len];
+    read_rest(reader, &mut data)?;
+    Ok(data)
+}
+
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<T> {
+    let data = read_frame_data(reader)?;
+    deserialize(&data).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "deserialize"))
+}
+
+/// The request connections of the Rpu by the address of the client, responses are
+/// written through them so the updates of account subscriptions can be pushed to
+/// tcp clients as well.
+#[derive(Default)]
+pub struct Connections {
+    streams: RwLock<HashMap<SocketAddr, Arc<Mutex<TcpStream>>>>,
+}
+
+impl Connections {
+    fn insert(&self, addr: SocketAddr, stream: TcpStream) {
+        self.streams
+            .write()
+            .unwrap()
+            .insert(addr, Arc::new(Mutex::new(stream)));
+    }
+
+    fn remove(&self, addr: &SocketAddr) {
+        self.streams.write().unwrap().remove(addr);
+    }
+
+    pub fn contains(&self, addr: &SocketAddr) -> bool {
+        self.streams.read().unwrap().contains_key(addr)
+    }
+
+    /// Send the `response` with `id` to the client connected from `addr`. A failed write
+    /// may leave part of a frame in the stream, so the connection is dropped.
+    pub fn send(&self, addr: &SocketAddr, id: u64, response: &Response) -> io::Result<()> {
+        let stream = self.streams.read().unwrap().get(addr).cloned();
+        let stream = match stream {
+            Some(stream) => stream,
+            None => return Err(io::Error::new(io::ErrorKind::NotConnected, "not connected")),
+        };
+        let mut stream = stream.lock().unwrap();
+        let result = write_frame(&mut *stream, &(id, response));
+        if result.is_err() {
+            self.remove(addr);
+            // the thread reading from the connection sees it closed and cleans up
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        result
+    }
+}
+
+fn prepare_stream(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    // wake up every second to check for the exit signal
+    stream.set_read_timeout(Some(Duration::new(1, 0)))?;
+    stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS)))?;
+    stream.set_nodelay(true)
+}
+
+// The number of connections a listener serves, in total and by the ip of the client
+#[derive(Default)]
+struct ConnectionCounts {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+// Held by the thread serving a connection, the connection counts against the limits
+// until it is dropped
+struct ConnectionSlot {
+    counts: Arc<Mutex<ConnectionCounts>>,
+    ip: IpAddr,
+}
+
+impl ConnectionSlot {
+    fn acquire(counts: &Arc<Mutex<ConnectionCounts>>, ip: IpAddr) -> Option<Self> {
+        let mut guard = counts.lock().unwrap();
+        let from_ip = guard.by_ip.get(&ip).cloned().unwrap_or(0);
+        if guard.total >= MAX_CONNECTIONS || from_ip >= MAX_CONNECTIONS_PER_IP {
+            return None;
+        }
+        guard.total += 1;
+        guard.by_ip.insert(ip, from_ip + 1);
+        Some(ConnectionSlot {
+            counts: counts.clone(),
+            ip,
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut guard = self.counts.lock().unwrap();
+        guard.total -= 1;
+        let from_ip = guard.by_ip[&self.ip] - 1;
+        if from_ip == 0 {
+            guard.by_ip.remove(&self.ip);
+        } else {
+            guard.by_ip.insert(self.ip, from_ip);
+        }
+    }
+}
+
+// Accept connections on `listener` until `exit` is set and serve each of them on its
+// own thread. The connection threads see the exit signal within a second. Connections
+// past `MAX_CONNECTIONS`, or `MAX_CONNECTIONS_PER_IP` from one ip, are closed at once.
+fn listen<F>(
+    listener: TcpListener,
+    exit: Arc<AtomicBool>,
+    name: &'static str,
+    serve: F,
+) -> JoinHandle<()>
+where
+    F: Fn(TcpStream, SocketAddr, &AtomicBool) + Send + Sync + 'static,
+{
+    listener
+        .set_nonblocking(true)
+        .expect("tcp_streamer set_nonblocking");
+    let serve = Arc::new(serve);
+    let counts = Arc::new(Mutex::new(ConnectionCounts::default()));
+    Builder::new()
+        .name(format!("bitconch-tcp-listener-{}", name))
+        .spawn(move || {
+            while !exit.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        debug!("{} tcp connection from {}", name, addr);
+                        let slot = match ConnectionSlot::acquire(&counts, addr.ip()) {
+                            Some(slot) => slot,
+                            None => {
+                                debug!("{} refusing tcp connection from {}", name, addr);
+                                inc_new_counter_info!("tcp_streamer-refused_connections", 1);
+                                continue;
+                            }
+                        };
+                        if let Err(e) = prepare_stream(&stream) {
+                            warn!("{} tcp connection from {}: {:?}", name, addr, e);
+                            continue;
+                        }
+                        let serve = serve.clone();
+                        let exit = exit.clone();
+                        Builder::new()
+                            .name(format!("bitconch-tcp-{}", name))
+                            .spawn(move || {
+                                let _slot = slot;
+                                serve(stream, addr, &exit)
+                            }).unwrap();
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        sleep(Duration::from_millis(ACCEPT_POLL_MS));
+                    }
+                    Err(e) => {
+                        warn!("{} tcp accept error: {:?}", name, e);
+                        sleep(Duration::from_millis(ACCEPT_POLL_MS));
+                    }
+                }
+            }
+        }).unwrap()
+}
+
+/// Receive the frames of tcp clients as packets, like `streamer::receiver` does with
+/// datagrams. Frames that don't fit in a packet are dropped.
+pub fn receiver(
+    listener: TcpListener,
+    exit: Arc<AtomicBool>,
+    packet_sender: PacketSender,
+    name: &'static str,
+) -> JoinHandle<()> {
+    let packet_sender = Mutex::new(packet_sender);
+    listen(listener, exit, name, move |mut stream, addr, exit| {
+        let packet_sender = packet_sender.lock().unwrap().clone();
+        while !exit.load(Ordering::Relaxed) {
+            match read_frame_data(&mut stream) {
+                Ok(data) => {
+                    if data.len() > PACKET_DATA_SIZE {
+                        warn!(
+                            "{} dropping a {} byte frame from {}",
+                            name,
+                            data.len(),
+                            addr
+                        );
+                        continue;
+                    }
+                    let mut packet = Packet::default();
+                    packet.data[..data.len()].copy_from_slice(&data);
+                    packet.meta.size = data.len();
+                    packet.meta.set_addr(&addr);
+                    let msgs = Packets {
+                        packets: vec![packet],
+                    };
+                    if packet_sender.send(Arc::new(RwLock::new(msgs))).is_err() {
+                        break;
+                    }
+                }
+                Err(ref e) if is_timeout(e) => (),
+                Err(e) => {
+                    debug!("{} tcp connection from {} closed: {:?}", name, addr, e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Answer the requests of tcp clients with the responses to them, tagged with the id
+/// of the request. The clients stay in `connections` while they are connected, and
+/// their account subscriptions end when they disconnect.
+pub fn request_server(
+    listener: TcpListener,
+    exit: Arc<AtomicBool>,
+    request_processor: Arc<RequestProcessor>,
+    connections: Arc<Connections>,
+) -> JoinHandle<()> {
+    listen(listener, exit, "rpu", move |stream, addr, exit| {
+        let mut reader = match stream.try_clone() {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("rpu tcp connection from {}: {:?}", addr, e);
+                return;
+            }
+        };
+        connections.insert(addr, stream);
+        while !exit.load(Ordering::Relaxed) {
+            match read_frame::<_, (u64, Request)>(&mut reader) {
+                Ok((id, request)) => {
+                    let rsps = request_processor.process_stream_requests(vec![(request, addr)]);
+                    if let Err(e) = rsps
+                        .iter()
+                        .map(|(rsp, _)| connections.send(&addr, id, rsp))
+                        .collect::<io::Result<()>>()
+                    {
+                        debug!("rpu tcp connection from {} closed: {:?}", addr, e);
+                        break;
+                    }
+                }
+                Err(ref e) if is_timeout(e) => (),
+                Err(e) => {
+                    debug!("rpu tcp connection from {} closed: {:?}", addr, e);
+                    break;
+                }
+            }
+        }
+        connections.remove(&addr);
+        request_processor.unsubscribe_all(&addr);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::Hash;
+    use std::io::Cursor;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buf = vec![];
+        write_frame(&mut buf, &(7u64, "hello".to_string())).unwrap();
+        write_frame_data(&mut buf, &[1, 2, 3]).unwrap();
+        assert!(write_frame_data(&mut buf, &vec![0; MAX_FRAME_SIZE + 1]).is_err());
+
+        let mut reader = Cursor::new(buf);
+        let msg: (u64, String) = read_frame(&mut reader).unwrap();
+        assert_eq!(msg, (7, "hello".to_string()));
+        assert_eq!(read_frame_data(&mut reader).unwrap(), vec![1, 2, 3]);
+        assert_eq!(
+            read_frame_data(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let mut buf = vec![];
+        write_frame_data(&mut buf, &[1, 2, 3]).unwrap();
+        buf.pop();
+        assert!(read_frame_data(&mut Cursor::new(buf)).is_err());
+
+        let mut buf = vec![0u8; 4];
+        LittleEndian::write_u32(&mut buf, MAX_FRAME_SIZE as u32 + 1);
+        assert_eq!(
+            read_frame_data(&mut Cursor::new(buf)).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_receiver() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exit = Arc::new(AtomicBool::new(false));
+        let (packet_sender, packet_receiver) = channel();
+        let t_receiver = receiver(listener, exit.clone(), packet_sender, "test");
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write_frame_data(&mut stream, &[1, 2, 3]).unwrap();
+        // dropped, too large for a packet
+        write_frame_data(&mut stream, &[0; PACKET_DATA_SIZE + 1]).unwrap();
+        write_frame_data(&mut stream, &[4]).unwrap();
+
+        let timeout = Duration::new(5, 0);
+        let msgs = packet_receiver.recv_timeout(timeout).unwrap();
+        let msgs = msgs.read().unwrap();
+        assert_eq!(msgs.packets.len(), 1);
+        assert_eq!(msgs.packets[0].meta.size, 3);
+        assert_eq!(&msgs.packets[0].data[..3], &[1, 2, 3]);
+        assert_eq!(msgs.packets[0].meta.addr(), stream.local_addr().unwrap());
+        let msgs = packet_receiver.recv_timeout(timeout).unwrap();
+        assert_eq!(msgs.read().unwrap().packets[0].meta.size, 1);
+
+        exit.store(true, Ordering::Relaxed);
+        t_receiver.join().unwrap();
+    }
+
+    #[test]
+    fn test_connections_per_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exit = Arc::new(AtomicBool::new(false));
+        let (packet_sender, packet_receiver) = channel();
+        let t_receiver = receiver(listener, exit.clone(), packet_sender, "test");
+
+        let timeout = Duration::new(5, 0);
+        let streams: Vec<_> = (0..MAX_CONNECTIONS_PER_IP)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect();
+        // the connections are accepted in order, the one past the limit is closed
+        let mut refused = TcpStream::connect(addr).unwrap();
+        refused.set_read_timeout(Some(timeout)).unwrap();
+        assert_eq!(refused.read(&mut [0u8; 1]).unwrap(), 0);
+
+        let mut stream = &streams[MAX_CONNECTIONS_PER_IP - 1];
+        write_frame_data(&mut stream, &[1]).unwrap();
+        let msgs = packet_receiver.recv_timeout(timeout).unwrap();
+        assert_eq!(msgs.read().unwrap().packets[0].meta.size, 1);
+
+        exit.store(true, Ordering::Relaxed);
+        t_receiver.join().unwrap();
+    }
+
+    #[test]
+    fn test_send_drops_failed_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+        prepare_stream(&stream).unwrap();
+        let connections = Connections::default();
+        connections.insert(addr, stream);
+
+        let response = Response::LastId {
+            id: Hash::default(),
+        };
+        connections.send(&addr, 7, &response).unwrap();
+        let (id, _): (u64, Response) = read_frame(&mut &client).unwrap();
+        assert_eq!(id, 7);
+
+        // the writes fail once the client is gone
+        drop(client);
+        let mut sent = 0;
+        while connections.send(&addr, 8, &response).is_ok() {
+            sent += 1;
+            assert!(sent < 100, "writes to a closed connection succeeded");
+            sleep(Duration::from_millis(10));
+        }
+        assert!(!connections.contains(&addr));
+    }
+}
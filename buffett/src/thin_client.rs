@@ -2,40 +2,82 @@
 //! a server-side TPU.  Client code should use this object instead of writing
 //! messages to the network directly. The binary encoding of its messages are
 //! unstable and may change in future releases.
+//!
+//! Requests and transactions go over UDP by default. With `Transport::Tcp` they are
+//! framed over TCP connections instead, and every response is matched with the
+//! request it answers by its id, so a lost or late answer can't be taken for another.
 
-use crate::tx_vault::Bank;
+use crate::tx_vault::{self, Bank};
 use bincode::{deserialize, serialize};
 use crate::crdt::{Crdt, CrdtError, NodeInfo};
 use crate::hash::Hash;
 use log::Level;
 use crate::ncp::Ncp;
-use crate::request::{Request, Response};
+use crate::packet::BLOB_SIZE;
+use crate::request::{
+    Request, Response, MAX_ACCOUNTS_PER_REQUEST, MAX_SIGNATURES_PER_REQUEST, SUBSCRIPTION_UPDATE_ID,
+};
 use crate::result::{Error, Result};
 use crate::signature::{Keypair, Signature};
 use buffett_program_interface::account::Account;
 use buffett_program_interface::pubkey::Pubkey;
 use std;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
 use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::sleep;
 use std::time::Duration;
 use std::time::Instant;
 use crate::system_transaction::SystemTransaction;
+use crate::tcp_streamer::{is_timeout, read_frame, write_frame, write_frame_data};
 use crate::timing;
 use crate::transaction::Transaction;
 
 use influx_db_client as influxdb;
 use crate::metrics;
 
+// how long a tcp connection to the leader may take
+const CONNECT_TIMEOUT_MS: u64 = 5000;
+
+// the pause before a request that couldn't be sent is retried, a leader that is
+// restarting refuses connections at once
+const SEND_RETRY_MS: u64 = 100;
+
+/// How a `ThinClient` reaches the Rpu and the Tpu.
+pub enum Transport {
+    /// A datagram per request, response and transaction. Responses are received on
+    /// `requests_socket`, which has to be bound to a public address.
+    Udp {
+        requests_socket: UdpSocket,
+        transactions_socket: UdpSocket,
+    },
+    /// Length-prefixed frames over a tcp connection to each of the Rpu and the Tpu,
+    /// made on first use and made again after they break. `read_timeout` bounds the
+    /// wait for a response like the read timeout of the udp `requests_socket` does.
+    Tcp { read_timeout: Option<Duration> },
+}
+
+fn connect(addr: &SocketAddr, read_timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(addr, Duration::from_millis(CONNECT_TIMEOUT_MS))?;
+    stream.set_read_timeout(read_timeout)?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
 /// An object for querying and sending transactions to the network.
 pub struct ThinClient {
     requests_addr: SocketAddr,
-    requests_socket: UdpSocket,
     transactions_addr: SocketAddr,
-    transactions_socket: UdpSocket,
+    transport: Transport,
+    // the connections of `Transport::Tcp`
+    requests_stream: Option<TcpStream>,
+    transactions_stream: Mutex<Option<TcpStream>>,
+    // the id of the last request sent over `requests_stream`
+    request_id: u64,
+    // account updates read from `requests_stream` while waiting for a response
+    account_updates: VecDeque<Response>,
     last_id: Option<Hash>,
     transaction_count: u64,
     balances: HashMap<Pubkey, Account>,
@@ -44,20 +86,21 @@ pub struct ThinClient {
 }
 
 impl ThinClient {
-    /// Create a new ThinClient that will interface with Rpu
-    /// over `requests_socket` and `transactions_socket`. To receive responses, the caller must bind `socket`
-    /// to a public address before invoking ThinClient methods.
+    /// Create a new ThinClient that will interface with the Rpu at `requests_addr`
+    /// and the Tpu at `transactions_addr` over `transport`.
     pub fn new(
         requests_addr: SocketAddr,
-        requests_socket: UdpSocket,
         transactions_addr: SocketAddr,
-        transactions_socket: UdpSocket,
+        transport: Transport,
     ) -> Self {
         ThinClient {
             requests_addr,
-            requests_socket,
             transactions_addr,
-            transactions_socket,
+            transport,
+            requests_stream: None,
+            transactions_stream: Mutex::new(None),
+            request_id: SUBSCRIPTION_UPDATE_ID,
+            account_updates: VecDeque::new(),
             last_id: None,
             transaction_count: 0,
             balances: HashMap::new(),
@@ -66,10 +109,97 @@ impl ThinClient {
         }
     }
 
-    pub fn recv_response(&self) -> io::Result<Response> {
-        let mut buf = vec![0u8; 1024];
+    fn send_request(&mut self, req: &Request) -> io::Result<()> {
+        match self.transport {
+            Transport::Udp {
+                ref requests_socket,
+                ..
+            } => {
+                let data = serialize(req).expect("serialize Request in fn send_request");
+                requests_socket.send_to(&data, &self.requests_addr)?;
+                Ok(())
+            }
+            Transport::Tcp { read_timeout } => {
+                if self.requests_stream.is_none() {
+                    self.requests_stream = Some(connect(&self.requests_addr, read_timeout)?);
+                }
+                self.request_id += 1;
+                let result = write_frame(
+                    self.requests_stream.as_mut().unwrap(),
+                    &(self.request_id, req),
+                );
+                if result.is_err() {
+                    self.requests_stream = None;
+                }
+                result
+            }
+        }
+    }
+
+    fn send_transaction(&self, data: &[u8]) -> io::Result<()> {
+        match self.transport {
+            Transport::Udp {
+                ref transactions_socket,
+                ..
+            } => {
+                transactions_socket.send_to(data, &self.transactions_addr)?;
+                Ok(())
+            }
+            Transport::Tcp { read_timeout } => {
+                let mut stream = self.transactions_stream.lock().unwrap();
+                if stream.is_none() {
+                    *stream = Some(connect(&self.transactions_addr, read_timeout)?);
+                }
+                let result = write_frame_data(stream.as_mut().unwrap(), data);
+                if result.is_err() {
+                    *stream = None;
+                }
+                result
+            }
+        }
+    }
+
+    // Read the next frame of `requests_stream`. Only a timeout keeps the connection,
+    // it is made again for the next request after any other error.
+    fn recv_frame(&mut self) -> io::Result<(u64, Response)> {
+        let result = match self.requests_stream {
+            Some(ref mut stream) => read_frame(stream),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "not connected")),
+        };
+        if let Err(ref e) = result {
+            if !is_timeout(e) {
+                self.requests_stream = None;
+            }
+        }
+        result
+    }
+
+    /// Receive a response. Over tcp it is the response to the last request sent, the
+    /// answers to earlier requests are skipped and account updates are kept for
+    /// `recv_account_update`.
+    pub fn recv_response(&mut self) -> io::Result<Response> {
+        match self.transport {
+            Transport::Udp {
+                ref requests_socket,
+                ..
+            } => Self::recv_datagram(requests_socket),
+            Transport::Tcp { .. } => loop {
+                let (id, resp) = self.recv_frame()?;
+                if id == self.request_id {
+                    return Ok(resp);
+                } else if id == SUBSCRIPTION_UPDATE_ID {
+                    self.account_updates.push_back(resp);
+                } else {
+                    trace!("skipping the response to request {}", id);
+                }
+            },
+        }
+    }
+
+    fn recv_datagram(requests_socket: &UdpSocket) -> io::Result<Response> {
+        let mut buf = vec![0u8; BLOB_SIZE];
         trace!("start recv_from");
-        match self.requests_socket.recv_from(&mut buf) {
+        match requests_socket.recv_from(&mut buf) {
             Ok((len, from)) => {
                 trace!("end recv_from got {} {}", len, from);
                 deserialize(&buf)
@@ -115,6 +245,24 @@ impl ThinClient {
                 trace!("Response finality {:?}", time);
                 self.finality = Some(time);
             }
+            Response::SignatureResult {
+                signature,
+                ref result,
+            } => {
+                trace!("Response signature result {:?} {:?}", signature, result);
+            }
+            Response::Accounts { ref accounts } => {
+                trace!("Response accounts {:?}", accounts);
+                for (key, account) in accounts {
+                    match account {
+                        Some(account) => self.balances.insert(*key, account.clone()),
+                        None => self.balances.remove(key),
+                    };
+                }
+            }
+            Response::SignatureResults { ref results } => {
+                trace!("Response signature results {:?}", results);
+            }
         }
     }
 
@@ -122,8 +270,7 @@ impl ThinClient {
     /// does not wait for a response.
     pub fn transfer_signed(&self, tx: &Transaction) -> io::Result<Signature> {
         let data = serialize(&tx).expect("serialize Transaction in pub fn transfer_signed");
-        self.transactions_socket
-            .send_to(&data, &self.transactions_addr)?;
+        self.send_transaction(&data)?;
         Ok(tx.signature)
     }
 
@@ -135,8 +282,7 @@ impl ThinClient {
     ) -> io::Result<Signature> {
         let data = serialize(&tx).expect("serialize Transaction in pub fn transfer_signed");
         for x in 0..tries {
-            self.transactions_socket
-                .send_to(&data, &self.transactions_addr)?;
+            self.send_transaction(&data)?;
             if self.poll_for_signature(&tx.signature).is_ok() {
                 return Ok(tx.signature);
             }
@@ -176,10 +322,7 @@ impl ThinClient {
     pub fn get_balance(&mut self, pubkey: &Pubkey) -> io::Result<i64> {
         trace!("get_balance sending request to {}", self.requests_addr);
         let req = Request::GetAccount { key: *pubkey };
-        let data = serialize(&req).expect("serialize GetAccount in pub fn get_balance");
-        self.requests_socket
-            .send_to(&data, &self.requests_addr)
-            .expect("buffer error in pub fn get_balance");
+        self.send_request(&req)?;
         let mut done = false;
         while !done {
             let resp = self.recv_response()?;
@@ -202,13 +345,14 @@ impl ThinClient {
     pub fn get_finality(&mut self) -> usize {
         trace!("get_finality");
         let req = Request::GetFinality;
-        let data = serialize(&req).expect("serialize GetFinality in pub fn get_finality");
         let mut done = false;
         while !done {
             debug!("get_finality send_to {}", &self.requests_addr);
-            self.requests_socket
-                .send_to(&data, &self.requests_addr)
-                .expect("buffer error in pub fn get_finality");
+            if let Err(e) = self.send_request(&req) {
+                debug!("thin_client get_finality send error: {}", e);
+                sleep(Duration::from_millis(SEND_RETRY_MS));
+                continue;
+            }
 
             match self.recv_response() {
                 Ok(resp) => {
@@ -230,13 +374,12 @@ impl ThinClient {
     pub fn transaction_count(&mut self) -> u64 {
         debug!("transaction_count");
         let req = Request::GetTransactionCount;
-        let data =
-            serialize(&req).expect("serialize GetTransactionCount in pub fn transaction_count");
         let mut tries_left = 5;
         while tries_left > 0 {
-            self.requests_socket
-                .send_to(&data, &self.requests_addr)
-                .expect("buffer error in pub fn transaction_count");
+            if self.send_request(&req).is_err() {
+                tries_left -= 1;
+                continue;
+            }
 
             if let Ok(resp) = self.recv_response() {
                 debug!("transaction_count recv_response: {:?}", resp);
@@ -256,13 +399,14 @@ impl ThinClient {
     pub fn get_last_id(&mut self) -> Hash {
         trace!("get_last_id");
         let req = Request::GetLastId;
-        let data = serialize(&req).expect("serialize GetLastId in pub fn get_last_id");
         let mut done = false;
         while !done {
             debug!("get_last_id send_to {}", &self.requests_addr);
-            self.requests_socket
-                .send_to(&data, &self.requests_addr)
-                .expect("buffer error in pub fn get_last_id");
+            if let Err(e) = self.send_request(&req) {
+                debug!("thin_client get_last_id send error: {}", e);
+                sleep(Duration::from_millis(SEND_RETRY_MS));
+                continue;
+            }
 
             match self.recv_response() {
                 Ok(resp) => {
@@ -279,6 +423,20 @@ impl ThinClient {
         self.last_id.expect("some last_id")
     }
 
+    /// Ask the server once for the last Entry ID. Unlike `get_last_id` this does not
+    /// retry, an unanswered request returns the read timeout error of the requests socket.
+    pub fn try_get_last_id(&mut self) -> io::Result<Hash> {
+        trace!("try_get_last_id");
+        self.send_request(&Request::GetLastId)?;
+        loop {
+            let resp = self.recv_response()?;
+            self.process_response(&resp);
+            if let Response::LastId { id } = resp {
+                return Ok(id);
+            }
+        }
+    }
+
     pub fn submit_poll_balance_metrics(elapsed: &Duration) {
         metrics::submit(
             influxdb::Point::new("thinclient")
@@ -338,13 +496,14 @@ impl ThinClient {
         let req = Request::GetSignature {
             signature: *signature,
         };
-        let data = serialize(&req).expect("serialize GetSignature in pub fn check_signature");
         let now = Instant::now();
         let mut done = false;
         while !done {
-            self.requests_socket
-                .send_to(&data, &self.requests_addr)
-                .expect("buffer error in pub fn get_last_id");
+            if let Err(e) = self.send_request(&req) {
+                debug!("thin_client check_signature send error: {}", e);
+                sleep(Duration::from_millis(SEND_RETRY_MS));
+                continue;
+            }
 
             if let Ok(resp) = self.recv_response() {
                 if let Response::SignatureStatus { .. } = resp {
@@ -363,6 +522,153 @@ impl ThinClient {
         );
         self.signature_status
     }
+
+    /// Ask the server once for the status of a signature, `BankError::SignatureNotFound`
+    /// means it has not been processed yet. Unlike `check_signature` this does not retry,
+    /// an unanswered request returns the read timeout error of the requests socket.
+    pub fn get_signature_status(
+        &mut self,
+        signature: &Signature,
+    ) -> io::Result<tx_vault::Result<()>> {
+        trace!("get_signature_status");
+        let req = Request::GetSignatureStatus {
+            signature: *signature,
+        };
+        self.send_request(&req)?;
+        loop {
+            let resp = self.recv_response()?;
+            self.process_response(&resp);
+            if let Response::SignatureResult {
+                signature: resp_signature,
+                result,
+            } = resp
+            {
+                // skip late answers to earlier requests
+                if resp_signature == *signature {
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    /// Request the accounts of `keys`, None for the ones the bank doesn't have. The keys
+    /// are sent `MAX_ACCOUNTS_PER_REQUEST` at a time, an unanswered request returns the
+    /// read timeout error of the requests socket.
+    pub fn get_accounts(&mut self, keys: &[Pubkey]) -> io::Result<Vec<Option<Account>>> {
+        trace!("get_accounts");
+        let mut accounts = Vec::with_capacity(keys.len());
+        for keys in keys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+            let req = Request::GetAccounts {
+                keys: keys.to_vec(),
+            };
+            self.send_request(&req)?;
+            loop {
+                let resp = self.recv_response()?;
+                self.process_response(&resp);
+                if let Response::Accounts {
+                    accounts: resp_accounts,
+                } = resp
+                {
+                    // skip late answers to earlier requests
+                    if resp_accounts.iter().map(|(key, _)| key).eq(keys.iter()) {
+                        accounts.extend(resp_accounts.into_iter().map(|(_, account)| account));
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(accounts)
+    }
+
+    /// Ask the server once for the statuses of `signatures`, in the same order, sent
+    /// `MAX_SIGNATURES_PER_REQUEST` at a time. See `get_signature_status`.
+    pub fn get_signature_statuses(
+        &mut self,
+        signatures: &[Signature],
+    ) -> io::Result<Vec<tx_vault::Result<()>>> {
+        trace!("get_signature_statuses");
+        let mut statuses = Vec::with_capacity(signatures.len());
+        for signatures in signatures.chunks(MAX_SIGNATURES_PER_REQUEST) {
+            let req = Request::GetSignatures {
+                signatures: signatures.to_vec(),
+            };
+            self.send_request(&req)?;
+            loop {
+                let resp = self.recv_response()?;
+                self.process_response(&resp);
+                if let Response::SignatureResults { results } = resp {
+                    // skip late answers to earlier requests
+                    if results
+                        .iter()
+                        .map(|(signature, _)| signature)
+                        .eq(signatures.iter())
+                    {
+                        statuses.extend(results.into_iter().map(|(_, result)| result));
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Subscribe to the changes of the account of `key`, returns its current state.
+    /// The server pushes every later change, read them with `recv_account_update`.
+    /// Over udp the subscription lasts `SUBSCRIPTION_TTL_SECS`, subscribe again to
+    /// renew it.
+    pub fn subscribe_account(&mut self, key: &Pubkey) -> io::Result<Option<Account>> {
+        trace!("subscribe_account");
+        let req = Request::SubscribeAccount { key: *key };
+        self.send_request(&req)?;
+        loop {
+            let resp = self.recv_response()?;
+            self.process_response(&resp);
+            if let Response::Account {
+                key: resp_key,
+                account,
+            } = resp
+            {
+                if resp_key == *key {
+                    return Ok(account);
+                }
+            }
+        }
+    }
+
+    /// Stop the updates of the account of `key`. This method does not wait for a response.
+    pub fn unsubscribe_account(&mut self, key: &Pubkey) -> io::Result<()> {
+        let req = Request::UnsubscribeAccount { key: *key };
+        self.send_request(&req)
+    }
+
+    // The next update of a subscribed account pushed over `requests_stream`.
+    fn recv_streamed_update(&mut self) -> io::Result<Response> {
+        if let Some(resp) = self.account_updates.pop_front() {
+            return Ok(resp);
+        }
+        loop {
+            let (id, resp) = self.recv_frame()?;
+            if id == SUBSCRIPTION_UPDATE_ID {
+                return Ok(resp);
+            }
+            trace!("skipping the response to request {}", id);
+        }
+    }
+
+    /// Wait for the next update of a subscribed account, an update that doesn't come
+    /// returns the read timeout error of the requests socket.
+    pub fn recv_account_update(&mut self) -> io::Result<(Pubkey, Option<Account>)> {
+        loop {
+            let resp = match self.transport {
+                Transport::Udp { .. } => self.recv_response()?,
+                Transport::Tcp { .. } => self.recv_streamed_update()?,
+            };
+            self.process_response(&resp);
+            if let Response::Account { key, account } = resp {
+                return Ok((key, account));
+            }
+        }
+    }
 }
 
 impl Drop for ThinClient {
@@ -430,6 +736,7 @@ mod tests {
     use crate::logger;
     use crate::coinery::Mint;
     use crate::signature::{Keypair, KeypairUtil};
+    use crate::tx_vault::BankError;
     use std::fs::remove_dir_all;
     use system_program::SystemProgram;
 
@@ -477,9 +784,11 @@ mod tests {
 
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
         let last_id = client.get_last_id();
         let signature = client
@@ -527,9 +836,11 @@ mod tests {
         let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
         let last_id = client.get_last_id();
 
@@ -586,9 +897,11 @@ mod tests {
         let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
         let last_id = client.get_last_id();
         let signature = client
@@ -596,6 +909,151 @@ mod tests {
             .unwrap();
 
         assert!(client.poll_for_signature(&signature).is_ok());
+        assert_eq!(client.get_signature_status(&signature).unwrap(), Ok(()));
+
+        let unknown = Transaction::system_new(&alice.keypair(), bob_pubkey, 1, last_id);
+        assert_eq!(
+            client.get_signature_status(&unknown.signature).unwrap(),
+            Err(BankError::SignatureNotFound)
+        );
+
+        server.close().unwrap();
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_client_batch_requests_and_subscriptions() {
+        logger::setup();
+        let leader_keypair = Keypair::new();
+        let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+        let alice = Mint::new(10_000);
+        let bank = Bank::new(&alice);
+        let leader_data = leader.info.clone();
+        let ledger_path = tmp_ledger("client_batch_requests", &alice);
+
+        let server = Fullnode::new_with_bank(
+            leader_keypair,
+            bank,
+            0,
+            &[],
+            leader,
+            None,
+            &ledger_path,
+            false,
+            None,
+            Some(0),
+        );
+        sleep(Duration::from_millis(300));
+
+        let requests_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        requests_socket
+            .set_read_timeout(Some(Duration::new(5, 0)))
+            .unwrap();
+        let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut client = ThinClient::new(
+            leader_data.contact_info.rpu,
+            leader_data.contact_info.tpu,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
+        );
+        let last_id = client.get_last_id();
+
+        // more keys and signatures than fit in one request
+        let keys: Vec<_> = (0..MAX_ACCOUNTS_PER_REQUEST + 2)
+            .map(|_| Keypair::new().pubkey())
+            .collect();
+        let bob_pubkey = keys[MAX_ACCOUNTS_PER_REQUEST];
+        assert!(client.subscribe_account(&bob_pubkey).unwrap().is_none());
+
+        let signatures: Vec<_> = keys
+            .iter()
+            .take(MAX_SIGNATURES_PER_REQUEST + 1)
+            .enumerate()
+            .map(|(i, key)| {
+                client
+                    .transfer(i as i64 + 1, &alice.keypair(), *key, &last_id)
+                    .unwrap()
+            }).collect();
+        for signature in &signatures {
+            client.poll_for_signature(signature).unwrap();
+        }
+
+        let statuses = client.get_signature_statuses(&signatures).unwrap();
+        assert_eq!(statuses, vec![Ok(()); signatures.len()]);
+
+        let accounts = client.get_accounts(&keys).unwrap();
+        assert_eq!(accounts.len(), keys.len());
+        for (i, account) in accounts.iter().enumerate() {
+            if i < signatures.len() {
+                assert_eq!(account.as_ref().unwrap().tokens, i as i64 + 1);
+            } else {
+                assert!(account.is_none());
+            }
+        }
+
+        // the subscription pushes the change to bob's account
+        let signature = client
+            .transfer(500, &alice.keypair(), bob_pubkey, &last_id)
+            .unwrap();
+        let (key, account) = client.recv_account_update().unwrap();
+        assert_eq!(key, bob_pubkey);
+        assert_eq!(account.unwrap().tokens, 500);
+        assert_eq!(client.get_signature_status(&signature).unwrap(), Ok(()));
+        client.unsubscribe_account(&bob_pubkey).unwrap();
+
+        server.close().unwrap();
+        remove_dir_all(ledger_path).unwrap();
+    }
+
+    #[test]
+    fn test_thin_client_tcp() {
+        logger::setup();
+        let leader_keypair = Keypair::new();
+        let leader = Node::new_localhost_with_pubkey(leader_keypair.pubkey());
+        let alice = Mint::new(10_000);
+        let bank = Bank::new(&alice);
+        let bob_pubkey = Keypair::new().pubkey();
+        let leader_data = leader.info.clone();
+        let ledger_path = tmp_ledger("thin_client_tcp", &alice);
+
+        let server = Fullnode::new_with_bank(
+            leader_keypair,
+            bank,
+            0,
+            &[],
+            leader,
+            None,
+            &ledger_path,
+            false,
+            None,
+            Some(0),
+        );
+        sleep(Duration::from_millis(300));
+
+        let mut client = ThinClient::new(
+            leader_data.contact_info.rpu,
+            leader_data.contact_info.tpu,
+            Transport::Tcp {
+                read_timeout: Some(Duration::new(5, 0)),
+            },
+        );
+        let last_id = client.get_last_id();
+        assert!(client.subscribe_account(&bob_pubkey).unwrap().is_none());
+
+        let signature = client
+            .transfer(500, &alice.keypair(), bob_pubkey, &last_id)
+            .unwrap();
+        client.poll_for_signature(&signature).unwrap();
+        assert_eq!(client.get_signature_status(&signature).unwrap(), Ok(()));
+        assert_eq!(client.get_balance(&bob_pubkey).unwrap(), 500);
+
+        // the update was pushed over the same connection while the client was polling
+        let (key, account) = client.recv_account_update().unwrap();
+        assert_eq!(key, bob_pubkey);
+        assert_eq!(account.unwrap().tokens, 500);
+        client.unsubscribe_account(&bob_pubkey).unwrap();
 
         server.close().unwrap();
         remove_dir_all(ledger_path).unwrap();
@@ -611,10 +1069,60 @@ mod tests {
             .set_read_timeout(Some(Duration::from_millis(250)))
             .unwrap();
         let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-        let mut client = ThinClient::new(addr, requests_socket, addr, transactions_socket);
+        let mut client = ThinClient::new(
+            addr,
+            addr,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
+        );
         assert_eq!(client.transaction_count(), 0);
     }
 
+    #[test]
+    fn test_try_get_last_id_times_out() {
+        // set a bogus address, see that we return instead of retrying forever
+        logger::setup();
+        let addr = "0.0.0.0:1234".parse().unwrap();
+        let requests_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        requests_socket
+            .set_read_timeout(Some(Duration::from_millis(250)))
+            .unwrap();
+        let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut client = ThinClient::new(
+            addr,
+            addr,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
+        );
+        assert!(client.try_get_last_id().is_err());
+    }
+
+    #[test]
+    fn test_tcp_connect_refused() {
+        // nothing listens on the port, the requests fail instead of panicking
+        logger::setup();
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut client = ThinClient::new(
+            addr,
+            addr,
+            Transport::Tcp {
+                read_timeout: Some(Duration::from_millis(250)),
+            },
+        );
+        assert!(client.get_balance(&Keypair::new().pubkey()).is_err());
+        assert!(client.try_get_last_id().is_err());
+        let tx =
+            Transaction::system_new(&Keypair::new(), Keypair::new().pubkey(), 1, Hash::default());
+        assert!(client.transfer_signed(&tx).is_err());
+    }
+
     #[test]
     fn test_zero_balance_after_nonzero() {
         logger::setup();
@@ -647,9 +1155,11 @@ mod tests {
         let transactions_socket = UdpSocket::bind("0.0.0.0:0").unwrap();
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
         let last_id = client.get_last_id();
 
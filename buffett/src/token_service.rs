@@ -23,7 +23,7 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use crate::system_transaction::SystemTransaction;
-use crate::thin_client::{poll_gossip_for_leader, ThinClient};
+use crate::thin_client::{poll_gossip_for_leader, ThinClient, Transport};
 use tokio;
 use tokio::net::TcpListener;
 use tokio::prelude::*;
@@ -117,9 +117,11 @@ impl Drone {
 
         let mut client = ThinClient::new(
             leader.contact_info.rpu,
-            requests_socket,
             leader.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
         let last_id = client.get_last_id();
 
@@ -239,7 +241,7 @@ mod tests {
     use std::fs::remove_dir_all;
     use std::net::{SocketAddr, UdpSocket};
     use std::time::Duration;
-    use crate::thin_client::ThinClient;
+    use crate::thin_client::{ThinClient, Transport};
 
     #[test]
     fn test_check_request_limit() {
@@ -363,9 +365,11 @@ mod tests {
 
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
 
         let bob_req = DroneRequest::GetAirdrop {
@@ -389,9 +393,11 @@ mod tests {
 
         let mut client = ThinClient::new(
             leader_data.contact_info.rpu,
-            requests_socket,
             leader_data.contact_info.tpu,
-            transactions_socket,
+            Transport::Udp {
+                requests_socket,
+                transactions_socket,
+            },
         );
 
         let carlos_req = DroneRequest::GetAirdrop {
@@ -32,8 +32,9 @@ use crate::entry::Entry;
 use crate::fetch_stage::FetchStage;
 use crate::service::Service;
 use crate::signature::Keypair;
+use crate::sigverify::SharedSigVerifyMode;
 use crate::sigverify_stage::SigVerifyStage;
-use std::net::UdpSocket;
+use std::net::{TcpListener, UdpSocket};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, RwLock};
@@ -59,19 +60,22 @@ impl Tpu {
         crdt: &Arc<RwLock<Crdt>>,
         tick_duration: Config,
         transactions_sockets: Vec<UdpSocket>,
+        transactions_listener: Option<TcpListener>,
         ledger_path: &str,
         sigverify_disabled: bool,
+        sigverify_mode: SharedSigVerifyMode,
         entry_height: u64,
     ) -> (Self, Receiver<Vec<Entry>>, Arc<AtomicBool>) {
         let exit = Arc::new(AtomicBool::new(false));
 
-        let (fetch_stage, packet_receiver) = FetchStage::new(transactions_sockets, exit.clone());
+        let (fetch_stage, packet_receiver) =
+            FetchStage::new(transactions_sockets, transactions_listener, exit.clone());
 
         let (sigverify_stage, verified_receiver) =
-            SigVerifyStage::new(packet_receiver, sigverify_disabled);
+            SigVerifyStage::new(packet_receiver, sigverify_disabled, sigverify_mode);
 
         let (banking_stage, entry_receiver) =
-            BankingStage::new(&bank, verified_receiver, tick_duration);
+            BankingStage::new(&bank, &keypair, verified_receiver, tick_duration);
 
         let (write_stage, entry_forwarder) = WriteStage::new(
             keypair,
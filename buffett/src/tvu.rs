@@ -88,6 +88,10 @@ impl Tvu {
         self.exit.store(true, Ordering::Relaxed);
     }
 
+    pub fn exit_signal(&self) -> Arc<AtomicBool> {
+        self.exit.clone()
+    }
+
     pub fn close(self) -> thread::Result<Option<TvuReturnType>> {
         self.fetch_stage.close();
         self.join()
@@ -13,7 +13,10 @@ use poh_recorder::PohRecorder;
 use rayon::prelude::*;
 use crate::result::{Error, Result};
 use crate::service::Service;
+use crate::signature::Keypair;
 use crate::sigverify_stage::VerifiedPackets;
+use crate::system_transaction::SystemTransaction;
+use std::iter;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
@@ -49,9 +52,11 @@ impl Default for Config {
     }
 }
 impl BankingStage {
-    /// Create the stage using `bank`. Exit when `verified_receiver` is dropped.
+    /// Create the stage using `bank`, the fees of its entries are collected for `keypair`.
+    /// Exit when `verified_receiver` is dropped.
     pub fn new(
         bank: &Arc<Bank>,
+        keypair: &Arc<Keypair>,
         verified_receiver: Receiver<VerifiedPackets>,
         config: Config,
     ) -> (Self, Receiver<Vec<Entry>>) {
@@ -88,6 +93,7 @@ impl BankingStage {
             .into_iter()
             .map(|_| {
                 let thread_bank = bank.clone();
+                let thread_keypair = keypair.clone();
                 let thread_verified_receiver = shared_verified_receiver.clone();
                 let thread_poh = poh.clone();
                 let thread_banking_exit = banking_exit.clone();
@@ -97,6 +103,7 @@ impl BankingStage {
                         loop {
                             if let Err(e) = Self::process_packets(
                                 &thread_bank,
+                                &thread_keypair,
                                 &thread_verified_receiver,
                                 &thread_poh,
                             ) {
@@ -155,19 +162,50 @@ impl BankingStage {
         }
     }
 
+    // The fees of a batch are paid to the leader whose collection is the first
+    // transaction of its entry, see `Bank::process_transactions`.
+    fn collect_fees(
+        bank: &Bank,
+        keypair: &Keypair,
+        transactions: &[Transaction],
+    ) -> Vec<Transaction> {
+        let batch = Transaction::hash(transactions);
+        let collect = Transaction::system_collect_fees(keypair, batch, bank.last_id());
+        iter::once(collect)
+            .chain(transactions.iter().cloned())
+            .collect()
+    }
+
     fn process_transactions(
         bank: &Arc<Bank>,
+        keypair: &Keypair,
         transactions: &[Transaction],
         poh: &PohRecorder,
     ) -> Result<()> {
         debug!("transactions: {}", transactions.len());
         let mut chunk_start = 0;
         while chunk_start != transactions.len() {
-            let chunk_end = chunk_start + Entry::num_will_fit(&transactions[chunk_start..]);
+            let mut chunk_end = chunk_start + Entry::num_will_fit(&transactions[chunk_start..]);
+            let mut chunk = &transactions[chunk_start..chunk_end];
+            let with_fees;
+            if chunk.iter().any(|tx| tx.fee > 0) {
+                // leave room in the entry for the collection
+                let mut fee_paying = chunk;
+                let mut collected = Self::collect_fees(bank, keypair, fee_paying);
+                while fee_paying.len() > 1 && !Entry::will_fit(collected.clone()) {
+                    fee_paying = &fee_paying[..fee_paying.len() - 1];
+                    collected = Self::collect_fees(bank, keypair, fee_paying);
+                }
+                chunk_end = chunk_start + fee_paying.len();
+                with_fees = collected;
+                chunk = &with_fees;
+            }
 
-            let results = bank.process_transactions(&transactions[chunk_start..chunk_end]);
+            // a checkpoint waits until the processed transactions are recorded
+            let _recording = bank.recording();
+            let results = bank.process_transactions(chunk);
 
-            let processed_transactions: Vec<_> = transactions[chunk_start..chunk_end]
+            let processed_transactions: Vec<_> = chunk
                 .into_iter()
                 .enumerate()
                 .filter_map(|(i, x)| match results[i] {
@@ -193,6 +231,7 @@ impl BankingStage {
     /// Discard packets via `packet_recycler`.
     pub fn process_packets(
         bank: &Arc<Bank>,
+        keypair: &Keypair,
         verified_receiver: &Arc<Mutex<Receiver<VerifiedPackets>>>,
         poh: &PohRecorder,
     ) -> Result<()> {
@@ -231,7 +270,7 @@ impl BankingStage {
                     },
                 }).collect();
             debug!("verified transactions {}", transactions.len());
-            Self::process_transactions(bank, &transactions, poh)?;
+            Self::process_transactions(bank, keypair, &transactions, poh)?;
         }
 
         inc_new_counter_info!(
@@ -279,13 +318,22 @@ mod tests {
     use std::thread::sleep;
     use crate::system_transaction::SystemTransaction;
     use crate::transaction::Transaction;
+    use system_program::SystemProgram;
+
+    fn leader() -> Arc<Keypair> {
+        Arc::new(Keypair::new())
+    }
 
     #[test]
     fn test_banking_stage_shutdown1() {
         let bank = Bank::new(&Mint::new(2));
         let (verified_sender, verified_receiver) = channel();
-        let (banking_stage, _entry_receiver) =
-            BankingStage::new(&Arc::new(bank), verified_receiver, Default::default());
+        let (banking_stage, _entry_receiver) = BankingStage::new(
+            &Arc::new(bank),
+            &leader(),
+            verified_receiver,
+            Default::default(),
+        );
         drop(verified_sender);
         assert_eq!(banking_stage.join().unwrap(), ());
     }
@@ -294,8 +342,12 @@ mod tests {
     fn test_banking_stage_shutdown2() {
         let bank = Bank::new(&Mint::new(2));
         let (_verified_sender, verified_receiver) = channel();
-        let (banking_stage, entry_receiver) =
-            BankingStage::new(&Arc::new(bank), verified_receiver, Default::default());
+        let (banking_stage, entry_receiver) = BankingStage::new(
+            &Arc::new(bank),
+            &leader(),
+            verified_receiver,
+            Default::default(),
+        );
         drop(entry_receiver);
         assert_eq!(banking_stage.join().unwrap(), ());
     }
@@ -307,6 +359,7 @@ mod tests {
         let (verified_sender, verified_receiver) = channel();
         let (banking_stage, entry_receiver) = BankingStage::new(
             &bank,
+            &leader(),
             verified_receiver,
             Config::Sleep(Duration::from_millis(1)),
         );
@@ -327,7 +380,7 @@ mod tests {
         let start_hash = bank.last_id();
         let (verified_sender, verified_receiver) = channel();
         let (banking_stage, entry_receiver) =
-            BankingStage::new(&bank, verified_receiver, Default::default());
+            BankingStage::new(&bank, &leader(), verified_receiver, Default::default());
 
         // good tx
         let keypair = mint.keypair();
@@ -373,7 +426,7 @@ mod tests {
         let bank = Arc::new(Bank::new(&mint));
         let (verified_sender, verified_receiver) = channel();
         let (banking_stage, entry_receiver) =
-            BankingStage::new(&bank, verified_receiver, Default::default());
+            BankingStage::new(&bank, &leader(), verified_receiver, Default::default());
 
         // Process a batch that includes a transaction that receives two tokens.
         let alice = Keypair::new();
@@ -411,4 +464,84 @@ mod tests {
         }
         assert_eq!(bank.get_balance(&alice.pubkey()), 1);
     }
+
+    #[test]
+    fn test_banking_stage_collects_fees() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        // the leader has no account until its first collection
+        let leader = leader();
+        let (verified_sender, verified_receiver) = channel();
+        let (banking_stage, entry_receiver) =
+            BankingStage::new(&bank, &leader, verified_receiver, Default::default());
+
+        let to = Keypair::new().pubkey();
+        let tx = Transaction::system_move(&mint.keypair(), to, 10, mint.last_id(), 3);
+        let packets = to_packets(&[tx]);
+        verified_sender
+            .send(vec![(packets[0].clone(), vec![1u8])])
+            .unwrap();
+        drop(verified_sender);
+        assert_eq!(banking_stage.join().unwrap(), ());
+        assert_eq!(bank.get_balance(&leader.pubkey()), 3);
+
+        // the entry starts with the leader's collection, so a replay pays it too
+        let entries: Vec<_> = entry_receiver.iter().flat_map(|x| x).collect();
+        let entry = entries
+            .iter()
+            .find(|entry| !entry.transactions.is_empty())
+            .unwrap();
+        assert_eq!(entry.transactions.len(), 2);
+        assert!(SystemProgram::is_collect_fees(&entry.transactions[0]));
+
+        let replayed = Bank::new(&mint);
+        replayed.process_entries(&entries).unwrap();
+        assert_eq!(replayed.get_balance(&leader.pubkey()), 3);
+        assert_eq!(
+            replayed.hash_internal_state().unwrap(),
+            bank.hash_internal_state().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_banking_stage_full_entries_collect_fees() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        let leader = leader();
+        let payers: Vec<_> = (0..1000).map(|_| Keypair::new()).collect();
+        for payer in &payers {
+            bank.transfer(2, &mint.keypair(), payer.pubkey(), mint.last_id())
+                .unwrap();
+        }
+        let (verified_sender, verified_receiver) = channel();
+        let (banking_stage, entry_receiver) =
+            BankingStage::new(&bank, &leader, verified_receiver, Default::default());
+
+        // more fee payers than fit in an entry, each one needs its own collection
+        let to = Keypair::new().pubkey();
+        let txs: Vec<_> = payers
+            .iter()
+            .map(|payer| Transaction::system_move(payer, to, 1, mint.last_id(), 1))
+            .collect();
+        let packets = to_packets(&txs);
+        let num_packets = packets[0].read().unwrap().packets.len();
+        verified_sender
+            .send(vec![(packets[0].clone(), vec![1u8; num_packets])])
+            .unwrap();
+        drop(verified_sender);
+        assert_eq!(banking_stage.join().unwrap(), ());
+
+        let entries: Vec<_> = entry_receiver.iter().flat_map(|x| x).collect();
+        let full: Vec<_> = entries
+            .iter()
+            .filter(|entry| !entry.transactions.is_empty())
+            .collect();
+        assert!(full.len() > 1);
+        for entry in full {
+            assert!(Entry::will_fit(entry.transactions.clone()));
+            assert!(SystemProgram::is_collect_fees(&entry.transactions[0]));
+        }
+        assert_eq!(bank.get_balance(&to), num_packets as i64);
+        assert_eq!(bank.get_balance(&leader.pubkey()), num_packets as i64);
+    }
 }
@@ -1,26 +1,31 @@
 //! Transaction Valut provide booking and accounting service for transactions.
 
-use bincode::deserialize;
-use bincode::serialize;
+use crate::account_store::{AccountStore, MemoryAccountStore};
+use bincode::{self, deserialize, deserialize_from, serialize, serialize_into};
 use crate::budget_program::BudgetState;
 use crate::budget_transaction::BudgetTransaction;
 use crate::counter::Counter;
+use crate::crdt::Crdt;
 use crate::dynamic_program::DynamicProgram;
 use crate::entry::Entry;
-use crate::hash::{hash, Hash};
+use crate::hash::{Hash, Hasher};
 use itertools::Itertools;
 use crate::ledger::Block;
+use crate::loader_program::LoaderProgram;
 use log::Level;
 use crate::coinery::Mint;
 use payment_plan::Payment;
+use crate::program_registry::ProgramRegistry;
 use crate::signature::{Keypair, Signature};
 use buffett_program_interface::account::{Account, KeyedAccount};
 use buffett_program_interface::pubkey::Pubkey;
 use std;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
 use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::RwLock;
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard};
 use std::time::Instant;
 use storage_program::StorageProgram;
 use system_program::SystemProgram;
@@ -42,7 +47,7 @@ pub const MAX_ENTRY_IDS: usize = 1024 * 16;
 pub const VERIFY_BLOCK_SIZE: usize = 16;
 
 /// Reasons a transaction might be rejected.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum BankError {
     /// Attempt to debit from `Pubkey`, but no found no record of a prior credit.
     AccountNotFound,
@@ -83,11 +88,67 @@ pub enum BankError {
 
     /// The program returned an error
     ProgramRuntimeError,
+
+    /// The accounts of a checkpoint do not hash to the state it was taken with
+    CheckpointVerificationFailed,
+
+    /// The transaction's fee is negative
+    NegativeFee,
+
+    /// Contract modified the userdata of an account that doesn't belong to it
+    ExternalAccountUserdataModified,
+
+    /// A `CollectFees` was signed by another node than the scheduled leader
+    UnscheduledLeader,
+
+    /// The accounts of the transaction could not be read from or written to the account store
+    AccountStoreFailed,
 }
 
 pub type Result<T> = result::Result<T, BankError>;
+
+fn store_error(err: io::Error) -> BankError {
+    error!("account store failed: {}", err);
+    BankError::AccountStoreFailed
+}
+
 type SignatureStatusMap = HashMap<Signature, Result<()>>;
 
+/// A snapshot of the state of a `Bank`, a fullnode restores it and processes only the
+/// ledger entries after `entry_height` instead of replaying the whole ledger. It is
+/// followed by the accounts of the bank, see `Bank::write_checkpoint`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BankCheckpoint {
+    /// The number of ledger entries processed by the bank the checkpoint was taken from
+    pub entry_height: u64,
+    pub transaction_count: u64,
+    /// The number of accounts that follow the checkpoint, ordered by key
+    pub num_accounts: u64,
+    last_ids: VecDeque<Hash>,
+    last_ids_sigs: HashMap<Hash, (SignatureStatusMap, u64)>,
+    /// `hash_internal_state` of the bank the checkpoint was taken from
+    pub hash: Hash,
+}
+
+impl BankCheckpoint {
+    /// The id of the last entry the checkpointed bank processed.
+    pub fn last_id(&self) -> Option<Hash> {
+        self.last_ids.back().cloned()
+    }
+}
+
+/// The accounts a batch of transactions holds, released when it is dropped.
+struct AccountLocks<'a> {
+    bank: &'a Bank,
+    keys: HashSet<Pubkey>,
+}
+
+impl<'a> Drop for AccountLocks<'a> {
+    fn drop(&mut self) {
+        self.bank.unlock_accounts(&self.keys);
+    }
+}
+
 #[derive(Default)]
 struct ErrorCounters {
     account_not_found_validator: usize,
@@ -98,7 +159,12 @@ struct ErrorCounters {
 /// The state of all accounts and contracts after processing its entries.
 pub struct Bank {
     /// A map of account public keys to the balance in that account.
-    accounts: RwLock<HashMap<Pubkey, Account>>,
+    accounts: Box<AccountStore>,
+
+    /// The accounts of the transactions being processed. A batch of transactions waits
+    /// for any other batch holding one of its accounts.
+    account_locks: Mutex<HashSet<Pubkey>>,
+    account_locks_released: Condvar,
 
     /// A FIFO queue of `last_id` items, where each item is a set of signatures
     /// that have been processed using that `last_id`. Rejected `last_id`
@@ -113,6 +179,10 @@ pub struct Bank {
     /// start of the ledger.
     transaction_count: AtomicUsize,
 
+    /// The number of entry ids registered with the bank, i.e. the height of the
+    /// ledger it has processed.
+    entry_height: AtomicUsize,
+
     /// This bool allows us to submit metrics that are specific for leaders or validators
     /// It is set to `true` by fullnode before creating the bank.
     pub is_leader: bool,
@@ -120,20 +190,37 @@ pub struct Bank {
     // The latest finality time for the network
     finality_time: AtomicUsize,
 
-    // loaded contracts hashed by program_id
-    loaded_contracts: RwLock<HashMap<Pubkey, DynamicProgram>>,
+    // programs deployed through the loader program by the hash of their code, and the
+    // ones `SystemProgram::Load` loaded by their program_id
+    programs: ProgramRegistry,
+
+    // held shared by the leader from processing transactions until their entry is
+    // recorded, and exclusively while taking a checkpoint
+    recording: RwLock<()>,
+
+    // the entry height of the last vote of each voter, only voters have a stake
+    votes: RwLock<HashMap<Pubkey, u64>>,
+
+    // the leader schedule fee collections are checked against, if there is one
+    leader_scheduler: RwLock<Option<Arc<RwLock<Crdt>>>>,
 }
 
 impl Default for Bank {
     fn default() -> Self {
         Bank {
-            accounts: RwLock::new(HashMap::new()),
+            accounts: Box::new(MemoryAccountStore::default()),
+            account_locks: Mutex::new(HashSet::new()),
+            account_locks_released: Condvar::new(),
             last_ids: RwLock::new(VecDeque::new()),
             last_ids_sigs: RwLock::new(HashMap::new()),
             transaction_count: AtomicUsize::new(0),
+            entry_height: AtomicUsize::new(0),
             is_leader: true,
             finality_time: AtomicUsize::new(std::usize::MAX),
-            loaded_contracts: RwLock::new(HashMap::new()),
+            programs: ProgramRegistry::default(),
+            recording: RwLock::new(()),
+            votes: RwLock::new(HashMap::new()),
+            leader_scheduler: RwLock::new(None),
         }
     }
 }
@@ -145,14 +232,26 @@ impl Bank {
         bank.is_leader = is_leader;
         bank
     }
+
+    /// Create a Bank that keeps its accounts in `accounts`. The bank starts without
+    /// accounts, the ones the store holds are removed.
+    pub fn new_with_account_store(
+        accounts: Box<AccountStore>,
+        is_leader: bool,
+    ) -> io::Result<Self> {
+        let mut bank = Bank::new_default(is_leader);
+        accounts.clear()?;
+        bank.accounts = accounts;
+        Ok(bank)
+    }
+
     /// Create an Bank using a deposit.
     pub fn new_from_deposit(deposit: &Payment) -> Self {
         let bank = Self::default();
-        {
-            let mut accounts = bank.accounts.write().unwrap();
-            let account = accounts.entry(deposit.to).or_insert_with(Account::default);
-            Self::apply_payment(deposit, account);
-        }
+        let mut account = Account::default();
+        Self::apply_payment(deposit, &mut account);
+        // the memory store doesn't fail
+        bank.accounts.store_account(&deposit.to, &account).unwrap();
         bank
     }
 
@@ -237,6 +336,31 @@ impl Bank {
         }
     }
 
+    /// Record the votes among `txs` that were processed, voters that haven't voted
+    /// within the last `MAX_ENTRY_IDS` entries are forgotten
+    fn update_votes(&self, txs: &[Transaction], res: &[Result<()>]) {
+        use crate::budget_instruction::Instruction;
+        let voters: Vec<_> = txs
+            .iter()
+            .zip(res)
+            .filter(|(tx, r)| r.is_ok() && BudgetState::check_id(&tx.program_id))
+            .filter(|(tx, _)| match tx.instruction() {
+                Some(Instruction::NewVote(_)) | Some(Instruction::NewSignedVote(..)) => true,
+                _ => false,
+            }).map(|(tx, _)| *tx.from())
+            .collect();
+        if voters.is_empty() {
+            return;
+        }
+        let entry_height = self.entry_height();
+        let mut votes = self.votes.write().unwrap();
+        for voter in voters {
+            if votes.insert(voter, entry_height).is_none() {
+                votes.retain(|_, height| *height + MAX_ENTRY_IDS as u64 >= entry_height);
+            }
+        }
+    }
+
     /// Look through the last_ids and find all the valid ids
     /// This is batched to avoid holding the lock for a significant amount of time
     ///
@@ -272,6 +396,7 @@ impl Bank {
         }
         last_ids_sigs.insert(*last_id, (HashMap::new(), timestamp()));
         last_ids.push_back(*last_id);
+        self.entry_height.fetch_add(1, Ordering::Relaxed);
     }
 
     /// Process a Transaction. This is used for unit tests and simply calls the vector Bank::process_transactions method.
@@ -285,14 +410,63 @@ impl Bank {
         }
     }
 
+    /// Run the native programs installed in `dir`, see `ProgramRegistry`.
+    pub fn set_program_dir(&self, dir: PathBuf) {
+        self.programs.set_program_dir(dir);
+    }
+
+    /// Only accept fee collections from the leaders scheduled in `crdt`.
+    pub fn set_leader_scheduler(&self, crdt: &Arc<RwLock<Crdt>>) {
+        *self.leader_scheduler.write().unwrap() = Some(crdt.clone());
+    }
+
+    /// The leader scheduled for the entry at `entry_height`, None without a schedule.
+    pub fn scheduled_leader(&self, entry_height: u64) -> Option<Pubkey> {
+        let crdt = self.leader_scheduler.read().unwrap().clone()?;
+        let crdt = crdt.read().unwrap();
+        let interval = crdt.get_leader_rotation_interval().max(1);
+        crdt.get_scheduled_leader(entry_height - entry_height % interval)
+            .filter(|leader_id| *leader_id != Pubkey::default())
+    }
+
+    // The leader that collects the fees of an entry may not have an account yet, it is
+    // created by the collection.
+    fn load_fee_collection(&self, tx: &Transaction) -> Result<Vec<Account>> {
+        if let Some(leader_id) = self.scheduled_leader(self.entry_height()) {
+            if leader_id != tx.keys[0] {
+                return Err(BankError::UnscheduledLeader);
+            }
+        }
+        if tx.fee != 0 {
+            return Err(BankError::InsufficientFundsForFee);
+        }
+        let accounts = self.load_stored_accounts(tx)?;
+        self.reserve_signature_with_last_id(&tx.signature, &tx.last_id)?;
+        Ok(accounts)
+    }
+
+    fn load_stored(&self, pubkey: &Pubkey) -> Result<Option<Account>> {
+        self.accounts.get_account(pubkey).map_err(store_error)
+    }
+
+    fn load_stored_accounts(&self, tx: &Transaction) -> Result<Vec<Account>> {
+        tx.keys
+            .iter()
+            .map(|key| Ok(self.load_stored(key)?.unwrap_or_default()))
+            .collect()
+    }
+
     fn load_account(
         &self,
         tx: &Transaction,
-        accounts: &HashMap<Pubkey, Account>,
         error_counters: &mut ErrorCounters,
     ) -> Result<Vec<Account>> {
+        if SystemProgram::is_collect_fees(tx) {
+            return self.load_fee_collection(tx);
+        }
         // Copy all the accounts
-        if accounts.get(&tx.keys[0]).is_none() {
+        let payer = self.load_stored(&tx.keys[0])?;
+        if payer.is_none() {
             if !self.is_leader {
                 error_counters.account_not_found_validator += 1;
             } else {
@@ -300,19 +474,20 @@ impl Bank {
             }
             if BudgetState::check_id(&tx.program_id) {
                 use crate::budget_instruction::Instruction;
-                if let Some(Instruction::NewVote(_vote)) = tx.instruction() {
-                    error_counters.account_not_found_vote += 1;
+                match tx.instruction() {
+                    Some(Instruction::NewVote(_)) | Some(Instruction::NewSignedVote(..)) => {
+                        error_counters.account_not_found_vote += 1;
+                    }
+                    _ => (),
                 }
             }
             Err(BankError::AccountNotFound)
-        } else if accounts.get(&tx.keys[0]).unwrap().tokens < tx.fee {
+        } else if tx.fee < 0 {
+            Err(BankError::NegativeFee)
+        } else if payer.unwrap().tokens < tx.fee {
             Err(BankError::InsufficientFundsForFee)
         } else {
-            let mut called_accounts: Vec<Account> = tx
-                .keys
-                .iter()
-                .map(|key| accounts.get(key).cloned().unwrap_or_default())
-                .collect();
+            let mut called_accounts = self.load_stored_accounts(tx)?;
             // There is no way to predict what contract will execute without an error
             // If a fee can pay for execution then the contract will be scheduled
             self.reserve_signature_with_last_id(&tx.signature, &tx.last_id)?;
@@ -324,18 +499,37 @@ impl Bank {
     fn load_accounts(
         &self,
         txs: &[Transaction],
-        accounts: &HashMap<Pubkey, Account>,
         error_counters: &mut ErrorCounters,
     ) -> Vec<Result<Vec<Account>>> {
         txs.iter()
-            .map(|tx| self.load_account(tx, accounts, error_counters))
+            .map(|tx| self.load_account(tx, error_counters))
             .collect()
     }
 
+    /// Lock the accounts of a batch of transactions, waiting for the batches that hold
+    /// any of them. They are all taken at once, so batches can't deadlock each other.
+    fn lock_accounts(&self, keys: HashSet<Pubkey>) -> AccountLocks {
+        let mut locked = self.account_locks.lock().unwrap();
+        while keys.iter().any(|key| locked.contains(key)) {
+            locked = self.account_locks_released.wait(locked).unwrap();
+        }
+        locked.extend(keys.iter().cloned());
+        AccountLocks { bank: self, keys }
+    }
+
+    fn unlock_accounts(&self, keys: &HashSet<Pubkey>) {
+        let mut locked = self.account_locks.lock().unwrap();
+        for key in keys {
+            locked.remove(key);
+        }
+        self.account_locks_released.notify_all();
+    }
+
     pub fn verify_transaction(
         tx: &Transaction,
         pre_program_id: &Pubkey,
         pre_tokens: i64,
+        pre_userdata: &[u8],
         account: &Account,
     ) -> Result<()> {
         // Verify the transaction
@@ -351,27 +545,53 @@ impl Bank {
         if tx.program_id != account.program_id && pre_tokens > account.tokens {
             return Err(BankError::ExternalAccountTokenSpend);
         }
+        // Only the contract an account is assigned to can change its userdata.
+        if tx.program_id != *pre_program_id && pre_userdata != &account.userdata[..] {
+            return Err(BankError::ExternalAccountUserdataModified);
+        }
         if account.tokens < 0 {
             return Err(BankError::ResultWithNegativeTokens);
         }
         Ok(())
     }
 
-    fn loaded_contract(&self, tx: &Transaction, accounts: &mut [Account]) -> bool {
-        let loaded_contracts = self.loaded_contracts.write().unwrap();
-        match loaded_contracts.get(&tx.program_id) {
-            Some(dc) => {
-                let mut infos: Vec<_> = (&tx.keys)
-                    .into_iter()
-                    .zip(accounts)
-                    .map(|(key, account)| KeyedAccount { key, account })
-                    .collect();
+    fn call_program(
+        program: &DynamicProgram,
+        tx: &Transaction,
+        accounts: &mut [Account],
+    ) -> Result<()> {
+        let mut infos: Vec<_> = (&tx.keys)
+            .into_iter()
+            .zip(accounts)
+            .map(|(key, account)| KeyedAccount { key, account })
+            .collect();
+
+        program.call(&mut infos, &tx.userdata).map_err(|err| {
+            debug!("program {} failed: {:?}", tx.program_id, err);
+            BankError::ProgramRuntimeError
+        })
+    }
+
+    fn loaded_contract(&self, tx: &Transaction, accounts: &mut [Account]) -> Option<Result<()>> {
+        let program = self.programs.loaded(&tx.program_id)?;
+        Some(Self::call_program(&program, tx, accounts))
+    }
 
-                dc.call(&mut infos, &tx.userdata);
-                true
+    // runs the program deployed in the tx.program_id account, if there is one
+    fn deployed_program(&self, tx: &Transaction, accounts: &mut [Account]) -> Option<Result<()>> {
+        let account = match self.load_stored(&tx.program_id) {
+            Ok(account) => account?,
+            Err(err) => return Some(Err(err)),
+        };
+        let (kind, code) = LoaderProgram::program(&account)?;
+        let result = match self.programs.load(kind, code) {
+            Ok(program) => Self::call_program(&program, tx, accounts),
+            Err(err) => {
+                warn!("unable to load program {}: {:?}", tx.program_id, err);
+                Err(BankError::ProgramRuntimeError)
             }
-            None => false,
-        }
+        };
+        Some(result)
     }
 
     /// Execute a transaction.
@@ -379,16 +599,20 @@ impl Bank {
     /// the contract does not violate the bank's accounting rules.
     /// The accounts are committed back to the bank only if this function returns Ok(_).
     fn execute_transaction(&self, tx: &Transaction, accounts: &mut [Account]) -> Result<()> {
-        let pre_total: i64 = accounts.iter().map(|a| a.tokens).sum();
+        // summed as i128, a program could otherwise set balances that wrap an i64
+        // sum back around to the original total
+        let pre_total: i128 = accounts.iter().map(|a| i128::from(a.tokens)).sum();
         let pre_data: Vec<_> = accounts
             .iter_mut()
-            .map(|a| (a.program_id, a.tokens))
+            .map(|a| (a.program_id, a.tokens, a.userdata.clone()))
             .collect();
 
         // Call the contract method
         // It's up to the contract to implement its own rules on moving funds
         if SystemProgram::check_id(&tx.program_id) {
-            SystemProgram::process_transaction(&tx, accounts, &self.loaded_contracts)
+            if SystemProgram::process_transaction(&tx, accounts, &self.programs).is_err() {
+                return Err(BankError::ProgramRuntimeError);
+            }
         } else if BudgetState::check_id(&tx.program_id) {
             // TODO: the runtime should be checking read/write access to memory
             // we are trusting the hard coded contracts not to clobber or allocate
@@ -407,16 +631,26 @@ impl Bank {
             if TicTacToeDashboardProgram::process_transaction(&tx, accounts).is_err() {
                 return Err(BankError::ProgramRuntimeError);
             }
-        } else if self.loaded_contract(&tx, accounts) {
+        } else if LoaderProgram::check_id(&tx.program_id) {
+            if LoaderProgram::process_transaction(&tx, accounts).is_err() {
+                return Err(BankError::ProgramRuntimeError);
+            }
+        } else if let Some(result) = self.loaded_contract(&tx, accounts) {
+            result?;
+        } else if let Some(result) = self.deployed_program(&tx, accounts) {
+            // deployed programs are held to the same accounting rules as the others
+            result?;
         } else {
             return Err(BankError::UnknownContractId);
         }
         // Verify the transaction
-        for ((pre_program_id, pre_tokens), post_account) in pre_data.iter().zip(accounts.iter()) {
-            Self::verify_transaction(&tx, pre_program_id, *pre_tokens, post_account)?;
+        for ((pre_program_id, pre_tokens, pre_userdata), post_account) in
+            pre_data.iter().zip(accounts.iter())
+        {
+            Self::verify_transaction(&tx, pre_program_id, *pre_tokens, pre_userdata, post_account)?;
         }
         // The total sum of all the tokens in all the pages cannot change.
-        let post_total: i64 = accounts.iter().map(|a| a.tokens).sum();
+        let post_total: i128 = accounts.iter().map(|a| i128::from(a.tokens)).sum();
         if pre_total != post_total {
             Err(BankError::UnbalancedTransaction)
         } else {
@@ -424,12 +658,15 @@ impl Bank {
         }
     }
 
+    /// Store the accounts of the transactions that succeeded, the ones the account store
+    /// fails to store fail with `AccountStoreFailed`.
     pub fn store_accounts(
+        &self,
         txs: &[Transaction],
-        res: &[Result<()>],
+        res: &mut [Result<()>],
         loaded: &[Result<Vec<Account>>],
-        accounts: &mut HashMap<Pubkey, Account>,
     ) {
+        let mut fees = 0;
         for (i, racc) in loaded.iter().enumerate() {
             if res[i].is_err() || racc.is_err() {
                 continue;
@@ -437,14 +674,27 @@ impl Bank {
 
             let tx = &txs[i];
             let acc = racc.as_ref().unwrap();
-            for (key, account) in tx.keys.iter().zip(acc.iter()) {
-                //purge if 0
-                if account.tokens == 0 {
-                    accounts.remove(&key);
-                } else {
-                    *accounts.entry(*key).or_insert_with(Account::default) = account.clone();
-                    assert_eq!(accounts.get(key).unwrap().tokens, account.tokens);
-                }
+            let accounts: Vec<_> = tx.keys.iter().zip(acc.iter()).collect();
+            if let Err(err) = self.accounts.store_accounts(&accounts) {
+                res[i] = Err(store_error(err));
+                continue;
+            }
+            fees += tx.fee;
+        }
+        // The fees of a batch, a whole entry when the ledger is replayed, go to the
+        // leader whose `CollectFees` is the first transaction. Without one they are
+        // burned, as they were before fees were paid out.
+        if fees > 0 && res[0].is_ok() && SystemProgram::is_collect_fees(&txs[0]) {
+            let leader_id = txs[0].keys[0];
+            let paid = self.load_stored(&leader_id).and_then(|account| {
+                let mut account = account.unwrap_or_default();
+                account.tokens += fees;
+                self.accounts
+                    .store_account(&leader_id, &account)
+                    .map_err(store_error)
+            });
+            if paid.is_ok() {
+                trace!("paid {} in fees to leader {}", fees, leader_id);
             }
         }
     }
@@ -453,18 +703,16 @@ impl Bank {
     #[must_use]
     pub fn process_transactions(&self, txs: &[Transaction]) -> Vec<Result<()>> {
         debug!("processing transactions: {}", txs.len());
-        // TODO right now a single write lock is held for the duration of processing all the
-        // transactions
-        // To break this lock each account needs to be locked to prevent concurrent access
-        let mut accounts = self.accounts.write().unwrap();
+        // only the accounts of the batch are locked, batches with other accounts run concurrently
+        let locks = self.lock_accounts(txs.iter().flat_map(|tx| tx.keys.iter().cloned()).collect());
         let txs_len = txs.len();
         let mut error_counters = ErrorCounters::default();
         let now = Instant::now();
-        let mut loaded_accounts = self.load_accounts(&txs, &accounts, &mut error_counters);
+        let mut loaded_accounts = self.load_accounts(&txs, &mut error_counters);
         let load_elapsed = now.elapsed();
         let now = Instant::now();
 
-        let res: Vec<_> = loaded_accounts
+        let mut res: Vec<_> = loaded_accounts
             .iter_mut()
             .zip(txs.iter())
             .map(|(acc, tx)| match acc {
@@ -473,8 +721,10 @@ impl Bank {
             }).collect();
         let execution_elapsed = now.elapsed();
         let now = Instant::now();
-        Self::store_accounts(&txs, &res, &loaded_accounts, &mut accounts);
+        self.store_accounts(&txs, &mut res, &loaded_accounts);
+        drop(locks);
         self.update_transaction_statuses(&txs, &res);
+        self.update_votes(&txs, &res);
         let write_elapsed = now.elapsed();
         debug!(
             "load: {}us execution: {}us write: {}us txs_len={}",
@@ -517,7 +767,7 @@ impl Bank {
         }
         let cur_tx_count = self.transaction_count.load(Ordering::Relaxed);
         if ((cur_tx_count + tx_count) & !(262_144 - 1)) > cur_tx_count & !(262_144 - 1) {
-            info!("accounts.len: {}", accounts.len());
+            info!("accounts.len: {}", self.accounts.len());
         }
         self.transaction_count
             .fetch_add(tx_count, Ordering::Relaxed);
@@ -603,28 +853,39 @@ impl Bank {
 
         // The first item in the ledger is required to be an entry with zero num_hashes,
         // which implies its id can be used as the ledger's seed.
-        let entry0 = entries.next().expect("invalid ledger: empty");
+        let entry0 = entries.next().ok_or_else(|| {
+            warn!("invalid ledger: empty");
+            BankError::LedgerVerificationFailed
+        })?;
 
         // The second item in the ledger is a special transaction where the to and from
         // fields are the same. That entry should be treated as a deposit, not a
         // transfer to oneself.
-        let entry1 = entries
-            .next()
-            .expect("invalid ledger: need at least 2 entries");
+        let entry1 = entries.next().ok_or_else(|| {
+            warn!("invalid ledger: need at least 2 entries");
+            BankError::LedgerVerificationFailed
+        })?;
         {
+            let deposit = entry1
+                .transactions
+                .first()
+                .filter(|tx| SystemProgram::check_id(&tx.program_id))
+                .and_then(|tx| match deserialize(&tx.userdata) {
+                    Ok(SystemProgram::Move { tokens }) => Some(tokens),
+                    _ => None,
+                });
+            let deposit = deposit.ok_or_else(|| {
+                warn!("invalid ledger, needs to start with a contract");
+                BankError::LedgerVerificationFailed
+            })?;
             let tx = &entry1.transactions[0];
-            assert!(SystemProgram::check_id(&tx.program_id), "Invalid ledger");
-            let instruction: SystemProgram = deserialize(&tx.userdata).unwrap();
-            let deposit = if let SystemProgram::Move { tokens } = instruction {
-                Some(tokens)
-            } else {
-                None
-            }.expect("invalid ledger, needs to start with a contract");
             {
-                let mut accounts = self.accounts.write().unwrap();
-                let account = accounts.entry(tx.keys[0]).or_insert_with(Account::default);
+                let mut account = self.load_stored(&tx.keys[0])?.unwrap_or_default();
                 account.tokens += deposit;
                 trace!("applied genesis payment {:?} => {:?}", deposit, account);
+                self.accounts
+                    .store_account(&tx.keys[0], &account)
+                    .map_err(store_error)?;
             }
         }
         self.register_entry_id(&entry0.id);
@@ -637,7 +898,7 @@ impl Bank {
         let mut tail_idx = 2;
         let entry_count = self.process_blocks(entry1_id, entries, &mut tail, &mut tail_idx)?;
 
-        // check f we need to rotate tail
+        // check if we need to rotate tail
         if tail.len() == WINDOW_SIZE as usize {
             tail.rotate_left(tail_idx)
         }
@@ -645,6 +906,105 @@ impl Bank {
         Ok((entry_count, tail))
     }
 
+    /// Process the ledger entries that follow the checkpoint the bank was restored from.
+    /// `tail` holds the entries before them, the last `WINDOW_SIZE` at most, and the
+    /// return is the same as `process_ledger`'s.
+    pub fn process_ledger_tail<I>(
+        &self,
+        mut tail: Vec<Entry>,
+        entries: I,
+    ) -> Result<(u64, Vec<Entry>)>
+    where
+        I: IntoIterator<Item = Entry>,
+    {
+        let start_hash = self.last_id();
+        if tail.len() > WINDOW_SIZE as usize
+            || tail.last().map(|entry| entry.id) != Some(start_hash)
+        {
+            warn!("ledger does not match the checkpoint at entry: {}", self.entry_height());
+            return Err(BankError::LedgerVerificationFailed);
+        }
+
+        let mut tail_idx = tail.len() % WINDOW_SIZE as usize;
+        self.process_blocks(start_hash, entries, &mut tail, &mut tail_idx)?;
+
+        // check if we need to rotate tail
+        if tail.len() == WINDOW_SIZE as usize {
+            tail.rotate_left(tail_idx)
+        }
+
+        Ok((self.entry_height(), tail))
+    }
+
+    /// Hold off checkpoints until the guard is dropped. The leader holds it from
+    /// processing transactions until their entry is recorded, so a checkpoint never
+    /// covers transactions that are not in an entry yet.
+    pub fn recording(&self) -> RwLockReadGuard<()> {
+        self.recording.read().unwrap()
+    }
+
+    /// Write a snapshot of the bank's state to `writer`, waiting for the entries being
+    /// recorded, see `recording`. The `BankCheckpoint` is followed by the accounts,
+    /// which are read from the store one at a time once it has been checkpointed, see
+    /// `AccountStore::checkpoint`.
+    pub fn write_checkpoint<W: Write>(&self, writer: &mut W) -> bincode::Result<BankCheckpoint> {
+        let _recording = self.recording.write().unwrap();
+        self.accounts.checkpoint()?;
+        let keys = self.accounts.ordered_keys();
+        let checkpoint = BankCheckpoint {
+            entry_height: self.entry_height(),
+            transaction_count: self.transaction_count() as u64,
+            num_accounts: keys.len() as u64,
+            last_ids: self.last_ids.read().unwrap().clone(),
+            last_ids_sigs: self.last_ids_sigs.read().unwrap().clone(),
+            hash: self.hash_accounts(&keys)?,
+        };
+        serialize_into(&mut *writer, &checkpoint)?;
+        for key in &keys {
+            serialize_into(&mut *writer, &(key, self.stored_account(key)?))?;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Restore a bank from a checkpoint written by `write_checkpoint`, keeping its
+    /// accounts in `accounts`. Only the accounts that differ from the store's are
+    /// written, and the ones the checkpoint doesn't have are purged. The restored
+    /// accounts have to hash to the state the checkpoint was taken with.
+    pub fn read_checkpoint<R: Read>(
+        reader: &mut R,
+        accounts: Box<AccountStore>,
+        is_leader: bool,
+    ) -> crate::result::Result<Self> {
+        let checkpoint: BankCheckpoint = deserialize_from(&mut *reader)?;
+        let mut bank = Bank::new_default(is_leader);
+        bank.accounts = accounts;
+        let mut restored = HashSet::new();
+        for _ in 0..checkpoint.num_accounts {
+            let (pubkey, account): (Pubkey, Account) = deserialize_from(&mut *reader)?;
+            let stored = bank.accounts.get_account(&pubkey)?;
+            if stored.map(|stored| serialize(&stored).unwrap()) != Some(serialize(&account)?) {
+                bank.accounts.store_account(&pubkey, &account)?;
+            }
+            restored.insert(pubkey);
+        }
+        for pubkey in bank.accounts.ordered_keys() {
+            if !restored.contains(&pubkey) {
+                bank.accounts.store_account(&pubkey, &Account::default())?;
+            }
+        }
+        *bank.last_ids.write().unwrap() = checkpoint.last_ids;
+        *bank.last_ids_sigs.write().unwrap() = checkpoint.last_ids_sigs;
+        bank.transaction_count
+            .store(checkpoint.transaction_count as usize, Ordering::Relaxed);
+        bank.entry_height
+            .store(checkpoint.entry_height as usize, Ordering::Relaxed);
+        if bank.hash_internal_state()? != checkpoint.hash {
+            warn!("checkpoint at entry {} failed verification", checkpoint.entry_height);
+            Err(BankError::CheckpointVerificationFailed)?;
+        }
+        Ok(bank)
+    }
+
     /// Create, sign, and process a Transaction from `keypair` to `to` of
     /// `n` tokens where `last_id` is the last Entry ID observed by the client.
     pub fn transfer(
@@ -676,12 +1036,32 @@ impl Bank {
             .unwrap_or(0)
     }
 
+    /// The account at `pubkey`, None when it has no tokens or the store fails to read it.
     pub fn get_account(&self, pubkey: &Pubkey) -> Option<Account> {
-        let accounts = self
-            .accounts
-            .read()
-            .expect("'accounts' read lock in get_balance");
-        accounts.get(pubkey).cloned()
+        self.load_stored(pubkey).unwrap_or(None)
+    }
+
+    /// The stake of each of the nodes `ids`, the balance of its account if it voted
+    /// within the last `MAX_ENTRY_IDS` entries, 0 otherwise
+    pub fn get_stakes(&self, ids: &[Pubkey]) -> HashMap<Pubkey, u64> {
+        let entry_height = self.entry_height();
+        let votes = self.votes.read().unwrap();
+        ids.iter()
+            .map(|id| {
+                let voted = votes.get(id).map_or(false, |height| {
+                    height + MAX_ENTRY_IDS as u64 >= entry_height
+                });
+                let stake = if voted {
+                    self.get_balance(id).max(0) as u64
+                } else {
+                    0
+                };
+                (*id, stake)
+            }).collect()
+    }
+
+    pub fn entry_height(&self) -> u64 {
+        self.entry_height.load(Ordering::Relaxed) as u64
     }
 
     pub fn transaction_count(&self) -> usize {
@@ -702,14 +1082,31 @@ impl Bank {
         self.get_signature_status(signature) != Err(BankError::SignatureNotFound)
     }
 
-    /// Hash the `accounts` HashMap. This represents a validator's interpretation
+    /// Hash the `accounts` store. This represents a validator's interpretation
     ///  of the ledger up to the `last_id`, to be sent back to the leader when voting.
-    pub fn hash_internal_state(&self) -> Hash {
-        let mut ordered_accounts = BTreeMap::new();
-        for (pubkey, account) in self.accounts.read().unwrap().iter() {
-            ordered_accounts.insert(*pubkey, account.clone());
+    pub fn hash_internal_state(&self) -> io::Result<Hash> {
+        let _recording = self.recording.write().unwrap();
+        self.hash_accounts(&self.accounts.ordered_keys())
+    }
+
+    /// Hash the accounts of the ordered `keys` as they serialize as a map, reading
+    /// them one at a time.
+    fn hash_accounts(&self, keys: &[Pubkey]) -> io::Result<Hash> {
+        let mut hasher = Hasher::default();
+        hasher.hash(&serialize(&(keys.len() as u64)).unwrap());
+        for key in keys {
+            hasher.hash(&serialize(&(key, self.stored_account(key)?)).unwrap());
         }
-        hash(&serialize(&ordered_accounts).unwrap())
+        Ok(hasher.result())
+    }
+
+    fn stored_account(&self, pubkey: &Pubkey) -> io::Result<Account> {
+        self.accounts.get_account(pubkey)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("account {} is not in the store", pubkey),
+            )
+        })
     }
 
     pub fn finality(&self) -> usize {
@@ -724,16 +1121,22 @@ impl Bank {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::account_store::AppendAccountStore;
     use bincode::serialize;
+    use crate::crdt::NodeInfo;
     use crate::entry::next_entry;
     use crate::entry::Entry;
     use crate::entry_writer::{self, EntryWriter};
     use crate::hash::hash;
     use ledger;
+    use crate::loader_program::{ProgramKind, PROGRAM_HEADER_SIZE};
+    use crate::loader_transaction::LoaderTransaction;
     use crate::logger;
+    use crate::result::Error;
     use crate::signature::{GenKeys, KeypairUtil};
     use std;
     use std::io::{BufReader, Cursor, Seek, SeekFrom};
+    use std::sync::Arc;
 
     #[test]
     fn test_bank_new() {
@@ -849,6 +1252,48 @@ mod tests {
         assert_eq!(bank.get_balance(&pubkey), 500);
     }
 
+    #[test]
+    fn test_get_stakes() {
+        use crate::budget_instruction::{Vote, VoteSignature};
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let unknown = Keypair::new().pubkey();
+        bank.transfer(500, &mint.keypair(), pubkey, mint.last_id())
+            .unwrap();
+
+        // only voters have a stake
+        let stakes = bank.get_stakes(&[mint.pubkey(), pubkey, unknown]);
+        assert_eq!(stakes.len(), 3);
+        assert_eq!(stakes[&pubkey], 0);
+
+        let vote = Vote {
+            version: 1,
+            contact_info_version: 0,
+        };
+        let tx = Transaction::budget_new_vote(
+            &keypair,
+            vote,
+            VoteSignature::default(),
+            mint.last_id(),
+            0,
+        );
+        bank.process_transaction(&tx).unwrap();
+        let stakes = bank.get_stakes(&[mint.pubkey(), pubkey, unknown]);
+        assert_eq!(stakes[&mint.pubkey()], 0);
+        assert_eq!(stakes[&pubkey], 500);
+        assert_eq!(stakes[&unknown], 0);
+
+        // until their last vote is older than the last ids the bank keeps
+        for i in 0..MAX_ENTRY_IDS {
+            bank.register_entry_id(&hash(&serialize(&i).unwrap()));
+        }
+        assert_eq!(bank.get_stakes(&[pubkey])[&pubkey], 500);
+        bank.register_entry_id(&Hash::default());
+        assert_eq!(bank.get_stakes(&[pubkey])[&pubkey], 0);
+    }
+
     #[test]
     fn test_duplicate_transaction_signature() {
         let mint = Mint::new(1);
@@ -1030,6 +1475,29 @@ mod tests {
         assert_eq!(bank.last_id(), last_entry.id);
     }
 
+    #[test]
+    fn test_process_ledger_without_genesis() {
+        let bank = Bank::default();
+        assert_eq!(
+            bank.process_ledger(vec![]),
+            Err(BankError::LedgerVerificationFailed)
+        );
+
+        // a ledger that starts after its genesis entries, like a pruned one
+        let (ledger, _) = create_sample_ledger(1);
+        let bank = Bank::default();
+        assert_eq!(
+            bank.process_ledger(ledger.skip(2)),
+            Err(BankError::LedgerVerificationFailed)
+        );
+        let ticks = vec![Entry::new_tick(0, &Hash::default()); 2];
+        let bank = Bank::default();
+        assert_eq!(
+            bank.process_ledger(ticks),
+            Err(BankError::LedgerVerificationFailed)
+        );
+    }
+
     #[test]
     fn test_process_ledger_around_window_size() {
         // TODO: put me back in when Criterion is up
@@ -1083,6 +1551,329 @@ mod tests {
         assert_eq!(bank.get_balance(&mint.pubkey()), 1);
     }
 
+    fn restore_checkpoint(bank: &Bank, is_leader: bool) -> Bank {
+        let mut data = vec![];
+        bank.write_checkpoint(&mut data).unwrap();
+        let accounts = Box::new(MemoryAccountStore::default());
+        Bank::read_checkpoint(&mut &data[..], accounts, is_leader).unwrap()
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let mint = Mint::new(10_000);
+        let ledger = mint.create_entries().into_iter().chain(create_sample_block(&mint, 5));
+        let (ledger, dup) = ledger.tee();
+        let bank = Bank::default();
+        bank.process_ledger(ledger).unwrap();
+
+        let checkpoint = bank.write_checkpoint(&mut std::io::sink()).unwrap();
+        assert_eq!(checkpoint.entry_height, 7);
+        assert_eq!(checkpoint.last_id(), Some(bank.last_id()));
+        assert_eq!(checkpoint.num_accounts, 1);
+
+        let restored = restore_checkpoint(&bank, false);
+        assert!(!restored.is_leader);
+        assert!(restore_checkpoint(&bank, true).is_leader);
+        assert_eq!(
+            restored.hash_internal_state().unwrap(),
+            bank.hash_internal_state().unwrap()
+        );
+        assert_eq!(restored.entry_height(), bank.entry_height());
+        assert_eq!(restored.transaction_count(), bank.transaction_count());
+        assert_eq!(restored.last_id(), bank.last_id());
+        assert_eq!(restored.get_balance(&mint.pubkey()), 10_000 - 5);
+
+        // transactions already processed are still rejected
+        let entries: Vec<_> = dup.collect();
+        let tx = &entries.last().unwrap().transactions[0];
+        assert_eq!(
+            restored.process_transaction(tx),
+            Err(BankError::DuplicateSignature)
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_tampered() {
+        let (ledger, pubkey) = create_sample_ledger(1);
+        let bank = Bank::default();
+        bank.process_ledger(ledger).unwrap();
+
+        let mut data = vec![];
+        let checkpoint = bank.write_checkpoint(&mut data).unwrap();
+        let mut tampered = serialize(&checkpoint).unwrap();
+        let mut accounts = &data[tampered.len()..];
+        for _ in 0..checkpoint.num_accounts {
+            let (key, mut account): (Pubkey, Account) = deserialize_from(&mut accounts).unwrap();
+            if key == pubkey {
+                account.tokens += 1;
+            }
+            serialize_into(&mut tampered, &(key, account)).unwrap();
+        }
+        let accounts = Box::new(MemoryAccountStore::default());
+        assert_matches!(
+            Bank::read_checkpoint(&mut &tampered[..], accounts, false),
+            Err(Error::BankError(BankError::CheckpointVerificationFailed))
+        );
+
+        // a checkpoint cut short doesn't restore either
+        let accounts = Box::new(MemoryAccountStore::default());
+        assert_matches!(
+            Bank::read_checkpoint(&mut &data[..data.len() - 1], accounts, false),
+            Err(Error::Serialize(_))
+        );
+    }
+
+    #[test]
+    fn test_process_ledger_tail() {
+        let mint = Mint::new(10_000);
+        let entries: Vec<_> = mint
+            .create_entries()
+            .into_iter()
+            .chain(create_sample_block(&mint, 5))
+            .collect();
+
+        let bank = Bank::default();
+        let (ledger_height, tail) = bank.process_ledger(entries.clone()).unwrap();
+
+        let checkpointed = Bank::default();
+        checkpointed.process_ledger(entries[..4].to_vec()).unwrap();
+        let resumed = restore_checkpoint(&checkpointed, false);
+        assert_eq!(
+            resumed.process_ledger_tail(entries[..4].to_vec(), entries[4..].to_vec()),
+            Ok((ledger_height, tail))
+        );
+        assert_eq!(
+            resumed.hash_internal_state().unwrap(),
+            bank.hash_internal_state().unwrap()
+        );
+
+        // the tail has to end at the checkpoint
+        let resumed = restore_checkpoint(&checkpointed, false);
+        assert_eq!(
+            resumed.process_ledger_tail(entries[..3].to_vec(), entries[4..].to_vec()),
+            Err(BankError::LedgerVerificationFailed)
+        );
+    }
+
+    #[test]
+    fn test_append_account_store() {
+        let mint = Mint::new(10_000);
+        let path = std::path::PathBuf::from(format!(
+            "/tmp/tmp-accounts-test_bank_append_account_store-{}",
+            mint.pubkey()
+        ));
+        let bank = Bank::new_with_account_store(
+            Box::new(AppendAccountStore::open(&path).unwrap()),
+            false,
+        ).unwrap();
+        assert!(!bank.is_leader);
+        let memory_bank = Bank::default();
+        let ledger: Vec<_> = mint
+            .create_entries()
+            .into_iter()
+            .chain(create_sample_block(&mint, 3))
+            .collect();
+        bank.process_ledger(ledger.clone()).unwrap();
+        memory_bank.process_ledger(ledger).unwrap();
+        let mut checkpoint = vec![];
+        memory_bank.write_checkpoint(&mut checkpoint).unwrap();
+
+        let pubkey = Keypair::new().pubkey();
+        bank.transfer(1_000, &mint.keypair(), pubkey, mint.last_id())
+            .unwrap();
+        memory_bank
+            .transfer(1_000, &mint.keypair(), pubkey, mint.last_id())
+            .unwrap();
+        assert_eq!(bank.get_balance(&pubkey), 1_000);
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000 - 3 - 1_000);
+        assert_eq!(
+            bank.hash_internal_state().unwrap(),
+            memory_bank.hash_internal_state().unwrap()
+        );
+        drop(bank);
+
+        // the reopened store is ahead of the checkpoint and is brought back to it
+        let accounts = AppendAccountStore::open(&path).unwrap();
+        let account = accounts.get_account(&pubkey).unwrap().unwrap();
+        assert_eq!(account.tokens, 1_000);
+        let restored =
+            Bank::read_checkpoint(&mut &checkpoint[..], Box::new(accounts), false).unwrap();
+        assert_eq!(restored.get_balance(&pubkey), 0);
+        assert_eq!(restored.get_balance(&mint.pubkey()), 10_000 - 3);
+        drop(restored);
+
+        // a new bank starts without the accounts of the store
+        let accounts = Box::new(AppendAccountStore::open(&path).unwrap());
+        let bank = Bank::new_with_account_store(accounts, false).unwrap();
+        assert_eq!(bank.get_balance(&mint.pubkey()), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_process_transactions_concurrently() {
+        let mint = Mint::new(10_000);
+        let bank = Arc::new(Bank::new(&mint));
+        let keypairs: Vec<_> = (0..4).map(|_| Keypair::new()).collect();
+        for keypair in &keypairs {
+            bank.transfer(100, &mint.keypair(), keypair.pubkey(), mint.last_id())
+                .unwrap();
+        }
+
+        // batches from every keypair share the destination account
+        let to = Keypair::new().pubkey();
+        let threads: Vec<_> = keypairs
+            .into_iter()
+            .map(|keypair| {
+                let bank = bank.clone();
+                let last_id = mint.last_id();
+                std::thread::spawn(move || {
+                    // distinct amounts keep the signatures distinct
+                    for tokens in 1..5 {
+                        let tx = Transaction::system_new(&keypair, to, tokens, last_id);
+                        assert_eq!(bank.process_transactions(&[tx]), vec![Ok(())]);
+                    }
+                })
+            }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(bank.get_balance(&to), 40);
+        assert!(bank.account_locks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fees_paid_to_leader() {
+        let mint = Mint::new(10_000);
+        let leader = Keypair::new();
+        let bank = Bank::new(&mint);
+        bank.transfer(1, &mint.keypair(), leader.pubkey(), mint.last_id())
+            .unwrap();
+
+        let pubkey = Keypair::new().pubkey();
+        let tx = Transaction::system_move(&mint.keypair(), pubkey, 100, mint.last_id(), 3);
+        let batch = Transaction::hash(&[tx.clone()]);
+        let collect = Transaction::system_collect_fees(&leader, batch, mint.last_id());
+        assert_eq!(
+            bank.process_transactions(&[collect, tx]),
+            vec![Ok(()), Ok(())]
+        );
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000 - 1 - 100 - 3);
+        assert_eq!(bank.get_balance(&leader.pubkey()), 1 + 3);
+
+        // only the first transaction of a batch collects its fees, otherwise they are burned
+        let tx = Transaction::system_move(&mint.keypair(), pubkey, 100, mint.last_id(), 2);
+        let collect = Transaction::system_collect_fees(&leader, hash(b"late"), mint.last_id());
+        assert_eq!(
+            bank.process_transactions(&[tx, collect]),
+            vec![Ok(()), Ok(())]
+        );
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000 - 1 - 200 - 5);
+        assert_eq!(bank.get_balance(&leader.pubkey()), 1 + 3);
+    }
+
+    #[test]
+    fn test_fees_paid_to_scheduled_leader() {
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let leader = Keypair::new();
+        let mut crdt = Crdt::new(NodeInfo::new_localhost(leader.pubkey())).unwrap();
+        crdt.set_scheduled_leader(0, leader.pubkey());
+        bank.set_leader_scheduler(&Arc::new(RwLock::new(crdt)));
+
+        // the leader doesn't need an account to be paid
+        let pubkey = Keypair::new().pubkey();
+        let tx = Transaction::system_move(&mint.keypair(), pubkey, 100, mint.last_id(), 3);
+        let batch = Transaction::hash(&[tx.clone()]);
+        let collect = Transaction::system_collect_fees(&leader, batch, mint.last_id());
+        assert_eq!(
+            bank.process_transactions(&[collect, tx]),
+            vec![Ok(()), Ok(())]
+        );
+        assert_eq!(bank.get_balance(&leader.pubkey()), 3);
+
+        // any other node collecting burns the fees
+        let other = Keypair::new();
+        let tx = Transaction::system_move(&mint.keypair(), pubkey, 100, mint.last_id(), 2);
+        let batch = Transaction::hash(&[tx.clone()]);
+        let collect = Transaction::system_collect_fees(&other, batch, mint.last_id());
+        assert_eq!(
+            bank.process_transactions(&[collect, tx]),
+            vec![Err(BankError::UnscheduledLeader), Ok(())]
+        );
+        assert_eq!(bank.get_balance(&other.pubkey()), 0);
+        assert_eq!(bank.get_balance(&leader.pubkey()), 3);
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000 - 200 - 5);
+    }
+
+    #[test]
+    fn test_fees_of_failed_transaction() {
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let keypair = Keypair::new();
+        bank.transfer(10, &mint.keypair(), keypair.pubkey(), mint.last_id())
+            .unwrap();
+
+        // overdrawn, the transaction is not recorded in the ledger and neither is its fee
+        let tx = Transaction::system_move(&keypair, mint.pubkey(), 20, mint.last_id(), 2);
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::ResultWithNegativeTokens)
+        );
+        assert_eq!(bank.get_balance(&keypair.pubkey()), 10);
+
+        let tx = Transaction::system_move(&keypair, mint.pubkey(), 1, mint.last_id(), -5);
+        assert_eq!(bank.process_transaction(&tx), Err(BankError::NegativeFee));
+        let tx = Transaction::system_move(&keypair, mint.pubkey(), 1, mint.last_id(), 11);
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::InsufficientFundsForFee)
+        );
+        assert_eq!(bank.get_balance(&keypair.pubkey()), 10);
+    }
+
+    #[test]
+    fn test_fees_replayed() {
+        let mint = Mint::new(10_000);
+        let leader = Keypair::new();
+        let mut entries = mint.create_entries();
+        let mut id = mint.last_id();
+        let mut num_hashes = 0;
+        let tx = Transaction::system_new(&mint.keypair(), leader.pubkey(), 1, mint.last_id());
+        entries.extend(ledger::next_entries_mut(&mut id, &mut num_hashes, vec![tx]));
+        for fee in 1..8 {
+            let tx = Transaction::system_move(
+                &mint.keypair(),
+                Keypair::new().pubkey(),
+                1,
+                mint.last_id(),
+                fee,
+            );
+            let batch = Transaction::hash(&[tx.clone()]);
+            let collect = Transaction::system_collect_fees(&leader, batch, mint.last_id());
+            entries.extend(ledger::next_entries_mut(
+                &mut id,
+                &mut num_hashes,
+                vec![collect, tx],
+            ));
+        }
+        // an entry without a collection burns its fees
+        let tx = Transaction::system_move(&mint.keypair(), leader.pubkey(), 1, mint.last_id(), 8);
+        entries.extend(ledger::next_entries_mut(&mut id, &mut num_hashes, vec![tx]));
+
+        let bank = Bank::default();
+        bank.process_ledger(entries.clone()).unwrap();
+        assert_eq!(bank.get_balance(&leader.pubkey()), 1 + 28 + 1);
+        assert_eq!(bank.get_balance(&mint.pubkey()), 10_000 - 1 - 7 - 28 - 1 - 8);
+
+        // the ledger alone decides who is paid
+        let replayed = Bank::default();
+        replayed.process_ledger(entries).unwrap();
+        assert_eq!(
+            replayed.hash_internal_state().unwrap(),
+            bank.hash_internal_state().unwrap()
+        );
+    }
+
     #[test]
     fn test_new_default() {
         let def_bank = Bank::default();
@@ -1106,19 +1897,22 @@ mod tests {
         let bank1 = Bank::default();
         bank1.process_ledger(ledger1).unwrap();
 
-        let initial_state = bank0.hash_internal_state();
+        let initial_state = bank0.hash_internal_state().unwrap();
 
-        assert_eq!(bank1.hash_internal_state(), initial_state);
+        assert_eq!(bank1.hash_internal_state().unwrap(), initial_state);
 
         let pubkey = keypairs[0].pubkey();
         bank0
             .transfer(1_000, &mint.keypair(), pubkey, mint.last_id())
             .unwrap();
-        assert_ne!(bank0.hash_internal_state(), initial_state);
+        assert_ne!(bank0.hash_internal_state().unwrap(), initial_state);
         bank1
             .transfer(1_000, &mint.keypair(), pubkey, mint.last_id())
             .unwrap();
-        assert_eq!(bank0.hash_internal_state(), bank1.hash_internal_state());
+        assert_eq!(
+            bank0.hash_internal_state().unwrap(),
+            bank1.hash_internal_state().unwrap()
+        );
     }
     #[test]
     fn test_finality() {
@@ -1142,4 +1936,205 @@ mod tests {
         );
         assert!(bank.process_transaction(&tx).is_err());
     }
+
+    fn deploy_program(bank: &Bank, mint: &Mint, kind: ProgramKind, code: &[u8]) -> Keypair {
+        let program = Keypair::new();
+        let tx = Transaction::system_create(
+            &mint.keypair(),
+            program.pubkey(),
+            mint.last_id(),
+            1,
+            (PROGRAM_HEADER_SIZE + code.len()) as u64,
+            LoaderProgram::id(),
+            0,
+        );
+        bank.process_transaction(&tx).unwrap();
+        let tx = Transaction::loader_write(&program, 0, code.to_vec(), mint.last_id(), 0);
+        bank.process_transaction(&tx).unwrap();
+        let tx = Transaction::loader_finalize(&program, kind, mint.last_id(), 0);
+        bank.process_transaction(&tx).unwrap();
+        program
+    }
+
+    #[test]
+    fn test_deployed_bpf_program() {
+        // move a token from the first account to the second, both without userdata
+        let move_token = vec![
+            0x79, 0x12, 40, 0, 0, 0, 0, 0, // ldxdw r2, [r1+40]
+            0x17, 0x02, 0, 0, 1, 0, 0, 0, // sub64 r2, 1
+            0x7b, 0x21, 40, 0, 0, 0, 0, 0, // stxdw [r1+40], r2
+            0x79, 0x13, 120, 0, 0, 0, 0, 0, // ldxdw r3, [r1+120]
+            0x07, 0x03, 0, 0, 1, 0, 0, 0, // add64 r3, 1
+            0x7b, 0x31, 120, 0, 0, 0, 0, 0, // stxdw [r1+120], r3
+            0xb7, 0x00, 0, 0, 0, 0, 0, 0, // mov64 r0, 0
+            0x95, 0x00, 0, 0, 0, 0, 0, 0, // exit
+        ];
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let program = deploy_program(&bank, &mint, ProgramKind::Bpf, &move_token);
+
+        // an account the program can spend from
+        let alice = Keypair::new();
+        let bob = Keypair::new().pubkey();
+        let tx = Transaction::system_create(
+            &mint.keypair(),
+            alice.pubkey(),
+            mint.last_id(),
+            10,
+            0,
+            program.pubkey(),
+            0,
+        );
+        bank.process_transaction(&tx).unwrap();
+
+        let tx = Transaction::new(&alice, &[bob], program.pubkey(), vec![], mint.last_id(), 0);
+        bank.process_transaction(&tx).unwrap();
+        assert_eq!(bank.get_balance(&alice.pubkey()), 9);
+        assert_eq!(bank.get_balance(&bob), 1);
+
+        // a program that creates tokens is stopped by the bank
+        let unbalanced = deploy_program(&bank, &mint, ProgramKind::Bpf, &move_token[24..]);
+        let tx = Transaction::new(&alice, &[bob], unbalanced.pubkey(), vec![1], mint.last_id(), 0);
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::UnbalancedTransaction)
+        );
+        assert_eq!(bank.get_balance(&bob), 1);
+
+        // a native program only runs where its library was installed
+        let native = Keypair::new();
+        let tx = Transaction::system_create(
+            &mint.keypair(),
+            native.pubkey(),
+            mint.last_id(),
+            1,
+            (PROGRAM_HEADER_SIZE + move_token.len()) as u64,
+            LoaderProgram::id(),
+            0,
+        );
+        bank.process_transaction(&tx).unwrap();
+        let tx = Transaction::loader_finalize(&native, ProgramKind::Native, mint.last_id(), 0);
+        bank.process_transaction(&tx).unwrap();
+        let tx = Transaction::new(&alice, &[bob], native.pubkey(), vec![2], mint.last_id(), 0);
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::ProgramRuntimeError)
+        );
+
+        // and a library that isn't installed can't be loaded either
+        let load = Keypair::new().pubkey();
+        let tx = Transaction::system_load(
+            &mint.keypair(),
+            mint.last_id(),
+            0,
+            load,
+            "move_funds".to_string(),
+        );
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::ProgramRuntimeError)
+        );
+    }
+
+    #[test]
+    fn test_deployed_program_overflowing_balances() {
+        // set the first two accounts to i64::MAX and the third to the old total
+        // plus 2, which an i64 sum of the balances wraps back to the old total
+        let overflow = vec![
+            0x79, 0x13, 40, 0, 0, 0, 0, 0, // ldxdw r3, [r1+40]
+            0x79, 0x14, 120, 0, 0, 0, 0, 0, // ldxdw r4, [r1+120]
+            0x0f, 0x43, 0, 0, 0, 0, 0, 0, // add64 r3, r4
+            0x79, 0x14, 200, 0, 0, 0, 0, 0, // ldxdw r4, [r1+200]
+            0x0f, 0x43, 0, 0, 0, 0, 0, 0, // add64 r3, r4
+            0x07, 0x03, 0, 0, 2, 0, 0, 0, // add64 r3, 2
+            0xb7, 0x02, 0, 0, 0xff, 0xff, 0xff, 0xff, // mov64 r2, -1
+            0x77, 0x02, 0, 0, 1, 0, 0, 0, // rsh64 r2, 1
+            0x7b, 0x21, 40, 0, 0, 0, 0, 0, // stxdw [r1+40], r2
+            0x7b, 0x21, 120, 0, 0, 0, 0, 0, // stxdw [r1+120], r2
+            0x7b, 0x31, 200, 0, 0, 0, 0, 0, // stxdw [r1+200], r3
+            0xb7, 0x00, 0, 0, 0, 0, 0, 0, // mov64 r0, 0
+            0x95, 0x00, 0, 0, 0, 0, 0, 0, // exit
+        ];
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let program = deploy_program(&bank, &mint, ProgramKind::Bpf, &overflow);
+
+        let alice = Keypair::new();
+        let carol = Keypair::new();
+        for (keypair, tokens) in &[(&alice, 10), (&carol, 1)] {
+            let tx = Transaction::system_create(
+                &mint.keypair(),
+                keypair.pubkey(),
+                mint.last_id(),
+                *tokens,
+                0,
+                program.pubkey(),
+                0,
+            );
+            bank.process_transaction(&tx).unwrap();
+        }
+        let bob = Keypair::new().pubkey();
+
+        let tx = Transaction::new(
+            &alice,
+            &[carol.pubkey(), bob],
+            program.pubkey(),
+            vec![],
+            mint.last_id(),
+            0,
+        );
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::UnbalancedTransaction)
+        );
+        assert_eq!(bank.get_balance(&alice.pubkey()), 10);
+        assert_eq!(bank.get_balance(&carol.pubkey()), 1);
+        assert_eq!(bank.get_balance(&bob), 0);
+    }
+
+    #[test]
+    fn test_deployed_program_foreign_userdata() {
+        // stb [r1+56], 7, the first byte of the first account's userdata
+        let write_userdata = vec![
+            0x72, 0x01, 56, 0, 7, 0, 0, 0, //
+            0xb7, 0x00, 0, 0, 0, 0, 0, 0, //
+            0x95, 0x00, 0, 0, 0, 0, 0, 0, //
+        ];
+        let mint = Mint::new(10_000);
+        let bank = Bank::new(&mint);
+        let program = deploy_program(&bank, &mint, ProgramKind::Bpf, &write_userdata);
+
+        let create = |keypair: &Keypair, program_id: Pubkey| {
+            let tx = Transaction::system_create(
+                &mint.keypair(),
+                keypair.pubkey(),
+                mint.last_id(),
+                1,
+                1,
+                program_id,
+                0,
+            );
+            bank.process_transaction(&tx).unwrap();
+        };
+
+        // the program writes the userdata of its own accounts
+        let own = Keypair::new();
+        create(&own, program.pubkey());
+        let tx = Transaction::new(&own, &[], program.pubkey(), vec![], mint.last_id(), 0);
+        bank.process_transaction(&tx).unwrap();
+        assert_eq!(bank.get_account(&own.pubkey()).unwrap().userdata, vec![7]);
+
+        // but not the state of another program's account
+        let foreign = Keypair::new();
+        create(&foreign, StorageProgram::id());
+        let tx = Transaction::new(&foreign, &[], program.pubkey(), vec![], mint.last_id(), 0);
+        assert_eq!(
+            bank.process_transaction(&tx),
+            Err(BankError::ExternalAccountUserdataModified)
+        );
+        assert_eq!(
+            bank.get_account(&foreign.pubkey()).unwrap().userdata,
+            vec![0]
+        );
+    }
 }
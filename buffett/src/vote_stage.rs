@@ -33,13 +33,13 @@ pub fn create_new_signed_vote_blob(
     crdt: &Arc<RwLock<Crdt>>,
 ) -> Result<SharedBlob> {
     let shared_blob = SharedBlob::default();
-    let (vote, addr) = {
+    let (vote, signature, addr) = {
         let mut wcrdt = crdt.write().unwrap();
         //TODO: doesn't seem like there is a synchronous call to get height and id
         debug!("voting on {:?}", &last_id.as_ref()[..8]);
         wcrdt.new_vote(*last_id)
     }?;
-    let tx = Transaction::budget_new_vote(&keypair, vote, *last_id, 0);
+    let tx = Transaction::budget_new_vote(&keypair, vote, signature, *last_id, 0);
     {
         let mut blob = shared_blob.write().unwrap();
         let bytes = serialize(&tx)?;
@@ -164,12 +164,13 @@ pub mod tests {
     use super::*;
     use crate::tx_vault::Bank;
     use bincode::deserialize;
-    use crate::budget_instruction::Vote;
+    use crate::budget_instruction::{Vote, VoteSignature};
     use crate::crdt::{Crdt, NodeInfo};
     use crate::entry::next_entry;
     use crate::hash::{hash, Hash};
     use crate::logger;
     use crate::coinery::Mint;
+    use crate::signature::{Keypair, KeypairUtil};
     use std::sync::mpsc::channel;
     use std::sync::{Arc, RwLock};
     use std::thread::sleep;
@@ -177,6 +178,27 @@ pub mod tests {
     use crate::system_transaction::SystemTransaction;
     use crate::transaction::Transaction;
 
+    // The vote of `validator` for `last_id` with the signature `Crdt::new_vote` gives it.
+    fn new_signed_vote(
+        keypair: &Keypair,
+        validator: &NodeInfo,
+        last_id: Hash,
+    ) -> (Vote, VoteSignature) {
+        let mut voted = validator.clone();
+        voted.version += 1;
+        voted.ledger_state.last_id = last_id;
+        voted.sign(keypair);
+        let vote = Vote {
+            version: voted.version,
+            contact_info_version: voted.contact_info.version,
+        };
+        let signature = VoteSignature {
+            leader_id: voted.leader_id,
+            signature: voted.signature,
+        };
+        (vote, signature)
+    }
+
     #[test]
     fn test_send_leader_vote() {
         logger::setup();
@@ -205,23 +227,22 @@ pub mod tests {
         // Insert 7 agreeing validators / 3 disagreeing
         // and votes for new last_id
         for i in 0..10 {
-            let mut validator =
-                NodeInfo::new_with_socketaddr(&format!("127.0.0.1:234{}", i).parse().unwrap());
+            let keypair = Keypair::new();
+            let validator = NodeInfo::new_with_pubkey_socketaddr(
+                keypair.pubkey(),
+                &format!("127.0.0.1:234{}", i).parse().unwrap(),
+            );
 
-            let vote = Vote {
-                version: validator.version + 1,
-                contact_info_version: 1,
-            };
-
-            if i < 7 {
-                validator.ledger_state.last_id = entry.id;
-            }
+            let last_id = if i < 7 { entry.id } else { hash0 };
+            let (vote, signature) = new_signed_vote(&keypair, &validator, last_id);
 
             leader_crdt.insert(&validator);
             trace!("validator id: {:?}", validator.id);
 
-            leader_crdt.insert_vote(&validator.id, &vote, entry.id);
+            leader_crdt.insert_vote(&validator.id, &vote, &signature, last_id);
+            assert_eq!(leader_crdt.table[&validator.id].version, vote.version);
         }
+        assert_eq!(leader_crdt.dropped_records, 0);
         let leader = Arc::new(RwLock::new(leader_crdt));
         let (vote_blob_sender, vote_blob_receiver) = channel();
         let mut last_vote: u64 = timing::timestamp() - VOTE_TIMEOUT_MS - 1;
@@ -245,15 +266,13 @@ pub mod tests {
 
         // add two more nodes and see that it succeeds
         for i in 0..2 {
-            let mut validator =
-                NodeInfo::new_with_socketaddr(&format!("127.0.0.1:234{}", i).parse().unwrap());
-
-            let vote = Vote {
-                version: validator.version + 1,
-                contact_info_version: 1,
-            };
+            let keypair = Keypair::new();
+            let validator = NodeInfo::new_with_pubkey_socketaddr(
+                keypair.pubkey(),
+                &format!("127.0.0.1:234{}", i).parse().unwrap(),
+            );
 
-            validator.ledger_state.last_id = entry.id;
+            let (vote, signature) = new_signed_vote(&keypair, &validator, entry.id);
 
             leader.write().unwrap().insert(&validator);
             trace!("validator id: {:?}", validator.id);
@@ -261,8 +280,9 @@ pub mod tests {
             leader
                 .write()
                 .unwrap()
-                .insert_vote(&validator.id, &vote, entry.id);
+                .insert_vote(&validator.id, &vote, &signature, entry.id);
         }
+        assert_eq!(leader.read().unwrap().dropped_records, 0);
 
         last_vote = timing::timestamp() - VOTE_TIMEOUT_MS - 1;
         let res = send_leader_vote(
@@ -1,5 +1,6 @@
 use bincode::{deserialize, serialize};
 use bs58;
+use crate::budget::{Budget, Condition};
 use crate::budget_program::BudgetState;
 use crate::budget_transaction::BudgetTransaction;
 use chrono::prelude::*;
@@ -8,6 +9,7 @@ use crate::crdt::NodeInfo;
 use crate::token_service::DroneRequest;
 use crate::fullnode::Config;
 use crate::hash::Hash;
+use payment_plan::Payment;
 use reqwest;
 use reqwest::header::CONTENT_TYPE;
 use ring::rand::SystemRandom;
@@ -34,19 +36,22 @@ pub enum WalletCommand {
     Balance,
     Cancel(Pubkey),
     Confirm(Signature),
-    // Pay(tokens, to, timestamp, timestamp_pubkey, witness(es), cancelable)
+    // Pay(tokens, to, timestamp, timestamp_pubkey, witness(es), required signatures,
+    //     splits, cancelable)
     Pay(
         i64,
         Pubkey,
         Option<DateTime<Utc>>,
         Option<Pubkey>,
         Option<Vec<Pubkey>>,
+        Option<u64>,
+        Vec<(Pubkey, i64)>,
         Option<Pubkey>,
     ),
-    // TimeElapsed(to, process_id, timestamp)
-    TimeElapsed(Pubkey, Pubkey, DateTime<Utc>),
-    // Witness(to, process_id)
-    Witness(Pubkey, Pubkey),
+    // TimeElapsed(payees, process_id, timestamp)
+    TimeElapsed(Vec<Pubkey>, Pubkey, DateTime<Utc>),
+    // Witness(payees, process_id)
+    Witness(Vec<Pubkey>, Pubkey),
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +99,30 @@ impl Default for WalletConfig {
     }
 }
 
+fn parse_pubkey(string: &str) -> Result<Pubkey, WalletError> {
+    let pubkey_vec = bs58::decode(string)
+        .into_vec()
+        .map_err(|_| WalletError::BadParameter(format!("Invalid public key {}", string)))?;
+    if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+        Err(WalletError::BadParameter(format!(
+            "Invalid public key {}",
+            string
+        )))?;
+    }
+    Ok(Pubkey::new(&pubkey_vec))
+}
+
+// `to` and the other payees of a contract, which a witness has to give for a payout
+fn parse_payees(to: Pubkey, matches: &ArgMatches) -> Result<Vec<Pubkey>, WalletError> {
+    let mut payees = vec![to];
+    if let Some(values) = matches.values_of("payee") {
+        for payee in values {
+            payees.push(parse_pubkey(payee)?);
+        }
+    }
+    Ok(payees)
+}
+
 pub fn parse_command(
     pubkey: Pubkey,
     matches: &ArgMatches,
@@ -193,6 +222,30 @@ pub fn parse_command(
             } else {
                 None
             };
+            let required_signatures = if pay_matches.is_present("required-signatures") {
+                Some(
+                    pay_matches
+                        .value_of("required-signatures")
+                        .unwrap()
+                        .parse()?,
+                )
+            } else {
+                None
+            };
+            let mut splits = Vec::new();
+            if let Some(values) = pay_matches.values_of("split") {
+                for split in values {
+                    let mut parts = split.splitn(2, ':');
+                    let payee = parse_pubkey(parts.next().unwrap())?;
+                    let split_tokens = parts
+                        .next()
+                        .ok_or_else(|| {
+                            WalletError::BadParameter(format!("Invalid split {}", split))
+                        })?
+                        .parse()?;
+                    splits.push((payee, split_tokens));
+                }
+            }
             let cancelable = if pay_matches.is_present("cancelable") {
                 Some(pubkey)
             } else {
@@ -205,6 +258,8 @@ pub fn parse_command(
                 timestamp,
                 timestamp_pubkey,
                 witness_vec,
+                required_signatures,
+                splits,
                 cancelable,
             ))
         }
@@ -228,7 +283,8 @@ pub fn parse_command(
                 Err(WalletError::BadParameter("Invalid public key".to_string()))?;
             }
             let process_id = Pubkey::new(&pubkey_vec);
-            Ok(WalletCommand::Witness(to, process_id))
+            let payees = parse_payees(to, sig_matches)?;
+            Ok(WalletCommand::Witness(payees, process_id))
         }
         ("send-timestamp", Some(timestamp_matches)) => {
             let pubkey_vec = bs58::decode(timestamp_matches.value_of("to").unwrap())
@@ -265,7 +321,8 @@ pub fn parse_command(
             } else {
                 Utc::now()
             };
-            Ok(WalletCommand::TimeElapsed(to, process_id, dt))
+            let payees = parse_payees(to, timestamp_matches)?;
+            Ok(WalletCommand::TimeElapsed(payees, process_id, dt))
         }
         ("", None) => {
             eprintln!("{}", matches.usage());
@@ -284,10 +341,6 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
         WalletCommand::Address => Ok(format!("{}", config.id.pubkey())),
         // Request an airdrop from tokenbots;
         WalletCommand::AirDrop(tokens) => {
-            println!(
-                "Requesting airdrop of {:?} tokens from {}",
-                tokens, config.drone_addr
-            );
             let params = json!(format!("{}", config.id.pubkey()));
             let previous_balance = match WalletRpcRequest::GetBalance
                 .make_rpc_request(&config.rpc_addr, 1, Some(params))?
@@ -314,7 +367,6 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
                 if previous_balance != current_balance {
                     break;
                 }
-                println!(".");
             }
             if current_balance - previous_balance != tokens {
                 Err("Airdrop failed!")?;
@@ -323,17 +375,9 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
         }
         // Check client balance
         WalletCommand::Balance => {
-            println!("Balance requested...");
-            let params = json!(format!("{}", config.id.pubkey()));
-            let balance = WalletRpcRequest::GetBalance
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
-                .as_i64();
-            match balance {
-                Some(0) => Ok("No account found! Request an airdrop to get started.".to_string()),
-                Some(tokens) => Ok(format!("Your balance is: {:?}", tokens)),
-                None => Err(WalletError::RpcRequestError(
-                    "Received result of an unexpected type".to_string(),
-                ))?,
+            match get_balance(&config)? {
+                0 => Ok("No account found! Request an airdrop to get started.".to_string()),
+                tokens => Ok(format!("Your balance is: {:?}", tokens)),
             }
         }
         // Cancel a contract by contract Pubkey
@@ -348,144 +392,114 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
         }
         // Confirm the last client transaction by signature
         WalletCommand::Confirm(signature) => {
-            let params = json!(format!("{}", signature));
-            let confirmation = WalletRpcRequest::ConfirmTransaction
-                .make_rpc_request(&config.rpc_addr, 1, Some(params))?
-                .as_bool();
-            match confirmation {
-                Some(b) => {
-                    if b {
-                        Ok("Confirmed".to_string())
-                    } else {
-                        Ok("Not found".to_string())
-                    }
-                }
-                None => Err(WalletError::RpcRequestError(
-                    "Received result of an unexpected type".to_string(),
-                ))?,
+            if confirm_transaction(&config, &signature)? {
+                Ok("Confirmed".to_string())
+            } else {
+                Ok("Not found".to_string())
             }
         }
         // If client has positive balance, pay tokens to another address
-        WalletCommand::Pay(tokens, to, timestamp, timestamp_pubkey, ref witnesses, cancelable) => {
+        WalletCommand::Pay(
+            tokens,
+            to,
+            timestamp,
+            timestamp_pubkey,
+            ref witnesses,
+            required_signatures,
+            ref splits,
+            cancelable,
+        ) => {
             let last_id = get_last_id(&config)?;
 
-            if timestamp == None && *witnesses == None {
+            if timestamp == None && *witnesses == None && splits.is_empty() {
                 let tx = Transaction::system_new(&config.id, to, tokens, last_id);
                 let signature_str = serialize_and_send_tx(&config, &tx)?;
-                Ok(signature_str.to_string())
-            } else if *witnesses == None {
-                let dt = timestamp.unwrap();
+                return Ok(signature_str.to_string());
+            }
+
+            // Payments are released in the order: witness signatures, then the timestamp,
+            // so the multisig threshold wraps the timestamp condition.
+            let split_tokens: i64 = splits.iter().map(|(_, split)| split).sum();
+            if split_tokens > tokens {
+                Err(WalletError::BadParameter(
+                    "Split payments exceed the amount being paid".to_string(),
+                ))?;
+            }
+            let mut budget = if splits.is_empty() {
+                Budget::new_payment(tokens, to)
+            } else {
+                let mut payments = vec![Payment {
+                    tokens: tokens - split_tokens,
+                    to,
+                }];
+                payments.extend(splits.iter().map(|&(to, tokens)| Payment { tokens, to }));
+                Budget::new_split_payment(payments)
+            };
+            if let Some(dt) = timestamp {
                 let dt_pubkey = match timestamp_pubkey {
                     Some(pubkey) => pubkey,
                     None => config.id.pubkey(),
                 };
+                budget = Budget::new_after(Condition::Timestamp(dt, dt_pubkey), budget);
+            }
+            if let Some(ref witness_vec) = *witnesses {
+                let m = required_signatures.unwrap_or(witness_vec.len() as u64);
+                budget = Budget::new_multisig(m, witness_vec, budget);
+            }
+            if let Some(from) = cancelable {
+                budget = Budget::new_cancelable(budget, from, tokens);
+            }
+            if !budget.verify(tokens) {
+                Err(WalletError::BadParameter(
+                    "Invalid payment plan".to_string(),
+                ))?;
+            }
 
-                let contract_funds = Keypair::new();
-                let contract_state = Keypair::new();
-                let budget_program_id = BudgetState::id();
-
-                // Create account for contract funds
-                let tx = Transaction::system_create(
-                    &config.id,
-                    contract_funds.pubkey(),
-                    last_id,
-                    tokens,
-                    0,
-                    budget_program_id,
-                    0,
-                );
-                let _signature_str = serialize_and_send_tx(&config, &tx)?;
-
-                // Create account for contract state
-                let tx = Transaction::system_create(
-                    &config.id,
-                    contract_state.pubkey(),
-                    last_id,
-                    1,
-                    196,
-                    budget_program_id,
-                    0,
-                );
-                let _signature_str = serialize_and_send_tx(&config, &tx)?;
-
-                // Initializing contract
-                let tx = Transaction::budget_new_on_date(
-                    &contract_funds,
-                    to,
-                    contract_state.pubkey(),
-                    dt,
-                    dt_pubkey,
-                    cancelable,
-                    tokens,
-                    last_id,
-                );
-                let signature_str = serialize_and_send_tx(&config, &tx)?;
-
-                Ok(json!({
-                    "signature": signature_str,
-                    "processId": format!("{}", contract_state.pubkey()),
-                }).to_string())
-            } else if timestamp == None {
-                let last_id = get_last_id(&config)?;
+            let contract_funds = Keypair::new();
+            let contract_state = Keypair::new();
+            let budget_program_id = BudgetState::id();
 
-                let witness = if let Some(ref witness_vec) = *witnesses {
-                    witness_vec[0]
-                } else {
-                    Err(WalletError::BadParameter(
-                        "Could not parse required signature pubkey(s)".to_string(),
-                    ))?
-                };
+            // Create account for contract funds
+            let tx = Transaction::system_create(
+                &config.id,
+                contract_funds.pubkey(),
+                last_id,
+                tokens,
+                0,
+                budget_program_id,
+                0,
+            );
+            let _signature_str = serialize_and_send_tx(&config, &tx)?;
+
+            // Create account for contract state
+            let tx = Transaction::system_create(
+                &config.id,
+                contract_state.pubkey(),
+                last_id,
+                1,
+                BudgetState::userdata_len(&budget),
+                budget_program_id,
+                0,
+            );
+            let _signature_str = serialize_and_send_tx(&config, &tx)?;
 
-                let contract_funds = Keypair::new();
-                let contract_state = Keypair::new();
-                let budget_program_id = BudgetState::id();
-
-                // Create account for contract funds
-                let tx = Transaction::system_create(
-                    &config.id,
-                    contract_funds.pubkey(),
-                    last_id,
-                    tokens,
-                    0,
-                    budget_program_id,
-                    0,
-                );
-                let _signature_str = serialize_and_send_tx(&config, &tx)?;
-
-                // Create account for contract state
-                let tx = Transaction::system_create(
-                    &config.id,
-                    contract_state.pubkey(),
-                    last_id,
-                    1,
-                    196,
-                    budget_program_id,
-                    0,
-                );
-                let _signature_str = serialize_and_send_tx(&config, &tx)?;
-
-                // Initializing contract
-                let tx = Transaction::budget_new_when_signed(
-                    &contract_funds,
-                    to,
-                    contract_state.pubkey(),
-                    witness,
-                    cancelable,
-                    tokens,
-                    last_id,
-                );
-                let signature_str = serialize_and_send_tx(&config, &tx)?;
+            // Initializing contract
+            let tx = Transaction::budget_new_contract(
+                &contract_funds,
+                contract_state.pubkey(),
+                budget,
+                tokens,
+                last_id,
+            );
+            let signature_str = serialize_and_send_tx(&config, &tx)?;
 
-                Ok(json!({
-                    "signature": signature_str,
-                    "processId": format!("{}", contract_state.pubkey()),
-                }).to_string())
-            } else {
-                Ok("Combo transactions not yet handled".to_string())
-            }
+            Ok(json!({
+                "signature": signature_str,
+                "processId": format!("{}", contract_state.pubkey()),
+            }).to_string())
         }
         // Apply time elapsed to contract
-        WalletCommand::TimeElapsed(to, pubkey, dt) => {
+        WalletCommand::TimeElapsed(ref payees, pubkey, dt) => {
             let params = json!(format!("{}", config.id.pubkey()));
             let balance = WalletRpcRequest::GetBalance
                 .make_rpc_request(&config.rpc_addr, 1, Some(params))?
@@ -496,13 +510,15 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
 
             let last_id = get_last_id(&config)?;
 
-            let tx = Transaction::budget_new_timestamp(&config.id, pubkey, to, dt, last_id);
+            let tx = Transaction::budget_new_timestamp_to_payees(
+                &config.id, pubkey, payees, dt, last_id,
+            );
             let signature_str = serialize_and_send_tx(&config, &tx)?;
 
             Ok(signature_str.to_string())
         }
         // Apply witness signature to contract
-        WalletCommand::Witness(to, pubkey) => {
+        WalletCommand::Witness(ref payees, pubkey) => {
             let last_id = get_last_id(&config)?;
 
             let params = json!(format!("{}", config.id.pubkey()));
@@ -513,7 +529,8 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
                 request_airdrop(&config.drone_addr, &config.id.pubkey(), 1)?;
             }
 
-            let tx = Transaction::budget_new_signature(&config.id, pubkey, to, last_id);
+            let tx =
+                Transaction::budget_new_signature_to_payees(&config.id, pubkey, payees, last_id);
             let signature_str = serialize_and_send_tx(&config, &tx)?;
 
             Ok(signature_str.to_string())
@@ -521,6 +538,37 @@ pub fn process_command(config: &WalletConfig) -> Result<String, Box<error::Error
     }
 }
 
+/// Return the balance of the wallet's keypair, as reported by the leader's rpc service.
+pub fn get_balance(config: &WalletConfig) -> Result<i64, Box<error::Error>> {
+    let params = json!(format!("{}", config.id.pubkey()));
+    let balance = WalletRpcRequest::GetBalance
+        .make_rpc_request(&config.rpc_addr, 1, Some(params))?
+        .as_i64();
+    match balance {
+        Some(tokens) => Ok(tokens),
+        None => Err(WalletError::RpcRequestError(
+            "Received result of an unexpected type".to_string(),
+        ))?,
+    }
+}
+
+/// Return true if the leader has processed the transaction with `signature`.
+pub fn confirm_transaction(
+    config: &WalletConfig,
+    signature: &Signature,
+) -> Result<bool, Box<error::Error>> {
+    let params = json!(format!("{}", signature));
+    let confirmation = WalletRpcRequest::ConfirmTransaction
+        .make_rpc_request(&config.rpc_addr, 1, Some(params))?
+        .as_bool();
+    match confirmation {
+        Some(b) => Ok(b),
+        None => Err(WalletError::RpcRequestError(
+            "Received result of an unexpected type".to_string(),
+        ))?,
+    }
+}
+
 pub fn read_leader(path: &str) -> Result<Config, WalletError> {
     let file = File::open(path.to_string()).or_else(|err| {
         Err(WalletError::BadParameter(format!(
@@ -543,7 +591,29 @@ pub fn request_airdrop(
     tokens: u64,
 ) -> Result<Signature, Error> {
     // TODO: make this async tokio client
-    let mut stream = TcpStream::connect(drone_addr)?;
+    let stream = TcpStream::connect(drone_addr)?;
+    send_airdrop_request(stream, id, tokens)
+}
+
+/// Like `request_airdrop`, but gives up with a `TimedOut` or `WouldBlock` error when
+/// the drone doesn't answer within `timeout`.
+pub fn request_airdrop_with_timeout(
+    drone_addr: &SocketAddr,
+    id: &Pubkey,
+    tokens: u64,
+    timeout: Duration,
+) -> Result<Signature, Error> {
+    let stream = TcpStream::connect_timeout(drone_addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    send_airdrop_request(stream, id, tokens)
+}
+
+fn send_airdrop_request(
+    mut stream: TcpStream,
+    id: &Pubkey,
+    tokens: u64,
+) -> Result<Signature, Error> {
     let req = DroneRequest::GetAirdrop {
         airdrop_request_amount: tokens,
         client_pubkey: *id,
@@ -551,16 +621,16 @@ pub fn request_airdrop(
     let tx = serialize(&req).expect("serialize drone request");
     stream.write_all(&tx)?;
     let mut buffer = [0; size_of::<Signature>()];
-    stream
-        .read_exact(&mut buffer)
-        .or_else(|_| Err(Error::new(ErrorKind::Other, "Airdrop failed")))?;
+    stream.read_exact(&mut buffer).or_else(|err| match err.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => Err(err),
+        _ => Err(Error::new(ErrorKind::Other, "Airdrop failed")),
+    })?;
     let signature: Signature = deserialize(&buffer).or_else(|err| {
         Err(Error::new(
             ErrorKind::Other,
             format!("deserialize signature in request_airdrop: {:?}", err),
         ))
     })?;
-    // TODO: add timeout to this function, in case of unresponsive drone
     Ok(signature)
 }
 
@@ -765,6 +835,21 @@ mod tests {
                             .multiple(true)
                             .use_delimiter(true)
                             .help("Any third party signatures required to unlock the tokens"),
+                    ).arg(
+                        Arg::with_name("required-signatures")
+                            .long("required-signatures")
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .requires("witness")
+                            .help("How many of the third party signatures unlock the tokens, all by default"),
+                    ).arg(
+                        Arg::with_name("split")
+                            .long("split-to")
+                            .value_name("PUBKEY:NUM")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("Pay NUM of the tokens to PUBKEY instead of the recipient"),
                     ).arg(
                         Arg::with_name("cancelable")
                             .long("cancelable")
@@ -787,6 +872,14 @@ mod tests {
                             .takes_value(true)
                             .required(true)
                             .help("The process id of the transfer to authorize"),
+                    ).arg(
+                        Arg::with_name("payee")
+                            .long("payee")
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("Any other payee of the transfer"),
                     ),
             ).subcommand(
                 SubCommand::with_name("send-timestamp")
@@ -805,6 +898,14 @@ mod tests {
                             .takes_value(true)
                             .required(true)
                             .help("The process id of the transfer to unlock"),
+                    ).arg(
+                        Arg::with_name("payee")
+                            .long("payee")
+                            .value_name("PUBKEY")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("Any other payee of the transfer"),
                     ).arg(
                         Arg::with_name("datetime")
                             .long("date")
@@ -867,7 +968,7 @@ mod tests {
                 .get_matches_from(vec!["test", "pay", &pubkey_string, "50"]);
         assert_eq!(
             parse_command(pubkey, &test_pay).unwrap(),
-            WalletCommand::Pay(50, pubkey, None, None, None, None)
+            WalletCommand::Pay(50, pubkey, None, None, None, None, vec![], None)
         );
         let test_bad_pubkey = test_commands
             .clone()
@@ -887,7 +988,16 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_multiple_witnesses).unwrap(),
-            WalletCommand::Pay(50, pubkey, None, None, Some(vec![witness0, witness1]), None)
+            WalletCommand::Pay(
+                50,
+                pubkey,
+                None,
+                None,
+                Some(vec![witness0, witness1]),
+                None,
+                vec![],
+                None
+            )
         );
         let test_pay_single_witness = test_commands.clone().get_matches_from(vec![
             "test",
@@ -899,9 +1009,58 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_single_witness).unwrap(),
-            WalletCommand::Pay(50, pubkey, None, None, Some(vec![witness0]), None)
+            WalletCommand::Pay(
+                50,
+                pubkey,
+                None,
+                None,
+                Some(vec![witness0]),
+                None,
+                vec![],
+                None
+            )
         );
 
+        // Test Pay Subcommand w/ M-of-N Witnesses and Split Payees
+        let test_pay_multisig_split = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--require-signature-from",
+            &witness0_string,
+            "--require-signature-from",
+            &witness1_string,
+            "--required-signatures",
+            "1",
+            "--split-to",
+            &format!("{}:20", witness0_string),
+            "--split-to",
+            &format!("{}:10", witness1_string),
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_pay_multisig_split).unwrap(),
+            WalletCommand::Pay(
+                50,
+                pubkey,
+                None,
+                None,
+                Some(vec![witness0, witness1]),
+                Some(1),
+                vec![(witness0, 20), (witness1, 10)],
+                None
+            )
+        );
+        let test_bad_split = test_commands.clone().get_matches_from(vec![
+            "test",
+            "pay",
+            &pubkey_string,
+            "50",
+            "--split-to",
+            &witness0_string,
+        ]);
+        assert!(parse_command(pubkey, &test_bad_split).is_err());
+
         // Test Pay Subcommand w/ Timestamp
         let test_pay_timestamp = test_commands.clone().get_matches_from(vec![
             "test",
@@ -915,7 +1074,16 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_pay_timestamp).unwrap(),
-            WalletCommand::Pay(50, pubkey, Some(dt), Some(witness0), None, None)
+            WalletCommand::Pay(
+                50,
+                pubkey,
+                Some(dt),
+                Some(witness0),
+                None,
+                None,
+                vec![],
+                None
+            )
         );
 
         // Test Send-Signature Subcommand
@@ -927,7 +1095,21 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_send_signature).unwrap(),
-            WalletCommand::Witness(pubkey, pubkey)
+            WalletCommand::Witness(vec![pubkey], pubkey)
+        );
+        let test_send_signature_payees = test_commands.clone().get_matches_from(vec![
+            "test",
+            "send-signature",
+            &pubkey_string,
+            &pubkey_string,
+            "--payee",
+            &witness0_string,
+            "--payee",
+            &witness1_string,
+        ]);
+        assert_eq!(
+            parse_command(pubkey, &test_send_signature_payees).unwrap(),
+            WalletCommand::Witness(vec![pubkey, witness0, witness1], pubkey)
         );
         let test_pay_multiple_witnesses = test_commands.clone().get_matches_from(vec![
             "test",
@@ -951,6 +1133,8 @@ mod tests {
                 Some(dt),
                 Some(witness0),
                 Some(vec![witness0, witness1]),
+                None,
+                vec![],
                 None
             )
         );
@@ -966,7 +1150,7 @@ mod tests {
         ]);
         assert_eq!(
             parse_command(pubkey, &test_send_timestamp).unwrap(),
-            WalletCommand::TimeElapsed(pubkey, pubkey, dt)
+            WalletCommand::TimeElapsed(vec![pubkey], pubkey, dt)
         );
         let test_bad_timestamp = test_commands.clone().get_matches_from(vec![
             "test",
@@ -1036,7 +1220,7 @@ mod tests {
             format!("{}", config.id.pubkey())
         );
 
-        config.command = WalletCommand::Pay(10, bob_pubkey, None, None, None, None);
+        config.command = WalletCommand::Pay(10, bob_pubkey, None, None, None, None, vec![], None);
         let sig_response = process_command(&config);
         assert!(sig_response.is_ok());
 
@@ -1177,6 +1361,8 @@ mod tests {
             Some(config_witness.id.pubkey()),
             None,
             None,
+            vec![],
+            None,
         );
         let sig_response = process_command(&config_payer);
         assert!(sig_response.is_ok());
@@ -1211,7 +1397,7 @@ mod tests {
         assert_eq!(recipient_balance, 0);
 
         // Sign transaction by config_witness
-        config_witness.command = WalletCommand::TimeElapsed(bob_pubkey, process_id, dt);
+        config_witness.command = WalletCommand::TimeElapsed(vec![bob_pubkey], process_id, dt);
         let sig_response = process_command(&config_witness);
         assert!(sig_response.is_ok());
 
@@ -1296,6 +1482,8 @@ mod tests {
             None,
             Some(vec![config_witness.id.pubkey()]),
             None,
+            vec![],
+            None,
         );
         let sig_response = process_command(&config_payer);
         assert!(sig_response.is_ok());
@@ -1330,7 +1518,7 @@ mod tests {
         assert_eq!(recipient_balance, 0);
 
         // Sign transaction by config_witness
-        config_witness.command = WalletCommand::Witness(bob_pubkey, process_id);
+        config_witness.command = WalletCommand::Witness(vec![bob_pubkey], process_id);
         let sig_response = process_command(&config_witness);
         assert!(sig_response.is_ok());
 
@@ -1414,6 +1602,8 @@ mod tests {
             None,
             None,
             Some(vec![config_witness.id.pubkey()]),
+            None,
+            vec![],
             Some(config_payer.id.pubkey()),
         );
         let sig_response = process_command(&config_payer);
@@ -3,7 +3,6 @@
 use crate::counter::Counter;
 use crate::crdt::{Crdt, NodeInfo};
 use crate::entry::Entry;
-#[cfg(feature = "erasure")]
 use crate::erasure;
 use crate::ledger::{reconstruct_entries_from_blobs, Block};
 use log::Level;
@@ -249,11 +248,8 @@ impl WindowUtil for Window {
         self[w].leader_unknown = leader_unknown;
         *pending_retransmits = true;
 
-        #[cfg(feature = "erasure")]
-        {
-            if erasure::recover(id, self, *consumed, (*consumed % WINDOW_SIZE) as usize).is_err() {
-                trace!("{}: erasure::recover failed", id);
-            }
+        if erasure::recover(id, self, *consumed, (*consumed % WINDOW_SIZE) as usize).is_err() {
+            trace!("{}: erasure::recover failed", id);
         }
 
         // push all contiguous blobs into consumed queue, increment consumed
@@ -3,6 +3,7 @@
 //! stdout, and then sends the Entry to its output channel.
 
 use crate::tx_vault::Bank;
+use crate::checkpoint::{checkpoint_due, commit_checkpoint, stage_checkpoint};
 use crate::counter::Counter;
 use crate::crdt::Crdt;
 use crate::entry::Entry;
@@ -174,6 +175,25 @@ impl WriteStage {
         Ok(())
     }
 
+    // The leader's bank registers entries before they are written, so its checkpoint is
+    // staged once the ledger grows past a checkpoint interval, at the staged height, and
+    // committed once the ledger holds all the entries it covers.
+    fn checkpoint_entries(
+        ledger_path: &str,
+        bank: &Bank,
+        staged: &mut Option<u64>,
+        from_height: u64,
+        entry_height: u64,
+    ) -> Result<()> {
+        if staged.is_none() && checkpoint_due(from_height, entry_height) {
+            *staged = Some(stage_checkpoint(ledger_path, bank)?);
+        }
+        if staged.map_or(false, |height| height <= entry_height) {
+            commit_checkpoint(ledger_path, staged.take().unwrap())?;
+        }
+        Ok(())
+    }
+
     /// Create a new WriteStage for writing and broadcasting entries.
     pub fn new(
         keypair: Arc<Keypair>,
@@ -192,6 +212,7 @@ impl WriteStage {
         );
         let (entry_sender, entry_receiver_forward) = channel();
         let mut ledger_writer = LedgerWriter::recover(ledger_path).unwrap();
+        let ledger_path = ledger_path.to_string();
 
         let write_thread = Builder::new()
             .name("bitconch-writer".to_string())
@@ -206,6 +227,7 @@ impl WriteStage {
                     leader_rotation_interval = rcrdt.get_leader_rotation_interval();
                 }
                 let mut entry_height = entry_height;
+                let mut staged_checkpoint = None;
                 loop {
                     // Note that entry height is not zero indexed, it starts at 1, so the
                     // old leader is in power up to and including entry height
@@ -229,6 +251,7 @@ impl WriteStage {
                         }
                     }
 
+                    let from_height = entry_height;
                     if let Err(e) = Self::write_and_send_entries(
                         &crdt,
                         &mut ledger_writer,
@@ -251,6 +274,17 @@ impl WriteStage {
                             }
                         }
                     };
+                    if let Err(e) = Self::checkpoint_entries(
+                        &ledger_path,
+                        &bank,
+                        &mut staged_checkpoint,
+                        from_height,
+                        entry_height,
+                    ) {
+                        inc_new_counter_info!("write_stage-checkpoint-error", 1);
+                        error!("{:?}", e);
+                    }
+                    Crdt::refresh_stakes(&crdt, &bank);
                     if let Err(e) = send_leader_vote(
                         &id,
                         &keypair,
@@ -292,9 +326,10 @@ impl Service for WriteStage {
 #[cfg(test)]
 mod tests {
     use crate::tx_vault::Bank;
+    use crate::checkpoint::{checkpoint_heights, CHECKPOINT_INTERVAL};
     use crate::crdt::{Crdt, Node};
     use crate::entry::Entry;
-    use crate::hash::Hash;
+    use crate::hash::{hash, Hash};
     use crate::ledger::{genesis, next_entries_mut, read_ledger};
     use crate::service::Service;
     use crate::signature::{Keypair, KeypairUtil};
@@ -427,6 +462,41 @@ mod tests {
         assert_eq!(entry_height, 2 * leader_rotation_interval);
     }
 
+    #[test]
+    fn test_checkpoint_entries() {
+        let (mint, ledger_path) = genesis("test_checkpoint_entries", 10_000);
+        let bank = Bank::new(&mint);
+        // the leader's bank registers entries before they are written
+        for i in 0..CHECKPOINT_INTERVAL {
+            bank.register_entry_id(&hash(&i.to_le_bytes()));
+        }
+        let height = bank.entry_height();
+
+        let mut staged = None;
+        WriteStage::checkpoint_entries(
+            &ledger_path,
+            &bank,
+            &mut staged,
+            CHECKPOINT_INTERVAL - 1,
+            CHECKPOINT_INTERVAL,
+        ).unwrap();
+        assert_eq!(staged, Some(height));
+        assert!(checkpoint_heights(&ledger_path).unwrap().is_empty());
+
+        // and the checkpoint is committed once the ledger holds them
+        WriteStage::checkpoint_entries(
+            &ledger_path,
+            &bank,
+            &mut staged,
+            CHECKPOINT_INTERVAL,
+            height,
+        ).unwrap();
+        assert!(staged.is_none());
+        assert_eq!(checkpoint_heights(&ledger_path).unwrap(), vec![height]);
+
+        remove_dir_all(ledger_path).unwrap();
+    }
+
     #[test]
     fn test_leader_index_calculation() {
         // Set up a dummy node
@@ -10,6 +10,7 @@ use buffett::ncp::Ncp;
 use buffett::packet::{Blob, SharedBlob};
 use buffett::result;
 use buffett::service::Service;
+use buffett::signature::{Keypair, KeypairUtil};
 use std::net::UdpSocket;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
@@ -17,8 +18,9 @@ use std::thread::sleep;
 use std::time::Duration;
 
 fn test_node(exit: Arc<AtomicBool>) -> (Arc<RwLock<Crdt>>, Ncp, UdpSocket) {
-    let mut tn = Node::new_localhost();
-    let crdt = Crdt::new(tn.info.clone()).expect("Crdt::new");
+    let keypair = Arc::new(Keypair::new());
+    let mut tn = Node::new_localhost_with_pubkey(keypair.pubkey());
+    let crdt = Crdt::new_with_keypair(tn.info.clone(), keypair).expect("Crdt::new");
     let c = Arc::new(RwLock::new(crdt));
     let w = Arc::new(RwLock::new(vec![]));
     let d = Ncp::new(&c.clone(), w, None, tn.sockets.gossip, exit);
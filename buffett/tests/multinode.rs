@@ -10,14 +10,14 @@ use buffett::crdt::{Crdt, Node, NodeInfo};
 use buffett::entry::Entry;
 use buffett::fullnode::{Fullnode, FullnodeReturnType};
 use buffett::hash::Hash;
-use buffett::ledger::{read_ledger, LedgerWriter};
+use buffett::ledger::{ledger_segments, read_ledger, LedgerWriter};
 use buffett::logger;
 use buffett::mint::Mint;
 use buffett::ncp::Ncp;
 use buffett::result;
 use buffett::service::Service;
 use buffett::signature::{Keypair, KeypairUtil};
-use buffett::thin_client::ThinClient;
+use buffett::thin_client::{ThinClient, Transport};
 use buffett::timing::{duration_as_ms, duration_as_s};
 use buffett::window::{default_window, WINDOW_SIZE};
 use buffett_program_interface::pubkey::Pubkey;
@@ -96,11 +96,12 @@ fn genesis(name: &str, num: i64) -> (Mint, String, Vec<Entry>) {
 fn tmp_copy_ledger(from: &str, name: &str) -> String {
     let tostr = tmp_ledger_path(name);
 
-    {
-        let to = Path::new(&tostr);
-        let from = Path::new(&from);
+    for first in ledger_segments(from).unwrap() {
+        let segment = Path::new("segments").join(first.to_string());
+        let to = Path::new(&tostr).join(&segment);
+        let from = Path::new(&from).join(&segment);
 
-        create_dir_all(to).unwrap();
+        create_dir_all(&to).unwrap();
 
         copy(from.join("data"), to.join("data")).unwrap();
         copy(from.join("index"), to.join("index")).unwrap();
@@ -1018,9 +1019,11 @@ fn mk_client(leader: &NodeInfo) -> ThinClient {
     assert!(Crdt::is_valid_address(&leader.contact_info.tpu));
     ThinClient::new(
         leader.contact_info.rpu,
-        requests_socket,
         leader.contact_info.tpu,
-        transactions_socket,
+        Transport::Udp {
+            requests_socket,
+            transactions_socket,
+        },
     )
 }
 
@@ -0,0 +1,24 @@
+extern crate cbindgen;
+
+use std::env;
+use std::path::Path;
+
+// Generate rustelo.h from the #[no_mangle] functions into OUT_DIR, so the header the Go
+// host compiles against can't drift from the Rust signatures. tests/c_harness.rs
+// checks that the copy shipped in include/ matches it.
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config::from_file(Path::new(&crate_dir).join("cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(Path::new(&out_dir).join("rustelo.h"));
+
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+}
@@ -1,13 +1,5 @@
 #pragma once
 
-#include <stdint.h>
+// Kept for code that includes the per-module header, everything is declared in rustelo.h.
 
-#ifdef __cplusplus
-extern "C" {
-#endif
-
-extern void benchcaster_main_entry(char *num_recv_sockets);
-
-#ifdef __cplusplus
-}
-#endif
\ No newline at end of file
+#include "rustelo.h"
@@ -1,16 +1,5 @@
 #pragma once
 
-#include <stdint.h>
+// Kept for code that includes the per-module header, everything is declared in rustelo.h.
 
-#ifdef __cplusplus
-extern "C" {
-#endif
-
-extern void coincaster_main_entry(char *parm1_network,
-                                  char *parm2_keypair,
-                                  char *parm3_slice,
-                                  char *parm4_cap);
-
-#ifdef __cplusplus
-}
-#endif
\ No newline at end of file
+#include "rustelo.h"
@@ -1,17 +1,5 @@
 #pragma once
 
-#include <stdint.h>
+// Kept for code that includes the per-module header, everything is declared in rustelo.h.
 
-#ifdef __cplusplus
-extern "C" {
-#endif
-
-extern void fullnode_config_main_entry(char *local,
-                                       char *keypair,
-                                       char *public,
-                                       char *bind,
-                                       char *outfile);
-
-#ifdef __cplusplus
-}
-#endif
\ No newline at end of file
+#include "rustelo.h"
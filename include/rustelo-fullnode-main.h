@@ -1,15 +1,5 @@
 #pragma once
 
-#include <stdint.h>
+// Kept for code that includes the per-module header, everything is declared in rustelo.h.
 
-#ifdef __cplusplus
-extern "C" {
-#endif
-
-extern void fullnode_main_entry(char *identity,
-                                char *network,
-                                char *ledger);
-
-#ifdef __cplusplus
-}
-#endif
\ No newline at end of file
+#include "rustelo.h"
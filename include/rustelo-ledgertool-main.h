@@ -1,17 +1,5 @@
 #pragma once
 
-#include <stdint.h>
+// Kept for code that includes the per-module header, everything is declared in rustelo.h.
 
-#ifdef __cplusplus
-extern "C" {
-#endif
-
-extern void ledgertool_main_entry(char *ledger,
-                                  char *head,
-                                  char *precheck,
-                                  char *continu,
-                                  char *subcommand);
-
-#ifdef __cplusplus
-}
-#endif
\ No newline at end of file
+#include "rustelo.h"
@@ -1,17 +1,340 @@
+/* Generated by build.rs with cbindgen from the #[no_mangle] functions in src/, do not edit. */
+
 #pragma once
 
-//All C headerfiles shoule be included here.
-
-#include "rustelo-benchcaster-main.h"
-#include "rustelo-benchmarker-main.h"
-#include "rustelo-coincaster-main.h"
-#include "rustelo-errors-main.h"
-#include "rustelo-fullnode-config-main.h"
-#include "rustelo-fullnode-main.h"
-#include "rustelo-genesis-main.h"
-#include "rustelo-keygen-main.h"
-#include "rustelo-ledgertool-main.h"
-#include "rustelo-main-execute.h"
-#include "rustelo-propagator-main.h"
-#include "rustelo-upload-enhancer-main.h"
-#include "rustelo-wallet-main.h"
\ No newline at end of file
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Public keys and process ids are written base58 encoded, 45 bytes is always enough
+#define RUSTELO_PUBKEY_BUFFER_LEN 45
+
+// Secret keys are the 32 byte seed followed by the 32 byte public key
+#define RUSTELO_SECRET_KEY_LEN 64
+
+// Signatures are written base58 encoded, 90 bytes is always enough
+#define RUSTELO_SIGNATURE_BUFFER_LEN 90
+
+// Signatures made by rustelo_keypair_sign are 64 bytes
+#define RUSTELO_SIGNATURE_LEN 64
+
+// RusteloErrorCode is the category of the last error, it is what Go switches on.
+enum RusteloErrorCode {
+    RUSTELO_ERROR_CODE_NO_ERROR = 0,
+    RUSTELO_ERROR_CODE_INVALID_ARGUMENT = 1,
+    RUSTELO_ERROR_CODE_IO = 2,
+    RUSTELO_ERROR_CODE_SERIALIZATION = 3,
+    RUSTELO_ERROR_CODE_KEYPAIR = 4,
+    RUSTELO_ERROR_CODE_NETWORK = 5,
+    RUSTELO_ERROR_CODE_LEDGER = 6,
+    RUSTELO_ERROR_CODE_WALLET = 7,
+    RUSTELO_ERROR_CODE_FULLNODE = 8,
+    // a panic was caught at the FFI boundary
+    RUSTELO_ERROR_CODE_PANIC = 9,
+    RUSTELO_ERROR_CODE_UNKNOWN = 255,
+};
+typedef int32_t RusteloErrorCode;
+
+enum RusteloFullnodeRole {
+    RUSTELO_FULLNODE_ROLE_UNKNOWN = 0,
+    RUSTELO_FULLNODE_ROLE_LEADER = 1,
+    RUSTELO_FULLNODE_ROLE_VALIDATOR = 2,
+};
+typedef int32_t RusteloFullnodeRole;
+
+// RusteloFullnodeState is the lifecycle of a fullnode started with rustelo_fullnode_start
+enum RusteloFullnodeState {
+    // joining the network and waiting for the airdrop
+    RUSTELO_FULLNODE_STATE_STARTING = 0,
+    RUSTELO_FULLNODE_STATE_RUNNING = 1,
+    // shut down by rustelo_fullnode_shutdown
+    RUSTELO_FULLNODE_STATE_EXITED = 2,
+    // stopped on its own, the reason is reported by rustelo_fullnode_status
+    RUSTELO_FULLNODE_STATE_FAILED = 3,
+};
+typedef int32_t RusteloFullnodeState;
+
+// RusteloLogLevel is the level passed to a RusteloLogCallback, it matches log::Level
+enum RusteloLogLevel {
+    RUSTELO_LOG_LEVEL_ERROR = 1,
+    RUSTELO_LOG_LEVEL_WARN = 2,
+    RUSTELO_LOG_LEVEL_INFO = 3,
+    RUSTELO_LOG_LEVEL_DEBUG = 4,
+    RUSTELO_LOG_LEVEL_TRACE = 5,
+};
+typedef int32_t RusteloLogLevel;
+
+enum RusteloResult {
+    RUSTELO_RESULT_SUCCESS = 0,
+    RUSTELO_RESULT_FAILURE = 1,
+};
+typedef uint8_t RusteloResult;
+
+// RusteloSubmitStatus is the outcome passed to a RusteloSubmitCallback
+enum RusteloSubmitStatus {
+    RUSTELO_SUBMIT_STATUS_CONFIRMED = 0,
+    // rejected by the bank or not sent, error names the BankError or the reason
+    RUSTELO_SUBMIT_STATUS_FAILED = 1,
+    // not confirmed before the submitter timeout, or the submitter was closed
+    RUSTELO_SUBMIT_STATUS_TIMED_OUT = 2,
+};
+typedef int32_t RusteloSubmitStatus;
+
+// RusteloFullnodeStatus is filled in by rustelo_fullnode_status,
+// the counters are 0 until the node is running
+typedef struct {
+    RusteloFullnodeState state;
+    RusteloFullnodeRole role;
+    uint64_t entry_height;
+    uint8_t last_id[32];
+    uint64_t transaction_count;
+    uint64_t peer_count;
+} RusteloFullnodeStatus;
+
+// level is a RusteloLogLevel
+typedef void (*RusteloLogCallback)(int32_t level, const char *target, const char *message);
+
+// json_point is a JSON object with measurement, tags, fields and timestamp
+typedef void (*RusteloMetricsCallback)(const char *json_point);
+
+// status is a RusteloSubmitStatus. signature is the base58 transaction signature,
+// NULL if the transaction was never sent, and error is NULL once confirmed.
+typedef void (*RusteloSubmitCallback)(uint64_t context,
+                                      uint64_t request_id,
+                                      int32_t status,
+                                      const char *signature,
+                                      const char *error);
+
+// RusteloWalletOptions carries the connection settings shared by every wallet call,
+// NULL or empty strings and zero numbers select the defaults.
+// keypair is a keypair file or "secret:" followed by a base58 secret key.
+typedef struct {
+    const char *network;
+    const char *keypair;
+    uint64_t timeout;
+    uint16_t rpc_port;
+    const char *proxy;
+} RusteloWalletOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// benchcaster_main_entry streams packets through num_recv_sockets receivers on the
+// local host and reports the throughput
+RusteloResult benchcaster_main_entry(const char *parm01_num_recv_sockets_ptr);
+
+RusteloResult benchmarker_main_entry(const char *parm01_network_ptr,
+                                     const char *parm02_identity_ptr,
+                                     const char *parm03_num_nodes_ptr,
+                                     const char *parm04_reject_extra_nodes_ptr,
+                                     const char *parm05_threads_ptr,
+                                     const char *parm06_duration_ptr,
+                                     const char *parm07_converge_only_ptr,
+                                     const char *parm08_sustained_ptr,
+                                     const char *parm09_tx_count_ptr);
+
+RusteloResult coincaster_main_entry(const char *parm01_network_ptr,
+                                    const char *parm02_keypair_ptr,
+                                    const char *parm03_slice_ptr,
+                                    const char *parm04_cap_ptr);
+
+RusteloResult fullnode_config_main_entry(const char *parm01_local_ptr,
+                                         const char *parm02_keypair_ptr,
+                                         const char *parm03_public_ptr,
+                                         const char *parm04_bind_ptr,
+                                         const char *parm05_outfile_ptr);
+
+RusteloResult fullnode_main_entry(const char *parm01_identity_ptr,
+                                  const char *parm02_network_ptr,
+                                  const char *parm03_ledger_ptr);
+
+RusteloResult genesis_main_entry(const char *parm01_tokens_ptr, const char *parm02_ledger_ptr);
+
+RusteloResult keygen_main_entry(const char *parm01_outfile_ptr);
+
+RusteloResult ledgertool_main_entry(const char *parm01_ledger_ptr,
+                                    const char *parm02_head_ptr,
+                                    const char *parm03_precheck_ptr,
+                                    const char *parm04_continue_ptr,
+                                    const char *parm05_subcommand_ptr);
+
+RusteloResult propagator_main_entry(const char *parm01_identity_ptr,
+                                    const char *parm02_network_ptr,
+                                    const char *parm03_ledger_ptr);
+
+// rustelo_client_close releases a handle returned by rustelo_client_open
+RusteloResult rustelo_client_close(uint64_t handle);
+
+// rustelo_client_open polls gossip for the leader once and returns a handle to reuse
+// across wallet calls, 0 means failure
+uint64_t rustelo_client_open(const RusteloWalletOptions *options);
+
+// rustelo_execute runs the command described by a JSON request and returns a JSON
+// response, {"status": "ok", "result": ..} or
+// {"status": "error", "code": .., "message": .., "causes": [..]}.
+// The request is an object whose "command" field is one of keygen, genesis, wallet,
+// ledger-tool, fullnode-config or benchmark.
+// The returned string must be released with rustelo_free_string.
+char *rustelo_execute(const char *json_request);
+
+// rustelo_free_string releases a string returned by this library,
+// MString buffers are allocated with malloc
+void rustelo_free_string(char *ptr);
+
+// rustelo_fullnode_shutdown signals the fullnode behind a handle to exit, waits for it
+// and releases the handle
+RusteloResult rustelo_fullnode_shutdown(uint64_t handle);
+
+// rustelo_fullnode_start starts a fullnode on a background thread and returns a handle
+// right away, 0 means the arguments were rejected. Poll rustelo_fullnode_status to
+// follow the node and release the handle with rustelo_fullnode_shutdown.
+uint64_t rustelo_fullnode_start(const char *parm01_identity_ptr,
+                                const char *parm02_network_ptr,
+                                const char *parm03_ledger_ptr);
+
+// rustelo_fullnode_status fills in the status of the fullnode behind a handle.
+// When the node has failed the status is still filled in, the reason is stored
+// in the last-error slot and Failure is returned.
+RusteloResult rustelo_fullnode_status(uint64_t handle, RusteloFullnodeStatus *status_out);
+
+// rustelo_handle_error returns the message of the last error on the calling thread,
+// followed by its causes, and clears the slot. Returns NULL if no error has occurred.
+// The returned string must be released with rustelo_free_string.
+char *rustelo_handle_error(void);
+
+// rustelo_keypair_generate creates a keypair in memory, copying its secret key to
+// secret_out and its base58 public key to pubkey_out
+RusteloResult rustelo_keypair_generate(uint8_t *secret_out,
+                                       uintptr_t secret_out_len,
+                                       char *pubkey_out,
+                                       uintptr_t pubkey_out_len);
+
+// rustelo_keypair_pubkey copies the base58 public key of a secret key to pubkey_out
+RusteloResult rustelo_keypair_pubkey(const uint8_t *secret,
+                                     uintptr_t secret_len,
+                                     char *pubkey_out,
+                                     uintptr_t pubkey_out_len);
+
+// rustelo_keypair_sign signs message with a secret key, copying the signature to
+// signature_out
+RusteloResult rustelo_keypair_sign(const uint8_t *secret,
+                                   uintptr_t secret_len,
+                                   const uint8_t *message,
+                                   uintptr_t message_len,
+                                   uint8_t *signature_out,
+                                   uintptr_t signature_out_len);
+
+// rustelo_keypair_verify stores true in valid_out if signature is a signature of message
+// by the base58 public key pubkey
+RusteloResult rustelo_keypair_verify(const char *pubkey,
+                                     const uint8_t *message,
+                                     uintptr_t message_len,
+                                     const uint8_t *signature,
+                                     uintptr_t signature_len,
+                                     bool *valid_out);
+
+// rustelo_last_error_code returns the category of the last error on the calling thread
+// without clearing it, call it before rustelo_handle_error
+int32_t rustelo_last_error_code(void);
+
+// rustelo_set_log_callback routes log records that pass the RUST_LOG filter to callback,
+// NULL restores logging to stderr
+void rustelo_set_log_callback(RusteloLogCallback callback);
+
+// rustelo_set_metrics_callback routes metrics points to callback, one call per point,
+// NULL restores submission to InfluxDB
+void rustelo_set_metrics_callback(RusteloMetricsCallback callback);
+
+// rustelo_submit_airdrop requests tokens from the drone for the wallet keypair and
+// returns the request id passed to callback, 0 means it was rejected
+uint64_t rustelo_submit_airdrop(uint64_t handle,
+                                int64_t tokens,
+                                RusteloSubmitCallback callback,
+                                uint64_t context);
+
+// rustelo_submit_transaction sends a bincode serialized, signed transaction and
+// returns the request id passed to callback, 0 means it was rejected
+uint64_t rustelo_submit_transaction(uint64_t handle,
+                                    const uint8_t *transaction,
+                                    uintptr_t transaction_len,
+                                    RusteloSubmitCallback callback,
+                                    uint64_t context);
+
+// rustelo_submit_transfer sends tokens from the wallet keypair to a base58 pubkey and
+// returns the request id passed to callback, 0 means it was rejected
+uint64_t rustelo_submit_transfer(uint64_t handle,
+                                 const char *to,
+                                 int64_t tokens,
+                                 RusteloSubmitCallback callback,
+                                 uint64_t context);
+
+// rustelo_submitter_close signals the workers to stop and releases the handle without
+// waiting for them. Callbacks of queued submissions fire with RUSTELO_SUBMIT_STATUS_TIMED_OUT
+// before it returns, the ones a worker is sending fire once that call returns.
+RusteloResult rustelo_submitter_close(uint64_t handle);
+
+// rustelo_submitter_open starts a pool of workers that send transactions for the wallet
+// client behind client_handle and returns a handle to submit to, 0 means failure.
+// workers 0 and timeout_ms 0 select 4 workers and 30 seconds.
+uint64_t rustelo_submitter_open(uint64_t client_handle, uint32_t workers, uint64_t timeout_ms);
+
+// rustelo_wallet_airdrop requests tokens from the drone and returns the airdrop signature
+RusteloResult rustelo_wallet_airdrop(uint64_t handle,
+                                     int64_t tokens,
+                                     char *signature_out,
+                                     uintptr_t signature_out_len);
+
+// rustelo_wallet_balance stores the balance of the wallet keypair in balance_out
+RusteloResult rustelo_wallet_balance(uint64_t handle, int64_t *balance_out);
+
+// rustelo_wallet_cancel cancels a cancelable contract by its process id
+RusteloResult rustelo_wallet_cancel(uint64_t handle,
+                                    const char *process_id,
+                                    char *signature_out,
+                                    uintptr_t signature_out_len);
+
+// rustelo_wallet_confirm stores true in confirmed_out if the signature has been processed
+RusteloResult rustelo_wallet_confirm(uint64_t handle, const char *signature, bool *confirmed_out);
+
+// rustelo_wallet_pay sends tokens to a base58 pubkey. With a timestamp or witness
+// a budget contract is created and its process id is copied to process_id_out,
+// which may be NULL for plain payments.
+RusteloResult rustelo_wallet_pay(uint64_t handle,
+                                 const char *to,
+                                 int64_t tokens,
+                                 const char *timestamp,
+                                 const char *timestamp_pubkey,
+                                 const char *witness,
+                                 bool cancelable,
+                                 char *signature_out,
+                                 uintptr_t signature_out_len,
+                                 char *process_id_out,
+                                 uintptr_t process_id_out_len);
+
+// rustelo_wallet_time_elapsed sends a timestamp to unlock a contract,
+// a NULL or empty datetime applies the current time
+RusteloResult rustelo_wallet_time_elapsed(uint64_t handle,
+                                          const char *to,
+                                          const char *process_id,
+                                          const char *datetime,
+                                          char *signature_out,
+                                          uintptr_t signature_out_len);
+
+// rustelo_wallet_witness sends a signature to authorize the transfer of a contract
+RusteloResult rustelo_wallet_witness(uint64_t handle,
+                                     const char *to,
+                                     const char *process_id,
+                                     char *signature_out,
+                                     uintptr_t signature_out_len);
+
+RusteloResult upload_enhancer_main_entry(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+typedef RusteloResult RusteloError;
+#define RUSTELO_ERROR_SUCCESS RUSTELO_RESULT_SUCCESS
+#define RUSTELO_ERROR_FAILURE RUSTELO_RESULT_FAILURE
@@ -12,8 +12,7 @@ use std::thread::sleep;
 use std::thread::{spawn, JoinHandle};
 use std::time::Duration;
 use std::time::SystemTime;
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::rustelo_error::{arg_str, catch_panic, thread_result, RusteloErrorCode, RusteloResult};
 
 fn producer(addr: &SocketAddr, exit: Arc<AtomicBool>) -> JoinHandle<()> {
     let send = UdpSocket::bind("0.0.0.0:0").unwrap();
@@ -53,97 +52,105 @@ fn sink(exit: Arc<AtomicBool>, rvs: Arc<AtomicUsize>, r: PacketReceiver) -> Join
 
 
 
-/// to do : rewrite benchcster 
+//to do : rewrite benchcster
+
+/// benchcaster_main_entry streams packets through num_recv_sockets receivers on the
+/// local host and reports the throughput
 #[no_mangle]
 pub extern "C" fn benchcaster_main_entry(parm01_num_recv_sockets_ptr: *const libc::c_char) -> RusteloResult  {
-/*
-#[no_mangle]
-  pub extern "C" fn benchcaster_main_entry(parm01_num_recv_sockets_ptr: *const libc::c_char) -> Result<()>  {
-*/
-    let mut num_sockets = 1usize;
-
-    //handle parameters, convert ptr to &str
-    let num_recv_sockets_str = unsafe { CStr::from_ptr(parm01_num_recv_sockets_ptr) }.to_str().unwrap(); 
+    catch_panic(RusteloResult::Failure, || {
     /*
-    let matches = App::new("bitconch-bench-caster")
-        .arg(
-            Arg::with_name("num-recv-sockets")
-                .long("num-recv-sockets")
-                .value_name("NUM")
-                .takes_value(true)
-                .help("Use NUM receive sockets"),
-        ).get_matches();
+    #[no_mangle]
+      pub extern "C" fn benchcaster_main_entry(parm01_num_recv_sockets_ptr: *const libc::c_char) -> Result<()>  {
     */
+        let mut num_sockets = 1usize;
 
-    /*
-    if let Some(n) = matches.value_of("num-recv-sockets") {
-        num_sockets = max(num_sockets, n.to_string().parse().expect("integer"));
-    }
-    */
-    if let Some(n) = Some(num_recv_sockets_str) {
-        num_sockets = max(num_sockets, n.to_string().parse().expect("integer"));
-    }
+        //handle parameters, convert ptr to &str
+        let num_recv_sockets_str = tryffi!(arg_str(parm01_num_recv_sockets_ptr, "num_recv_sockets"));
+        /*
+        let matches = App::new("bitconch-bench-caster")
+            .arg(
+                Arg::with_name("num-recv-sockets")
+                    .long("num-recv-sockets")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .help("Use NUM receive sockets"),
+            ).get_matches();
+        */
 
-    let mut port = 0;
-    let mut addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+        /*
+        if let Some(n) = matches.value_of("num-recv-sockets") {
+            num_sockets = max(num_sockets, n.to_string().parse().expect("integer"));
+        }
+        */
+        if let Some(n) = Some(num_recv_sockets_str) {
+            num_sockets = max(
+                num_sockets,
+                tryffi!(n.parse::<usize>(), RusteloErrorCode::InvalidArgument),
+            );
+        }
 
-    let exit = Arc::new(AtomicBool::new(false));
+        let mut port = 0;
+        let mut addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
 
-    let mut read_channels = Vec::new();
-    let mut read_threads = Vec::new();
-    for _ in 0..num_sockets {
-        let read = bind_to(port, false).unwrap();
-        read.set_read_timeout(Some(Duration::new(1, 0))).unwrap();
+        let exit = Arc::new(AtomicBool::new(false));
 
-        addr = read.local_addr().unwrap();
-        port = addr.port();
+        let mut read_channels = Vec::new();
+        let mut read_threads = Vec::new();
+        for _ in 0..num_sockets {
+            let read = tryffi!(bind_to(port, false), RusteloErrorCode::Network);
+            tryffi!(read.set_read_timeout(Some(Duration::new(1, 0))), RusteloErrorCode::Network);
 
-        let (s_reader, r_reader) = channel();
-        read_channels.push(r_reader);
-        read_threads.push(receiver(
-            Arc::new(read),
-            exit.clone(),
-            s_reader,
-            "bench-streamer",
-        ));
-    }
+            addr = tryffi!(read.local_addr(), RusteloErrorCode::Network);
+            port = addr.port();
 
-    let t_producer1 = producer(&addr, exit.clone());
-    let t_producer2 = producer(&addr, exit.clone());
-    let t_producer3 = producer(&addr, exit.clone());
+            let (s_reader, r_reader) = channel();
+            read_channels.push(r_reader);
+            read_threads.push(receiver(
+                Arc::new(read),
+                exit.clone(),
+                s_reader,
+                "bench-streamer",
+            ));
+        }
 
-    let rvs = Arc::new(AtomicUsize::new(0));
-    let sink_threads: Vec<_> = read_channels
-        .into_iter()
-        .map(|r_reader| sink(exit.clone(), rvs.clone(), r_reader))
-        .collect();
-    let start = SystemTime::now();
-    let start_val = rvs.load(Ordering::Relaxed);
-    sleep(Duration::new(5, 0));
-    let elapsed = start.elapsed().unwrap();
-    let end_val = rvs.load(Ordering::Relaxed);
-    let time = elapsed.as_secs() * 10_000_000_000 + u64::from(elapsed.subsec_nanos());
-    let ftime = (time as f64) / 10_000_000_000_f64;
-    let fcount = (end_val - start_val) as f64;
-    println!("performance: {:?}", fcount / ftime);
-    exit.store(true, Ordering::Relaxed);
-    for t_reader in read_threads {
-        //t_reader.join()?;
-        tryffi!(t_reader.join());
-    }
-    /*
-    t_producer1.join()?;
-    t_producer2.join()?;
-    t_producer3.join()?;
-    */
-    tryffi!(t_producer1.join());
-    tryffi!(t_producer2.join());
-    tryffi!(t_producer3.join());
+        let t_producer1 = producer(&addr, exit.clone());
+        let t_producer2 = producer(&addr, exit.clone());
+        let t_producer3 = producer(&addr, exit.clone());
+
+        let rvs = Arc::new(AtomicUsize::new(0));
+        let sink_threads: Vec<_> = read_channels
+            .into_iter()
+            .map(|r_reader| sink(exit.clone(), rvs.clone(), r_reader))
+            .collect();
+        let start = SystemTime::now();
+        let start_val = rvs.load(Ordering::Relaxed);
+        sleep(Duration::new(5, 0));
+        let elapsed = start.elapsed().unwrap();
+        let end_val = rvs.load(Ordering::Relaxed);
+        let time = elapsed.as_secs() * 10_000_000_000 + u64::from(elapsed.subsec_nanos());
+        let ftime = (time as f64) / 10_000_000_000_f64;
+        let fcount = (end_val - start_val) as f64;
+        println!("performance: {:?}", fcount / ftime);
+        exit.store(true, Ordering::Relaxed);
+        for t_reader in read_threads {
+            //t_reader.join()?;
+            tryffi!(thread_result(t_reader.join()));
+        }
+        /*
+        t_producer1.join()?;
+        t_producer2.join()?;
+        t_producer3.join()?;
+        */
+        tryffi!(thread_result(t_producer1.join()));
+        tryffi!(thread_result(t_producer2.join()));
+        tryffi!(thread_result(t_producer3.join()));
     
-    for t_sink in sink_threads {
-        //t_sink.join()?;
-        tryffi!(t_sink.join());
-    }
-    //Ok(())
-    RusteloResult::Success
+        for t_sink in sink_threads {
+            //t_sink.join()?;
+            tryffi!(thread_result(t_sink.join()));
+        }
+        //Ok(())
+        RusteloResult::Success
+    })
 }
@@ -10,7 +10,7 @@ use buffett::logger;
 use buffett::metrics;
 use buffett::ncp::Ncp;
 use buffett::service::Service;
-use buffett::signature::{read_keypair, GenKeys, Keypair, KeypairUtil};
+use buffett::signature::{GenKeys, Keypair, KeypairUtil};
 use buffett::system_transaction::SystemTransaction;
 use buffett::thin_client::{poll_gossip_for_leader, ThinClient};
 use buffett::timing::{duration_as_ms, duration_as_s};
@@ -19,7 +19,6 @@ use buffett::wallet::request_airdrop;
 use buffett::window::default_window;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
-use std::process::exit;
 use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread::sleep;
@@ -29,8 +28,11 @@ use std::time::Instant;
 use buffett::asciiart; //mvp001
 use std::io::Write; //mvp001
 
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::keygen_main::read_keypair_arg;
+use crate::rustelo_error::{
+    arg_str, catch_panic, record_error, thread_result, RusteloError, RusteloErrorCode,
+    RusteloResult,
+};
 
 
 //mvp001
@@ -183,7 +185,11 @@ fn sample_tx_count(
 }
 
 /// Send loopback payment of 0 tokens and confirm the network processed it
-fn send_barrier_transaction(barrier_client: &mut ThinClient, last_id: &mut Hash, id: &Keypair) {
+fn send_barrier_transaction(
+    barrier_client: &mut ThinClient,
+    last_id: &mut Hash,
+    id: &Keypair,
+) -> Result<(), RusteloError> {
     let transfer_start = Instant::now();
 
     let mut poll_count = 0;
@@ -201,7 +207,7 @@ fn send_barrier_transaction(barrier_client: &mut ThinClient, last_id: &mut Hash,
         *last_id = barrier_client.get_last_id();
         let signature = barrier_client
             .transfer(0, &id, id.pubkey(), last_id)
-            .expect("Unable to send barrier transaction");
+            .map_err(|err| RusteloError::from_error(RusteloErrorCode::Network, &err))?;
 
         let confirmatiom = barrier_client.poll_for_signature(&signature);
         let duration_ms = duration_as_ms(&transfer_start.elapsed());
@@ -224,9 +230,12 @@ fn send_barrier_transaction(barrier_client: &mut ThinClient, last_id: &mut Hash,
                     &id.pubkey(),
                     &Duration::from_millis(100),
                     &Duration::from_secs(10),
-                ).expect("Failed to get balance");
+                ).map_err(|err| RusteloError::from_error(RusteloErrorCode::Network, &err))?;
             if balance != 1 {
-                panic!("Expected an account balance of 1 (balance: {}", balance);
+                return Err(RusteloError::new(
+                    RusteloErrorCode::Network,
+                    &format!("Expected an account balance of 1 (balance: {})", balance),
+                ));
             }
             break;
         }
@@ -234,8 +243,10 @@ fn send_barrier_transaction(barrier_client: &mut ThinClient, last_id: &mut Hash,
         // Timeout after 3 minutes.  When running a CPU-only leader+validator+drone+bench-tps on a dev
         // machine, some batches of transactions can take upwards of 1 minute...
         if duration_ms > 1000 * 60 * 3 {
-            println!("Error: Couldn't confirm barrier transaction!");
-            exit(1);
+            return Err(RusteloError::new(
+                RusteloErrorCode::Network,
+                "Couldn't confirm barrier transaction",
+            ));
         }
 
         let new_last_id = barrier_client.get_last_id();
@@ -249,6 +260,7 @@ fn send_barrier_transaction(barrier_client: &mut ThinClient, last_id: &mut Hash,
 
         poll_count += 1;
     }
+    Ok(())
 }
 
 fn generate_txs(
@@ -392,7 +404,12 @@ fn do_tx_transfers(
     }
 }
 
-fn airdrop_tokens(client: &mut ThinClient, leader: &NodeInfo, id: &Keypair, tx_count: i64) {
+fn airdrop_tokens(
+    client: &mut ThinClient,
+    leader: &NodeInfo,
+    id: &Keypair,
+    tx_count: i64,
+) -> Result<(), RusteloError> {
     let mut drone_addr = leader.contact_info.tpu;
     drone_addr.set_port(DRONE_PORT);
 
@@ -416,10 +433,13 @@ fn airdrop_tokens(client: &mut ThinClient, leader: &NodeInfo, id: &Keypair, tx_c
         );
 
         if let Err(e) = request_airdrop(&drone_addr, &id.pubkey(), airdrop_amount as u64) {
-            panic!(
-                "Error requesting airdrop: {:?} to addr: {:?} amount: {}",
-                e, drone_addr, airdrop_amount
-            );
+            return Err(RusteloError::new(
+                RusteloErrorCode::Network,
+                &format!(
+                    "Error requesting airdrop: {:?} to addr: {:?} amount: {}",
+                    e, drone_addr, airdrop_amount
+                ),
+            ));
         }
 
         // TODO: return airdrop Result from Drone instead of polling the
@@ -444,15 +464,18 @@ fn airdrop_tokens(client: &mut ThinClient, leader: &NodeInfo, id: &Keypair, tx_c
         }
         metrics_submit_token_balance(current_balance);
         if current_balance - starting_balance != airdrop_amount {
-            println!(
-                "Airdrop failed! {} {} {}",
-                id.pubkey(),
-                current_balance,
-                starting_balance
-            );
-            exit(1);
+            return Err(RusteloError::new(
+                RusteloErrorCode::Network,
+                &format!(
+                    "Airdrop failed! {} {} {}",
+                    id.pubkey(),
+                    current_balance,
+                    starting_balance
+                ),
+            ));
         }
     }
+    Ok(())
 }
 
 fn compute_and_report_stats(
@@ -604,421 +627,435 @@ pub extern "C" fn benchmarker_main_entry(parm01_network_ptr: *const libc::c_char
                                          parm06_duration_ptr: *const libc::c_char,
                                          parm07_converge_only_ptr: *const libc::c_char,
                                          parm08_sustained_ptr: *const libc::c_char,
-                                         parm09_tx_count_ptr: *const libc::c_char) {
+                                         parm09_tx_count_ptr: *const libc::c_char) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
       
-    //setup log and pannic hook
-    logger::setup();
-    metrics::set_panic_hook("bench-tps");
-
-    //handle parameters, convert ptr to &str
-    let network_str = unsafe { CStr::from_ptr(parm01_network_ptr) }.to_str().unwrap(); 
-    let identity_str= unsafe { CStr::from_ptr(parm02_identity_ptr) }.to_str().unwrap(); 
-    let num_nodes_str= unsafe { CStr::from_ptr(parm03_num_nodes_ptr) }.to_str().unwrap(); 
-    let reject_extra_nodes_str= unsafe { CStr::from_ptr(parm04_reject_extra_nodes_ptr) }.to_str().unwrap(); 
-    let threads_str= unsafe { CStr::from_ptr(parm05_threads_ptr) }.to_str().unwrap(); 
-    let duration_str= unsafe { CStr::from_ptr(parm06_duration_ptr) }.to_str().unwrap(); 
-    let converge_only_str= unsafe { CStr::from_ptr(parm07_converge_only_ptr) }.to_str().unwrap(); 
-    let sustained_str= unsafe { CStr::from_ptr(parm08_sustained_ptr) }.to_str().unwrap(); 
-    let tx_count_str= unsafe { CStr::from_ptr(parm09_tx_count_ptr) }.to_str().unwrap(); 
+        //setup log and pannic hook
+        logger::setup();
+        metrics::set_panic_hook("bench-tps");
+
+        //handle parameters, convert ptr to &str
+        let network_str = tryffi!(arg_str(parm01_network_ptr, "network"));
+        let identity_str = tryffi!(arg_str(parm02_identity_ptr, "identity"));
+        let num_nodes_str = tryffi!(arg_str(parm03_num_nodes_ptr, "num_nodes"));
+        let reject_extra_nodes_str =
+            tryffi!(arg_str(parm04_reject_extra_nodes_ptr, "reject_extra_nodes"));
+        let threads_str = tryffi!(arg_str(parm05_threads_ptr, "threads"));
+        let duration_str = tryffi!(arg_str(parm06_duration_ptr, "duration"));
+        let converge_only_str = tryffi!(arg_str(parm07_converge_only_ptr, "converge_only"));
+        let sustained_str = tryffi!(arg_str(parm08_sustained_ptr, "sustained"));
+        let tx_count_str = tryffi!(arg_str(parm09_tx_count_ptr, "tx_count"));
 
 
-    /*
-    let matches = App::new("solana-bench-tps")
-        .version(crate_version!())
-        .arg(
-            Arg::with_name("network")
-                .short("n")
-                .long("network")
-                .value_name("HOST:PORT")
-                .takes_value(true)
-                .help("Rendezvous with the network at this gossip entry point; defaults to 127.0.0.1:8001"),
-        )
-        .arg(
-            Arg::with_name("identity")
-                .short("i")
-                .long("identity")
-                .value_name("PATH")
-                .takes_value(true)
-                .required(true)
-                .help("File containing a client identity (keypair)"),
-        )
-        .arg(
-            Arg::with_name("num-nodes")
-                .short("N")
-                .long("num-nodes")
-                .value_name("NUM")
-                .takes_value(true)
-                .help("Wait for NUM nodes to converge"),
-        )
-        .arg(
-            Arg::with_name("reject-extra-nodes")
-                .long("reject-extra-nodes")
-                .help("Require exactly `num-nodes` on convergence. Appropriate only for internal networks"),
-        )
-        .arg(
-            Arg::with_name("threads")
-                .short("t")
-                .long("threads")
-                .value_name("NUM")
-                .takes_value(true)
-                .help("Number of threads"),
-        )
-        .arg(
-            Arg::with_name("duration")
-                .long("duration")
-                .value_name("SECS")
-                .takes_value(true)
-                .help("Seconds to run benchmark, then exit; default is forever"),
-        )
-        .arg(
-            Arg::with_name("converge-only")
-                .long("converge-only")
-                .help("Exit immediately after converging"),
-        )
-        .arg(
-            Arg::with_name("sustained")
-                .long("sustained")
-                .help("Use sustained performance mode vs. peak mode. This overlaps the tx generation with transfers."),
-        )
-        .arg(
-            Arg::with_name("tx_count")
-                .long("tx_count")
-                .value_name("NUM")
-                .takes_value(true)
-                .help("Number of transactions to send per batch")
-        )
-        .get_matches();
-    */
+        /*
+        let matches = App::new("solana-bench-tps")
+            .version(crate_version!())
+            .arg(
+                Arg::with_name("network")
+                    .short("n")
+                    .long("network")
+                    .value_name("HOST:PORT")
+                    .takes_value(true)
+                    .help("Rendezvous with the network at this gossip entry point; defaults to 127.0.0.1:8001"),
+            )
+            .arg(
+                Arg::with_name("identity")
+                    .short("i")
+                    .long("identity")
+                    .value_name("PATH")
+                    .takes_value(true)
+                    .required(true)
+                    .help("File containing a client identity (keypair)"),
+            )
+            .arg(
+                Arg::with_name("num-nodes")
+                    .short("N")
+                    .long("num-nodes")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .help("Wait for NUM nodes to converge"),
+            )
+            .arg(
+                Arg::with_name("reject-extra-nodes")
+                    .long("reject-extra-nodes")
+                    .help("Require exactly `num-nodes` on convergence. Appropriate only for internal networks"),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .short("t")
+                    .long("threads")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .help("Number of threads"),
+            )
+            .arg(
+                Arg::with_name("duration")
+                    .long("duration")
+                    .value_name("SECS")
+                    .takes_value(true)
+                    .help("Seconds to run benchmark, then exit; default is forever"),
+            )
+            .arg(
+                Arg::with_name("converge-only")
+                    .long("converge-only")
+                    .help("Exit immediately after converging"),
+            )
+            .arg(
+                Arg::with_name("sustained")
+                    .long("sustained")
+                    .help("Use sustained performance mode vs. peak mode. This overlaps the tx generation with transfers."),
+            )
+            .arg(
+                Arg::with_name("tx_count")
+                    .long("tx_count")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .help("Number of transactions to send per batch")
+            )
+            .get_matches();
+        */
 
 
-    /*
-    let network = if let Some(addr) = matches.value_of("network") {
-        addr.parse().unwrap_or_else(|e| {
-            eprintln!("failed to parse network: {}", e);
-            exit(1)
-        })
-    } else {
-        socketaddr!("127.0.0.1:8001")
-    };
-    */
-    let network = if let Some(addr) =Some(network_str) {
-        addr.parse().unwrap_or_else(|e| {
-            eprintln!("failed to parse network: {}", e);
-            exit(1)
-        })
-    } else {
-        socketaddr!("127.0.0.1:8001")
-    };
+        /*
+        let network = if let Some(addr) = matches.value_of("network") {
+            addr.parse().unwrap_or_else(|e| {
+                eprintln!("failed to parse network: {}", e);
+                exit(1)
+            })
+        } else {
+            socketaddr!("127.0.0.1:8001")
+        };
+        */
+        let network = if let Some(addr) =Some(network_str) {
+            tryffi!(addr.parse::<SocketAddr>(), RusteloErrorCode::InvalidArgument)
+        } else {
+            socketaddr!("127.0.0.1:8001")
+        };
 
 
-    /*
-    let id =
-        read_keypair(matches.value_of("identity").unwrap()).expect("can't read client identity");
-    */
-    let id =
-        read_keypair(Some(identity_str).unwrap()).expect("can't read client identity");
+        /*
+        let id =
+            read_keypair_arg(matches.value_of("identity").unwrap()).expect("can't read client identity");
+        */
+        let id = tryffi!(read_keypair_arg(identity_str), RusteloErrorCode::Keypair);
 
     
-    /*
-    let threads = if let Some(t) = matches.value_of("threads") {
-        t.to_string().parse().expect("can't parse threads")
-    } else {
-        4usize
-    };
-    */
-    let threads = if !threads_str.is_empty() {
-        threads_str.to_string().parse::<usize>().expect("can't parse threads")
-    } else {
-        4usize
-    };
-
-
-    /*
-    let num_nodes = if let Some(n) = matches.value_of("num-nodes") {
-        n.to_string().parse().expect("can't parse num-nodes")
-    } else {
-        1usize
-    };
-    */
-    let num_nodes = if !num_nodes_str.is_empty() {
-        num_nodes_str.to_string().parse::<usize>().expect("can't parse num-nodes")
-    } else {
-        1usize
-    };
+        /*
+        let threads = if let Some(t) = matches.value_of("threads") {
+            t.to_string().parse().expect("can't parse threads")
+        } else {
+            4usize
+        };
+        */
+        let threads = if !threads_str.is_empty() {
+            tryffi!(threads_str.parse::<usize>(), RusteloErrorCode::InvalidArgument)
+        } else {
+            4usize
+        };
 
 
-    /*
-    let duration = if let Some(s) = matches.value_of("duration") {
-        Duration::new(s.to_string().parse().expect("can't parse duration"), 0)
-    } else {
-        Duration::new(std::u64::MAX, 0)
-    };
-    */
-    let duration = if !duration_str.is_empty() {
-        Duration::new(duration_str.to_string().parse::<u64>().expect("can't parse duration"), 0)
-    } else {
-        Duration::new(std::u64::MAX, 0)
-    };
+        /*
+        let num_nodes = if let Some(n) = matches.value_of("num-nodes") {
+            n.to_string().parse().expect("can't parse num-nodes")
+        } else {
+            1usize
+        };
+        */
+        let num_nodes = if !num_nodes_str.is_empty() {
+            tryffi!(num_nodes_str.parse::<usize>(), RusteloErrorCode::InvalidArgument)
+        } else {
+            1usize
+        };
 
 
-    /*
-    let tx_count = if let Some(s) = matches.value_of("tx_count") {
-        s.to_string().parse().expect("can't parse tx_count")
-    } else {
-        500_000
-    };
-    */
-    let tx_count = if !tx_count_str.is_empty() {
-        tx_count_str.to_string().parse::<i64>().expect("can't parse tx_count")
-    } else {
-        500_000
-    };
+        /*
+        let duration = if let Some(s) = matches.value_of("duration") {
+            Duration::new(s.to_string().parse().expect("can't parse duration"), 0)
+        } else {
+            Duration::new(std::u64::MAX, 0)
+        };
+        */
+        let duration = if !duration_str.is_empty() {
+            Duration::new(
+                tryffi!(duration_str.parse::<u64>(), RusteloErrorCode::InvalidArgument),
+                0,
+            )
+        } else {
+            Duration::new(std::u64::MAX, 0)
+        };
 
 
-    /*
-    let sustained = matches.is_present("sustained");
-    */
-    let sustained = if sustained_str == "TRUE"{true} else {false};
+        /*
+        let tx_count = if let Some(s) = matches.value_of("tx_count") {
+            s.to_string().parse().expect("can't parse tx_count")
+        } else {
+            500_000
+        };
+        */
+        let tx_count = if !tx_count_str.is_empty() {
+            tryffi!(tx_count_str.parse::<i64>(), RusteloErrorCode::InvalidArgument)
+        } else {
+            500_000
+        };
 
-    //select leader node on given network entry point
-    asciiart::welcome(); 
-    dividing_line(); 
-    leader_node_selection(); 
-    println!(
-        "{0: <2}{1: <40}: {2: <60}",
-        "|", "Search for Leader Node On Network", network
-    );
-    dividing_line();
-    print_animation_arrows();
-    let leader = poll_gossip_for_leader(network, None).expect("unable to find leader on network");
-    //set the exit signal
-    let exit_signal = Arc::new(AtomicBool::new(false));
 
-    dividing_line();
-    println!(
-        "| Leader Node is found!, ID: {:?}",
-        &leader.id
-    );
-    dividing_line();
-    //wait the node to be found
-    sleep(Duration::from_millis(100));
-    
-    //try to converge the nodes, 
-    let (nodes, leader, ncp) = converge(&leader, &exit_signal, num_nodes);
+        /*
+        let sustained = matches.is_present("sustained");
+        */
+        let sustained = if sustained_str == "TRUE"{true} else {false};
 
-    //node number is big enough
-    if nodes.len() < num_nodes {
+        //select leader node on given network entry point
+        asciiart::welcome(); 
+        dividing_line(); 
+        leader_node_selection(); 
         println!(
-            "Error: Insufficient nodes discovered.  Expecting {} or more",
-            num_nodes
+            "{0: <2}{1: <40}: {2: <60}",
+            "|", "Search for Leader Node On Network", network
         );
-        exit(1);
-    }
-    
-    //sometimes node number is too big 
-    //if matches.is_present("reject-extra-nodes") && nodes.len() > num_nodes {
-    if reject_extra_nodes_str == "TRUE" && nodes.len() > num_nodes { 
+        dividing_line();
+        print_animation_arrows();
+        let leader = tryffi!(poll_gossip_for_leader(network, None), RusteloErrorCode::Network);
+        //set the exit signal
+        let exit_signal = Arc::new(AtomicBool::new(false));
+
+        dividing_line();
         println!(
-            "Error: Extra nodes discovered.  Expecting exactly {}",
-            num_nodes
+            "| Leader Node is found!, ID: {:?}",
+            &leader.id
         );
-        exit(1);
-    }
+        dividing_line();
+        //wait the node to be found
+        sleep(Duration::from_millis(100));
+    
+        //try to converge the nodes, 
+        let (nodes, leader, ncp) = converge(&leader, &exit_signal, num_nodes);
 
-    if leader.is_none() {
-        println!("no leader");
-        exit(1);
-    }
+        //from here on the gossip and client threads must be stopped before returning
+        let fail = |err: RusteloError| {
+            exit_signal.store(true, Ordering::Relaxed);
+            record_error(RusteloErrorCode::Unknown, err)
+        };
 
+        //node number is big enough
+        if nodes.len() < num_nodes {
+            return fail(RusteloError::new(
+                RusteloErrorCode::Network,
+                &format!("Insufficient nodes discovered.  Expecting {} or more", num_nodes),
+            ));
+        }
     
-    //if matches.is_present("converge-only") {
-    if converge_only_str == "TRUE" {
-        return;
-    }
+        //sometimes node number is too big 
+        //if matches.is_present("reject-extra-nodes") && nodes.len() > num_nodes {
+        if reject_extra_nodes_str == "TRUE" && nodes.len() > num_nodes { 
+            return fail(RusteloError::new(
+                RusteloErrorCode::Network,
+                &format!("Extra nodes discovered.  Expecting exactly {}", num_nodes),
+            ));
+        }
 
-    let leader = leader.unwrap();
+        if leader.is_none() {
+            return fail(RusteloError::new(RusteloErrorCode::Network, "no leader"));
+        }
 
-    //mvp001
-    dividing_line();
-    println!(
-        "{0: <2}{1: <40}: {2: <60}",
-        "|", "Leader Node Contact Information", leader.contact_info.rpu
-    );
-    println!(
-        "{0: <2}{1: <40}: {2: <60}",
-        "|", "Leader Node ID", leader.id
-    );
-    dividing_line();
-    //*
-    //println!("leader is at {} {}", leader.contact_info.rpu, leader.id);
     
-    let mut client = mk_client(&leader);
-    let mut barrier_client = mk_client(&leader);
+        //if matches.is_present("converge-only") {
+        if converge_only_str == "TRUE" {
+            exit_signal.store(true, Ordering::Relaxed);
+            tryffi!(thread_result(ncp.join()));
+            return RusteloResult::Success;
+        }
 
-    let mut seed = [0u8; 32];
-    seed.copy_from_slice(&id.public_key_bytes()[..32]);
-    let mut rnd = GenKeys::new(seed);
+        let leader = leader.unwrap();
 
-    //mvp
-    println!("| Begin to prepare data and send some Transactions:");
-    dividing_line();
-    print_animation_arrows();
-    //println!("Creating {} keypairs...", tx_count / 2);
-    println!(
-        "{0: <2}{1: <40}: {2: <60}",
-        "|",
-        "Create Key Pairs",
-        tx_count / 2
-    );
-    //*
+        //mvp001
+        dividing_line();
+        println!(
+            "{0: <2}{1: <40}: {2: <60}",
+            "|", "Leader Node Contact Information", leader.contact_info.rpu
+        );
+        println!(
+            "{0: <2}{1: <40}: {2: <60}",
+            "|", "Leader Node ID", leader.id
+        );
+        dividing_line();
+        //*
+        //println!("leader is at {} {}", leader.contact_info.rpu, leader.id);
+    
+        let mut client = mk_client(&leader);
+        let mut barrier_client = mk_client(&leader);
 
-    let keypairs = rnd.gen_n_keypairs(tx_count / 2);
-    let barrier_id = rnd.gen_n_keypairs(1).pop().unwrap();
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&id.public_key_bytes()[..32]);
+        let mut rnd = GenKeys::new(seed);
 
-    //mvp001
-    print_animation_arrows();
-    println!(
-        "{0: <2}{1: <40}: {2: <60}",
-        "|", "Issue Tokens", "Yes, issue some tokens to each account."
-    );
-    //*
-    //println!("Get tokens...");
-    let num_tokens_per_account = 20;
-
-    // Sample the first keypair, see if it has tokens, if so then resume
-    // to avoid token loss
-    let keypair0_balance = client.poll_get_balance(&keypairs[0].pubkey()).unwrap_or(0);
-
-    if num_tokens_per_account > keypair0_balance {
-        airdrop_tokens(
-            &mut client,
-            &leader,
-            &id,
-            (num_tokens_per_account - keypair0_balance) * tx_count,
+        //mvp
+        println!("| Begin to prepare data and send some Transactions:");
+        dividing_line();
+        print_animation_arrows();
+        //println!("Creating {} keypairs...", tx_count / 2);
+        println!(
+            "{0: <2}{1: <40}: {2: <60}",
+            "|",
+            "Create Key Pairs",
+            tx_count / 2
         );
-    }
-    airdrop_tokens(&mut barrier_client, &leader, &barrier_id, 1);
-
-    //println!("Get last ID...");
-    let mut last_id = client.get_last_id();
-    //println!("Got last ID {:?}", last_id);
-
-    let first_tx_count = client.transaction_count();
-    println!("Initial transaction count {}", first_tx_count);
-
-    // Setup a thread per validator to sample every period
-    // collect the max transaction rate and total tx count seen
-    let maxes = Arc::new(RwLock::new(Vec::new()));
-    let sample_period = 1; // in seconds
-    println!("Sampling TPS every {} second...", sample_period);
-    let v_threads: Vec<_> = nodes
-        .into_iter()
-        .map(|v| {
-            let exit_signal = exit_signal.clone();
-            let maxes = maxes.clone();
-            Builder::new()
-                .name("solana-client-sample".to_string())
-                .spawn(move || {
-                    sample_tx_count(&exit_signal, &maxes, first_tx_count, &v, sample_period);
-                }).unwrap()
-        }).collect();
-
-    let shared_txs: Arc<RwLock<VecDeque<Vec<Transaction>>>> =
-        Arc::new(RwLock::new(VecDeque::new()));
-
-    let shared_tx_active_thread_count = Arc::new(AtomicIsize::new(0));
-    let total_tx_sent_count = Arc::new(AtomicUsize::new(0));
-
-    let s_threads: Vec<_> = (0..threads)
-        .map(|_| {
-            let exit_signal = exit_signal.clone();
-            let shared_txs = shared_txs.clone();
-            let leader = leader.clone();
-            let shared_tx_active_thread_count = shared_tx_active_thread_count.clone();
-            let total_tx_sent_count = total_tx_sent_count.clone();
-            Builder::new()
-                .name("solana-client-sender".to_string())
-                .spawn(move || {
-                    do_tx_transfers(
-                        &exit_signal,
-                        &shared_txs,
-                        &leader,
-                        &shared_tx_active_thread_count,
-                        &total_tx_sent_count,
-                    );
-                }).unwrap()
-        }).collect();
+        //*
 
-    // generate and send transactions for the specified duration
-    let start = Instant::now();
-    let mut reclaim_tokens_back_to_source_account = false;
-    let mut i = keypair0_balance;
-    while start.elapsed() < duration {
-        let balance = client.poll_get_balance(&id.pubkey()).unwrap_or(-1);
-        metrics_submit_token_balance(balance);
+        let keypairs = rnd.gen_n_keypairs(tx_count / 2);
+        let barrier_id = rnd.gen_n_keypairs(1).pop().unwrap();
 
-        // ping-pong between source and destination accounts for each loop iteration
-        // this seems to be faster than trying to determine the balance of individual
-        // accounts
-        generate_txs(
-            &shared_txs,
-            &id,
-            &keypairs,
-            &last_id,
-            threads,
-            reclaim_tokens_back_to_source_account,
+        //mvp001
+        print_animation_arrows();
+        println!(
+            "{0: <2}{1: <40}: {2: <60}",
+            "|", "Issue Tokens", "Yes, issue some tokens to each account."
         );
-        // In sustained mode overlap the transfers with generation
-        // this has higher average performance but lower peak performance
-        // in tested environments.
-        if !sustained {
-            while shared_tx_active_thread_count.load(Ordering::Relaxed) > 0 {
-                sleep(Duration::from_millis(100));
+        //*
+        //println!("Get tokens...");
+        let num_tokens_per_account = 20;
+
+        // Sample the first keypair, see if it has tokens, if so then resume
+        // to avoid token loss
+        let keypair0_balance = client.poll_get_balance(&keypairs[0].pubkey()).unwrap_or(0);
+
+        if num_tokens_per_account > keypair0_balance {
+            if let Err(err) = airdrop_tokens(
+                &mut client,
+                &leader,
+                &id,
+                (num_tokens_per_account - keypair0_balance) * tx_count,
+            ) {
+                return fail(err);
             }
         }
-        // It's not feasible (would take too much time) to confirm each of the `tx_count / 2`
-        // transactions sent by `generate_txs()` so instead send and confirm a single transaction
-        // to validate the network is still functional.
-        send_barrier_transaction(&mut barrier_client, &mut last_id, &barrier_id);
-
-        i += 1;
-        if should_switch_directions(num_tokens_per_account, i) {
-            reclaim_tokens_back_to_source_account = !reclaim_tokens_back_to_source_account;
+        if let Err(err) = airdrop_tokens(&mut barrier_client, &leader, &barrier_id, 1) {
+            return fail(err);
         }
-    }
 
-    // Stop the sampling threads so it will collect the stats
-    exit_signal.store(true, Ordering::Relaxed);
+        //println!("Get last ID...");
+        let mut last_id = client.get_last_id();
+        //println!("Got last ID {:?}", last_id);
+
+        let first_tx_count = client.transaction_count();
+        println!("Initial transaction count {}", first_tx_count);
+
+        // Setup a thread per validator to sample every period
+        // collect the max transaction rate and total tx count seen
+        let maxes = Arc::new(RwLock::new(Vec::new()));
+        let sample_period = 1; // in seconds
+        println!("Sampling TPS every {} second...", sample_period);
+        let v_threads: Vec<_> = nodes
+            .into_iter()
+            .map(|v| {
+                let exit_signal = exit_signal.clone();
+                let maxes = maxes.clone();
+                Builder::new()
+                    .name("solana-client-sample".to_string())
+                    .spawn(move || {
+                        sample_tx_count(&exit_signal, &maxes, first_tx_count, &v, sample_period);
+                    }).unwrap()
+            }).collect();
+
+        let shared_txs: Arc<RwLock<VecDeque<Vec<Transaction>>>> =
+            Arc::new(RwLock::new(VecDeque::new()));
+
+        let shared_tx_active_thread_count = Arc::new(AtomicIsize::new(0));
+        let total_tx_sent_count = Arc::new(AtomicUsize::new(0));
+
+        let s_threads: Vec<_> = (0..threads)
+            .map(|_| {
+                let exit_signal = exit_signal.clone();
+                let shared_txs = shared_txs.clone();
+                let leader = leader.clone();
+                let shared_tx_active_thread_count = shared_tx_active_thread_count.clone();
+                let total_tx_sent_count = total_tx_sent_count.clone();
+                Builder::new()
+                    .name("solana-client-sender".to_string())
+                    .spawn(move || {
+                        do_tx_transfers(
+                            &exit_signal,
+                            &shared_txs,
+                            &leader,
+                            &shared_tx_active_thread_count,
+                            &total_tx_sent_count,
+                        );
+                    }).unwrap()
+            }).collect();
+
+        // generate and send transactions for the specified duration
+        let start = Instant::now();
+        let mut reclaim_tokens_back_to_source_account = false;
+        let mut i = keypair0_balance;
+        while start.elapsed() < duration {
+            let balance = client.poll_get_balance(&id.pubkey()).unwrap_or(-1);
+            metrics_submit_token_balance(balance);
+
+            // ping-pong between source and destination accounts for each loop iteration
+            // this seems to be faster than trying to determine the balance of individual
+            // accounts
+            generate_txs(
+                &shared_txs,
+                &id,
+                &keypairs,
+                &last_id,
+                threads,
+                reclaim_tokens_back_to_source_account,
+            );
+            // In sustained mode overlap the transfers with generation
+            // this has higher average performance but lower peak performance
+            // in tested environments.
+            if !sustained {
+                while shared_tx_active_thread_count.load(Ordering::Relaxed) > 0 {
+                    sleep(Duration::from_millis(100));
+                }
+            }
+            // It's not feasible (would take too much time) to confirm each of the `tx_count / 2`
+            // transactions sent by `generate_txs()` so instead send and confirm a single transaction
+            // to validate the network is still functional.
+            if let Err(err) = send_barrier_transaction(&mut barrier_client, &mut last_id, &barrier_id) {
+                return fail(err);
+            }
 
-    dividing_line(); //mvp001
-    println!("| Kill all the remaining threads.");
-    print_animation_arrows();
-    for t in v_threads {
-        if let Err(err) = t.join() {
-            println!("  join() failed with: {:?}", err);
+            i += 1;
+            if should_switch_directions(num_tokens_per_account, i) {
+                reclaim_tokens_back_to_source_account = !reclaim_tokens_back_to_source_account;
+            }
         }
-    }
 
-    // join the tx send threads
-    //println!("Waiting for transmit threads...");
-    for t in s_threads {
-        if let Err(err) = t.join() {
-            println!("  join() failed with: {:?}", err);
+        // Stop the sampling threads so it will collect the stats
+        exit_signal.store(true, Ordering::Relaxed);
+
+        dividing_line(); //mvp001
+        println!("| Kill all the remaining threads.");
+        print_animation_arrows();
+        for t in v_threads {
+            if let Err(err) = t.join() {
+                println!("  join() failed with: {:?}", err);
+            }
         }
-    }
 
-    let balance = client.poll_get_balance(&id.pubkey()).unwrap_or(-1);
-    metrics_submit_token_balance(balance);
+        // join the tx send threads
+        //println!("Waiting for transmit threads...");
+        for t in s_threads {
+            if let Err(err) = t.join() {
+                println!("  join() failed with: {:?}", err);
+            }
+        }
 
-    compute_and_report_stats(
-        &maxes,
-        sample_period,
-        &start.elapsed(),
-        total_tx_sent_count.load(Ordering::Relaxed),
-    );
+        let balance = client.poll_get_balance(&id.pubkey()).unwrap_or(-1);
+        metrics_submit_token_balance(balance);
+
+        compute_and_report_stats(
+            &maxes,
+            sample_period,
+            &start.elapsed(),
+            total_tx_sent_count.load(Ordering::Relaxed),
+        );
 
-    // join the crdt client threads
-    ncp.join().unwrap();
+        // join the crdt client threads
+        tryffi!(thread_result(ncp.join()));
+        RusteloResult::Success
+    })
 }
 
 fn converge(
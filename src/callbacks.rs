@@ -0,0 +1,95 @@
+//callbacks lets the Go host receive log records and metrics points through C function
+//pointers instead of stderr and InfluxDB. The strings handed to a callback are only
+//valid for the duration of the call, copy them before returning.
+use crate::rustelo_error::catch_panic;
+use buffett::logger;
+use buffett::metrics;
+use influx_db_client::{Point, Value};
+use libc::c_char;
+use log::Record;
+use serde_json::{json, Map};
+use std::ffi::CString;
+use std::sync::Arc;
+
+/// RusteloLogLevel is the level passed to a RusteloLogCallback, it matches log::Level
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RusteloLogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+/// level is a RusteloLogLevel
+pub type RusteloLogCallback =
+    Option<extern "C" fn(level: i32, target: *const c_char, message: *const c_char)>;
+
+/// json_point is a JSON object with measurement, tags, fields and timestamp
+pub type RusteloMetricsCallback = Option<extern "C" fn(json_point: *const c_char)>;
+
+//to_cstring drops interior NULs rather than losing the whole string
+fn to_cstring(value: String) -> CString {
+    CString::new(value.replace('\0', "")).unwrap()
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match *value {
+        Value::String(ref s) => json!(s),
+        Value::Integer(i) => json!(i),
+        Value::Float(f) => json!(f),
+        Value::Boolean(b) => json!(b),
+    }
+}
+
+pub fn point_to_json(point: &Point) -> serde_json::Value {
+    let tags: Map<String, serde_json::Value> = point
+        .tags
+        .iter()
+        .map(|(k, v)| (k.clone(), value_to_json(v)))
+        .collect();
+    let fields: Map<String, serde_json::Value> = point
+        .fields
+        .iter()
+        .map(|(k, v)| (k.clone(), value_to_json(v)))
+        .collect();
+    json!({
+        "measurement": point.measurement,
+        "tags": tags,
+        "fields": fields,
+        "timestamp": point.timestamp,
+    })
+}
+
+/// rustelo_set_log_callback routes log records that pass the RUST_LOG filter to callback,
+/// NULL restores logging to stderr
+#[no_mangle]
+pub extern "C" fn rustelo_set_log_callback(callback: RusteloLogCallback) {
+    catch_panic((), || {
+        logger::setup();
+        logger::set_sink(callback.map(|callback| {
+            Arc::new(move |record: &Record| {
+                let target = to_cstring(record.target().to_string());
+                let message = to_cstring(record.args().to_string());
+                callback(record.level() as i32, target.as_ptr(), message.as_ptr());
+            }) as Arc<logger::LogSink>
+        }));
+    })
+}
+
+/// rustelo_set_metrics_callback routes metrics points to callback, one call per point,
+/// NULL restores submission to InfluxDB
+#[no_mangle]
+pub extern "C" fn rustelo_set_metrics_callback(callback: RusteloMetricsCallback) {
+    catch_panic((), || {
+        metrics::set_sink(callback.map(|callback| {
+            Arc::new(move |points: &[Point]| {
+                for point in points {
+                    let json_point = to_cstring(point_to_json(point).to_string());
+                    callback(json_point.as_ptr());
+                }
+            }) as Arc<metrics::MetricsSink>
+        }));
+    })
+}
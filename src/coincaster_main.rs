@@ -4,19 +4,19 @@ use clap::{App, Arg};
  use buffett::token_service::{Drone, DroneRequest, DRONE_PORT};
 use buffett::logger;
 use buffett::metrics::set_panic_hook;
-use buffett::signature::read_keypair;
 use std::error;
 use std::io;
 use std::net::{Ipv4Addr, SocketAddr};
-use std::process::exit;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use tokio::net::TcpListener;
 use tokio::prelude::*;
 use tokio_codec::{BytesCodec, Decoder};
 
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::keygen_main::read_keypair_arg;
+use crate::rustelo_error::{
+    arg_str, catch_panic, categorize, RusteloError, RusteloErrorCode, RusteloResult,
+};
 
 macro_rules! socketaddr {
     ($ip:expr, $port:expr) => {
@@ -34,18 +34,23 @@ pub extern "C" fn coincaster_main_entry(parm01_network_ptr:    *const libc::c_ch
                                         parm02_keypair_ptr:    *const libc::c_char,
                                         parm03_slice_ptr:  *const libc::c_char,
                                         parm04_cap_ptr:    *const libc::c_char) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
 
-    //handle parameters, convert ptr to &str
-    let network_str = unsafe { CStr::from_ptr(parm01_network_ptr) }.to_str().unwrap();
-    let keypair_str = unsafe { CStr::from_ptr(parm02_keypair_ptr) }.to_str().unwrap();
-    let slice_str = unsafe { CStr::from_ptr(parm03_slice_ptr) }.to_str().unwrap();
-    let cap_str = unsafe { CStr::from_ptr(parm04_cap_ptr) }.to_str().unwrap();
+        //handle parameters, convert ptr to &str
+        let network_str = tryffi!(arg_str(parm01_network_ptr, "network"));
+        let keypair_str = tryffi!(arg_str(parm02_keypair_ptr, "keypair"));
+        let slice_str = tryffi!(arg_str(parm03_slice_ptr, "slice"));
+        let cap_str = tryffi!(arg_str(parm04_cap_ptr, "cap"));
 
-    
+        tryffi!(main_entry(network_str, keypair_str, slice_str, cap_str)
+            .map_err(|err| categorize(RusteloErrorCode::Network, &*err)));
 
-    let _rc = main_entry(network_str,keypair_str,slice_str,cap_str);
-    
-    RusteloResult::Success
+        RusteloResult::Success
+    })
+}
+
+fn invalid_argument(message: &str) -> RusteloError {
+    RusteloError::new(RusteloErrorCode::InvalidArgument, message)
 }
 
 fn main_entry(network_str:&str,
@@ -56,22 +61,20 @@ fn main_entry(network_str:&str,
     set_panic_hook("drone");
     
     // parse the network  
-    let network = Some(network_str)
-        .unwrap()
-        .parse()
-        .unwrap_or_else(|e| {
-            eprintln!("failed to parse network: {}", e);
-            exit(1)
-        });
+    let network = network_str
+        .parse::<SocketAddr>()
+        .map_err(|e| invalid_argument(&format!("failed to parse network: {}", e)))?;
 
     // parse the keypair  
-    let mint_keypair =
-        read_keypair(Some(keypair_str).unwrap()).expect("failed to read client keypair");
+    let mint_keypair = read_keypair_arg(keypair_str)
+        .map_err(|err| categorize(RusteloErrorCode::Keypair, &*err))?;
 
     // parse the time slice 
     let time_slice: Option<u64>;
     if !slice_str.is_empty(){
-        time_slice = Some(slice_str.to_string().parse().expect("failed to parse slice"));
+        time_slice = Some(slice_str.parse::<u64>().map_err(|e| {
+            invalid_argument(&format!("failed to parse slice: {}", e))
+        })?);
     } else {
         time_slice = None;
     }
@@ -79,7 +82,9 @@ fn main_entry(network_str:&str,
     // parse the requeset cap
     let request_cap: Option<u64>;
     if !cap_str.is_empty() {
-        request_cap = Some(cap_str.to_string().parse().expect("failed to parse cap"));
+        request_cap = Some(cap_str.parse::<u64>().map_err(|e| {
+            invalid_argument(&format!("failed to parse cap: {}", e))
+        })?);
     } else {
         request_cap = None;
     }
@@ -102,7 +107,7 @@ fn main_entry(network_str:&str,
         drone1.lock().unwrap().clear_request_count();
     });
 
-    let socket = TcpListener::bind(&drone_addr).unwrap();
+    let socket = TcpListener::bind(&drone_addr)?;
     println!("Drone started. Listening on: {}", drone_addr);
     let done = socket
         .incoming()
@@ -1,45 +1,348 @@
 use clap::{App, Arg};
+use buffett::account_store::AccountStoreKind;
 use buffett::client::mk_client;
 use buffett::crdt::Node;
  use buffett::token_service::DRONE_PORT;
-use buffett::fullnode::{Config, Fullnode, FullnodeReturnType};
+use buffett::crdt::Crdt;
+use buffett::fullnode::{Config, Fullnode, FullnodeReturnType, RoleExitSignal};
 use buffett::logger;
 use buffett::metrics::set_panic_hook;
 use buffett::signature::{Keypair, KeypairUtil};
 use buffett::thin_client::poll_gossip_for_leader;
+use buffett::tx_vault::Bank;
 use buffett::wallet::request_airdrop;
 use std::fs::File;
 use std::net::{Ipv4Addr, SocketAddr};
-use std::process::exit;
-use std::thread::sleep;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{sleep, Builder, JoinHandle};
 use std::time::Duration;
+use once_cell::{sync::Lazy, sync_lazy};
+use parking_lot::Mutex;
 
-use std::ffi::c_void; //use ffi c_void
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::handle_registry::HandleRegistry;
+use crate::rustelo_error::{
+    arg_str, catch_panic, record_error, set_last_error, RusteloError, RusteloErrorCode,
+    RusteloResult,
+};
 
-fn create_network(_network_str: &str) -> Option<std::net::SocketAddr> {
+fn create_network(_network_str: &str) -> Result<Option<std::net::SocketAddr>, RusteloError> {
         if _network_str.is_empty(){
-            None
+            Ok(None)
         } else {
-            Some(_network_str).map(|network| network.parse::<std::net::SocketAddr>().expect("failed to parse network address"))
+            _network_str.parse::<std::net::SocketAddr>().map(Some).or_else(|_| {
+                Err(RusteloError::new(
+                    RusteloErrorCode::InvalidArgument,
+                    &format!("failed to parse network address {}", _network_str),
+                ))
+            })
         }
     }
 
+/// RusteloFullnodeState is the lifecycle of a fullnode started with rustelo_fullnode_start
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RusteloFullnodeState {
+    /// joining the network and waiting for the airdrop
+    Starting = 0,
+    Running = 1,
+    /// shut down by rustelo_fullnode_shutdown
+    Exited = 2,
+    /// stopped on its own, the reason is reported by rustelo_fullnode_status
+    Failed = 3,
+}
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RusteloFullnodeRole {
+    Unknown = 0,
+    Leader = 1,
+    Validator = 2,
+}
+
+/// RusteloFullnodeStatus is filled in by rustelo_fullnode_status,
+/// the counters are 0 until the node is running
+#[repr(C)]
+pub struct RusteloFullnodeStatus {
+    pub state: RusteloFullnodeState,
+    pub role: RusteloFullnodeRole,
+    pub entry_height: u64,
+    pub last_id: [u8; 32],
+    pub transaction_count: u64,
+    pub peer_count: u64,
+}
+
+//FullnodeShared is what the supervisor thread publishes for status polling,
+//the Fullnode itself is owned by that thread
+struct FullnodeShared {
+    state: RusteloFullnodeState,
+    error: Option<RusteloError>,
+    role_exit: Option<RoleExitSignal>,
+    bank: Option<Arc<Bank>>,
+    crdt: Option<Arc<RwLock<Crdt>>>,
+}
+
+impl FullnodeShared {
+    fn fail(&mut self, err: RusteloError) {
+        self.state = RusteloFullnodeState::Failed;
+        self.error = Some(err);
+    }
+}
+
+pub struct FullnodeService {
+    shutdown: Arc<AtomicBool>,
+    shared: Arc<Mutex<FullnodeShared>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FullnodeService {
+    fn start(identity: String, network: String, ledger: String) -> Result<Self, RusteloError> {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shared = Arc::new(Mutex::new(FullnodeShared {
+            state: RusteloFullnodeState::Starting,
+            error: None,
+            role_exit: None,
+            bank: None,
+            crdt: None,
+        }));
+
+        let thread_shutdown = shutdown.clone();
+        let thread_shared = shared.clone();
+        let thread = Builder::new()
+            .name("rustelo-fullnode".to_string())
+            .spawn(move || {
+                run_fullnode(&identity, &network, &ledger, &thread_shutdown, &thread_shared)
+            }).or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::Fullnode, &err)))?;
+
+        Ok(FullnodeService {
+            shutdown,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    fn shutdown(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(ref role_exit) = self.shared.lock().role_exit {
+            role_exit.exit();
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                self.shared
+                    .lock()
+                    .fail(RusteloError::new(RusteloErrorCode::Fullnode, "fullnode thread panicked"));
+            }
+        }
+    }
+
+    fn status(&self) -> (RusteloFullnodeStatus, Option<RusteloError>) {
+        let shared = self.shared.lock();
+        let mut status = RusteloFullnodeStatus {
+            state: shared.state,
+            role: RusteloFullnodeRole::Unknown,
+            entry_height: 0,
+            last_id: [0; 32],
+            transaction_count: 0,
+            peer_count: 0,
+        };
+        if let Some(ref bank) = shared.bank {
+            status.entry_height = bank.entry_height();
+            status.last_id.copy_from_slice(bank.last_id().as_ref());
+            status.transaction_count = bank.transaction_count() as u64;
+        }
+        if let Some(ref crdt) = shared.crdt {
+            let crdt = crdt.read().unwrap();
+            let me = crdt.my_data();
+            status.role = if me.leader_id == me.id {
+                RusteloFullnodeRole::Leader
+            } else {
+                RusteloFullnodeRole::Validator
+            };
+            status.peer_count = crdt.get_valid_peers().len() as u64;
+        }
+        (status, shared.error.clone())
+    }
+}
+
+//run_fullnode is the body of the supervisor thread behind rustelo_fullnode_start
+fn run_fullnode(
+    identity: &str,
+    network: &str,
+    ledger: &str,
+    shutdown: &Arc<AtomicBool>,
+    shared: &Arc<Mutex<FullnodeShared>>,
+) {
+    let mut fullnode = match new_fullnode(identity, network, ledger, shutdown) {
+        Ok(fullnode) => fullnode,
+        Err(err) => {
+            if !shutdown.load(Ordering::SeqCst) {
+                shared.lock().fail(err);
+            }
+            return;
+        }
+    };
+
+    {
+        let mut shared = shared.lock();
+        shared.role_exit = Some(fullnode.role_exit_signal());
+        shared.bank = Some(fullnode.bank());
+        shared.crdt = Some(fullnode.crdt());
+        shared.state = RusteloFullnodeState::Running;
+    }
+
+    // rustelo_fullnode_shutdown signals the role after setting the flag, so the flag
+    // has to be checked again once the role is published
+    while !shutdown.load(Ordering::SeqCst) {
+        match fullnode.handle_role_transition() {
+            // a leader to validator transition replaces the bank
+            Ok(Some(FullnodeReturnType::LeaderRotation)) => {
+                shared.lock().bank = Some(fullnode.bank())
+            }
+            Ok(None) => {
+                if !shutdown.load(Ordering::SeqCst) {
+                    shared.lock().fail(RusteloError::new(
+                        RusteloErrorCode::Fullnode,
+                        "fullnode exited unexpectedly",
+                    ));
+                }
+                break;
+            }
+            Err(_) => {
+                shared.lock().fail(RusteloError::new(
+                    RusteloErrorCode::Fullnode,
+                    "fullnode service panicked",
+                ));
+                break;
+            }
+        }
+    }
+
+    let _ = fullnode.close();
+    let mut shared = shared.lock();
+    shared.role_exit = None;
+    if shared.state == RusteloFullnodeState::Running {
+        shared.state = RusteloFullnodeState::Exited;
+    }
+}
+
+pub(crate) static FULLNODES: Lazy<Mutex<HandleRegistry<FullnodeService>>> = sync_lazy! {
+    Mutex::new(HandleRegistry::new())
+};
+
 #[no_mangle]
 pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
                                       parm02_network_ptr: *const libc::c_char,
-                                      parm03_ledger_ptr: *const libc::c_char){
+                                      parm03_ledger_ptr: *const libc::c_char) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+
+        //handle parameters, convert ptr to &str
+        let identity_str = tryffi!(arg_str(parm01_identity_ptr, "identity"));
+        let network_str = tryffi!(arg_str(parm02_network_ptr, "network"));
+        let ledger_str = tryffi!(arg_str(parm03_ledger_ptr, "ledger"));
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut fullnode = tryffi!(new_fullnode(identity_str, network_str, ledger_str, &shutdown));
+
+        loop {
+            let status = fullnode.handle_role_transition();
+            match status {
+                Ok(Some(FullnodeReturnType::LeaderRotation)) => (),
+                Ok(None) => {
+                    // Fullnode tpu/tvu exited for some unexpected
+                    // reason, so report it to the caller
+                    return record_error(RusteloErrorCode::Fullnode, "fullnode exited unexpectedly");
+                }
+                Err(_) => return record_error(RusteloErrorCode::Fullnode, "fullnode service panicked"),
+            }
+        }
+    })
+}
+
+/// rustelo_fullnode_start starts a fullnode on a background thread and returns a handle
+/// right away, 0 means the arguments were rejected. Poll rustelo_fullnode_status to
+/// follow the node and release the handle with rustelo_fullnode_shutdown.
+#[no_mangle]
+pub extern "C" fn rustelo_fullnode_start(parm01_identity_ptr: *const libc::c_char,
+                                         parm02_network_ptr: *const libc::c_char,
+                                         parm03_ledger_ptr: *const libc::c_char) -> u64 {
+    catch_panic(0, || {
+        let service = arg_str(parm01_identity_ptr, "identity").and_then(|identity| {
+            let network = arg_str(parm02_network_ptr, "network")?;
+            let ledger = arg_str(parm03_ledger_ptr, "ledger")?;
+            //fail early on a bad network address rather than in the background
+            create_network(network)?;
+            FullnodeService::start(identity.to_string(), network.to_string(), ledger.to_string())
+        });
+        match service {
+            Ok(service) => FULLNODES.lock().insert(service),
+            Err(err) => {
+                set_last_error(err);
+                0
+            }
+        }
+    })
+}
+
+/// rustelo_fullnode_status fills in the status of the fullnode behind a handle.
+/// When the node has failed the status is still filled in, the reason is stored
+/// in the last-error slot and Failure is returned.
+#[no_mangle]
+pub extern "C" fn rustelo_fullnode_status(handle: u64,
+                                          status_out: *mut RusteloFullnodeStatus) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        if status_out.is_null() {
+            return record_error(RusteloErrorCode::InvalidArgument, "argument 'status_out' is NULL");
+        }
+        let service = match FULLNODES.lock().get(handle) {
+            Some(service) => service,
+            None => {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("unknown fullnode handle {}", handle),
+                );
+            }
+        };
+        let (status, error) = service.lock().status();
+        unsafe { *status_out = status };
+
+        match error {
+            Some(err) => record_error(RusteloErrorCode::Fullnode, err),
+            None => RusteloResult::Success,
+        }
+    })
+}
+
+/// rustelo_fullnode_shutdown signals the fullnode behind a handle to exit, waits for it
+/// and releases the handle
+#[no_mangle]
+pub extern "C" fn rustelo_fullnode_shutdown(handle: u64) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let service = match FULLNODES.lock().remove(handle) {
+            Some(service) => service,
+            None => {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("unknown fullnode handle {}", handle),
+                );
+            }
+        };
+        service.lock().shutdown();
+
+        RusteloResult::Success
+    })
+}
+
+//new_fullnode builds the fullnode and waits for its airdrop,
+//giving up when shutdown is set
+fn new_fullnode(identity_str: &str,
+                network_str: &str,
+                ledger_str: &str,
+                shutdown: &Arc<AtomicBool>) -> Result<Fullnode, RusteloError> {
 
     //setup log and pannic hook                                   
     logger::setup();
     set_panic_hook("fullnode");
 
-    //handle parameters, convert ptr to &str
-    let identity_str =  unsafe { CStr::from_ptr(parm01_identity_ptr) }.to_str().unwrap(); 
-    let network_str =  unsafe { CStr::from_ptr(parm02_network_ptr) }.to_str().unwrap(); 
-    let ledger_str =  unsafe { CStr::from_ptr(parm03_ledger_ptr) }.to_str().unwrap(); 
-    
     println!("identity:{:?}", identity_str);
     println!("network:{:?}", network_str);
     println!("ledger:{:?}", ledger_str);
@@ -90,22 +393,14 @@ pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
         (Keypair::new(), socketaddr!(0, 8000))
     };
     */
-    let (keypair, ncp) = if let Some(i) = Some(identity_str) {
-        let path = i.to_string();
-        if let Ok(file) = File::open(path.clone()) {
-            let parse: serde_json::Result<Config> = serde_json::from_reader(file);
-            if let Ok(data) = parse {
-                (data.keypair(), data.node_info.contact_info.ncp)
-            } else {
-                eprintln!("failed to parse {}", path);
-                exit(1);
-            }
-        } else {
-            eprintln!("failed to read {}", path);
-            exit(1);
-        }
+    let (keypair, ncp, account_store) = if !identity_str.is_empty() {
+        let file = File::open(identity_str)
+            .or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::Io, &err)))?;
+        let data: Config = serde_json::from_reader(file)
+            .or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::Serialization, &err)))?;
+        (data.keypair(), data.node_info.contact_info.ncp, data.account_store)
     } else {
-        (Keypair::new(), socketaddr!(0, 8000))
+        (Keypair::new(), socketaddr!(0, 8000), AccountStoreKind::default())
     };
 
     //let ledger_path = matches.value_of("ledger").unwrap();
@@ -117,7 +412,7 @@ pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
         .value_of("network")
         .map(|network| network.parse().expect("failed to parse network address"));
     */
-    let network =create_network(network_str);
+    let network = create_network(network_str)?;
     
     let node = Node::new_with_external_ip(keypair.pubkey(), &ncp);
 
@@ -125,13 +420,26 @@ pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
     let node_info = node.info.clone();
     let pubkey = keypair.pubkey();
 
-    let mut fullnode = Fullnode::new(node, ledger_path, keypair, network, false, None);
+    let fullnode = Fullnode::new_with_account_store(
+        node,
+        ledger_path,
+        keypair,
+        network,
+        false,
+        None,
+        account_store,
+    );
 
     // airdrop stuff, probably goes away at some point
     let leader = match network {
-        Some(network) => {
-            poll_gossip_for_leader(network, None).expect("can't find leader on network")
-        }
+        Some(network) => match poll_gossip_for_leader(network, None) {
+            Ok(leader) => leader,
+            Err(err) => {
+                // don't leave the node's services running behind the caller's back
+                let _ = fullnode.close();
+                return Err(RusteloError::from_error(RusteloErrorCode::Network, &err));
+            }
+        },
         None => node_info,
     };
 
@@ -144,6 +452,14 @@ pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
     };
 
     loop {
+        if shutdown.load(Ordering::SeqCst) {
+            let _ = fullnode.close();
+            return Err(RusteloError::new(
+                RusteloErrorCode::Fullnode,
+                "fullnode shut down while waiting for the airdrop",
+            ));
+        }
+
         let balance = client.poll_get_balance(&pubkey).unwrap_or(0);
         info!("balance is {}", balance);
 
@@ -161,19 +477,12 @@ pub extern "C" fn fullnode_main_entry(parm01_identity_ptr: *const libc::c_char,
                 "airdrop request, is the drone address correct {:?}, drone running?",
                 drone_addr
             );
+            if shutdown.load(Ordering::SeqCst) {
+                break;
+            }
             sleep(Duration::from_secs(2));
         }
     }
 
-    loop {
-        let status = fullnode.handle_role_transition();
-        match status {
-            Ok(Some(FullnodeReturnType::LeaderRotation)) => (),
-            _ => {
-                // Fullnode tpu/tvu exited for some unexpected
-                // reason, so exit
-                exit(1);
-            }
-        }
-    }
+    Ok(fullnode)
 }
@@ -2,12 +2,10 @@
 use atty::{is, Stream};
 use clap::{App, Arg};
 use buffett::ledger::LedgerWriter;
-use buffett::mint::Mint;
+use buffett::coinery::Mint;
 use std::error;
 use std::io::{stdin, Read};
-use std::process::exit;
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::rustelo_error::{arg_str, catch_panic, record_error, RusteloErrorCode, RusteloResult};
 
 /*
 #[no_mangle]
@@ -17,95 +15,100 @@ pub extern "C" fn genesis_main_entry(parm01_tokens_ptr: *const libc::c_char,
 
 #[no_mangle]
 pub extern "C" fn genesis_main_entry(parm01_tokens_ptr: *const libc::c_char,
-                                     parm02_ledger_ptr: *const libc::c_char,) -> RusteloResult {  
+                                     parm02_ledger_ptr: *const libc::c_char,) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
    
-    //handle parameters, convert ptr to &str
-    let tokens_str  = unsafe { CStr::from_ptr(parm01_tokens_ptr) }.to_str().unwrap();  
-    let ledger_str  = unsafe { CStr::from_ptr(parm02_ledger_ptr) }.to_str().unwrap();  
+        //handle parameters, convert ptr to &str
+        let tokens_str  = tryffi!(arg_str(parm01_tokens_ptr, "tokens"));
+        let ledger_str  = tryffi!(arg_str(parm02_ledger_ptr, "ledger"));
     
-    /*let matches = App::new("solana-genesis")
-        .version(crate_version!())
-        .arg(
-            Arg::with_name("tokens")
-                .short("t")
-                .long("tokens")
-                .value_name("NUM")
-                .takes_value(true)
-                .required(true)
-                .help("Number of tokens with which to initialize mint"),
-        ).arg(
-            Arg::with_name("ledger")
-                .short("l")
-                .long("ledger")
-                .value_name("DIR")
-                .takes_value(true)
-                .required(true)
-                .help("Use directory as persistent ledger location"),
-        ).get_matches(); */
+        /*let matches = App::new("solana-genesis")
+            .version(crate_version!())
+            .arg(
+                Arg::with_name("tokens")
+                    .short("t")
+                    .long("tokens")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Number of tokens with which to initialize mint"),
+            ).arg(
+                Arg::with_name("ledger")
+                    .short("l")
+                    .long("ledger")
+                    .value_name("DIR")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Use directory as persistent ledger location"),
+            ).get_matches(); */
 
-    //cast token_str to i64
-    //let tokens = value_t_or_exit!(matches, "tokens", i64);
-    //refer to https://github.com/clap-rs/clap/blob/master/src/macros.rs as the source code for macro
-    let tokens = if let Some(v) = Some(tokens_str) {
-        match v.parse::<i64>() {
+        //cast token_str to i64
+        //let tokens = value_t_or_exit!(matches, "tokens", i64);
+        //refer to https://github.com/clap-rs/clap/blob/master/src/macros.rs as the source code for macro
+        let tokens = match tokens_str.parse::<i64>() {
             Ok(val) => val,
-            Err(_)  =>
-                ::clap::Error::value_validation_auto(
-                    format!("The argument '{}' isn't a valid value", v)).exit(),
-        }
-    } else {
-        ::clap::Error::argument_not_found_auto("token").exit()
-    };
-    /*
-    if !tokens_str.is_empty(){
-        match tokens_str.parse::<i64>(){
-            Ok(i)  => {
-                        let tokens =i;
-            },
-            Err(e) => {
-                        println!("{} '{}' isn't a valid value\n\n{}\n\nPlease re-run with {} for \
-                                        more information",
-                                        ::clap::Format::Error("error:"),
-                                        ::clap::Format::Warning(tokens_str.to_string()),
-                                        matches.usage(),
-                                        ::clap::Format::Good("--help"));
-                                    ::std::process::exit(1);
+            Err(_)  => {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("The argument '{}' isn't a valid value", tokens_str),
+                );
+            }
+        };
+        /*
+        if !tokens_str.is_empty(){
+            match tokens_str.parse::<i64>(){
+                Ok(i)  => {
+                            let tokens =i;
+                },
+                Err(e) => {
+                            println!("{} '{}' isn't a valid value\n\n{}\n\nPlease re-run with {} for \
+                                            more information",
+                                            ::clap::Format::Error("error:"),
+                                            ::clap::Format::Warning(tokens_str.to_string()),
+                                            matches.usage(),
+                                            ::clap::Format::Good("--help"));
+                                        ::std::process::exit(1);
+                }
             }
         }
-    }
-    */
+        */
 
-    //ledger path 
-    //let ledger_path = matches.value_of("ledger").unwrap();
-    let ledger_path = ledger_str;
+        //ledger path 
+        //let ledger_path = matches.value_of("ledger").unwrap();
+        let ledger_path = ledger_str;
 
-    if is(Stream::Stdin) {
-        eprintln!("nothing found on stdin, expected a json file");
-        exit(1);
-    }
+        if is(Stream::Stdin) {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                "nothing found on stdin, expected a json file",
+            );
+        }
 
-    let mut buffer = String::new();
-    //let num_bytes = stdin().read_to_string(&mut buffer)?;
-    let num_bytes =tryffi!(stdin().read_to_string(&mut buffer));
-    if num_bytes == 0 {
-        eprintln!("empty file on stdin, expected a json file");
-        exit(1);
-    }
+        let mut buffer = String::new();
+        //let num_bytes = stdin().read_to_string(&mut buffer)?;
+        let num_bytes =tryffi!(stdin().read_to_string(&mut buffer), RusteloErrorCode::Io);
+        if num_bytes == 0 {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                "empty file on stdin, expected a json file",
+            );
+        }
 
-    //let pkcs8: Vec<u8> = serde_json::from_str(&buffer)?;
-    let pkcs8: Vec<u8> = tryffi!(serde_json::from_str(&buffer));
+        //let pkcs8: Vec<u8> = serde_json::from_str(&buffer)?;
+        let pkcs8: Vec<u8> = tryffi!(serde_json::from_str(&buffer), RusteloErrorCode::Serialization);
     
-    let mint = Mint::new_with_pkcs8(tokens, pkcs8);
+        let mint = Mint::new_with_pkcs8(tokens, pkcs8);
 
-    /*
-    let mut ledger_writer = LedgerWriter::open(&ledger_path, true)?;
-    ledger_writer.write_entries(mint.create_entries())?;
-    */
-    let mut ledger_writer = tryffi!(LedgerWriter::open(&ledger_path, true));
-    tryffi!(ledger_writer.write_entries(mint.create_entries()));
+        /*
+        let mut ledger_writer = LedgerWriter::open(&ledger_path, true)?;
+        ledger_writer.write_entries(mint.create_entries())?;
+        */
+        let mut ledger_writer = tryffi!(LedgerWriter::open(&ledger_path, true), RusteloErrorCode::Ledger);
+        tryffi!(ledger_writer.write_entries(mint.create_entries()), RusteloErrorCode::Ledger);
 
-    //Ok(())
-    RusteloResult::Success
+        //Ok(())
+        RusteloResult::Success
+    })
 }
 
 
@@ -0,0 +1,43 @@
+//HandleRegistry keeps long-lived Rust objects alive between FFI calls and hands
+//Go an opaque u64 for each of them. 0 is never a valid handle.
+use parking_lot::Mutex;
+use slotmap::{DefaultKey, KeyData, SecondaryMap, SlotMap};
+use std::sync::Arc;
+
+pub struct HandleRegistry<V> {
+    //slotmap values must be Copy, the objects live in the secondary map
+    keys: SlotMap<DefaultKey, ()>,
+    values: SecondaryMap<DefaultKey, Arc<Mutex<V>>>,
+}
+
+impl<V> HandleRegistry<V> {
+    pub fn new() -> Self {
+        HandleRegistry {
+            keys: SlotMap::new(),
+            values: SecondaryMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: V) -> u64 {
+        let key = self.keys.insert(());
+        self.values.insert(key, Arc::new(Mutex::new(value)));
+        KeyData::from(key).as_ffi()
+    }
+
+    pub fn get(&self, handle: u64) -> Option<Arc<Mutex<V>>> {
+        if handle == 0 {
+            return None;
+        }
+        let key = DefaultKey::from(KeyData::from_ffi(handle));
+        self.values.get(key).cloned()
+    }
+
+    pub fn remove(&mut self, handle: u64) -> Option<Arc<Mutex<V>>> {
+        if handle == 0 {
+            return None;
+        }
+        let key = DefaultKey::from(KeyData::from_ffi(handle));
+        self.keys.remove(key)?;
+        self.values.remove(key)
+    }
+}
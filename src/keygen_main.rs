@@ -1,33 +1,181 @@
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
-use clap::{App, Arg};
+use crate::rustelo_error::{
+    arg_bytes, arg_str, catch_panic, record_error, write_out_bytes, write_out_str, RusteloError,
+    RusteloErrorCode, RusteloResult,
+};
+use crate::wallet_main::parse_pubkey;
+use buffett::signature::{
+    gen_secret_key, keypair_from_secret_key, pkcs8_from_secret_key, read_keypair, read_pkcs8,
+    Keypair, KeypairUtil, Signature,
+};
 use buffett::wallet::gen_keypair_file;
 use std::error;
 
 
 #[no_mangle]
 pub extern "C" fn keygen_main_entry(parm01_outfile_ptr: *const libc::c_char) -> RusteloResult  {
+    catch_panic(RusteloResult::Failure, || {
 
-    //handle parameters, convert ptr to &str
-    let outfile_str = unsafe {CStr::from_ptr(parm01_outfile_ptr)}.to_str().unwrap();
+        //handle parameters, convert ptr to &str
+        let outfile_str = tryffi!(arg_str(parm01_outfile_ptr, "outfile"));
 
-    main_entry(outfile_str);
-    
-    RusteloResult::Success
+        tryffi!(main_entry(outfile_str), RusteloErrorCode::Keypair);
+
+        RusteloResult::Success
+    })
 }
 
 fn main_entry(outfile_str:&str) -> Result<(), Box<error::Error>> {
-    let mut path = dirs::home_dir().expect("home directory");
+    let mut path = dirs::home_dir().ok_or("home directory not found")?;
     let outfile = if !outfile_str.is_empty() {
-        Some(outfile_str).unwrap()
+        outfile_str.to_string()
     } else {
         path.extend(&[".config", "solana", "id.json"]);
-        path.to_str().unwrap()
+        path.to_str().ok_or("home directory is not valid UTF-8")?.to_string()
     };
 
-    let serialized_keypair = gen_keypair_file(outfile.to_string())?;
+    let serialized_keypair = gen_keypair_file(outfile.clone())?;
     if outfile == "-" {
         println!("{}", serialized_keypair);
     }
     Ok(())
 }
+
+/// Secret keys are the 32 byte seed followed by the 32 byte public key
+pub const RUSTELO_SECRET_KEY_LEN: usize = 64;
+
+/// Signatures made by rustelo_keypair_sign are 64 bytes
+pub const RUSTELO_SIGNATURE_LEN: usize = 64;
+
+//a keypair argument is the path of a keypair file, or this prefix followed by
+//the base58 encoded secret key, so hosts never have to write keys to disk
+const SECRET_KEY_ARG_PREFIX: &str = "secret:";
+
+fn decode_secret_key_arg(value: &str) -> Option<Result<Vec<u8>, Box<error::Error>>> {
+    if !value.starts_with(SECRET_KEY_ARG_PREFIX) {
+        return None;
+    }
+    Some(
+        bs58::decode(&value[SECRET_KEY_ARG_PREFIX.len()..])
+            .into_vec()
+            .or_else(|_| Err("secret key is not valid base58".into())),
+    )
+}
+
+//read_keypair_arg accepts a keypair argument wherever a keypair file path used to be
+pub fn read_keypair_arg(value: &str) -> Result<Keypair, Box<error::Error>> {
+    match decode_secret_key_arg(value) {
+        Some(secret) => keypair_from_secret_key(&secret?),
+        None => read_keypair(value),
+    }
+}
+
+//read_pkcs8_arg is read_keypair_arg for callers that keep the PKCS#8 document
+pub fn read_pkcs8_arg(value: &str) -> Result<Vec<u8>, Box<error::Error>> {
+    match decode_secret_key_arg(value) {
+        Some(secret) => pkcs8_from_secret_key(&secret?),
+        None => read_pkcs8(value),
+    }
+}
+
+//keypair_arg_display is what error messages show of a keypair argument,
+//an inline secret key is never echoed back
+pub fn keypair_arg_display(value: &str) -> &str {
+    if value.starts_with(SECRET_KEY_ARG_PREFIX) {
+        "secret:<redacted>"
+    } else {
+        value
+    }
+}
+
+fn secret_key_arg(secret: *const u8, secret_len: usize) -> Result<Keypair, RusteloError> {
+    let secret = arg_bytes(secret, secret_len, "secret")?;
+    keypair_from_secret_key(secret)
+        .or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::Keypair, &*err)))
+}
+
+/// rustelo_keypair_generate creates a keypair in memory, copying its secret key to
+/// secret_out and its base58 public key to pubkey_out
+#[no_mangle]
+pub extern "C" fn rustelo_keypair_generate(
+    secret_out: *mut u8,
+    secret_out_len: usize,
+    pubkey_out: *mut libc::c_char,
+    pubkey_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let secret = tryffi!(gen_secret_key(), RusteloErrorCode::Keypair);
+        let keypair = tryffi!(keypair_from_secret_key(&secret), RusteloErrorCode::Keypair);
+        tryffi!(write_out_bytes(&secret, secret_out, secret_out_len));
+        tryffi!(write_out_str(&keypair.pubkey().to_string(), pubkey_out, pubkey_out_len));
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_keypair_pubkey copies the base58 public key of a secret key to pubkey_out
+#[no_mangle]
+pub extern "C" fn rustelo_keypair_pubkey(
+    secret: *const u8,
+    secret_len: usize,
+    pubkey_out: *mut libc::c_char,
+    pubkey_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let keypair = tryffi!(secret_key_arg(secret, secret_len));
+        tryffi!(write_out_str(&keypair.pubkey().to_string(), pubkey_out, pubkey_out_len));
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_keypair_sign signs message with a secret key, copying the signature to
+/// signature_out
+#[no_mangle]
+pub extern "C" fn rustelo_keypair_sign(
+    secret: *const u8,
+    secret_len: usize,
+    message: *const u8,
+    message_len: usize,
+    signature_out: *mut u8,
+    signature_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let keypair = tryffi!(secret_key_arg(secret, secret_len));
+        let message = tryffi!(arg_bytes(message, message_len, "message"));
+        let signature = keypair.sign(message);
+        tryffi!(write_out_bytes(signature.as_ref(), signature_out, signature_out_len));
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_keypair_verify stores true in valid_out if signature is a signature of message
+/// by the base58 public key pubkey
+#[no_mangle]
+pub extern "C" fn rustelo_keypair_verify(
+    pubkey: *const libc::c_char,
+    message: *const u8,
+    message_len: usize,
+    signature: *const u8,
+    signature_len: usize,
+    valid_out: *mut bool,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        if valid_out.is_null() {
+            return record_error(RusteloErrorCode::InvalidArgument, "argument 'valid_out' is NULL");
+        }
+        let pubkey = tryffi!(parse_pubkey(tryffi!(arg_str(pubkey, "pubkey")), "pubkey"));
+        let message = tryffi!(arg_bytes(message, message_len, "message"));
+        let signature = tryffi!(arg_bytes(signature, signature_len, "signature"));
+        if signature.len() != RUSTELO_SIGNATURE_LEN {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("signature must be {} bytes, got {}", RUSTELO_SIGNATURE_LEN, signature.len()),
+            );
+        }
+        let valid = Signature::new(signature).verify(pubkey.as_ref(), message);
+        unsafe { *valid_out = valid };
+
+        RusteloResult::Success
+    })
+}
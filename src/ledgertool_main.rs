@@ -1,284 +1,272 @@
 use clap::{App, Arg, SubCommand};
-use buffett::bank::Bank;
+use buffett::tx_vault::Bank;
 use buffett::ledger::{read_ledger, verify_ledger};
 use buffett::logger;
 use std::io::{stdout, Write};
-use std::process::exit;
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::rustelo_error::{arg_str, catch_panic, record_error, RusteloErrorCode, RusteloResult};
 
 #[no_mangle]
 pub extern "C" fn ledgertool_main_entry(parm01_ledger_ptr: *const libc::c_char,
                                         parm02_head_ptr: *const libc::c_char,
                                         parm03_precheck_ptr: *const libc::c_char,
                                         parm04_continue_ptr: *const libc::c_char,
-                                        parm05_subcommand_ptr: *const libc::c_char) {
-    //setup log and pannic hook
-    logger::setup();
+                                        parm05_subcommand_ptr: *const libc::c_char) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        //setup log and pannic hook
+        logger::setup();
 
-    //handle parameters, convert ptr to &str
-    let ledger_str = unsafe { CStr::from_ptr(parm01_ledger_ptr) }.to_str().unwrap();
-    let head_str = unsafe { CStr::from_ptr(parm02_head_ptr) }.to_str().unwrap();
-    let precheck_str = unsafe { CStr::from_ptr(parm03_precheck_ptr) }.to_str().unwrap();
-    let continue_str = unsafe { CStr::from_ptr(parm04_continue_ptr) }.to_str().unwrap();
-    let subcommand_str = unsafe { CStr::from_ptr(parm05_subcommand_ptr) }.to_str().unwrap();
+        //handle parameters, convert ptr to &str
+        let ledger_str = tryffi!(arg_str(parm01_ledger_ptr, "ledger"));
+        let head_str = tryffi!(arg_str(parm02_head_ptr, "head"));
+        let precheck_str = tryffi!(arg_str(parm03_precheck_ptr, "precheck"));
+        let continue_str = tryffi!(arg_str(parm04_continue_ptr, "continue"));
+        let subcommand_str = tryffi!(arg_str(parm05_subcommand_ptr, "subcommand"));
 
 
-    /*
-    let matches = App::new("ledger-tool")
-        .version(crate_version!())
-        .arg(
-            Arg::with_name("ledger")
-                .short("l")
-                .long("ledger")
-                .value_name("DIR")
-                .takes_value(true)
-                .required(true)
-                .help("Use directory for ledger location"),
-        )
-        .arg(
-            Arg::with_name("head")
-                .short("n")
-                .long("head")
-                .value_name("NUM")
-                .takes_value(true)
-                .help("Limit to at most the first NUM entries in ledger\n  (only applies to verify, print, json commands)"),
-        )
-        .arg(
-            Arg::with_name("precheck")
-                .short("p")
-                .long("precheck")
-                .help("Use ledger_verify() to check internal ledger consistency before proceeding"),
-        )
-        .arg(
-            Arg::with_name("continue")
-                .short("c")
-                .long("continue")
-                .help("Continue verify even if verification fails"),
-        )
-        .subcommand(SubCommand::with_name("print").about("Print the ledger"))
-        .subcommand(SubCommand::with_name("json").about("Print the ledger in JSON format"))
-        .subcommand(SubCommand::with_name("verify").about("Verify the ledger's PoH"))
-        .get_matches();
-    */
+        /*
+        let matches = App::new("ledger-tool")
+            .version(crate_version!())
+            .arg(
+                Arg::with_name("ledger")
+                    .short("l")
+                    .long("ledger")
+                    .value_name("DIR")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Use directory for ledger location"),
+            )
+            .arg(
+                Arg::with_name("head")
+                    .short("n")
+                    .long("head")
+                    .value_name("NUM")
+                    .takes_value(true)
+                    .help("Limit to at most the first NUM entries in ledger\n  (only applies to verify, print, json commands)"),
+            )
+            .arg(
+                Arg::with_name("precheck")
+                    .short("p")
+                    .long("precheck")
+                    .help("Use ledger_verify() to check internal ledger consistency before proceeding"),
+            )
+            .arg(
+                Arg::with_name("continue")
+                    .short("c")
+                    .long("continue")
+                    .help("Continue verify even if verification fails"),
+            )
+            .subcommand(SubCommand::with_name("print").about("Print the ledger"))
+            .subcommand(SubCommand::with_name("json").about("Print the ledger in JSON format"))
+            .subcommand(SubCommand::with_name("verify").about("Verify the ledger's PoH"))
+            .get_matches();
+        */
 
-    //retrieve the ledger file location
-    //let ledger_path = matches.value_of("ledger").unwrap();
-    let ledger_path = ledger_str;
+        //retrieve the ledger file location
+        //let ledger_path = matches.value_of("ledger").unwrap();
+        let ledger_path = ledger_str;
 
-    //if matches.is_present("precheck") {
-    if  precheck_str == "TRUE" {    
-        if let Err(e) = verify_ledger(&ledger_path) {
-            eprintln!("ledger precheck failed, error: {:?} ", e);
-            exit(1);
+        //if matches.is_present("precheck") {
+        if  precheck_str == "TRUE" {    
+            tryffi!(verify_ledger(&ledger_path), RusteloErrorCode::Ledger);
         }
-    }
-
-    let entries = match read_ledger(ledger_path, true) {
-        Ok(entries) => entries,
-        Err(err) => {
-            eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
-            exit(1);
-        }
-    };
 
+        let entries = tryffi!(read_ledger(ledger_path, true), RusteloErrorCode::Ledger);
 
-    //retreive the head number 
-    /*
-    let head = match matches.value_of("head") {
-        Some(head) => head.parse().expect("please pass a number for --head"),
-        None => <usize>::max_value(),
-    };
-    */
-    let head = if !head_str.is_empty() {
-        //cast the head_str to usize
-        match head_str.parse::<usize>(){
-            Ok(i)  => {
-                         i
-            },
-            Err(e) => {
-                        println!("A correct number is needed for head");
-                        std::process::exit(1);
-            }
-        }
-    } else {
-        <usize>::max_value()
-    };
 
-    /*
-    match matches.subcommand() {
-        ("print", _) => {
-            let entries = match read_ledger(ledger_path, true) {
-                Ok(entries) => entries,
-                Err(err) => {
-                    eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
-                    exit(1);
+        //retreive the head number 
+        /*
+        let head = match matches.value_of("head") {
+            Some(head) => head.parse().expect("please pass a number for --head"),
+            None => <usize>::max_value(),
+        };
+        */
+        let head = if !head_str.is_empty() {
+            //cast the head_str to usize
+            match head_str.parse::<usize>(){
+                Ok(i)  => {
+                             i
+                },
+                Err(e) => {
+                            return record_error(
+                                RusteloErrorCode::InvalidArgument,
+                                format!("A correct number is needed for head: {}", e),
+                            );
                 }
-            };
-            for (i, entry) in entries.enumerate() {
-                if i >= head {
-                    break;
-                }
-                let entry = entry.unwrap();
-                println!("{:?}", entry);
             }
-        }
-        ("json", _) => {
-            stdout().write_all(b"{\"ledger\":[\n").expect("open array");
-            for (i, entry) in entries.enumerate() {
-                if i >= head {
-                    break;
-                }
-                let entry = entry.unwrap();
-                serde_json::to_writer(stdout(), &entry).expect("serialize");
-                stdout().write_all(b",\n").expect("newline");
-            }
-            stdout().write_all(b"\n]}\n").expect("close array");
-        }
-        ("verify", _) => {
-            if head < 2 {
-                eprintln!("verify requires at least 2 entries to run");
-                exit(1);
-            }
-            let bank = Bank::default();
+        } else {
+            <usize>::max_value()
+        };
 
-            {
-                let genesis = match read_ledger(ledger_path, true) {
+        /*
+        match matches.subcommand() {
+            ("print", _) => {
+                let entries = match read_ledger(ledger_path, true) {
                     Ok(entries) => entries,
                     Err(err) => {
                         eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
                         exit(1);
                     }
                 };
-
-                let genesis = genesis.take(2).map(|e| e.unwrap());
-
-                if let Err(e) = bank.process_ledger(genesis) {
-                    eprintln!("verify failed at genesis err: {:?}", e);
-                    if !matches.is_present("continue") {
-                        exit(1);
+                for (i, entry) in entries.enumerate() {
+                    if i >= head {
+                        break;
                     }
+                    let entry = entry.unwrap();
+                    println!("{:?}", entry);
                 }
             }
-            let entries = entries.map(|e| e.unwrap());
-
-            let head = head - 2;
-            for (i, entry) in entries.skip(2).enumerate() {
-                if i >= head {
-                    break;
-                }
-                if !entry.verify(&bank.last_id()) {
-                    eprintln!("entry.verify() failed at entry[{}]", i + 2);
-                    if !matches.is_present("continue") {
-                        exit(1);
-                    }
-                }
-                if let Err(e) = bank.process_entry(&entry) {
-                    eprintln!("verify failed at entry[{}], err: {:?}", i + 2, e);
-                    if !matches.is_present("continue") {
-                        exit(1);
+            ("json", _) => {
+                stdout().write_all(b"{\"ledger\":[\n").expect("open array");
+                for (i, entry) in entries.enumerate() {
+                    if i >= head {
+                        break;
                     }
+                    let entry = entry.unwrap();
+                    serde_json::to_writer(stdout(), &entry).expect("serialize");
+                    stdout().write_all(b",\n").expect("newline");
                 }
+                stdout().write_all(b"\n]}\n").expect("close array");
             }
-        }
-        ("", _) => {
-            eprintln!("{}", matches.usage());
-            exit(1);
-        }
-        _ => unreachable!(),
-    };
-    */
-    //handle subcommand parmeter: PRINT, JSON, VERIFY
-    match subcommand_str {
-        "PRINT" => {
-            let entries = match read_ledger(ledger_path, true) {
-                Ok(entries) => entries,
-                Err(err) => {
-                    eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
+            ("verify", _) => {
+                if head < 2 {
+                    eprintln!("verify requires at least 2 entries to run");
                     exit(1);
                 }
-            };
-            for (i, entry) in entries.enumerate() {
-                if i >= head {
-                    break;
+                let bank = Bank::default();
+
+                {
+                    let genesis = match read_ledger(ledger_path, true) {
+                        Ok(entries) => entries,
+                        Err(err) => {
+                            eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
+                            exit(1);
+                        }
+                    };
+
+                    let genesis = genesis.take(2).map(|e| e.unwrap());
+
+                    if let Err(e) = bank.process_ledger(genesis) {
+                        eprintln!("verify failed at genesis err: {:?}", e);
+                        if !matches.is_present("continue") {
+                            exit(1);
+                        }
+                    }
                 }
-                let entry = entry.unwrap();
-                println!("{:?}", entry);
-            }
-        },
-        "JSON" => {
-             stdout().write_all(b"{\"ledger\":[\n").expect("open array");
-            for (i, entry) in entries.enumerate() {
-                if i >= head {
-                    break;
+                let entries = entries.map(|e| e.unwrap());
+
+                let head = head - 2;
+                for (i, entry) in entries.skip(2).enumerate() {
+                    if i >= head {
+                        break;
+                    }
+                    if !entry.verify(&bank.last_id()) {
+                        eprintln!("entry.verify() failed at entry[{}]", i + 2);
+                        if !matches.is_present("continue") {
+                            exit(1);
+                        }
+                    }
+                    if let Err(e) = bank.process_entry(&entry) {
+                        eprintln!("verify failed at entry[{}], err: {:?}", i + 2, e);
+                        if !matches.is_present("continue") {
+                            exit(1);
+                        }
+                    }
                 }
-                let entry = entry.unwrap();
-                serde_json::to_writer(stdout(), &entry).expect("serialize");
-                stdout().write_all(b",\n").expect("newline");
             }
-            stdout().write_all(b"\n]}\n").expect("close array");
-        },
-        "VERIFY" => {
-            
-            if head < 2 {
-                eprintln!("verify requires at least 2 entries to run");
+            ("", _) => {
+                eprintln!("{}", matches.usage());
                 exit(1);
             }
-            let bank = Bank::default();
-
-            {
-                let genesis = match read_ledger(ledger_path, true) {
-                    Ok(entries) => entries,
-                    Err(err) => {
-                        eprintln!("Failed to open ledger at {}: {}", ledger_path, err);
-                        exit(1);
+            _ => unreachable!(),
+        };
+        */
+        //handle subcommand parmeter: PRINT, JSON, VERIFY
+        match subcommand_str {
+            "PRINT" => {
+                let entries = tryffi!(read_ledger(ledger_path, true), RusteloErrorCode::Ledger);
+                for (i, entry) in entries.enumerate() {
+                    if i >= head {
+                        break;
                     }
-                };
-
-                let genesis = genesis.take(2).map(|e| e.unwrap());
-
-                /* 
-                if let Err(e) = bank.process_ledger(genesis) {
-                    eprintln!("verify failed at genesis err: {:?}", e);
-                    if !matches.is_present("continue") {
-                        exit(1);
-                    }
-                } 
-                */
-                if let Err(e) = bank.process_ledger(genesis) {
-                    eprintln!("verify failed at genesis err: {:?}", e);
-                    if continue_str == "TRUE" {
-                        exit(1);
+                    let entry = tryffi!(entry, RusteloErrorCode::Ledger);
+                    println!("{:?}", entry);
+                }
+            },
+            "JSON" => {
+                tryffi!(stdout().write_all(b"{\"ledger\":[\n"), RusteloErrorCode::Io);
+                for (i, entry) in entries.enumerate() {
+                    if i >= head {
+                        break;
                     }
+                    let entry = tryffi!(entry, RusteloErrorCode::Ledger);
+                    tryffi!(serde_json::to_writer(stdout(), &entry), RusteloErrorCode::Serialization);
+                    tryffi!(stdout().write_all(b",\n"), RusteloErrorCode::Io);
                 }
-            }
-            let entries = entries.map(|e| e.unwrap());
-
-            let head = head - 2;
-            for (i, entry) in entries.skip(2).enumerate() {
-                if i >= head {
-                    break;
+                tryffi!(stdout().write_all(b"\n]}\n"), RusteloErrorCode::Io);
+            },
+            "VERIFY" => {
+            
+                if head < 2 {
+                    return record_error(
+                        RusteloErrorCode::InvalidArgument,
+                        "verify requires at least 2 entries to run",
+                    );
                 }
-                if !entry.verify(&bank.last_id()) {
-                    eprintln!("entry.verify() failed at entry[{}]", i + 2);
-                    //if !matches.is_present("continue") {
-                    if continue_str == "FALSE" {
-                        exit(1);
+                let bank = Bank::default();
+
+                {
+                    let genesis = tryffi!(read_ledger(ledger_path, true), RusteloErrorCode::Ledger);
+
+                    let genesis: Vec<_> = tryffi!(genesis.take(2).collect::<Result<Vec<_>, _>>(), RusteloErrorCode::Ledger);
+
+                    /* 
+                    if let Err(e) = bank.process_ledger(genesis) {
+                        eprintln!("verify failed at genesis err: {:?}", e);
+                        if !matches.is_present("continue") {
+                            exit(1);
+                        }
+                    } 
+                    */
+                    if let Err(e) = bank.process_ledger(genesis) {
+                        eprintln!("verify failed at genesis err: {:?}", e);
+                        if continue_str == "FALSE" {
+                            return record_error(RusteloErrorCode::Ledger, format!("{:?}", e));
+                        }
                     }
                 }
-                if let Err(e) = bank.process_entry(&entry) {
-                    eprintln!("verify failed at entry[{}], err: {:?}", i + 2, e);
-                    //if !matches.is_present("continue") {
-                    if continue_str == "FALSE" {    
-                        exit(1);
+                let head = head - 2;
+                for (i, entry) in entries.skip(2).enumerate() {
+                    if i >= head {
+                        break;
+                    }
+                    let entry = tryffi!(entry, RusteloErrorCode::Ledger);
+                    if !entry.verify(&bank.last_id()) {
+                        eprintln!("entry.verify() failed at entry[{}]", i + 2);
+                        //if !matches.is_present("continue") {
+                        if continue_str == "FALSE" {
+                            return record_error(
+                                RusteloErrorCode::Ledger,
+                                format!("entry.verify() failed at entry[{}]", i + 2),
+                            );
+                        }
+                    }
+                    if let Err(e) = bank.process_entry(&entry) {
+                        eprintln!("verify failed at entry[{}], err: {:?}", i + 2, e);
+                        //if !matches.is_present("continue") {
+                        if continue_str == "FALSE" {    
+                            return record_error(RusteloErrorCode::Ledger, format!("{:?}", e));
+                        }
                     }
                 }
+            },
+            _ =>  {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("unknown subcommand '{}', expected PRINT, JSON or VERIFY", subcommand_str),
+                );
             }
-        },
-        "NULL" =>  {
-            eprintln!("{}", "something wrong with subcommand");
-            exit(1);
         }
-        _ =>  unreachable!(),
-    }
+
+        RusteloResult::Success
+    })
 }
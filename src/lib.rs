@@ -15,22 +15,27 @@ extern crate tokio;
 extern crate tokio_codec;
 extern crate influx_db_client;
 extern crate rayon;
+extern crate buffett_program_interface;
 
 #[macro_use]
 pub mod macros;
 #[macro_use]
 pub mod benchcaster_main;
 pub mod benchmarker_main;
+pub mod callbacks;
 #[macro_use]
 pub mod coincaster_main;
 pub mod fullnode_config_main;
 pub mod fullnode_main;
 #[macro_use]
 pub mod genesis_main;
+pub mod handle_registry;
 #[macro_use]
 pub mod keygen_main;
 pub mod ledgertool_main;
+pub mod main_execute;
 pub mod propagator_main;
+pub mod submitter;
 pub mod upload_enhancer_main;
 #[macro_use]
 pub mod wallet_main;
@@ -1,16 +1,14 @@
 #[macro_export]
 macro_rules! tryffi {
     ($expr:expr) => {
+        tryffi!($expr, crate::rustelo_error::RusteloErrorCode::Unknown)
+    };
+    ($expr:expr, $code:expr) => {
         match $expr {
             Ok(expr) => expr,
             Err(err) => {
-                //crate::rustelo_error::ERROR
-                //    .lock()
-                //    .replace(failure::Error::from(err));
-                println!("Expr error when running {:?}", err);
-                return crate::rustelo_error::RusteloResult::Failure;
+                return crate::rustelo_error::record_error($code, err);
             }
-            
         }
     };
-}
\ No newline at end of file
+}
@@ -1 +1,464 @@
-//Try FFI features in Rust
\ No newline at end of file
+//main_execute is the JSON entry point for the Go bridge. rustelo_execute takes a request
+//object whose "command" field selects the operation and answers with a response object,
+//so a new operation only needs a new ExecuteRequest variant.
+use crate::benchmarker_main::benchmarker_main_entry;
+use crate::fullnode_config_main::fullnode_config_main_entry;
+use crate::genesis_main::genesis_main_entry;
+use crate::keygen_main::keygen_main_entry;
+use crate::ledgertool_main::ledgertool_main_entry;
+use crate::rustelo_error::{
+    arg_str, catch_panic, take_last_error, RusteloError, RusteloErrorCode, RusteloResult,
+};
+use crate::wallet_main::{
+    parse_datetime, parse_pubkey, parse_signature, split_pay_result, wallet_error, with_client,
+};
+use buffett::signature::KeypairUtil;
+use buffett::wallet::{
+    confirm_transaction, get_balance, process_command, request_airdrop, WalletCommand,
+};
+use buffett_program_interface::pubkey::Pubkey;
+use chrono::prelude::*;
+use libc::c_char;
+use mbox::MString;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::ffi::CString;
+use std::ptr::null_mut;
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ExecuteRequest {
+    //an empty outfile writes ~/.config/solana/id.json
+    Keygen {
+        #[serde(default)]
+        outfile: String,
+    },
+    //the mint keypair is read from stdin, as for genesis_main_entry
+    Genesis {
+        tokens: i64,
+        ledger: String,
+    },
+    //client is a handle returned by rustelo_client_open
+    Wallet {
+        client: u64,
+        #[serde(flatten)]
+        action: WalletAction,
+    },
+    LedgerTool {
+        ledger: String,
+        head: Option<u64>,
+        #[serde(default)]
+        precheck: bool,
+        #[serde(default, rename = "continue")]
+        continue_on_error: bool,
+        subcommand: LedgerSubcommand,
+    },
+    FullnodeConfig {
+        #[serde(default)]
+        local: bool,
+        #[serde(default)]
+        keypair: String,
+        #[serde(default)]
+        public: bool,
+        #[serde(default)]
+        bind: String,
+        #[serde(default)]
+        outfile: String,
+    },
+    Benchmark {
+        network: String,
+        #[serde(default)]
+        identity: String,
+        num_nodes: Option<u64>,
+        #[serde(default)]
+        reject_extra_nodes: bool,
+        threads: Option<u64>,
+        duration: Option<u64>,
+        #[serde(default)]
+        converge_only: bool,
+        #[serde(default)]
+        sustained: bool,
+        tx_count: Option<u64>,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum WalletAction {
+    Balance,
+    Airdrop {
+        tokens: i64,
+    },
+    //witness and witnesses are joined, required_signatures of them must sign;
+    //the splits are paid out alongside to
+    Pay {
+        to: String,
+        tokens: i64,
+        timestamp: Option<String>,
+        timestamp_pubkey: Option<String>,
+        witness: Option<String>,
+        #[serde(default)]
+        witnesses: Vec<String>,
+        required_signatures: Option<u64>,
+        #[serde(default)]
+        splits: Vec<Split>,
+        #[serde(default)]
+        cancelable: bool,
+    },
+    Confirm {
+        signature: String,
+    },
+    //payees are paid along with to, as the wallet's --payee
+    Witness {
+        to: String,
+        #[serde(default)]
+        payees: Vec<String>,
+        process_id: String,
+    },
+    //a missing datetime applies the current time
+    TimeElapsed {
+        to: String,
+        #[serde(default)]
+        payees: Vec<String>,
+        process_id: String,
+        datetime: Option<String>,
+    },
+    Cancel {
+        process_id: String,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Split {
+    to: String,
+    tokens: i64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerSubcommand {
+    Print,
+    Json,
+    Verify,
+}
+
+impl LedgerSubcommand {
+    //the spelling ledgertool_main_entry matches on
+    fn as_str(self) -> &'static str {
+        match self {
+            LedgerSubcommand::Print => "PRINT",
+            LedgerSubcommand::Json => "JSON",
+            LedgerSubcommand::Verify => "VERIFY",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ExecuteResponse {
+    Ok { result: Value },
+    //code is a RusteloErrorCode, causes is the source chain of message
+    Error {
+        code: i32,
+        message: String,
+        causes: Vec<String>,
+    },
+}
+
+impl From<RusteloError> for ExecuteResponse {
+    fn from(err: RusteloError) -> Self {
+        ExecuteResponse::Error {
+            code: err.code as i32,
+            message: err.message,
+            causes: err.causes,
+        }
+    }
+}
+
+fn c_string(value: &str, name: &str) -> Result<CString, RusteloError> {
+    CString::new(value).or_else(|_| {
+        Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("'{}' contains a NUL byte", name),
+        ))
+    })
+}
+
+//the positional entry points take booleans as "TRUE" or "FALSE"
+fn flag(value: bool) -> CString {
+    CString::new(if value { "TRUE" } else { "FALSE" }).unwrap()
+}
+
+fn opt_number(value: Option<u64>) -> CString {
+    CString::new(value.map(|n| n.to_string()).unwrap_or_default()).unwrap()
+}
+
+//entry_result turns the result of a positional entry point into the command result
+fn entry_result(result: RusteloResult) -> Result<Value, RusteloError> {
+    match result {
+        RusteloResult::Success => Ok(Value::Null),
+        RusteloResult::Failure => Err(take_last_error().unwrap_or_else(|| {
+            RusteloError::new(RusteloErrorCode::Unknown, "command failed")
+        })),
+    }
+}
+
+//to followed by the payees, as parse_payees in the wallet builds them
+fn parse_payees(to: &str, payees: &[String]) -> Result<Vec<Pubkey>, RusteloError> {
+    let mut pubkeys = vec![parse_pubkey(to, "to")?];
+    for payee in payees {
+        pubkeys.push(parse_pubkey(payee, "payee")?);
+    }
+    Ok(pubkeys)
+}
+
+fn execute_wallet(client: u64, action: WalletAction) -> Result<Value, RusteloError> {
+    let command = match action {
+        WalletAction::Balance => {
+            let balance = with_client(client, |client| get_balance(&client.config))
+                .map_err(wallet_error(RusteloErrorCode::Wallet))?;
+            return Ok(json!(balance));
+        }
+        WalletAction::Airdrop { tokens } => {
+            if tokens <= 0 {
+                return Err(RusteloError::new(
+                    RusteloErrorCode::InvalidArgument,
+                    &format!("Invalid airdrop amount: {}", tokens),
+                ));
+            }
+            let signature = with_client(client, |client| {
+                let config = &client.config;
+                Ok(request_airdrop(&config.drone_addr, &config.id.pubkey(), tokens as u64)?)
+            }).map_err(wallet_error(RusteloErrorCode::Network))?;
+            return Ok(json!(signature.to_string()));
+        }
+        WalletAction::Confirm { signature } => {
+            let signature = parse_signature(&signature)?;
+            let confirmed =
+                with_client(client, |client| confirm_transaction(&client.config, &signature))
+                    .map_err(wallet_error(RusteloErrorCode::Wallet))?;
+            return Ok(json!(confirmed));
+        }
+        WalletAction::Pay {
+            to,
+            tokens,
+            timestamp,
+            timestamp_pubkey,
+            witness,
+            witnesses,
+            required_signatures,
+            splits,
+            cancelable,
+        } => {
+            if tokens <= 0 {
+                return Err(RusteloError::new(
+                    RusteloErrorCode::InvalidArgument,
+                    &format!("Invalid transfer amount: {}", tokens),
+                ));
+            }
+            let to = parse_pubkey(&to, "to")?;
+            let timestamp = match timestamp {
+                Some(dt) => Some(parse_datetime(&dt)?),
+                None => None,
+            };
+            let timestamp_pubkey = match timestamp_pubkey {
+                Some(pubkey) => Some(parse_pubkey(&pubkey, "timestamp")?),
+                None => None,
+            };
+            let mut witness_vec = Vec::new();
+            for witness in witness.iter().chain(witnesses.iter()) {
+                witness_vec.push(parse_pubkey(witness, "witness")?);
+            }
+            let witnesses = if witness_vec.is_empty() {
+                None
+            } else {
+                Some(witness_vec)
+            };
+            if let Some(required) = required_signatures {
+                let count = witnesses.as_ref().map_or(0, |witnesses| witnesses.len());
+                if required == 0 || required > count as u64 {
+                    return Err(RusteloError::new(
+                        RusteloErrorCode::InvalidArgument,
+                        &format!(
+                            "Invalid required signatures: {} of {} witnesses",
+                            required, count
+                        ),
+                    ));
+                }
+            }
+            let mut split_vec = Vec::new();
+            for split in splits {
+                if split.tokens <= 0 {
+                    return Err(RusteloError::new(
+                        RusteloErrorCode::InvalidArgument,
+                        &format!("Invalid split amount: {}", split.tokens),
+                    ));
+                }
+                split_vec.push((parse_pubkey(&split.to, "split")?, split.tokens));
+            }
+            let result = with_client(client, |client| {
+                let cancelable = if cancelable {
+                    Some(client.config.id.pubkey())
+                } else {
+                    None
+                };
+                client.config.command = WalletCommand::Pay(
+                    tokens,
+                    to,
+                    timestamp,
+                    timestamp_pubkey,
+                    witnesses,
+                    required_signatures,
+                    split_vec,
+                    cancelable,
+                );
+                process_command(&client.config)
+            }).map_err(wallet_error(RusteloErrorCode::Wallet))?;
+            let (signature, process_id) = split_pay_result(result);
+            return Ok(json!({ "signature": signature, "process_id": process_id }));
+        }
+        WalletAction::Witness {
+            to,
+            payees,
+            process_id,
+        } => WalletCommand::Witness(
+            parse_payees(&to, &payees)?,
+            parse_pubkey(&process_id, "process id")?,
+        ),
+        WalletAction::TimeElapsed {
+            to,
+            payees,
+            process_id,
+            datetime,
+        } => WalletCommand::TimeElapsed(
+            parse_payees(&to, &payees)?,
+            parse_pubkey(&process_id, "process id")?,
+            match datetime {
+                Some(dt) => parse_datetime(&dt)?,
+                None => Utc::now(),
+            },
+        ),
+        WalletAction::Cancel { process_id } => {
+            WalletCommand::Cancel(parse_pubkey(&process_id, "process id")?)
+        }
+    };
+
+    let signature = with_client(client, |client| {
+        client.config.command = command;
+        process_command(&client.config)
+    }).map_err(wallet_error(RusteloErrorCode::Wallet))?;
+    Ok(json!(signature))
+}
+
+pub fn execute(request: ExecuteRequest) -> Result<Value, RusteloError> {
+    match request {
+        ExecuteRequest::Keygen { outfile } => {
+            let outfile = c_string(&outfile, "outfile")?;
+            entry_result(keygen_main_entry(outfile.as_ptr()))
+        }
+        ExecuteRequest::Genesis { tokens, ledger } => {
+            let tokens = c_string(&tokens.to_string(), "tokens")?;
+            let ledger = c_string(&ledger, "ledger")?;
+            entry_result(genesis_main_entry(tokens.as_ptr(), ledger.as_ptr()))
+        }
+        ExecuteRequest::Wallet { client, action } => execute_wallet(client, action),
+        ExecuteRequest::LedgerTool {
+            ledger,
+            head,
+            precheck,
+            continue_on_error,
+            subcommand,
+        } => {
+            let ledger = c_string(&ledger, "ledger")?;
+            let head = opt_number(head);
+            let subcommand = CString::new(subcommand.as_str()).unwrap();
+            entry_result(ledgertool_main_entry(
+                ledger.as_ptr(),
+                head.as_ptr(),
+                flag(precheck).as_ptr(),
+                flag(continue_on_error).as_ptr(),
+                subcommand.as_ptr(),
+            ))
+        }
+        ExecuteRequest::FullnodeConfig {
+            local,
+            keypair,
+            public,
+            bind,
+            outfile,
+        } => {
+            let keypair = c_string(&keypair, "keypair")?;
+            let bind = c_string(&bind, "bind")?;
+            let outfile = c_string(&outfile, "outfile")?;
+            entry_result(fullnode_config_main_entry(
+                flag(local).as_ptr(),
+                keypair.as_ptr(),
+                flag(public).as_ptr(),
+                bind.as_ptr(),
+                outfile.as_ptr(),
+            ))
+        }
+        ExecuteRequest::Benchmark {
+            network,
+            identity,
+            num_nodes,
+            reject_extra_nodes,
+            threads,
+            duration,
+            converge_only,
+            sustained,
+            tx_count,
+        } => {
+            let network = c_string(&network, "network")?;
+            let identity = c_string(&identity, "identity")?;
+            let num_nodes = opt_number(num_nodes);
+            let threads = opt_number(threads);
+            let duration = opt_number(duration);
+            let tx_count = opt_number(tx_count);
+            entry_result(benchmarker_main_entry(
+                network.as_ptr(),
+                identity.as_ptr(),
+                num_nodes.as_ptr(),
+                flag(reject_extra_nodes).as_ptr(),
+                threads.as_ptr(),
+                duration.as_ptr(),
+                flag(converge_only).as_ptr(),
+                flag(sustained).as_ptr(),
+                tx_count.as_ptr(),
+            ))
+        }
+    }
+}
+
+fn execute_json(json_request: *const c_char) -> Result<Value, RusteloError> {
+    let request = arg_str(json_request, "json_request")?;
+    let request: ExecuteRequest = serde_json::from_str(request)
+        .or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::InvalidArgument, &err)))?;
+    execute(request)
+}
+
+/// rustelo_execute runs the command described by a JSON request and returns a JSON
+/// response, {"status": "ok", "result": ..} or
+/// {"status": "error", "code": .., "message": .., "causes": [..]}.
+/// The request is an object whose "command" field is one of keygen, genesis, wallet,
+/// ledger-tool, fullnode-config or benchmark.
+/// The returned string must be released with rustelo_free_string.
+#[no_mangle]
+pub extern "C" fn rustelo_execute(json_request: *const c_char) -> *mut c_char {
+    let result = catch_panic(None, || Some(execute_json(json_request)));
+    let response = match result {
+        Some(Ok(result)) => ExecuteResponse::Ok { result },
+        Some(Err(err)) => ExecuteResponse::from(err),
+        // the panic was recorded in the last-error slot
+        None => ExecuteResponse::from(take_last_error().unwrap_or_else(|| {
+            RusteloError::new(RusteloErrorCode::Panic, "command panicked")
+        })),
+    };
+
+    match serde_json::to_string(&response) {
+        Ok(response) => MString::from_str(&response)
+            .into_mbox_with_sentinel()
+            .into_raw() as _,
+        Err(_) => null_mut(),
+    }
+}
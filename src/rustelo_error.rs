@@ -1,9 +1,13 @@
-use failure::Error;
-use libc::c_char;
+use libc::{c_char, c_void};
 use mbox::MString;
-use once_cell::{sync::Lazy, sync_lazy};
-use parking_lot::Mutex;
+use std::any::Any;
+use std::cell::RefCell;
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr::null_mut;
+use std::thread;
 
 //RusteloResult is a workaround for Rust Result data type.
 
@@ -13,19 +17,247 @@ pub enum RusteloResult {
     Failure = 1,
 }
 
+/// RusteloErrorCode is the category of the last error, it is what Go switches on.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RusteloErrorCode {
+    NoError = 0,
+    InvalidArgument = 1,
+    Io = 2,
+    Serialization = 3,
+    Keypair = 4,
+    Network = 5,
+    Ledger = 6,
+    Wallet = 7,
+    Fullnode = 8,
+    /// a panic was caught at the FFI boundary
+    Panic = 9,
+    Unknown = 255,
+}
+
+//RusteloError is the typed error stored in the last-error slot:
+//a category code, a message and the chain of underlying causes.
+#[derive(Debug, Clone)]
+pub struct RusteloError {
+    pub code: RusteloErrorCode,
+    pub message: String,
+    pub causes: Vec<String>,
+}
+
+impl RusteloError {
+    pub fn new(code: RusteloErrorCode, message: &str) -> Self {
+        RusteloError {
+            code,
+            message: message.to_string(),
+            causes: vec![],
+        }
+    }
+
+    //build an error from any std error, walking its source chain. An error that was
+    //already categorized keeps its own code.
+    pub fn from_error(code: RusteloErrorCode, err: &(error::Error + 'static)) -> Self {
+        if let Some(rustelo_err) = err.downcast_ref::<RusteloError>() {
+            return rustelo_err.clone();
+        }
+
+        let mut causes = vec![];
+        let mut source = err.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        RusteloError {
+            code,
+            message: err.to_string(),
+            causes,
+        }
+    }
+}
+
+impl fmt::Display for RusteloError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for cause in &self.causes {
+            write!(f, "\ncaused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for RusteloError {}
+
+//the last-error slot is per thread like errno, a call only sees the errors of the calls
+//made on its own thread
+thread_local! {
+    static LAST_ERROR: RefCell<Option<RusteloError>> = RefCell::new(None);
+}
+
+//set_last_error replaces the error in the last-error slot of the calling thread
+pub fn set_last_error(err: RusteloError) {
+    LAST_ERROR.with(|slot| slot.borrow_mut().replace(err));
+}
+
+//categorize gives code to an error that has no category yet, a RusteloError raised
+//earlier (a bad handle or argument) keeps its own
+pub fn categorize(code: RusteloErrorCode, err: &(error::Error + 'static)) -> RusteloError {
+    RusteloError::from_error(code, err)
+}
+
+//take_last_error empties the last-error slot of the calling thread
+pub fn take_last_error() -> Option<RusteloError> {
+    LAST_ERROR.with(|slot| slot.borrow_mut().take())
+}
+
+fn last_error_code() -> RusteloErrorCode {
+    LAST_ERROR.with(|slot| {
+        slot.borrow()
+            .as_ref()
+            .map_or(RusteloErrorCode::NoError, |err| err.code)
+    })
+}
+
+//record_error categorizes an error, stores it and returns the FFI failure value,
+//it is what tryffi! expands to on the Err branch
+pub fn record_error<E>(code: RusteloErrorCode, err: E) -> RusteloResult
+where
+    E: Into<Box<error::Error>>,
+{
+    let err: Box<error::Error> = err.into();
+    set_last_error(RusteloError::from_error(code, &*err));
+    RusteloResult::Failure
+}
+
+//catch_panic runs the body of an exported function. A panic is recorded in the
+//last-error slot and turned into the failure value instead of unwinding into Go.
+pub fn catch_panic<T, F>(failure: T, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            set_last_error(panic_error(&*payload));
+            failure
+        }
+    }
+}
+
+fn panic_error(payload: &(Any + Send)) -> RusteloError {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    };
+    RusteloError::new(RusteloErrorCode::Panic, &format!("panicked: {}", message))
+}
+
+//thread_result turns the result of joining a thread into a Result tryffi! accepts,
+//a thread that panicked is reported like a panic caught at the FFI boundary
+pub fn thread_result<T>(result: thread::Result<T>) -> Result<T, RusteloError> {
+    result.map_err(|payload| panic_error(&*payload))
+}
+
+//arg_str converts a C string argument into &str, reporting NULL or invalid UTF-8
+pub fn arg_str<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, RusteloError> {
+    if ptr.is_null() {
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("argument '{}' is NULL", name),
+        ));
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().or_else(|_| {
+        Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("argument '{}' is not valid UTF-8", name),
+        ))
+    })
+}
+
+//arg_bytes borrows a byte buffer argument, reporting a NULL pointer
+pub fn arg_bytes<'a>(ptr: *const u8, len: usize, name: &str) -> Result<&'a [u8], RusteloError> {
+    if ptr.is_null() {
+        if len == 0 {
+            return Ok(&[]);
+        }
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("argument '{}' is NULL", name),
+        ));
+    }
+    Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
 
-pub(crate) static ERROR: Lazy<Mutex<Option<Error>>> = sync_lazy! {
-    Mutex::new(None)
-};
+//write_out_bytes copies bytes into a caller-provided buffer
+pub fn write_out_bytes(value: &[u8], out: *mut u8, out_len: usize) -> Result<(), RusteloError> {
+    if out.is_null() {
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            "output buffer is NULL",
+        ));
+    }
+    if value.len() > out_len {
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("output buffer too small, {} bytes needed", value.len()),
+        ));
+    }
+    unsafe { std::ptr::copy_nonoverlapping(value.as_ptr(), out, value.len()) };
+    Ok(())
+}
 
-//rustelo_handle_error returns an error 
+//write_out_str copies a string and its NUL terminator into a caller-provided buffer
+pub fn write_out_str(value: &str, out: *mut c_char, out_len: usize) -> Result<(), RusteloError> {
+    if out.is_null() {
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            "output buffer is NULL",
+        ));
+    }
+    if value.len() + 1 > out_len {
+        return Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("output buffer too small, {} bytes needed", value.len() + 1),
+        ));
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(value.as_ptr(), out as *mut u8, value.len());
+        *out.add(value.len()) = 0;
+    }
+    Ok(())
+}
+
+/// rustelo_handle_error returns the message of the last error on the calling thread,
+/// followed by its causes, and clears the slot. Returns NULL if no error has occurred.
+/// The returned string must be released with rustelo_free_string.
 #[no_mangle]
 pub extern "C" fn rustelo_handle_error() -> *mut c_char {
-    match ERROR.lock().take() {
-        Some(err) => MString::from_str(&err.to_string())
-            .into_mbox_with_sentinel()
-            .into_raw() as _,
+    catch_panic(null_mut(), || {
+        match take_last_error() {
+            Some(err) => MString::from_str(&err.to_string())
+                .into_mbox_with_sentinel()
+                .into_raw() as _,
 
-        None => null_mut(),
-    }
-}
\ No newline at end of file
+            None => null_mut(),
+        }
+    })
+}
+
+/// rustelo_last_error_code returns the category of the last error on the calling thread
+/// without clearing it, call it before rustelo_handle_error
+#[no_mangle]
+pub extern "C" fn rustelo_last_error_code() -> i32 {
+    catch_panic(RusteloErrorCode::Panic as i32, || last_error_code() as i32)
+}
+
+/// rustelo_free_string releases a string returned by this library,
+/// MString buffers are allocated with malloc
+#[no_mangle]
+pub extern "C" fn rustelo_free_string(ptr: *mut c_char) {
+    catch_panic((), || {
+        if !ptr.is_null() {
+            unsafe { libc::free(ptr as *mut c_void) };
+        }
+    })
+}
@@ -0,0 +1,771 @@
+//submitter sends transactions without blocking the caller. A pool of worker threads
+//owned by a submitter handle sends each submission, then polls the leader for its
+//signature status and reports the outcome through a RusteloSubmitCallback. Workers
+//take the submissions that are due a round at a time, poll the statuses of all of them
+//with one request and requeue the ones still pending, so thousands of submissions can
+//be in flight with only a few threads. Every call a worker makes is bounded by a read
+//timeout, so a worker never waits on the network past a submission's deadline for long.
+use bincode::deserialize;
+use buffett::client::mk_client;
+use buffett::request::MAX_SIGNATURES_PER_REQUEST;
+use buffett::signature::{KeypairUtil, Signature};
+use buffett::system_transaction::SystemTransaction;
+use buffett::tcp_streamer::is_timeout;
+use buffett::thin_client::ThinClient;
+use buffett::transaction::Transaction;
+use buffett::tx_vault::BankError;
+use buffett::wallet::request_airdrop_with_timeout;
+use buffett_program_interface::pubkey::Pubkey;
+use libc::c_char;
+use once_cell::{sync::Lazy, sync_lazy};
+use parking_lot::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use crate::handle_registry::HandleRegistry;
+use crate::rustelo_error::{
+    arg_bytes, arg_str, catch_panic, record_error, RusteloError, RusteloErrorCode,
+    RusteloResult,
+};
+use crate::wallet_main::{parse_pubkey, WalletClient, CLIENTS};
+
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const AIRDROP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// RusteloSubmitStatus is the outcome passed to a RusteloSubmitCallback
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RusteloSubmitStatus {
+    Confirmed = 0,
+    /// rejected by the bank or not sent, error names the BankError or the reason
+    Failed = 1,
+    /// not confirmed before the submitter timeout, or the submitter was closed
+    TimedOut = 2,
+}
+
+/// status is a RusteloSubmitStatus. signature is the base58 transaction signature,
+/// NULL if the transaction was never sent, and error is NULL once confirmed.
+pub type RusteloSubmitCallback = Option<
+    extern "C" fn(
+        context: u64,
+        request_id: u64,
+        status: i32,
+        signature: *const c_char,
+        error: *const c_char,
+    ),
+>;
+
+enum Job {
+    Transaction(Transaction),
+    Transfer { to: Pubkey, tokens: i64 },
+    Airdrop { tokens: u64 },
+}
+
+struct Submission {
+    request_id: u64,
+    context: u64,
+    callback: extern "C" fn(u64, u64, i32, *const c_char, *const c_char),
+    //taken once the transaction has been sent
+    job: Option<Job>,
+    signature: Option<Signature>,
+    deadline: Instant,
+    next_poll: Instant,
+}
+
+impl Submission {
+    fn complete(self, status: RusteloSubmitStatus, error: Option<String>) {
+        let signature = self
+            .signature
+            .map(|signature| CString::new(signature.to_string()).unwrap());
+        let error = error.map(|error| CString::new(error.replace('\0', "")).unwrap());
+        (self.callback)(
+            self.context,
+            self.request_id,
+            status as i32,
+            signature.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            error.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+        );
+    }
+}
+
+struct Queue {
+    submissions: VecDeque<Submission>,
+    next_request_id: u64,
+    closed: bool,
+}
+
+//SubmitShared is what the workers of a submitter share, the wallet client supplies
+//the keypair for transfers and the drone address for airdrops
+struct SubmitShared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    wallet: Arc<Mutex<WalletClient>>,
+    timeout: Duration,
+}
+
+pub struct Submitter {
+    shared: Arc<SubmitShared>,
+}
+
+impl Submitter {
+    fn open(
+        wallet: Arc<Mutex<WalletClient>>,
+        workers: usize,
+        timeout: Duration,
+    ) -> Result<Self, RusteloError> {
+        let leader = wallet.lock().config.leader.clone();
+        let shared = Arc::new(SubmitShared {
+            queue: Mutex::new(Queue {
+                submissions: VecDeque::new(),
+                next_request_id: 1,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            wallet,
+            timeout,
+        });
+
+        let submitter = Submitter { shared };
+        for i in 0..workers {
+            //each worker has its own sockets so status polls do not steal each
+            //other's responses
+            let client = mk_client(&leader);
+            let shared = submitter.shared.clone();
+            let worker = Builder::new()
+                .name(format!("rustelo-submit-{}", i))
+                .spawn(move || run_worker(&shared, client));
+            //workers are not joined, they exit on their own once the submitter is closed
+            match worker {
+                Ok(_) => (),
+                Err(err) => {
+                    submitter.close();
+                    return Err(RusteloError::from_error(RusteloErrorCode::Wallet, &err));
+                }
+            }
+        }
+        Ok(submitter)
+    }
+
+    fn submit(
+        &self,
+        job: Job,
+        callback: extern "C" fn(u64, u64, i32, *const c_char, *const c_char),
+        context: u64,
+    ) -> u64 {
+        let now = Instant::now();
+        let mut queue = self.shared.queue.lock();
+        let request_id = queue.next_request_id;
+        queue.next_request_id += 1;
+        queue.submissions.push_back(Submission {
+            request_id,
+            context,
+            callback,
+            job: Some(job),
+            signature: None,
+            deadline: now + self.shared.timeout,
+            next_poll: now,
+        });
+        self.shared.ready.notify_one();
+        request_id
+    }
+
+    //close signals the workers to stop without waiting for them and times out the
+    //queued submissions, a submission a worker is sending times out once that call returns
+    fn close(&self) {
+        let submissions: Vec<_> = {
+            let mut queue = self.shared.queue.lock();
+            queue.closed = true;
+            queue.submissions.drain(..).collect()
+        };
+        self.shared.ready.notify_all();
+        for submission in submissions {
+            submission.complete(
+                RusteloSubmitStatus::TimedOut,
+                Some("submitter was closed".to_string()),
+            );
+        }
+    }
+}
+
+//next_submissions waits for the submission at the front of the queue to be due. A
+//submission still to be sent is taken alone, sent ones are taken with the sent ones
+//due after them, as many as one status request holds. None means the submitter is closing
+fn next_submissions(shared: &SubmitShared) -> Option<Vec<Submission>> {
+    let mut queue = shared.queue.lock();
+    loop {
+        if queue.closed {
+            return None;
+        }
+        let now = Instant::now();
+        match queue.submissions.front().map(|submission| submission.next_poll) {
+            Some(next_poll) if next_poll <= now => {
+                let due = queue
+                    .submissions
+                    .iter()
+                    .take(MAX_SIGNATURES_PER_REQUEST)
+                    .take_while(|submission| {
+                        submission.job.is_none() && submission.next_poll <= now
+                    })
+                    .count()
+                    .max(1);
+                return Some(queue.submissions.drain(..due).collect());
+            }
+            Some(next_poll) => {
+                shared.ready.wait_for(&mut queue, next_poll - now);
+            }
+            None => shared.ready.wait(&mut queue),
+        }
+    }
+}
+
+enum SendError {
+    //the leader did not answer in time, the job is sent again on the next round
+    Retry(Job),
+    //the drone did not answer in time, it may still have sent the tokens
+    TimedOut(String),
+    Failed(String),
+}
+
+//send sends a job, taking no longer than the read timeout of the client or
+//remaining, whichever is shorter
+fn send(
+    shared: &SubmitShared,
+    client: &mut ThinClient,
+    job: Job,
+    remaining: Duration,
+) -> Result<Signature, SendError> {
+    match job {
+        Job::Transaction(tx) => client
+            .transfer_signed(&tx)
+            .map_err(|err| SendError::Failed(err.to_string())),
+        Job::Transfer { to, tokens } => {
+            let last_id = match client.try_get_last_id() {
+                Ok(last_id) => last_id,
+                Err(ref err) if is_timeout(err) => {
+                    return Err(SendError::Retry(Job::Transfer { to, tokens }));
+                }
+                Err(err) => return Err(SendError::Failed(err.to_string())),
+            };
+            let tx = {
+                let wallet = shared.wallet.lock();
+                Transaction::system_new(&wallet.config.id, to, tokens, last_id)
+            };
+            client
+                .transfer_signed(&tx)
+                .map_err(|err| SendError::Failed(err.to_string()))
+        }
+        Job::Airdrop { tokens } => {
+            let (drone_addr, pubkey) = {
+                let wallet = shared.wallet.lock();
+                (wallet.config.drone_addr, wallet.config.id.pubkey())
+            };
+            let timeout = remaining.min(AIRDROP_TIMEOUT);
+            request_airdrop_with_timeout(&drone_addr, &pubkey, tokens, timeout).map_err(|err| {
+                if is_timeout(&err) {
+                    SendError::TimedOut("drone did not answer in time".to_string())
+                } else {
+                    SendError::Failed(err.to_string())
+                }
+            })
+        }
+    }
+}
+
+//step moves submissions forward by one send each or by one status poll for all the
+//ones already sent, it returns the submissions that still have to be polled
+fn step(
+    shared: &SubmitShared,
+    client: &mut ThinClient,
+    submissions: Vec<Submission>,
+) -> Vec<Submission> {
+    let mut sent = vec![];
+    let mut pending = vec![];
+    for mut submission in submissions {
+        let now = Instant::now();
+        if now >= submission.deadline {
+            submission.complete(
+                RusteloSubmitStatus::TimedOut,
+                Some("transaction was not confirmed in time".to_string()),
+            );
+            continue;
+        }
+
+        match submission.job.take() {
+            Some(job) => match send(shared, client, job, submission.deadline - now) {
+                Ok(signature) => {
+                    submission.signature = Some(signature);
+                    pending.push(submission);
+                }
+                Err(SendError::Retry(job)) => {
+                    submission.job = Some(job);
+                    pending.push(submission);
+                }
+                Err(SendError::TimedOut(err)) => {
+                    submission.complete(RusteloSubmitStatus::TimedOut, Some(err))
+                }
+                Err(SendError::Failed(err)) => {
+                    submission.complete(RusteloSubmitStatus::Failed, Some(err))
+                }
+            },
+            None => sent.push(submission),
+        }
+    }
+
+    if !sent.is_empty() {
+        let signatures: Vec<_> = sent
+            .iter()
+            .filter_map(|submission| submission.signature)
+            .collect();
+        match client.get_signature_statuses(&signatures) {
+            Ok(statuses) => {
+                for (submission, status) in sent.into_iter().zip(statuses) {
+                    match status {
+                        Ok(()) => submission.complete(RusteloSubmitStatus::Confirmed, None),
+                        Err(BankError::SignatureNotFound) => pending.push(submission),
+                        Err(err) => submission
+                            .complete(RusteloSubmitStatus::Failed, Some(format!("{:?}", err))),
+                    }
+                }
+            }
+            // an unanswered poll is retried like signatures that are not processed yet
+            Err(_) => pending.extend(sent),
+        }
+    }
+
+    let now = Instant::now();
+    pending
+        .into_iter()
+        .filter_map(|mut submission| {
+            if now >= submission.deadline {
+                submission.complete(
+                    RusteloSubmitStatus::TimedOut,
+                    Some("transaction was not confirmed in time".to_string()),
+                );
+                return None;
+            }
+            submission.next_poll = now + POLL_INTERVAL;
+            Some(submission)
+        }).collect()
+}
+
+fn run_worker(shared: &SubmitShared, mut client: ThinClient) {
+    while let Some(submissions) = next_submissions(shared) {
+        let submissions = step(shared, &mut client, submissions);
+        if submissions.is_empty() {
+            continue;
+        }
+        let mut queue = shared.queue.lock();
+        if !queue.closed {
+            queue.submissions.extend(submissions);
+            shared.ready.notify_one();
+            continue;
+        }
+        drop(queue);
+        for submission in submissions {
+            submission.complete(
+                RusteloSubmitStatus::TimedOut,
+                Some("submitter was closed".to_string()),
+            );
+        }
+    }
+}
+
+pub(crate) static SUBMITTERS: Lazy<Mutex<HandleRegistry<Submitter>>> = sync_lazy! {
+    Mutex::new(HandleRegistry::new())
+};
+
+//submit queues a job on the submitter behind a handle, 0 means it was rejected
+fn submit(handle: u64, job: Job, callback: RusteloSubmitCallback, context: u64) -> u64 {
+    let callback = match callback {
+        Some(callback) => callback,
+        None => {
+            record_error(RusteloErrorCode::InvalidArgument, "argument 'callback' is NULL");
+            return 0;
+        }
+    };
+    let submitter = match SUBMITTERS.lock().get(handle) {
+        Some(submitter) => submitter,
+        None => {
+            record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("unknown submitter handle {}", handle),
+            );
+            return 0;
+        }
+    };
+    let request_id = submitter.lock().submit(job, callback, context);
+    request_id
+}
+
+/// rustelo_submitter_open starts a pool of workers that send transactions for the wallet
+/// client behind client_handle and returns a handle to submit to, 0 means failure.
+/// workers 0 and timeout_ms 0 select 4 workers and 30 seconds.
+#[no_mangle]
+pub extern "C" fn rustelo_submitter_open(client_handle: u64, workers: u32, timeout_ms: u64) -> u64 {
+    catch_panic(0, || {
+        let wallet = match CLIENTS.lock().get(client_handle) {
+            Some(wallet) => wallet,
+            None => {
+                record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("unknown client handle {}", client_handle),
+                );
+                return 0;
+            }
+        };
+        let workers = if workers > 0 {
+            workers as usize
+        } else {
+            DEFAULT_WORKERS
+        };
+        let timeout = if timeout_ms > 0 {
+            Duration::from_millis(timeout_ms)
+        } else {
+            DEFAULT_TIMEOUT
+        };
+
+        match Submitter::open(wallet, workers, timeout) {
+            Ok(submitter) => SUBMITTERS.lock().insert(submitter),
+            Err(err) => {
+                record_error(RusteloErrorCode::Wallet, err);
+                0
+            }
+        }
+    })
+}
+
+/// rustelo_submitter_close signals the workers to stop and releases the handle without
+/// waiting for them. Callbacks of queued submissions fire with RUSTELO_SUBMIT_STATUS_TIMED_OUT
+/// before it returns, the ones a worker is sending fire once that call returns.
+#[no_mangle]
+pub extern "C" fn rustelo_submitter_close(handle: u64) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let submitter = match SUBMITTERS.lock().remove(handle) {
+            Some(submitter) => submitter,
+            None => {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    format!("unknown submitter handle {}", handle),
+                );
+            }
+        };
+        submitter.lock().close();
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_submit_transaction sends a bincode serialized, signed transaction and
+/// returns the request id passed to callback, 0 means it was rejected
+#[no_mangle]
+pub extern "C" fn rustelo_submit_transaction(
+    handle: u64,
+    transaction: *const u8,
+    transaction_len: usize,
+    callback: RusteloSubmitCallback,
+    context: u64,
+) -> u64 {
+    catch_panic(0, || {
+        let tx = match arg_bytes(transaction, transaction_len, "transaction").and_then(|bytes| {
+            deserialize::<Transaction>(bytes).or_else(|err| {
+                Err(RusteloError::from_error(RusteloErrorCode::Serialization, &*err))
+            })
+        }) {
+            Ok(tx) => tx,
+            Err(err) => {
+                record_error(err.code, err);
+                return 0;
+            }
+        };
+        submit(handle, Job::Transaction(tx), callback, context)
+    })
+}
+
+/// rustelo_submit_transfer sends tokens from the wallet keypair to a base58 pubkey and
+/// returns the request id passed to callback, 0 means it was rejected
+#[no_mangle]
+pub extern "C" fn rustelo_submit_transfer(
+    handle: u64,
+    to: *const c_char,
+    tokens: i64,
+    callback: RusteloSubmitCallback,
+    context: u64,
+) -> u64 {
+    catch_panic(0, || {
+        let to = match arg_str(to, "to").and_then(|to| parse_pubkey(to, "to")) {
+            Ok(to) => to,
+            Err(err) => {
+                record_error(RusteloErrorCode::InvalidArgument, err);
+                return 0;
+            }
+        };
+        if tokens <= 0 {
+            record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("Invalid transfer amount: {}", tokens),
+            );
+            return 0;
+        }
+        submit(handle, Job::Transfer { to, tokens }, callback, context)
+    })
+}
+
+/// rustelo_submit_airdrop requests tokens from the drone for the wallet keypair and
+/// returns the request id passed to callback, 0 means it was rejected
+#[no_mangle]
+pub extern "C" fn rustelo_submit_airdrop(
+    handle: u64,
+    tokens: i64,
+    callback: RusteloSubmitCallback,
+    context: u64,
+) -> u64 {
+    catch_panic(0, || {
+        if tokens <= 0 {
+            record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("Invalid airdrop amount: {}", tokens),
+            );
+            return 0;
+        }
+        submit(
+            handle,
+            Job::Airdrop {
+                tokens: tokens as u64,
+            },
+            callback,
+            context,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::serialize;
+    use buffett::coinery::Mint;
+    use buffett::crdt::{Node, NodeInfo};
+    use buffett::fullnode::Fullnode;
+    use buffett::hash::Hash;
+    use buffett::ledger::LedgerWriter;
+    use buffett::signature::Keypair;
+    use buffett::tx_vault::Bank;
+    use buffett::wallet::WalletConfig;
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::fs::remove_dir_all;
+    use std::net::UdpSocket;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread::sleep;
+
+    type Outcome = (u64, i32, Option<String>);
+
+    //the outcomes of the submissions made with a context are sent to its channel
+    static OUTCOMES: Lazy<Mutex<HashMap<u64, Sender<Outcome>>>> = sync_lazy! {
+        Mutex::new(HashMap::new())
+    };
+
+    extern "C" fn send_outcome(
+        context: u64,
+        request_id: u64,
+        status: i32,
+        _signature: *const c_char,
+        error: *const c_char,
+    ) {
+        let error = if error.is_null() {
+            None
+        } else {
+            let error = unsafe { CStr::from_ptr(error) };
+            Some(error.to_string_lossy().into_owned())
+        };
+        if let Some(outcomes) = OUTCOMES.lock().get(&context) {
+            outcomes.send((request_id, status, error)).unwrap();
+        }
+    }
+
+    fn outcomes(context: u64) -> Receiver<Outcome> {
+        let (sender, receiver) = channel();
+        OUTCOMES.lock().insert(context, sender);
+        receiver
+    }
+
+    struct Leader {
+        server: Fullnode,
+        info: NodeInfo,
+        mint: Mint,
+        ledger_path: String,
+    }
+
+    impl Leader {
+        fn start(name: &str) -> Self {
+            let keypair = Keypair::new();
+            let node = Node::new_localhost_with_pubkey(keypair.pubkey());
+            let info = node.info.clone();
+            let mint = Mint::new(10_000);
+            let ledger_path = format!("target/tmp-ledger-{}-{}", name, Keypair::new().pubkey());
+            LedgerWriter::open(&ledger_path, true)
+                .unwrap()
+                .write_entries(mint.create_entries())
+                .unwrap();
+            let server = Fullnode::new_with_bank(
+                keypair,
+                Bank::new(&mint),
+                0,
+                &[],
+                node,
+                None,
+                &ledger_path,
+                false,
+                None,
+                Some(0),
+            );
+            sleep(Duration::from_millis(900));
+            Leader {
+                server,
+                info,
+                mint,
+                ledger_path,
+            }
+        }
+
+        //a wallet client that signs with the mint keypair
+        fn client(&self) -> u64 {
+            let config = WalletConfig {
+                leader: self.info.clone(),
+                id: self.mint.keypair(),
+                ..WalletConfig::default()
+            };
+            CLIENTS.lock().insert(WalletClient { config })
+        }
+
+        fn close(self) {
+            self.server.close().unwrap();
+            remove_dir_all(self.ledger_path).unwrap();
+        }
+    }
+
+    fn close(handle: u64) -> bool {
+        match rustelo_submitter_close(handle) {
+            RusteloResult::Success => true,
+            RusteloResult::Failure => false,
+        }
+    }
+
+    fn submit_transfer(handle: u64, to: &Pubkey, tokens: i64, context: u64) -> u64 {
+        let to = CString::new(to.to_string()).unwrap();
+        rustelo_submit_transfer(handle, to.as_ptr(), tokens, Some(send_outcome), context)
+    }
+
+    #[test]
+    fn test_submit_transfers() {
+        let leader = Leader::start("submit_transfers");
+        let client = leader.client();
+        let handle = rustelo_submitter_open(client, 2, 10_000);
+        assert_ne!(handle, 0);
+        let outcomes = outcomes(1);
+        let bob = Keypair::new().pubkey();
+
+        let confirmed = submit_transfer(handle, &bob, 500, 1);
+        let failed = submit_transfer(handle, &bob, 20_000, 1);
+        let mut received: Vec<_> = (0..2)
+            .map(|_| outcomes.recv_timeout(Duration::from_secs(10)).unwrap())
+            .collect();
+        received.sort();
+        assert_eq!(
+            received,
+            vec![
+                (confirmed, RusteloSubmitStatus::Confirmed as i32, None),
+                (
+                    failed,
+                    RusteloSubmitStatus::Failed as i32,
+                    Some("ResultWithNegativeTokens".to_string())
+                ),
+            ]
+        );
+
+        assert!(close(handle));
+        CLIENTS.lock().remove(client);
+        leader.close();
+    }
+
+    #[test]
+    fn test_submit_times_out() {
+        let leader = Leader::start("submit_times_out");
+        let client = leader.client();
+        let handle = rustelo_submitter_open(client, 1, 1_000);
+        assert_ne!(handle, 0);
+        let outcomes = outcomes(2);
+
+        //the leader never processes a transaction with an unknown last id
+        let bob = Keypair::new().pubkey();
+        let tx = Transaction::system_new(&leader.mint.keypair(), bob, 1, Hash::default());
+        let tx = serialize(&tx).unwrap();
+        let request_id =
+            rustelo_submit_transaction(handle, tx.as_ptr(), tx.len(), Some(send_outcome), 2);
+        assert_ne!(request_id, 0);
+        assert_eq!(
+            outcomes.recv_timeout(Duration::from_secs(5)).unwrap(),
+            (
+                request_id,
+                RusteloSubmitStatus::TimedOut as i32,
+                Some("transaction was not confirmed in time".to_string())
+            )
+        );
+
+        assert!(close(handle));
+        CLIENTS.lock().remove(client);
+        leader.close();
+    }
+
+    #[test]
+    fn test_submitter_close() {
+        //a leader that never answers keeps the only worker waiting on its first transfer
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_addr = silent.local_addr().unwrap();
+        let config = WalletConfig {
+            leader: NodeInfo::new(
+                Keypair::new().pubkey(),
+                silent_addr,
+                silent_addr,
+                silent_addr,
+                silent_addr,
+                silent_addr,
+            ),
+            ..WalletConfig::default()
+        };
+        let client = CLIENTS.lock().insert(WalletClient { config });
+        let handle = rustelo_submitter_open(client, 1, 60_000);
+        assert_ne!(handle, 0);
+        let outcomes = outcomes(3);
+        let bob = Keypair::new().pubkey();
+        let request_ids: Vec<_> = (0..5)
+            .map(|_| submit_transfer(handle, &bob, 1, 3))
+            .collect();
+        sleep(Duration::from_millis(100));
+
+        //the queued submissions time out before close returns
+        assert!(close(handle));
+        let mut closed: Vec<_> = outcomes.try_iter().collect();
+        assert_eq!(closed.len(), request_ids.len() - 1);
+        //and the one being sent once the worker's call returns
+        closed.push(outcomes.recv_timeout(Duration::from_secs(10)).unwrap());
+        closed.sort();
+        let expected: Vec<_> = request_ids
+            .into_iter()
+            .map(|request_id| {
+                (
+                    request_id,
+                    RusteloSubmitStatus::TimedOut as i32,
+                    Some("submitter was closed".to_string()),
+                )
+            }).collect();
+        assert_eq!(closed, expected);
+
+        assert!(!close(handle));
+        CLIENTS.lock().remove(client);
+    }
+}
@@ -5,46 +5,64 @@ use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::process::Command;
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::rustelo_error::{catch_panic, record_error, RusteloErrorCode, RusteloResult};
 
 #[no_mangle]
-pub extern "C" fn upload_enhancer_main_entry() {
-    let args: Vec<String> = env::args().collect();
-    // Open the path in read-only mode, returns `io::Result<File>`
-    let fname = &args[1];
-    let file = match File::open(fname) {
-        Err(why) => panic!("couldn't open {}: {:?}", fname, why),
-        Ok(file) => file,
-    };
+pub extern "C" fn upload_enhancer_main_entry() -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        let args: Vec<String> = env::args().collect();
+        // Open the path in read-only mode, returns `io::Result<File>`
+        let fname = match args.get(1) {
+            Some(fname) => fname,
+            None => {
+                return record_error(
+                    RusteloErrorCode::InvalidArgument,
+                    "no benchmark results file given",
+                );
+            }
+        };
+        let file = tryffi!(File::open(fname), RusteloErrorCode::Io);
 
-    let git_output = Command::new("git")
-        .args(&["rev-parse", "HEAD"])
-        .output()
-        .expect("failed to execute git rev-parse");
-    let git_commit_hash = String::from_utf8_lossy(&git_output.stdout);
-    let trimmed_hash = git_commit_hash.trim().to_string();
-    println!("uploading hash: {}", trimmed_hash);
+        let git_output = tryffi!(
+            Command::new("git").args(&["rev-parse", "HEAD"]).output(),
+            RusteloErrorCode::Io
+        );
+        let git_commit_hash = String::from_utf8_lossy(&git_output.stdout);
+        let trimmed_hash = git_commit_hash.trim().to_string();
+        println!("uploading hash: {}", trimmed_hash);
 
-    for line in BufReader::new(file).lines() {
-        if let Ok(v) = serde_json::from_str(&line.unwrap()) {
-            let v: Value = v;
-            if v["type"] == "bench" {
-                println!("{}", v);
-                println!("  {}", v["type"]);
-                let median = v["median"].to_string().parse().unwrap();
-                let deviation = v["deviation"].to_string().parse().unwrap();
-                metrics::submit(
-                    influxdb::Point::new(&v["name"].as_str().unwrap().trim_matches('\"'))
-                        .add_field("median", influxdb::Value::Integer(median))
-                        .add_field("deviation", influxdb::Value::Integer(deviation))
-                        .add_field(
-                            "commit",
-                            influxdb::Value::String(git_commit_hash.trim().to_string()),
-                        ).to_owned(),
-                );
+        for line in BufReader::new(file).lines() {
+            let line = tryffi!(line, RusteloErrorCode::Io);
+            if let Ok(v) = serde_json::from_str(&line) {
+                let v: Value = v;
+                if v["type"] == "bench" {
+                    println!("{}", v);
+                    println!("  {}", v["type"]);
+                    let median = tryffi!(
+                        v["median"].to_string().parse::<i64>(),
+                        RusteloErrorCode::Serialization
+                    );
+                    let deviation = tryffi!(
+                        v["deviation"].to_string().parse::<i64>(),
+                        RusteloErrorCode::Serialization
+                    );
+                    let name = tryffi!(
+                        v["name"].as_str().ok_or("benchmark name is not a string"),
+                        RusteloErrorCode::Serialization
+                    );
+                    metrics::submit(
+                        influxdb::Point::new(&name.trim_matches('\"'))
+                            .add_field("median", influxdb::Value::Integer(median))
+                            .add_field("deviation", influxdb::Value::Integer(deviation))
+                            .add_field(
+                                "commit",
+                                influxdb::Value::String(git_commit_hash.trim().to_string()),
+                            ).to_owned(),
+                    );
+                }
             }
         }
-    }
-    metrics::flush();
+        metrics::flush();
+        RusteloResult::Success
+    })
 }
@@ -1,17 +1,122 @@
-use clap::{App, Arg, ArgMatches, SubCommand};
- use buffett::token_service::DRONE_PORT;
+use buffett::token_service::DRONE_PORT;
 use buffett::logger;
 use buffett::rpc::RPC_PORT;
-use buffett::signature::{read_keypair, KeypairUtil};
+use buffett::signature::{KeypairUtil, Signature};
 use buffett::thin_client::poll_gossip_for_leader;
-use buffett::wallet::{gen_keypair_file, parse_command, process_command, WalletConfig, WalletError};
+use buffett::wallet::{
+    confirm_transaction, gen_keypair_file, get_balance, process_command, request_airdrop,
+    WalletCommand, WalletConfig, WalletError,
+};
+use buffett_program_interface::pubkey::Pubkey;
+use chrono::prelude::*;
+use once_cell::{sync::Lazy, sync_lazy};
+use parking_lot::Mutex;
 use std::error;
+use std::mem;
 use std::net::SocketAddr;
-use std::ffi::CStr;
-use crate::rustelo_error::RusteloResult;
+use crate::handle_registry::HandleRegistry;
+use crate::keygen_main::{keypair_arg_display, read_keypair_arg};
+use crate::rustelo_error::{
+    arg_str, catch_panic, categorize, record_error, write_out_str, RusteloError,
+    RusteloErrorCode, RusteloResult,
+};
 
-pub fn parse_args(matches: &ArgMatches) -> Result<WalletConfig, Box<error::Error>> {
-    let network = if let Some(addr) = matches.value_of("network") {
+/// Signatures are written base58 encoded, 90 bytes is always enough
+pub const RUSTELO_SIGNATURE_BUFFER_LEN: usize = 90;
+
+/// Public keys and process ids are written base58 encoded, 45 bytes is always enough
+pub const RUSTELO_PUBKEY_BUFFER_LEN: usize = 45;
+
+/// RusteloWalletOptions carries the connection settings shared by every wallet call,
+/// NULL or empty strings and zero numbers select the defaults.
+/// keypair is a keypair file or "secret:" followed by a base58 secret key.
+#[repr(C)]
+pub struct RusteloWalletOptions {
+    pub network: *const libc::c_char,
+    pub keypair: *const libc::c_char,
+    pub timeout: u64,
+    pub rpc_port: u16,
+    pub proxy: *const libc::c_char,
+}
+
+//WalletClient is the state behind a client handle: the resolved leader, keypair
+//and rpc address that every wallet call reuses
+pub struct WalletClient {
+    pub config: WalletConfig,
+}
+
+pub(crate) static CLIENTS: Lazy<Mutex<HandleRegistry<WalletClient>>> = sync_lazy! {
+    Mutex::new(HandleRegistry::new())
+};
+
+fn opt_str<'a>(ptr: *const libc::c_char, name: &str) -> Result<Option<&'a str>, RusteloError> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    let value = arg_str(ptr, name)?;
+    if value.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(value))
+    }
+}
+
+pub(crate) fn parse_pubkey(value: &str, name: &str) -> Result<Pubkey, RusteloError> {
+    let invalid = || {
+        RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("Invalid {} public key: {}", name, value),
+        )
+    };
+    let pubkey_vec = bs58::decode(value).into_vec().or_else(|_| Err(invalid()))?;
+    if pubkey_vec.len() != mem::size_of::<Pubkey>() {
+        Err(invalid())?;
+    }
+    Ok(Pubkey::new(&pubkey_vec))
+}
+
+pub(crate) fn parse_signature(value: &str) -> Result<Signature, RusteloError> {
+    let invalid = || {
+        RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("Invalid signature: {}", value),
+        )
+    };
+    let signature_vec = bs58::decode(value).into_vec().or_else(|_| Err(invalid()))?;
+    if signature_vec.len() != mem::size_of::<Signature>() {
+        Err(invalid())?;
+    }
+    Ok(Signature::new(&signature_vec))
+}
+
+pub(crate) fn parse_datetime(value: &str) -> Result<DateTime<Utc>, RusteloError> {
+    // Parse input for serde_json
+    let date_string = if !value.contains('Z') {
+        format!("\"{}Z\"", value)
+    } else {
+        format!("\"{}\"", value)
+    };
+    serde_json::from_str(&date_string).or_else(|_| {
+        Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("Invalid datetime: {}", value),
+        ))
+    })
+}
+
+pub fn parse_args(
+    options: *const RusteloWalletOptions,
+    command: WalletCommand,
+) -> Result<WalletConfig, Box<error::Error>> {
+    if options.is_null() {
+        Err(RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            "argument 'options' is NULL",
+        ))?;
+    }
+    let options = unsafe { &*options };
+
+    let network = if let Some(addr) = opt_str(options.network, "network")? {
         addr.parse().or_else(|_| {
             Err(WalletError::BadParameter(
                 "Invalid network location".to_string(),
@@ -20,41 +125,44 @@ pub fn parse_args(matches: &ArgMatches) -> Result<WalletConfig, Box<error::Error
     } else {
         socketaddr!("127.0.0.1:8001")
     };
-    let timeout = if let Some(secs) = matches.value_of("timeout") {
-        Some(secs.to_string().parse().expect("integer"))
+    let timeout = if options.timeout > 0 {
+        Some(options.timeout)
     } else {
         None
     };
 
-    let mut path = dirs::home_dir().expect("home directory");
-    let id_path = if matches.is_present("keypair") {
-        matches.value_of("keypair").unwrap()
+    let mut path = dirs::home_dir().ok_or("home directory not found")?;
+    let id_path = if let Some(keypair) = opt_str(options.keypair, "keypair")? {
+        keypair
     } else {
         path.extend(&[".config", "solana", "id.json"]);
+        let default_path = path.to_str().ok_or("home directory is not valid UTF-8")?;
         if !path.exists() {
-            gen_keypair_file(path.to_str().unwrap().to_string())?;
-            println!("New keypair generated at: {:?}", path.to_str().unwrap());
+            gen_keypair_file(default_path.to_string())?;
+            info!("New keypair generated at: {:?}", default_path);
         }
 
-        path.to_str().unwrap()
+        default_path
     };
-    let id = read_keypair(id_path).or_else(|err| {
+    let id = read_keypair_arg(id_path).or_else(|err| {
         Err(WalletError::BadParameter(format!(
-            "{}: Unable to open keypair file: {}",
-            err, id_path
+            "{}: Unable to open keypair: {}",
+            err,
+            keypair_arg_display(id_path)
         )))
     })?;
 
-    let leader = poll_gossip_for_leader(network, timeout)?;
+    let leader = poll_gossip_for_leader(network, timeout)
+        .or_else(|err| Err(RusteloError::from_error(RusteloErrorCode::Network, &err)))?;
 
     let mut drone_addr = leader.contact_info.tpu;
     drone_addr.set_port(DRONE_PORT);
 
-    let rpc_addr = if let Some(proxy) = matches.value_of("proxy") {
+    let rpc_addr = if let Some(proxy) = opt_str(options.proxy, "proxy")? {
         proxy.to_string()
     } else {
-        let rpc_port = if let Some(port) = matches.value_of("rpc-port") {
-            port.to_string().parse().expect("integer")
+        let rpc_port = if options.rpc_port > 0 {
+            options.rpc_port
         } else {
             RPC_PORT
         };
@@ -63,8 +171,6 @@ pub fn parse_args(matches: &ArgMatches) -> Result<WalletConfig, Box<error::Error
         format!("http://{}", rpc_addr.to_string())
     };
 
-    let command = parse_command(id.pubkey(), &matches)?;
-
     Ok(WalletConfig {
         leader,
         id,
@@ -74,179 +180,317 @@ pub fn parse_args(matches: &ArgMatches) -> Result<WalletConfig, Box<error::Error
     })
 }
 
-/*
+//contract payments answer with {"signature": .., "processId": ..},
+//plain payments with the signature alone
+pub(crate) fn split_pay_result(result: String) -> (String, Option<String>) {
+    match serde_json::from_str::<serde_json::Value>(&result) {
+        Ok(ref value) if value.is_object() => (
+            value["signature"].as_str().unwrap_or_default().to_string(),
+            value["processId"].as_str().map(|id| id.to_string()),
+        ),
+        _ => (result, None),
+    }
+}
+
+//wallet_error categorizes the errors of a wallet call, a bad handle keeps InvalidArgument
+pub(crate) fn wallet_error(code: RusteloErrorCode) -> impl Fn(Box<error::Error>) -> RusteloError {
+    move |err| categorize(code, &*err)
+}
+
+//run f against the wallet client behind a handle, the client stays locked for the call
+pub(crate) fn with_client<F, T>(handle: u64, f: F) -> Result<T, Box<error::Error>>
+where
+    F: FnOnce(&mut WalletClient) -> Result<T, Box<error::Error>>,
+{
+    let client = CLIENTS.lock().get(handle).ok_or_else(|| {
+        RusteloError::new(
+            RusteloErrorCode::InvalidArgument,
+            &format!("unknown client handle {}", handle),
+        )
+    })?;
+    let mut client = client.lock();
+    f(&mut client)
+}
+
+//run a command that submits a transaction and copy its base58 signature to the out-buffer
+fn process_signature_command(
+    handle: u64,
+    command: WalletCommand,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+) -> RusteloResult {
+    let signature = tryffi!(
+        with_client(handle, |client| {
+            client.config.command = command;
+            process_command(&client.config)
+        }).map_err(wallet_error(RusteloErrorCode::Wallet))
+    );
+    tryffi!(write_out_str(&signature, signature_out, signature_out_len));
+
+    RusteloResult::Success
+}
+
+/// rustelo_client_open polls gossip for the leader once and returns a handle to reuse
+/// across wallet calls, 0 means failure
+#[no_mangle]
+pub extern "C" fn rustelo_client_open(options: *const RusteloWalletOptions) -> u64 {
+    catch_panic(0, || {
+        logger::setup();
+        let config = match parse_args(options, WalletCommand::Balance) {
+            Ok(config) => config,
+            Err(err) => {
+                record_error(
+                    RusteloErrorCode::Unknown,
+                    categorize(RusteloErrorCode::InvalidArgument, &*err),
+                );
+                return 0;
+            }
+        };
+        CLIENTS.lock().insert(WalletClient { config })
+    })
+}
+
+/// rustelo_client_close releases a handle returned by rustelo_client_open
 #[no_mangle]
-pub extern "C" fn wallet_main_entry() -> Result<(), Box<error::Error>> {
-*/
+pub extern "C" fn rustelo_client_close(handle: u64) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        match CLIENTS.lock().remove(handle) {
+            Some(_) => RusteloResult::Success,
+            None => record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("unknown client handle {}", handle),
+            ),
+        }
+    })
+}
+
+/// rustelo_wallet_balance stores the balance of the wallet keypair in balance_out
 #[no_mangle]
-pub extern "C" fn wallet_main_entry() -> RusteloResult {
-
-    logger::setup();
-    let matches = App::new("solana-wallet")
-        .version(crate_version!())
-        .arg(
-            Arg::with_name("network")
-                .short("n")
-                .long("network")
-                .value_name("HOST:PORT")
-                .takes_value(true)
-                .help("Rendezvous with the network at this gossip entry point; defaults to 127.0.0.1:8001"),
-        ).arg(
-            Arg::with_name("keypair")
-                .short("k")
-                .long("keypair")
-                .value_name("PATH")
-                .takes_value(true)
-                .help("/path/to/id.json"),
-        ).arg(
-            Arg::with_name("timeout")
-                .long("timeout")
-                .value_name("SECS")
-                .takes_value(true)
-                .help("Max seconds to wait to get necessary gossip from the network"),
-        ).arg(
-            Arg::with_name("rpc-port")
-                .long("port")
-                .takes_value(true)
-                .value_name("NUM")
-                .help("Optional rpc-port configuration to connect to non-default nodes")
-        ).arg(
-            Arg::with_name("proxy")
-                .long("proxy")
-                .takes_value(true)
-                .value_name("URL")
-                .help("Address of TLS proxy")
-                .conflicts_with("rpc-port")
-        ).subcommand(SubCommand::with_name("address").about("Get your public key"))
-        .subcommand(
-            SubCommand::with_name("airdrop")
-                .about("Request a batch of tokens")
-                .arg(
-                    Arg::with_name("tokens")
-                        .index(1)
-                        .value_name("NUM")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The number of tokens to request"),
-                ),
-        ).subcommand(SubCommand::with_name("balance").about("Get your balance"))
-        .subcommand(
-            SubCommand::with_name("cancel")
-                .about("Cancel a transfer")
-                .arg(
-                    Arg::with_name("process-id")
-                        .index(1)
-                        .value_name("PROCESS_ID")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The process id of the transfer to cancel"),
-                ),
-        ).subcommand(
-            SubCommand::with_name("confirm")
-                .about("Confirm transaction by signature")
-                .arg(
-                    Arg::with_name("signature")
-                        .index(1)
-                        .value_name("SIGNATURE")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The transaction signature to confirm"),
-                ),
-        ).subcommand(
-            SubCommand::with_name("pay")
-                .about("Send a payment")
-                .arg(
-                    Arg::with_name("to")
-                        .index(1)
-                        .value_name("PUBKEY")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The pubkey of recipient"),
-                ).arg(
-                    Arg::with_name("tokens")
-                        .index(2)
-                        .value_name("NUM")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The number of tokens to send"),
-                ).arg(
-                    Arg::with_name("timestamp")
-                        .long("after")
-                        .value_name("DATETIME")
-                        .takes_value(true)
-                        .help("A timestamp after which transaction will execute"),
-                ).arg(
-                    Arg::with_name("timestamp-pubkey")
-                        .long("require-timestamp-from")
-                        .value_name("PUBKEY")
-                        .takes_value(true)
-                        .requires("timestamp")
-                        .help("Require timestamp from this third party"),
-                ).arg(
-                    Arg::with_name("witness")
-                        .long("require-signature-from")
-                        .value_name("PUBKEY")
-                        .takes_value(true)
-                        .multiple(true)
-                        .use_delimiter(true)
-                        .help("Any third party signatures required to unlock the tokens"),
-                ).arg(
-                    Arg::with_name("cancelable")
-                        .long("cancelable")
-                        .takes_value(false),
-                ),
-        ).subcommand(
-            SubCommand::with_name("send-signature")
-                .about("Send a signature to authorize a transfer")
-                .arg(
-                    Arg::with_name("to")
-                        .index(1)
-                        .value_name("PUBKEY")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The pubkey of recipient"),
-                ).arg(
-                    Arg::with_name("process-id")
-                        .index(2)
-                        .value_name("PROCESS_ID")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The process id of the transfer to authorize")
-                )
-        ).subcommand(
-            SubCommand::with_name("send-timestamp")
-                .about("Send a timestamp to unlock a transfer")
-                .arg(
-                    Arg::with_name("to")
-                        .index(1)
-                        .value_name("PUBKEY")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The pubkey of recipient"),
-                ).arg(
-                    Arg::with_name("process-id")
-                        .index(2)
-                        .value_name("PROCESS_ID")
-                        .takes_value(true)
-                        .required(true)
-                        .help("The process id of the transfer to unlock")
-                ).arg(
-                    Arg::with_name("datetime")
-                        .long("date")
-                        .value_name("DATETIME")
-                        .takes_value(true)
-                        .help("Optional arbitrary timestamp to apply")
-                )
-        ).get_matches();
-
-    /*
-    let config = parse_args(&matches)?;
-    let result = process_command(&config)?;
-    */
-    let config = tryffi!(parse_args(&matches));
-    let result = tryffi!(process_command(&config));
-    
-    println!("{:?}", result);
-    //Ok(())
-    RusteloResult::Success
+pub extern "C" fn rustelo_wallet_balance(
+    handle: u64,
+    balance_out: *mut i64,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        if balance_out.is_null() {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                "argument 'balance_out' is NULL",
+            );
+        }
+        let balance = tryffi!(with_client(handle, |client| get_balance(&client.config))
+            .map_err(wallet_error(RusteloErrorCode::Wallet)));
+        unsafe { *balance_out = balance };
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_wallet_airdrop requests tokens from the drone and returns the airdrop signature
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_airdrop(
+    handle: u64,
+    tokens: i64,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        if tokens <= 0 {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("Invalid airdrop amount: {}", tokens),
+            );
+        }
+        let signature = tryffi!(
+            with_client(handle, |client| {
+                let config = &client.config;
+                Ok(request_airdrop(&config.drone_addr, &config.id.pubkey(), tokens as u64)?)
+            }).map_err(wallet_error(RusteloErrorCode::Network))
+        );
+        tryffi!(write_out_str(&signature.to_string(), signature_out, signature_out_len));
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_wallet_pay sends tokens to a base58 pubkey. With a timestamp or witness
+/// a budget contract is created and its process id is copied to process_id_out,
+/// which may be NULL for plain payments.
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_pay(
+    handle: u64,
+    to: *const libc::c_char,
+    tokens: i64,
+    timestamp: *const libc::c_char,
+    timestamp_pubkey: *const libc::c_char,
+    witness: *const libc::c_char,
+    cancelable: bool,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+    process_id_out: *mut libc::c_char,
+    process_id_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        if tokens <= 0 {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                format!("Invalid transfer amount: {}", tokens),
+            );
+        }
+        let to_str = tryffi!(arg_str(to, "to"));
+        let to = tryffi!(parse_pubkey(to_str, "to"));
+        let timestamp = match tryffi!(opt_str(timestamp, "timestamp")) {
+            Some(dt) => Some(tryffi!(parse_datetime(dt))),
+            None => None,
+        };
+        let timestamp_pubkey = match tryffi!(opt_str(timestamp_pubkey, "timestamp_pubkey")) {
+            Some(pubkey) => Some(tryffi!(parse_pubkey(pubkey, "timestamp"))),
+            None => None,
+        };
+        let witnesses = match tryffi!(opt_str(witness, "witness")) {
+            Some(witness) => Some(vec![tryffi!(parse_pubkey(witness, "witness"))]),
+            None => None,
+        };
+
+        let result = tryffi!(
+            with_client(handle, |client| {
+                let cancelable = if cancelable {
+                    Some(client.config.id.pubkey())
+                } else {
+                    None
+                };
+                client.config.command = WalletCommand::Pay(
+                    tokens,
+                    to,
+                    timestamp,
+                    timestamp_pubkey,
+                    witnesses,
+                    None,
+                    vec![],
+                    cancelable,
+                );
+                process_command(&client.config)
+            }).map_err(wallet_error(RusteloErrorCode::Wallet))
+        );
+
+        let (signature, process_id) = split_pay_result(result);
+        tryffi!(write_out_str(&signature, signature_out, signature_out_len));
+        if let Some(process_id) = process_id {
+            if !process_id_out.is_null() {
+                tryffi!(write_out_str(&process_id, process_id_out, process_id_out_len));
+            }
+        }
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_wallet_confirm stores true in confirmed_out if the signature has been processed
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_confirm(
+    handle: u64,
+    signature: *const libc::c_char,
+    confirmed_out: *mut bool,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        if confirmed_out.is_null() {
+            return record_error(
+                RusteloErrorCode::InvalidArgument,
+                "argument 'confirmed_out' is NULL",
+            );
+        }
+        let signature = tryffi!(parse_signature(tryffi!(arg_str(signature, "signature"))));
+        let confirmed = tryffi!(
+            with_client(handle, |client| confirm_transaction(&client.config, &signature))
+                .map_err(wallet_error(RusteloErrorCode::Wallet))
+        );
+        unsafe { *confirmed_out = confirmed };
+
+        RusteloResult::Success
+    })
+}
+
+/// rustelo_wallet_witness sends a signature to authorize the transfer of a contract
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_witness(
+    handle: u64,
+    to: *const libc::c_char,
+    process_id: *const libc::c_char,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        let to = tryffi!(parse_pubkey(tryffi!(arg_str(to, "to")), "to"));
+        let process_id = tryffi!(parse_pubkey(
+            tryffi!(arg_str(process_id, "process_id")),
+            "process id"
+        ));
+
+        process_signature_command(
+            handle,
+            WalletCommand::Witness(vec![to], process_id),
+            signature_out,
+            signature_out_len,
+        )
+    })
+}
+
+/// rustelo_wallet_time_elapsed sends a timestamp to unlock a contract,
+/// a NULL or empty datetime applies the current time
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_time_elapsed(
+    handle: u64,
+    to: *const libc::c_char,
+    process_id: *const libc::c_char,
+    datetime: *const libc::c_char,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        let to = tryffi!(parse_pubkey(tryffi!(arg_str(to, "to")), "to"));
+        let process_id = tryffi!(parse_pubkey(
+            tryffi!(arg_str(process_id, "process_id")),
+            "process id"
+        ));
+        let dt = match tryffi!(opt_str(datetime, "datetime")) {
+            Some(dt) => tryffi!(parse_datetime(dt)),
+            None => Utc::now(),
+        };
+
+        process_signature_command(
+            handle,
+            WalletCommand::TimeElapsed(vec![to], process_id, dt),
+            signature_out,
+            signature_out_len,
+        )
+    })
+}
+
+/// rustelo_wallet_cancel cancels a cancelable contract by its process id
+#[no_mangle]
+pub extern "C" fn rustelo_wallet_cancel(
+    handle: u64,
+    process_id: *const libc::c_char,
+    signature_out: *mut libc::c_char,
+    signature_out_len: usize,
+) -> RusteloResult {
+    catch_panic(RusteloResult::Failure, || {
+        logger::setup();
+        let process_id = tryffi!(parse_pubkey(
+            tryffi!(arg_str(process_id, "process_id")),
+            "process id"
+        ));
+
+        process_signature_command(
+            handle,
+            WalletCommand::Cancel(process_id),
+            signature_out,
+            signature_out_len,
+        )
+    })
 }
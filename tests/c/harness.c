@@ -0,0 +1,347 @@
+// harness calls every rustelo entry point through include/rustelo.h, it is built and
+// run by tests/c_harness.rs against the staticlib. A signature that drifts from the
+// Rust side fails to compile here, and a return type or argument that is passed
+// differently fails one of the checks below.
+
+#include <pthread.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "rustelo.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+// check_failure checks that the last call failed with code and that its message can
+// be taken exactly once
+static void check_failure(RusteloResult result, int32_t code, const char *call) {
+    if (result != RUSTELO_RESULT_FAILURE) {
+        fprintf(stderr, "%s: expected RUSTELO_RESULT_FAILURE, got %d\n", call, result);
+        failures++;
+        return;
+    }
+    if (rustelo_last_error_code() != code) {
+        fprintf(stderr, "%s: expected error code %d, got %d\n", call, code,
+                rustelo_last_error_code());
+        failures++;
+    }
+    char *message = rustelo_handle_error();
+    CHECK(message != NULL);
+    rustelo_free_string(message);
+    CHECK(rustelo_handle_error() == NULL);
+    CHECK(rustelo_last_error_code() == RUSTELO_ERROR_CODE_NO_ERROR);
+}
+
+static void check_null_handle(uint64_t handle, int32_t code, const char *call) {
+    check_failure(handle == 0 ? RUSTELO_RESULT_FAILURE : RUSTELO_RESULT_SUCCESS, code, call);
+}
+
+static void log_callback(int32_t level, const char *target, const char *message) {
+    (void)level;
+    (void)target;
+    (void)message;
+}
+
+static void metrics_callback(const char *json_point) {
+    (void)json_point;
+}
+
+static void submit_callback(uint64_t context, uint64_t request_id, int32_t status,
+                            const char *signature, const char *error) {
+    (void)context;
+    (void)request_id;
+    (void)status;
+    (void)signature;
+    (void)error;
+}
+
+static void check_layouts(void) {
+#if UINTPTR_MAX == 0xffffffffffffffffu
+    CHECK(sizeof(RusteloResult) == 1);
+    CHECK(sizeof(RusteloFullnodeStatus) == 64);
+    CHECK(offsetof(RusteloFullnodeStatus, last_id) == 16);
+    CHECK(offsetof(RusteloFullnodeStatus, peer_count) == 56);
+    CHECK(sizeof(RusteloWalletOptions) == 40);
+    CHECK(offsetof(RusteloWalletOptions, rpc_port) == 24);
+    CHECK(offsetof(RusteloWalletOptions, proxy) == 32);
+#endif
+}
+
+// fail_on_thread leaves an error in the last-error slot of its thread
+static void *fail_on_thread(void *arg) {
+    (void)arg;
+    CHECK(keygen_main_entry(NULL) == RUSTELO_RESULT_FAILURE);
+    CHECK(rustelo_last_error_code() == RUSTELO_ERROR_CODE_INVALID_ARGUMENT);
+    return NULL;
+}
+
+static void check_errors(void) {
+    CHECK(rustelo_handle_error() == NULL);
+    CHECK(rustelo_last_error_code() == RUSTELO_ERROR_CODE_NO_ERROR);
+    rustelo_free_string(NULL);
+
+    // the last error is per thread, another thread's failure isn't seen here
+    pthread_t thread;
+    CHECK(pthread_create(&thread, NULL, fail_on_thread, NULL) == 0);
+    CHECK(pthread_join(thread, NULL) == 0);
+    CHECK(rustelo_last_error_code() == RUSTELO_ERROR_CODE_NO_ERROR);
+    CHECK(rustelo_handle_error() == NULL);
+}
+
+static void check_keygen(const char *dir) {
+    char outfile[4096];
+    snprintf(outfile, sizeof(outfile), "%s/id.json", dir);
+    CHECK(keygen_main_entry(outfile) == RUSTELO_RESULT_SUCCESS);
+    FILE *file = fopen(outfile, "r");
+    CHECK(file != NULL);
+    if (file != NULL) {
+        fclose(file);
+    }
+
+    check_failure(keygen_main_entry(NULL), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "keygen_main_entry");
+}
+
+static void check_keypair(void) {
+    uint8_t secret[RUSTELO_SECRET_KEY_LEN];
+    char pubkey[RUSTELO_PUBKEY_BUFFER_LEN];
+    char derived[RUSTELO_PUBKEY_BUFFER_LEN];
+    uint8_t signature[RUSTELO_SIGNATURE_LEN];
+    const uint8_t message[] = "hello";
+    bool valid = false;
+
+    CHECK(rustelo_keypair_generate(secret, sizeof(secret), pubkey, sizeof(pubkey)) ==
+          RUSTELO_RESULT_SUCCESS);
+    CHECK(rustelo_keypair_pubkey(secret, sizeof(secret), derived, sizeof(derived)) ==
+          RUSTELO_RESULT_SUCCESS);
+    CHECK(strcmp(pubkey, derived) == 0);
+
+    CHECK(rustelo_keypair_sign(secret, sizeof(secret), message, sizeof(message), signature,
+                               sizeof(signature)) == RUSTELO_RESULT_SUCCESS);
+    CHECK(rustelo_keypair_verify(pubkey, message, sizeof(message), signature, sizeof(signature),
+                                 &valid) == RUSTELO_RESULT_SUCCESS);
+    CHECK(valid);
+    signature[0] ^= 0xff;
+    CHECK(rustelo_keypair_verify(pubkey, message, sizeof(message), signature, sizeof(signature),
+                                 &valid) == RUSTELO_RESULT_SUCCESS);
+    CHECK(!valid);
+
+    check_failure(rustelo_keypair_generate(secret, 32, pubkey, sizeof(pubkey)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_keypair_generate");
+    check_failure(rustelo_keypair_pubkey(secret, 32, derived, sizeof(derived)),
+                  RUSTELO_ERROR_CODE_KEYPAIR, "rustelo_keypair_pubkey");
+    check_failure(rustelo_keypair_verify(pubkey, message, sizeof(message), signature, 32, &valid),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_keypair_verify");
+}
+
+static void check_genesis_and_ledgertool(const char *dir) {
+    check_failure(genesis_main_entry("not a number", dir), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "genesis_main_entry");
+    check_failure(genesis_main_entry(NULL, dir), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "genesis_main_entry");
+    check_failure(ledgertool_main_entry(NULL, "", "", "", "print"),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "ledgertool_main_entry");
+}
+
+static void check_fullnode(const char *dir) {
+    RusteloFullnodeStatus status;
+
+    check_failure(fullnode_main_entry(NULL, "", dir), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "fullnode_main_entry");
+    check_null_handle(rustelo_fullnode_start(NULL, "", dir), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                      "rustelo_fullnode_start");
+    check_null_handle(rustelo_fullnode_start("", "not an address", dir),
+                      RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_fullnode_start");
+    check_failure(rustelo_fullnode_status(0, &status), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_fullnode_status");
+    check_failure(rustelo_fullnode_status(0, NULL), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_fullnode_status");
+    check_failure(rustelo_fullnode_shutdown(0), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_fullnode_shutdown");
+}
+
+static void check_wallet(void) {
+    char signature[RUSTELO_SIGNATURE_BUFFER_LEN];
+    char process_id[RUSTELO_PUBKEY_BUFFER_LEN];
+    int64_t balance = 0;
+    bool confirmed = false;
+
+    check_null_handle(rustelo_client_open(NULL), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                      "rustelo_client_open");
+    check_failure(rustelo_client_close(0), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_client_close");
+    check_failure(rustelo_wallet_balance(0, &balance), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_wallet_balance");
+    check_failure(rustelo_wallet_airdrop(0, 50, signature, sizeof(signature)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_wallet_airdrop");
+    check_failure(rustelo_wallet_pay(0, NULL, 50, NULL, NULL, NULL, false, signature,
+                                     sizeof(signature), process_id, sizeof(process_id)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_wallet_pay");
+    check_failure(rustelo_wallet_confirm(0, "", &confirmed), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_wallet_confirm");
+    check_failure(rustelo_wallet_witness(0, NULL, NULL, signature, sizeof(signature)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_wallet_witness");
+    check_failure(rustelo_wallet_time_elapsed(0, NULL, NULL, NULL, signature, sizeof(signature)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_wallet_time_elapsed");
+    check_failure(rustelo_wallet_cancel(0, NULL, signature, sizeof(signature)),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_wallet_cancel");
+}
+
+// an inline secret key that fails to load is not echoed back in the error
+static void check_wallet_secret_redacted(void) {
+    RusteloWalletOptions options = {0};
+    options.keypair = "secret:0OIl-not-base58";
+    CHECK(rustelo_client_open(&options) == 0);
+    CHECK(rustelo_last_error_code() == RUSTELO_ERROR_CODE_INVALID_ARGUMENT);
+    char *message = rustelo_handle_error();
+    CHECK(message != NULL);
+    if (message != NULL) {
+        CHECK(strstr(message, "0OIl-not-base58") == NULL);
+        rustelo_free_string(message);
+    }
+}
+
+static void check_submitter(void) {
+    const uint8_t transaction[] = {0};
+
+    check_null_handle(rustelo_submitter_open(0, 0, 0), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                      "rustelo_submitter_open");
+    check_null_handle(rustelo_submit_transaction(0, transaction, sizeof(transaction),
+                                                 submit_callback, 0),
+                      RUSTELO_ERROR_CODE_SERIALIZATION, "rustelo_submit_transaction");
+    check_null_handle(rustelo_submit_transfer(0, "not a pubkey", 1, submit_callback, 0),
+                      RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_submit_transfer");
+    check_null_handle(rustelo_submit_airdrop(0, 50, NULL, 0), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                      "rustelo_submit_airdrop");
+    check_null_handle(rustelo_submit_airdrop(0, 50, submit_callback, 0),
+                      RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "rustelo_submit_airdrop");
+    check_failure(rustelo_submitter_close(0), RUSTELO_ERROR_CODE_INVALID_ARGUMENT,
+                  "rustelo_submitter_close");
+}
+
+// check_execute checks that request gets a response containing expected
+static void check_execute(const char *request, const char *expected) {
+    char *response = rustelo_execute(request);
+    CHECK(response != NULL);
+    if (response != NULL) {
+        if (strstr(response, expected) == NULL) {
+            fprintf(stderr, "rustelo_execute(%s): expected %s in %s\n", request, expected,
+                    response);
+            failures++;
+        }
+        rustelo_free_string(response);
+    }
+}
+
+static void check_execute_commands(const char *dir) {
+    char request[4096];
+    snprintf(request, sizeof(request), "{\"command\": \"keygen\", \"outfile\": \"%s/execute.json\"}",
+             dir);
+    check_execute(request, "\"status\":\"ok\"");
+    check_execute("not json", "\"code\":1");
+    check_execute("{\"command\": \"no-such-command\"}", "\"status\":\"error\"");
+    check_execute("{\"command\": \"wallet\", \"client\": 0, \"action\": \"balance\"}",
+                  "\"code\":1");
+}
+
+// genesis and ledger-tool requests reach genesis_main_entry and ledgertool_main_entry
+static void check_execute_ledger(const char *dir) {
+    char path[4096];
+    char request[4096];
+    // genesis reads the mint keypair from stdin, use the one keygen wrote
+    snprintf(path, sizeof(path), "%s/execute.json", dir);
+    CHECK(freopen(path, "r", stdin) != NULL);
+    snprintf(request, sizeof(request),
+             "{\"command\": \"genesis\", \"tokens\": 1000, \"ledger\": \"%s/ledger\"}", dir);
+    check_execute(request, "\"status\":\"ok\"");
+
+    const char *subcommands[] = {"print", "json", "verify"};
+    for (size_t i = 0; i < sizeof(subcommands) / sizeof(subcommands[0]); i++) {
+        snprintf(request, sizeof(request),
+                 "{\"command\": \"ledger-tool\", \"ledger\": \"%s/ledger\", "
+                 "\"precheck\": true, \"subcommand\": \"%s\"}",
+                 dir, subcommands[i]);
+        check_execute(request, "\"status\":\"ok\"");
+    }
+    snprintf(request, sizeof(request),
+             "{\"command\": \"ledger-tool\", \"ledger\": \"%s/ledger\", \"subcommand\": \"dump\"}",
+             dir);
+    check_execute(request, "\"code\":1");
+}
+
+// malformed arguments that used to abort the process are reported as errors
+static void check_entry_errors(const char *dir) {
+    char keypair[4096];
+    snprintf(keypair, sizeof(keypair), "%s/missing-keypair.json", dir);
+    check_failure(coincaster_main_entry("127.0.0.1:8001", keypair, "", ""),
+                  RUSTELO_ERROR_CODE_KEYPAIR, "coincaster_main_entry");
+    check_failure(coincaster_main_entry("not-an-address", keypair, "", ""),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "coincaster_main_entry");
+    check_failure(coincaster_main_entry(NULL, keypair, "", ""),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "coincaster_main_entry");
+    check_failure(fullnode_config_main_entry("FALSE", keypair, "FALSE", "8000", "-"),
+                  RUSTELO_ERROR_CODE_KEYPAIR, "fullnode_config_main_entry");
+    check_failure(fullnode_config_main_entry("FALSE", keypair, "FALSE", "8000", NULL),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "fullnode_config_main_entry");
+    check_failure(propagator_main_entry(keypair, "127.0.0.1:8001", dir),
+                  RUSTELO_ERROR_CODE_IO, "propagator_main_entry");
+    check_failure(benchcaster_main_entry("many"),
+                  RUSTELO_ERROR_CODE_INVALID_ARGUMENT, "benchcaster_main_entry");
+}
+
+static void check_callbacks(void) {
+    rustelo_set_log_callback(log_callback);
+    rustelo_set_log_callback(NULL);
+    rustelo_set_metrics_callback(metrics_callback);
+    rustelo_set_metrics_callback(NULL);
+}
+
+// the remaining entry points run a node or a benchmark until the process exits, so
+// they are only linked
+typedef void (*entry_point)(void);
+static volatile entry_point linked_only[] = {
+    (entry_point)benchmarker_main_entry,
+    (entry_point)upload_enhancer_main_entry,
+};
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <scratch dir>\n", argv[0]);
+        return 2;
+    }
+    const char *dir = argv[1];
+
+    for (size_t i = 0; i < sizeof(linked_only) / sizeof(linked_only[0]); i++) {
+        CHECK(linked_only[i] != NULL);
+    }
+
+    check_layouts();
+    check_errors();
+    check_keygen(dir);
+    check_keypair();
+    check_genesis_and_ledgertool(dir);
+    check_fullnode(dir);
+    check_wallet();
+    check_wallet_secret_redacted();
+    check_submitter();
+    check_callbacks();
+    check_execute_commands(dir);
+    check_execute_ledger(dir);
+    check_entry_errors(dir);
+
+    if (failures != 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("all checks passed\n");
+    return 0;
+}
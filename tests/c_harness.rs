@@ -0,0 +1,110 @@
+//! Builds tests/c/harness.c against include/rustelo.h and the rustelo staticlib,
+//! then runs it. Also checks that include/ holds the header build.rs generated.
+
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+#[cfg(target_os = "macos")]
+const NATIVE_LIBS: &[&str] = &[
+    "-framework",
+    "Security",
+    "-framework",
+    "CoreFoundation",
+    "-lresolv",
+    "-lc",
+    "-lm",
+];
+
+#[cfg(not(target_os = "macos"))]
+const NATIVE_LIBS: &[&str] = &[
+    "-lssl", "-lcrypto", "-ldl", "-lpthread", "-lrt", "-lutil", "-lm",
+];
+
+// the test binary lives in target/<profile>/deps, the staticlib in target/<profile>
+fn staticlib_path() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let profile_dir = exe.parent().and_then(Path::parent).unwrap();
+    let staticlib = profile_dir.join("librustelo.a");
+    assert!(
+        staticlib.exists(),
+        "{} not found, run `cargo build` first",
+        staticlib.display()
+    );
+    staticlib
+}
+
+fn cc() -> Command {
+    let mut cc = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()));
+    cc.args(&["-std=c99", "-Wall", "-Werror"])
+        .arg("-I")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("include"));
+    cc
+}
+
+#[test]
+fn test_shipped_header_is_generated() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let generated = fs::read_to_string(Path::new(env!("OUT_DIR")).join("rustelo.h")).unwrap();
+    let shipped = fs::read_to_string(manifest_dir.join("include").join("rustelo.h")).unwrap();
+    assert!(
+        generated == shipped,
+        "include/rustelo.h is stale, copy it from {}/rustelo.h",
+        env!("OUT_DIR")
+    );
+}
+
+// the per-module headers of the original bindings must still compile on their own
+#[test]
+fn test_module_headers() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let scratch_dir = env::temp_dir().join(format!("rustelo-c-headers-{}", process::id()));
+    fs::create_dir_all(&scratch_dir).unwrap();
+    let source = scratch_dir.join("headers.c");
+
+    let mut includes = String::new();
+    for entry in fs::read_dir(manifest_dir.join("include")).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        if name.starts_with("rustelo-") {
+            includes.push_str(&format!("#include \"{}\"\n", name));
+        }
+    }
+    includes.push_str("RusteloError check(void) { return RUSTELO_ERROR_SUCCESS; }\n");
+    fs::write(&source, includes).unwrap();
+
+    let status = cc()
+        .arg("-fsyntax-only")
+        .arg(&source)
+        .status()
+        .expect("failed to run the C compiler");
+    fs::remove_dir_all(&scratch_dir).unwrap();
+    assert!(status.success(), "the per-module headers failed to compile");
+}
+
+#[test]
+fn test_c_harness() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let scratch_dir = env::temp_dir().join(format!("rustelo-c-harness-{}", process::id()));
+    fs::create_dir_all(&scratch_dir).unwrap();
+    let harness = scratch_dir.join("harness");
+
+    let status = cc()
+        .arg(manifest_dir.join("tests").join("c").join("harness.c"))
+        .arg(staticlib_path())
+        .args(NATIVE_LIBS)
+        .arg("-o")
+        .arg(&harness)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "harness.c failed to compile");
+
+    let status = Command::new(&harness)
+        .arg(&scratch_dir)
+        .status()
+        .expect("failed to run the harness");
+    fs::remove_dir_all(&scratch_dir).unwrap();
+    assert!(status.success(), "harness checks failed");
+}